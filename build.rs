@@ -5,8 +5,12 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use sha2::{Digest, Sha256};
+
 const DEFAULT_REPO: &str = "KolbyML/Manatan-Server-Public";
 const DEFAULT_TAG: &str = "stable";
+const UNIVERSAL_DARWIN: &str = "universal-apple-darwin";
+const DARWIN_SLICES: [&str; 2] = ["x86_64-apple-darwin", "aarch64-apple-darwin"];
 
 #[derive(Debug, Clone)]
 struct ReleaseAsset {
@@ -15,21 +19,35 @@ struct ReleaseAsset {
 }
 
 fn main() {
+    default_webui_dist();
+    embed_default_config();
+
     let target = env::var("TARGET").expect("TARGET not set");
     let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR"));
-    let lib_dir = manifest_dir.join("lib").join(&target);
-
-    let is_windows = target.contains("windows");
-    let lib_name = if is_windows {
-        "manatan_server.lib"
+    // Both slices of a universal build link against the same fat archive.
+    let universal = target.contains("apple-darwin")
+        && env::var_os("MANATAN_SERVER_UNIVERSAL").is_some_and(|value| value != "0");
+    let lib_dir = manifest_dir
+        .join("lib")
+        .join(if universal { UNIVERSAL_DARWIN } else { &target });
+
+    // MinGW targets link GNU archives; only MSVC takes a `.lib`.
+    let is_msvc = env::var("CARGO_CFG_TARGET_ENV").is_ok_and(|env| env == "msvc");
+    let (lib_name, asset_ext) = if is_msvc {
+        ("manatan_server.lib", "lib")
     } else {
-        "libmanatan_server.a"
+        ("libmanatan_server.a", "a")
     };
 
     let lib_path = lib_dir.join(lib_name);
     let meta_path = lib_dir.join(format!("{}.asset-meta", lib_name));
 
-    if let Err(err) = sync_release_asset(&lib_path, &meta_path, &target, is_windows) {
+    let synced = match local_library(lib_name) {
+        Some(source) => use_local_library(&source, &lib_path, &meta_path),
+        None if universal => sync_universal_archive(&lib_path, &meta_path),
+        None => sync_release_asset(&lib_path, &meta_path, &target, asset_ext),
+    };
+    if let Err(err) = synced {
         panic!(
             "Failed to sync static library for {} at {}: {}",
             target,
@@ -46,7 +64,13 @@ fn main() {
         );
     }
 
-    if let Err(err) = maybe_repack_darwin_archive(&lib_path, &target) {
+    // Universal archives are joined from slices that were each repacked already.
+    let repacked = if universal {
+        Ok(())
+    } else {
+        maybe_repack_darwin_archive(&lib_path, &target)
+    };
+    if let Err(err) = repacked {
         panic!(
             "Failed to post-process static library for {} at {}: {}",
             target,
@@ -55,7 +79,7 @@ fn main() {
         );
     }
 
-    if let Err(err) = ensure_sqlite_alias(&lib_dir, &target, &lib_path) {
+    if let Err(err) = ensure_sqlite_alias(&lib_dir, &target, is_msvc, &lib_path) {
         panic!(
             "Failed to prepare sqlite3 compatibility library for {} in {}: {}",
             target,
@@ -73,48 +97,298 @@ fn main() {
     }
     println!("cargo:rerun-if-changed={}", lib_path.display());
     println!("cargo:rerun-if-env-changed=MANATAN_SERVER_PUBLIC_REPO");
+    println!("cargo:rerun-if-env-changed=MANATAN_SERVER_PUBLIC_TAG");
+    println!("cargo:rerun-if-env-changed=MANATAN_SERVER_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=MANATAN_SERVER_LIB_PATH");
+    println!("cargo:rerun-if-env-changed=MANATAN_SERVER_ALLOW_UNVERIFIED");
+    println!("cargo:rerun-if-env-changed=MANATAN_SERVER_UNIVERSAL");
+}
+
+// A library built or fetched elsewhere, for offline and sealed CI builds.
+fn local_library(lib_name: &str) -> Option<PathBuf> {
+    if let Some(path) = env::var_os("MANATAN_SERVER_LIB_PATH").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    env::var_os("MANATAN_SERVER_LIB_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join(lib_name))
+}
+
+fn use_local_library(source: &Path, lib_path: &Path, meta_path: &Path) -> Result<(), String> {
+    if !source.is_file() {
+        return Err(format!("{} does not exist", source.display()));
+    }
+    println!("cargo:rerun-if-changed={}", source.display());
+    if let Some(parent) = lib_path.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("create dir failed: {err}"))?;
+    }
+    if source != lib_path {
+        fs::copy(source, lib_path).map_err(|err| {
+            format!(
+                "copy {} -> {} failed: {err}",
+                source.display(),
+                lib_path.display()
+            )
+        })?;
+    }
+    // Not a release asset, so the next download-based build fetches a fresh one.
+    fs::write(meta_path, format!("local={}\n", source.display()))
+        .map_err(|err| format!("write meta failed: {err}"))
+}
+
+// `include_dir!` needs a directory at compile time, so `--all-features` builds
+// without a frontend embed an empty one and rely on MANATAN_WEBUI_PATH instead.
+fn default_webui_dist() {
+    println!("cargo:rerun-if-env-changed=MANATAN_WEBUI_DIST");
+    if env::var_os("CARGO_FEATURE_WEBUI_EMBED").is_none()
+        || env::var_os("MANATAN_WEBUI_DIST").is_some()
+    {
+        return;
+    }
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR not set"));
+    let empty = out_dir.join("webui-empty");
+    if let Err(err) = fs::create_dir_all(&empty) {
+        panic!("Failed to create {}: {}", empty.display(), err);
+    }
+    println!("cargo:warning=MANATAN_WEBUI_DIST is not set; embedding an empty web UI");
+    println!("cargo:rustc-env=MANATAN_WEBUI_DIST={}", empty.display());
+}
+
+// Distributions can bake their own defaults (branding, index URLs, ports) into the
+// binary; the file sits below every runtime source, so users can still override it.
+fn embed_default_config() {
+    println!("cargo:rerun-if-env-changed=MANATAN_DEFAULT_CONFIG");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR not set"));
+    let embedded = out_dir.join("default-config.toml");
+    let text = match env::var_os("MANATAN_DEFAULT_CONFIG").filter(|path| !path.is_empty()) {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            println!("cargo:rerun-if-changed={}", path.display());
+            let text = fs::read_to_string(&path).unwrap_or_else(|err| {
+                panic!("Failed to read default config {}: {}", path.display(), err)
+            });
+            if let Err(err) = text.parse::<toml::Table>() {
+                panic!(
+                    "Invalid default config {}: {}",
+                    path.display(),
+                    err.message()
+                );
+            }
+            text
+        }
+        None => String::new(),
+    };
+    if let Err(err) = fs::write(&embedded, text) {
+        panic!("Failed to write {}: {}", embedded.display(), err);
+    }
 }
 
 fn sync_release_asset(
     lib_path: &Path,
     meta_path: &Path,
     target: &str,
-    is_windows: bool,
+    asset_ext: &str,
 ) -> Result<(), String> {
-    let asset = release_asset_info(target, is_windows)?;
+    let tag = release_tag();
     let existing_meta = fs::read_to_string(meta_path).ok();
-    let expected_meta = format!("name={}\n", asset.name);
+    let cached_sha = existing_meta
+        .as_deref()
+        .and_then(|meta| meta_value(meta, "sha256"))
+        .filter(|sha| lib_path.exists() && file_sha256(lib_path).ok().as_deref() == Some(*sha));
+
+    // A pinned tag never changes, so a verified copy from it is reused without asking GitHub.
+    if tag != DEFAULT_TAG
+        && cached_sha.is_some()
+        && existing_meta
+            .as_deref()
+            .and_then(|meta| meta_value(meta, "tag"))
+            == Some(tag.as_str())
+    {
+        return Ok(());
+    }
 
-    let needs_download =
-        !lib_path.exists() || existing_meta.as_deref() != Some(expected_meta.as_str());
+    let asset = release_asset_info(target, asset_ext, &tag)?;
+    let expected_sha = published_sha256(&asset)?;
+    let up_to_date = existing_meta.as_deref().is_some_and(|meta| {
+        meta_value(meta, "name") == Some(asset.name.as_str())
+            && meta_value(meta, "tag") == Some(tag.as_str())
+    }) && match &expected_sha {
+        Some(expected) => cached_sha.is_some_and(|sha| sha.eq_ignore_ascii_case(expected)),
+        None => lib_path.exists(),
+    };
+    if up_to_date {
+        return Ok(());
+    }
 
-    if needs_download {
-        if let Some(parent) = lib_path.parent() {
-            fs::create_dir_all(parent).map_err(|err| format!("create dir failed: {err}"))?;
+    if let Some(parent) = lib_path.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("create dir failed: {err}"))?;
+    }
+    let partial = lib_path.with_extension("part");
+    download_file(&asset.download_url, &partial)?;
+    let actual_sha = file_sha256(&partial)?;
+    if let Some(expected) = &expected_sha {
+        if !actual_sha.eq_ignore_ascii_case(expected) {
+            let _ = fs::remove_file(&partial);
+            return Err(format!(
+                "checksum mismatch for {}: expected {expected}, got {actual_sha}",
+                asset.name
+            ));
         }
-        download_file(&asset.download_url, lib_path)?;
-        fs::write(meta_path, expected_meta).map_err(|err| format!("write meta failed: {err}"))?;
     }
+    fs::rename(&partial, lib_path).map_err(|err| format!("replace library failed: {err}"))?;
+    let meta = format!("name={}\ntag={tag}\nsha256={actual_sha}\n", asset.name);
+    fs::write(meta_path, meta).map_err(|err| format!("write meta failed: {err}"))?;
 
     Ok(())
 }
 
-fn release_asset_info(target: &str, is_windows: bool) -> Result<ReleaseAsset, String> {
+// A universal asset from the release is used as it is; without one, the x86_64 and aarch64
+// assets are fetched into their own `lib/<target>/` directories and joined with `lipo`.
+fn sync_universal_archive(lib_path: &Path, meta_path: &Path) -> Result<(), String> {
+    let published = sync_release_asset(lib_path, meta_path, UNIVERSAL_DARWIN, "a");
+    if published.is_ok() {
+        return published;
+    }
+    let lib_root = lib_path
+        .parent()
+        .and_then(Path::parent)
+        .ok_or("library path has no parent")?;
+    let mut slices = Vec::with_capacity(DARWIN_SLICES.len());
+    for target in DARWIN_SLICES {
+        let dir = lib_root.join(target);
+        let slice = dir.join("libmanatan_server.a");
+        let slice_meta = dir.join("libmanatan_server.a.asset-meta");
+        sync_release_asset(&slice, &slice_meta, target, "a")?;
+        maybe_repack_darwin_archive(&slice, target)?;
+        slices.push(slice);
+    }
+
+    let joined_at = fs::metadata(lib_path).and_then(|meta| meta.modified()).ok();
+    let fresh = joined_at.is_some_and(|joined_at| {
+        slices.iter().all(|slice| {
+            fs::metadata(slice)
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|modified| modified <= joined_at)
+        })
+    });
+    if fresh {
+        return Ok(());
+    }
+
+    let lipo = ["lipo", "llvm-lipo"]
+        .into_iter()
+        .find(|command| {
+            Command::new(command)
+                .arg("-info")
+                .arg(&slices[0])
+                .output()
+                .is_ok()
+        })
+        .ok_or("universal builds need lipo or llvm-lipo")?;
+    let output = Command::new(lipo)
+        .arg("-create")
+        .args(&slices)
+        .arg("-output")
+        .arg(lib_path)
+        .output()
+        .map_err(|err| format!("{lipo} -create failed: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{lipo} -create failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    let meta = format!("universal={}\n", DARWIN_SLICES.join(","));
+    fs::write(meta_path, meta).map_err(|err| format!("write meta failed: {err}"))
+}
+
+fn release_tag() -> String {
+    env::var("MANATAN_SERVER_PUBLIC_TAG")
+        .ok()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .unwrap_or_else(|| DEFAULT_TAG.to_string())
+}
+
+fn meta_value<'a>(meta: &'a str, key: &str) -> Option<&'a str> {
+    meta.lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+}
+
+// Releases publish `<asset>.sha256` next to each library, in `sha256sum` format.
+fn published_sha256(asset: &ReleaseAsset) -> Result<Option<String>, String> {
+    let url = format!("{}.sha256", asset.download_url);
+    let text = match ureq::get(&url)
+        .set("User-Agent", "manatan-server-public-build")
+        .call()
+    {
+        Ok(response) => response
+            .into_string()
+            .map_err(|err| format!("read {url} failed: {err}"))?,
+        Err(err) => {
+            if env::var_os("MANATAN_SERVER_ALLOW_UNVERIFIED").is_some() {
+                println!(
+                    "cargo:warning=no checksum for {} ({err}); using it unverified",
+                    asset.name
+                );
+                return Ok(None);
+            }
+            return Err(format!(
+                "checksum {url} not available: {err}; \
+                 set MANATAN_SERVER_ALLOW_UNVERIFIED=1 to skip verification"
+            ));
+        }
+    };
+    let sha = text.split_whitespace().next().unwrap_or_default();
+    if sha.len() != 64 || !sha.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(format!("{url} does not hold a sha256 checksum"));
+    }
+    Ok(Some(sha.to_ascii_lowercase()))
+}
+
+fn file_sha256(path: &Path) -> Result<String, String> {
+    let mut file =
+        fs::File::open(path).map_err(|err| format!("open {} failed: {err}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .map_err(|err| format!("read {} failed: {err}", path.display()))?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+// Targets whose assets can stand in for `target`, best first. Windows targets fall back
+// to the `pc` vendor build for their ABI (`gnullvm` shares the MinGW one), never to the
+// other ABI.
+fn asset_targets(target: &str) -> Vec<String> {
+    let mut targets = vec![target.to_string()];
+    let parts = target.split('-').collect::<Vec<_>>();
+    if let [arch, _, "windows", env] = parts.as_slice() {
+        let env = if *env == "gnullvm" { "gnu" } else { env };
+        let fallback = format!("{arch}-pc-windows-{env}");
+        if fallback != target {
+            targets.push(fallback);
+        }
+    }
+    targets
+}
+
+fn release_asset_info(target: &str, asset_ext: &str, tag: &str) -> Result<ReleaseAsset, String> {
     let repo = env::var("MANATAN_SERVER_PUBLIC_REPO").unwrap_or_else(|_| DEFAULT_REPO.to_string());
-    let tag = DEFAULT_TAG;
-    let asset_ext = if is_windows { "lib" } else { "a" };
-    let primary_asset_name = format!("manatan-server-{}.{}", target, asset_ext);
-    let legacy_asset_name = format!("manatan-server-manatan-server-{}.{}", target, asset_ext);
-    let candidates = [
-        (
-            primary_asset_name.clone(),
-            format!("https://github.com/{repo}/releases/download/{tag}/{primary_asset_name}"),
-        ),
-        (
-            legacy_asset_name.clone(),
-            format!("https://github.com/{repo}/releases/download/{tag}/{legacy_asset_name}"),
-        ),
-    ];
+    let candidates = asset_targets(target)
+        .into_iter()
+        .flat_map(|target| {
+            [
+                format!("manatan-server-{target}.{asset_ext}"),
+                format!("manatan-server-manatan-server-{target}.{asset_ext}"),
+            ]
+        })
+        .map(|name| {
+            let url = format!("https://github.com/{repo}/releases/download/{tag}/{name}");
+            (name, url)
+        });
 
     let mut last_err = String::new();
     for (name, url) in candidates {
@@ -149,12 +423,17 @@ fn download_file(url: &str, path: &Path) -> Result<(), String> {
     Ok(())
 }
 
-fn ensure_sqlite_alias(lib_dir: &Path, target: &str, manatan_lib: &Path) -> Result<(), String> {
+fn ensure_sqlite_alias(
+    lib_dir: &Path,
+    target: &str,
+    is_msvc: bool,
+    manatan_lib: &Path,
+) -> Result<(), String> {
     if target.contains("apple-ios") {
         return Ok(());
     }
 
-    let sqlite_alias = if target.contains("windows") {
+    let sqlite_alias = if is_msvc {
         lib_dir.join("sqlite3.lib")
     } else {
         lib_dir.join("libsqlite3.a")
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Json, Router,
+};
+use chrono::{Datelike, Local, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::app::AppState;
+use crate::store::{load_json, save_json};
+
+// A stretch of the day a principal is kept out, in the server's local time. A window
+// whose `to` is earlier than its `from` runs past midnight; `days` (`mon`..`sun`) names
+// the days it starts on, and every day when empty.
+#[derive(Clone, Serialize, Deserialize)]
+struct Window {
+    from: String,
+    to: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    days: Vec<String>,
+}
+
+struct Parsed {
+    from: NaiveTime,
+    to: NaiveTime,
+    days: Vec<Weekday>,
+}
+
+impl Window {
+    fn parse(&self) -> Result<Parsed, String> {
+        let time = |value: &str| {
+            NaiveTime::parse_from_str(value, "%H:%M")
+                .map_err(|_| format!("{value:?} is not a HH:MM time"))
+        };
+        let days = self
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| format!("{day:?} is not a weekday"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Parsed {
+            from: time(&self.from)?,
+            to: time(&self.to)?,
+            days,
+        })
+    }
+}
+
+impl Parsed {
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    fn covers(&self, day: Weekday, now: NaiveTime) -> bool {
+        if self.from <= self.to {
+            self.starts_on(day) && self.from <= now && now < self.to
+        } else {
+            (self.starts_on(day) && now >= self.from)
+                || (self.starts_on(day.pred()) && now < self.to)
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ScheduleData {
+    // Keyed by principal id: a token id, `static-token` or `basic:{user}`.
+    schedules: BTreeMap<String, Vec<Window>>,
+}
+
+// Parental-control style access windows per user or token, enforced by the auth layer.
+pub(crate) struct AccessSchedules {
+    path: PathBuf,
+    data: Mutex<ScheduleData>,
+}
+
+impl AccessSchedules {
+    pub(crate) fn new(data_path: &str) -> Self {
+        let path = PathBuf::from(data_path).join("access-schedules.json");
+        Self {
+            data: Mutex::new(load_json(&path)),
+            path,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ScheduleData> {
+        self.data.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn persist(&self, data: &ScheduleData) {
+        if let Err(err) = save_json(&self.path, data) {
+            warn!(
+                "failed to persist access schedules to {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+
+    // When the window keeping `id` out ends, if one covers the current time.
+    pub(crate) fn blocked_until(&self, id: &str) -> Option<String> {
+        let data = self.lock();
+        let windows = data.schedules.get(id)?;
+        let now = Local::now();
+        let (day, time) = (now.weekday(), now.time());
+        windows
+            .iter()
+            .filter_map(|window| window.parse().ok())
+            .find(|window| window.covers(day, time))
+            .map(|window| window.to.format("%H:%M").to_string())
+    }
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/admin/users", get(list)).route(
+        "/admin/users/{id}/schedule",
+        put(set_schedule).delete(clear_schedule),
+    )
+}
+
+async fn list(State(state): State<AppState>) -> Json<Vec<Value>> {
+    let mut users = state.auth.users();
+    let data = state.access_schedules.lock();
+    for user in &mut users {
+        let id = user["id"].as_str().unwrap_or_default();
+        user["schedule"] = json!(data.schedules.get(id).cloned().unwrap_or_default());
+    }
+    // Schedules for principals that no longer exist, such as deleted tokens.
+    for (id, windows) in &data.schedules {
+        if !users.iter().any(|user| user["id"] == id.as_str()) {
+            users.push(json!({ "id": id, "kind": "unknown", "schedule": windows }));
+        }
+    }
+    Json(users)
+}
+
+#[derive(Deserialize)]
+struct SetSchedule {
+    blocked: Vec<Window>,
+}
+
+async fn set_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(body): Json<SetSchedule>,
+) -> Response {
+    if let Some(err) = body.blocked.iter().find_map(|window| window.parse().err()) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, err).into_response();
+    }
+    let known = state
+        .auth
+        .users()
+        .iter()
+        .any(|user| user["id"] == id.as_str());
+    if !known {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let mut data = state.access_schedules.lock();
+    if body.blocked.is_empty() {
+        data.schedules.remove(&id);
+    } else {
+        data.schedules.insert(id.clone(), body.blocked.clone());
+    }
+    state.access_schedules.persist(&data);
+    Json(json!({ "id": id, "schedule": body.blocked })).into_response()
+}
+
+async fn clear_schedule(State(state): State<AppState>, Path(id): Path<String>) -> StatusCode {
+    let mut data = state.access_schedules.lock();
+    if data.schedules.remove(&id).is_none() {
+        return StatusCode::NOT_FOUND;
+    }
+    state.access_schedules.persist(&data);
+    StatusCode::NO_CONTENT
+}
@@ -0,0 +1,73 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::app::AppState;
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/status", get(status))
+        .route("/admin/outbound/profiles", get(outbound_profiles))
+        .route("/admin/outbound/log", get(outbound_log))
+        .route("/admin/image/profiles", get(image_profiles))
+        .route("/admin/webview", get(webview).put(set_webview))
+}
+
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "backend": {
+            "url": state.backend_url,
+            "port": state.backend.port(),
+            "running": state.backend.port().is_some(),
+            "restarts": state.backend.restarts(),
+        },
+        "webview": { "enabled": state.backend.webview_enabled() },
+        "hwaccel": state.backend.hwaccel(),
+        "watchdog": state.watchdog.status(),
+        "workers": state.workers.describe(),
+        "probes": state.probes.describe(),
+    }))
+}
+
+async fn outbound_profiles(State(state): State<AppState>) -> Json<Value> {
+    Json(state.outbound.describe())
+}
+
+async fn outbound_log(State(state): State<AppState>) -> Json<Value> {
+    Json(state.outbound.log())
+}
+
+async fn image_profiles(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "profiles": state.image_transform.profiles() }))
+}
+
+#[derive(Deserialize)]
+struct SetWebview {
+    enabled: bool,
+}
+
+async fn webview(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "enabled": state.backend.webview_enabled() }))
+}
+
+async fn set_webview(State(state): State<AppState>, Json(body): Json<SetWebview>) -> Response {
+    let backend = state.backend.clone();
+    let enabled = body.enabled;
+    match tokio::task::spawn_blocking(move || backend.set_webview_enabled(enabled)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => return (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+    info!(
+        "webview {}",
+        if body.enabled { "enabled" } else { "disabled" }
+    );
+    Json(json!({ "enabled": body.enabled })).into_response()
+}
@@ -0,0 +1,223 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::app::AppState;
+
+const MAX_PACKAGE_BYTES: usize = 64 * 1024 * 1024;
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/admin/aidoku/extensions",
+            get(list_extensions).post(install_extension),
+        )
+        .route("/admin/aidoku/extensions/{id}", delete(uninstall_extension))
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct IndexEntry {
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    version: Value,
+    file: String,
+    #[serde(default, alias = "hash")]
+    sha256: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct InstallRequest {
+    id: String,
+}
+
+fn disabled(state: &AppState) -> Option<Response> {
+    if state.config.aidoku_enabled && !state.config.aidoku_index_url.is_empty() {
+        return None;
+    }
+    Some((StatusCode::CONFLICT, "aidoku is not enabled").into_response())
+}
+
+async fn list_extensions(State(state): State<AppState>) -> Response {
+    if let Some(resp) = disabled(&state) {
+        return resp;
+    }
+    let backend = state.backend.clone();
+    let installed = match tokio::task::spawn_blocking(move || backend.aidoku_installed()).await {
+        Ok(Ok(installed)) => installed,
+        Ok(Err(err)) => return (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    let available = match fetch_index(&state).await {
+        Ok(entries) => json!(entries
+            .iter()
+            .map(|entry| json!({
+                "id": entry.id,
+                "name": entry.name,
+                "version": entry.version,
+                "verifiable": entry.sha256.is_some(),
+            }))
+            .collect::<Vec<_>>()),
+        Err(err) => json!({ "error": err }),
+    };
+    Json(json!({ "installed": installed, "available": available })).into_response()
+}
+
+async fn install_extension(
+    State(state): State<AppState>,
+    Json(body): Json<InstallRequest>,
+) -> Response {
+    if let Some(resp) = disabled(&state) {
+        return resp;
+    }
+    let entries = match fetch_index(&state).await {
+        Ok(entries) => entries,
+        Err(err) => return (StatusCode::BAD_GATEWAY, err).into_response(),
+    };
+    let Some(entry) = entries.into_iter().find(|entry| entry.id == body.id) else {
+        return (StatusCode::NOT_FOUND, "extension not found in index").into_response();
+    };
+    let Some(expected) = entry.sha256.as_deref().map(str::to_lowercase) else {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "index entry has no sha256; refusing to install an unverified package",
+        )
+            .into_response();
+    };
+
+    let bytes = match download(
+        &state,
+        &package_url(&state.config.aidoku_index_url, &entry.file),
+    )
+    .await
+    {
+        Ok(bytes) => bytes,
+        Err(err) => return (StatusCode::BAD_GATEWAY, err).into_response(),
+    };
+    let actual = hex_digest(&bytes);
+    if actual != expected {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("sha256 mismatch: expected {expected}, got {actual}"),
+        )
+            .into_response();
+    }
+    if !bytes.starts_with(b"PK\x03\x04") {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "package is not a zip archive",
+        )
+            .into_response();
+    }
+
+    let dir = PathBuf::from(&state.config.aidoku_cache_path).join("packages");
+    let path = dir.join(format!("{}.aix", sanitize(&entry.id)));
+    if let Err(err) = std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, &bytes)) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+    }
+
+    let backend = state.backend.clone();
+    let package_path = path.to_string_lossy().into_owned();
+    match tokio::task::spawn_blocking(move || backend.aidoku_install(&package_path)).await {
+        Ok(Ok(())) => {
+            info!("installed aidoku extension {} ({})", entry.id, actual);
+            (
+                StatusCode::CREATED,
+                Json(json!({ "id": entry.id, "version": entry.version, "sha256": actual })),
+            )
+                .into_response()
+        }
+        Ok(Err(err)) => (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+async fn uninstall_extension(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    if let Some(resp) = disabled(&state) {
+        return resp;
+    }
+    let backend = state.backend.clone();
+    let target = id.clone();
+    match tokio::task::spawn_blocking(move || backend.aidoku_uninstall(&target)).await {
+        Ok(Ok(())) => {
+            let path = PathBuf::from(&state.config.aidoku_cache_path)
+                .join("packages")
+                .join(format!("{}.aix", sanitize(&id)));
+            let _ = std::fs::remove_file(path);
+            info!("removed aidoku extension {}", id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(Err(err)) => (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+async fn fetch_index(state: &AppState) -> Result<Vec<IndexEntry>, String> {
+    let bytes = download(state, &state.config.aidoku_index_url).await?;
+    serde_json::from_slice(&bytes).map_err(|err| format!("invalid aidoku index: {err}"))
+}
+
+async fn download(state: &AppState, url: &str) -> Result<Vec<u8>, String> {
+    let resp = state
+        .outbound
+        .request(Method::GET, url)
+        .send()
+        .await
+        .map_err(|err| format!("{url}: {err}"))?;
+    if !resp.status().is_success() {
+        return Err(format!("{url}: status {}", resp.status()));
+    }
+    if resp
+        .content_length()
+        .is_some_and(|len| len > MAX_PACKAGE_BYTES as u64)
+    {
+        return Err(format!("{url}: response too large"));
+    }
+    let bytes = resp.bytes().await.map_err(|err| format!("{url}: {err}"))?;
+    if bytes.len() > MAX_PACKAGE_BYTES {
+        return Err(format!("{url}: response too large"));
+    }
+    Ok(bytes.to_vec())
+}
+
+fn package_url(index_url: &str, file: &str) -> String {
+    if file.starts_with("http://") || file.starts_with("https://") {
+        return file.to_string();
+    }
+    let base = match index_url.rsplit_once('/') {
+        Some((base, last)) if last.ends_with(".json") => base,
+        _ => index_url.trim_end_matches('/'),
+    };
+    format!("{base}/sources/{}", file.trim_start_matches('/'))
+}
+
+fn sanitize(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -1,172 +1,754 @@
 use axum::{
     Router,
-    body::{Body, Bytes},
-    extract::{FromRequestParts, Request, State, ws::{Message, WebSocket, WebSocketUpgrade}},
-    http::{HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
-    routing::any,
+    body::Body,
+    extract::Request,
+    http::{header, request::Parts, HeaderMap, Method, StatusCode},
+    response::Response,
 };
-use futures::{SinkExt, StreamExt};
 use reqwest::Client;
-use tokio_tungstenite::{
-    connect_async,
-    tungstenite::{
-        client::IntoClientRequest,
-        protocol::{Message as TungsteniteMessage, frame::coding::CloseCode},
-    },
-};
-use tower_http::cors::{Any, CorsLayer};
-use tracing::error;
+use tracing::{debug, info};
 
-use crate::config::Config;
-use crate::ffi;
+#[cfg(feature = "auth")]
+use crate::access_schedule::{self, AccessSchedules};
+use crate::admin;
+use crate::aidoku;
+#[cfg(feature = "auth")]
+use crate::auth::{self, Auth};
+use crate::backend_addr::{BackendAddr, Transport};
+use crate::backup;
+use crate::bandwidth::{self, Bandwidth};
+use crate::base_path;
+use crate::devices::{self, DeviceProgress};
+use crate::discovery::Discovery;
+use crate::image_cache::{self, ImageCache};
+use crate::image_transform::ImageTransformer;
+use crate::inbox::{self, Inbox};
+#[cfg(feature = "notifications")]
+use crate::integrations::{self, Integrations};
+use crate::layers::{compression_layer_for, cors_layer_for, AuthLayer, ProxyLayer};
+use crate::calendar;
+use crate::canonical;
+use crate::capabilities;
+use crate::cassette::Cassette;
+use crate::coalesce::{self, Coalescer};
+use crate::config::{CassetteMode, Config, ConfigUpdate, RedirectPolicy, RoutesConfig};
+use crate::content_filter::{self, ContentFilter};
+use crate::control::{self, Control, ControlMessage};
+use crate::docs_cache::{self, DocsCache};
+use crate::credentials::Credentials;
+use crate::embedded::EmbeddedServer;
+use crate::error_pages::{self, ErrorPages};
+use crate::events::{self, BackendEvent};
+#[cfg(feature = "opds")]
+use crate::feeds;
+use crate::jobs::{self, JobQueue};
+use crate::json_delta::{DeltaRequest, JsonDelta};
+use crate::local_manga;
+#[cfg(feature = "notifications")]
+use crate::media_servers::{self, MediaServers};
+use crate::metrics::{self, Metrics};
+#[cfg(feature = "metrics")]
+use crate::metrics_push;
+use crate::normalize;
+#[cfg(feature = "cache")]
+use crate::offline_cache::OfflineCache;
+use crate::outbound::Outbound;
+#[cfg(feature = "cache")]
+use crate::page_archive::{self, PageArchive};
+use crate::principal::Principal;
+use crate::probes::{self, Probes};
+use crate::quota::{self, Quota};
+use crate::rate_limit::{self, RateLimiter, WebSocketPermit};
+use crate::resources;
+use crate::resumable::{self, ResumableUploads};
+use crate::reconcile;
+use crate::relay_sync::{self, RelaySync};
+use crate::retention;
+use crate::runtime_config;
+use crate::sampling::{self, Sampler};
+use crate::selftest;
+use crate::share::{self, Shares};
+use crate::signed_urls::{self, SignedUrls};
+use crate::signing::RequestSigning;
+use crate::skip_markers::{self, SkipMarkers};
+use crate::ssdp;
+use crate::stats::{self, ReadingStats};
+use crate::storage;
+use crate::supervisor::{self, BackendUnreachable, Lifecycle, Supervisor};
+use crate::tls;
+#[cfg(feature = "transcode")]
+use crate::transcodes::{self, Transcodes};
+use crate::trash::{self, Trash};
+use crate::uploads;
+use crate::watchdog::{self, Watchdog};
+#[cfg(feature = "webui")]
+use crate::webui;
+use crate::well_known::{self, WellKnown};
+use crate::workers::{self, WorkerPool};
+use crate::wol;
+use crate::ws::{self, Bridges, WsBridge};
+use crate::Error;
 
 #[derive(Clone)]
 pub struct AppState {
     pub config: Config,
     pub backend_url: String,
-    client: Client,
-    _server: std::sync::Arc<EmbeddedServer>,
+    pub(crate) client: Client,
+    pub(crate) backend_transport: Transport,
+    pub(crate) backend: std::sync::Arc<EmbeddedServer>,
+    pub(crate) metrics: std::sync::Arc<Metrics>,
+    pub(crate) watchdog: std::sync::Arc<Watchdog>,
+    pub(crate) content_filter: std::sync::Arc<ContentFilter>,
+    pub(crate) stats: std::sync::Arc<ReadingStats>,
+    pub(crate) devices: std::sync::Arc<DeviceProgress>,
+    pub(crate) relay_sync: std::sync::Arc<RelaySync>,
+    pub(crate) shares: std::sync::Arc<Shares>,
+    pub(crate) signed_urls: std::sync::Arc<SignedUrls>,
+    pub(crate) cassette: std::sync::Arc<Cassette>,
+    pub(crate) outbound: std::sync::Arc<Outbound>,
+    pub(crate) well_known: std::sync::Arc<WellKnown>,
+    pub(crate) error_pages: std::sync::Arc<ErrorPages>,
+    #[cfg(feature = "auth")]
+    pub(crate) auth: std::sync::Arc<Auth>,
+    #[cfg(feature = "auth")]
+    pub(crate) access_schedules: std::sync::Arc<AccessSchedules>,
+    pub(crate) signing: std::sync::Arc<RequestSigning>,
+    pub(crate) workers: std::sync::Arc<WorkerPool>,
+    pub(crate) jobs: std::sync::Arc<JobQueue>,
+    pub(crate) sampler: std::sync::Arc<Sampler>,
+    pub(crate) docs_cache: std::sync::Arc<DocsCache>,
+    pub(crate) image_cache: std::sync::Arc<ImageCache>,
+    #[cfg(feature = "cache")]
+    pub(crate) page_archive: std::sync::Arc<PageArchive>,
+    pub(crate) image_transform: std::sync::Arc<ImageTransformer>,
+    #[cfg(feature = "cache")]
+    pub(crate) offline_cache: std::sync::Arc<OfflineCache>,
+    pub(crate) json_delta: std::sync::Arc<JsonDelta>,
+    pub(crate) coalescer: std::sync::Arc<Coalescer>,
+    pub(crate) events: tokio::sync::broadcast::Sender<BackendEvent>,
+    pub(crate) supervisor: std::sync::Arc<Supervisor>,
+    pub(crate) probes: std::sync::Arc<Probes>,
+    pub(crate) quota: std::sync::Arc<Quota>,
+    pub(crate) rate_limit: std::sync::Arc<RateLimiter>,
+    pub(crate) bandwidth: std::sync::Arc<Bandwidth>,
+    #[cfg(feature = "transcode")]
+    pub(crate) transcodes: std::sync::Arc<Transcodes>,
+    pub(crate) skip_markers: std::sync::Arc<SkipMarkers>,
+    pub(crate) trash: std::sync::Arc<Trash>,
+    pub(crate) inbox: std::sync::Arc<Inbox>,
+    #[cfg(feature = "notifications")]
+    pub(crate) integrations: std::sync::Arc<Integrations>,
+    #[cfg(feature = "notifications")]
+    pub(crate) media_servers: std::sync::Arc<MediaServers>,
+    pub(crate) discovery: Option<std::sync::Arc<Discovery>>,
+    pub(crate) control: std::sync::Arc<Control>,
+    pub(crate) resumable: std::sync::Arc<ResumableUploads>,
+    // Held for the whole of a backup restore so two cannot swap files at once.
+    pub(crate) restoring: std::sync::Arc<tokio::sync::Mutex<()>>,
+    pub(crate) ws_bridges: std::sync::Arc<Bridges>,
+}
+
+impl AppState {
+    // Withdraws anything announced on the network; the backend stops when the last state drops.
+    pub fn shutdown(&self) {
+        if let Some(discovery) = &self.discovery {
+            discovery.stop();
+        }
+        control::close(&self.config);
+    }
+
+    // Control channel commands for the embedding shell, such as `show` from a second launch.
+    pub fn control_messages(&self) -> tokio::sync::broadcast::Receiver<ControlMessage> {
+        self.control.subscribe()
+    }
+
+    // Current values of the settings `update_config` can change.
+    pub fn live_config(&self) -> ConfigUpdate {
+        self.backend.settings()
+    }
+
+    // Applies the fields that differ from the running values and returns their keys.
+    // `config` keeps the startup values; this only reaches the backend.
+    pub fn update_config(&self, update: &ConfigUpdate) -> Result<Vec<String>, Error> {
+        self.backend.apply(update)
+    }
+
+    pub(crate) async fn backend_json(
+        &self,
+        path: &str,
+        headers: &HeaderMap,
+    ) -> Result<serde_json::Value, String> {
+        let mut request = self.client.get(format!("{}{}", self.backend_url, path));
+        for name in ["authorization", "cookie"] {
+            if let Some(value) = headers.get(name) {
+                request = request.header(name, value);
+            }
+        }
+        let resp = request.send().await.map_err(|err| err.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("{path}: status {}", resp.status()));
+        }
+        resp.json().await.map_err(|err| err.to_string())
+    }
+
+    pub(crate) fn external_base_url(&self, headers: &HeaderMap) -> String {
+        match self.config.external_url.as_deref() {
+            Some(url) => url.to_string(),
+            None => format!(
+                "{}{}",
+                request_base_url(headers, self.config.tls_enabled()),
+                self.config.base_path
+            ),
+        }
+    }
 }
 
 pub fn build_router(state: AppState) -> Router {
-    build_router_without_cors(state).layer(
-        CorsLayer::new()
-            .allow_origin(Any)
-            .allow_methods(Any)
-            .allow_headers(Any),
-    )
+    let cors = cors_layer_for(&state.config.cors);
+    let compression = state
+        .config
+        .compression
+        .enabled
+        .then(|| compression_layer_for(&state.config.compression));
+    let router = build_router_without_cors(state);
+    match compression {
+        Some(layer) => router.layer(layer).layer(cors),
+        None => router.layer(cors),
+    }
 }
 
 pub fn build_router_without_cors(state: AppState) -> Router {
-    let docs = Router::new()
-        .route("/docs", any(proxy_handler))
-        .route("/docs/{*path}", any(proxy_handler))
-        .route("/openapi.json", any(proxy_handler));
+    let routes = Router::new()
+        .merge(admin::router())
+        .merge(selftest::router())
+        .merge(backup::router())
+        .merge(storage::router())
+        .merge(aidoku::router())
+        .merge(stats::router())
+        .merge(devices::router())
+        .merge(relay_sync::router())
+        .merge(calendar::router())
+        .merge(capabilities::router())
+        .merge(share::router())
+        .merge(signed_urls::router())
+        .merge(well_known::router())
+        .merge(ssdp::router())
+        .merge(wol::router())
+        .merge(jobs::router())
+        .merge(coalesce::router())
+        .merge(events::router())
+        .merge(supervisor::router())
+        .merge(probes::router())
+        .merge(quota::router())
+        .merge(bandwidth::router())
+        .merge(skip_markers::router())
+        .merge(trash::router())
+        .merge(inbox::router())
+        .merge(resources::router())
+        .merge(retention::router())
+        .merge(reconcile::router())
+        .merge(runtime_config::router())
+        .merge(local_manga::router())
+        .merge(resumable::router())
+        .merge(ws::router());
+    #[cfg(feature = "metrics")]
+    let routes = routes.route("/metrics", axum::routing::get(metrics::metrics_handler));
+    #[cfg(feature = "opds")]
+    let routes = routes.merge(feeds::router());
+    #[cfg(feature = "auth")]
+    let routes = routes
+        .merge(auth::router())
+        .merge(access_schedule::router());
+    #[cfg(feature = "cache")]
+    let routes = routes.merge(page_archive::router());
+    #[cfg(feature = "notifications")]
+    let routes = routes
+        .merge(integrations::router())
+        .merge(media_servers::router());
+    #[cfg(feature = "transcode")]
+    let routes = routes.merge(transcodes::router());
+    #[cfg(feature = "webui")]
+    let routes = routes.fallback(webui::fallback);
+    #[cfg(not(feature = "webui"))]
+    if state.config.webui_path.is_some() {
+        tracing::warn!("MANATAN_WEBUI_PATH is set but this build has no webui feature");
+    }
+    let routes = routes
+        .layer(ProxyLayer::new(state.clone()))
+        .layer(AuthLayer::new(state.clone()))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            error_pages::middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            sampling::middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit::middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            metrics::middleware,
+        ))
+        .with_state(state.clone());
 
     Router::new()
-        .route("/health", any(proxy_handler))
-        .route("/extension/icon/{apk_name}", any(proxy_handler))
-        .route("/api/v1", any(proxy_handler))
-        .route("/api/v1/{*path}", any(proxy_handler))
-        .merge(docs)
-        .with_state(state)
+        .fallback_service(routes)
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            normalize::middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            canonical::middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state,
+            base_path::middleware,
+        ))
+}
+
+pub async fn serve(state: AppState) -> Result<(), Error> {
+    let addr = state.config.addr();
+    let acceptor = tls::acceptor(&state.config)?;
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .map_err(|err| Error::io(format!("failed to bind {addr}"), err))?;
+    let router = build_router(state.clone());
+    tokio::spawn(control::serve(state.clone()));
+    info!("{}", capabilities::banner(&state));
+    let result = tokio::select! {
+        result = async {
+            match acceptor {
+                Some(acceptor) => {
+                    info!("listening on https://{}", addr);
+                    tls::serve(listener, acceptor, router).await;
+                    Ok(())
+                }
+                None => {
+                    info!("listening on http://{}", addr);
+                    axum::serve(
+                        listener,
+                        router.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+                    )
+                    .await
+                    .map_err(|err| Error::io("server error", err))
+                }
+            }
+        } => result,
+        _ = shutdown_signal() => {
+            info!("shutting down");
+            Ok(())
+        }
+        _ = state.control.shutdown_requested() => {
+            info!("shutting down");
+            Ok(())
+        }
+    };
+    state.shutdown();
+    result
 }
 
-pub(crate) fn new_state(config: Config, backend_url: String, handle: *mut ffi::ManatanServerHandle) -> AppState {
-    AppState {
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+pub(crate) fn new_state(config: Config, addr: &BackendAddr, server: EmbeddedServer) -> AppState {
+    let backend_url = addr.url.clone();
+    let client = addr.client().clone();
+    let backend = std::sync::Arc::new(server);
+    let outbound = std::sync::Arc::new(Outbound::new(&config.outbound));
+    let metrics = std::sync::Arc::new(Metrics::default());
+    let watchdog = std::sync::Arc::new(Watchdog::new(config.watchdog.clone()));
+    let content_filter = std::sync::Arc::new(ContentFilter::new(config.content_filter.clone()));
+    let stats = std::sync::Arc::new(ReadingStats::new(
+        config.stats.clone(),
+        &config.proxy_data_path,
+    ));
+    let devices = std::sync::Arc::new(DeviceProgress::new(
+        config.devices.clone(),
+        &config.proxy_data_path,
+    ));
+    let relay_sync = std::sync::Arc::new(RelaySync::new(
+        config.relay_sync.clone(),
+        &config.proxy_data_path,
+    ));
+    let shares = std::sync::Arc::new(Shares::new(config.share.clone(), &config.proxy_data_path));
+    let signed_urls = std::sync::Arc::new(SignedUrls::new(
+        config.signed_urls.clone(),
+        &config.proxy_data_path,
+    ));
+    let cassette = std::sync::Arc::new(Cassette::new(&config.cassette, &config.proxy_data_path));
+    let well_known = std::sync::Arc::new(WellKnown::new(&config.well_known));
+    let error_pages = std::sync::Arc::new(ErrorPages::new(&config.error_pages));
+    #[cfg(feature = "auth")]
+    let auth = std::sync::Arc::new(Auth::new(config.auth.clone(), &config.proxy_data_path));
+    #[cfg(feature = "auth")]
+    let access_schedules = std::sync::Arc::new(AccessSchedules::new(&config.proxy_data_path));
+    let signing = std::sync::Arc::new(RequestSigning::new(config.signing.clone()));
+    let workers = std::sync::Arc::new(WorkerPool::new(config.workers.clone()));
+    let jobs = std::sync::Arc::new(JobQueue::new(config.jobs.clone(), &config.proxy_data_path));
+    let sampler = std::sync::Arc::new(Sampler::new(config.sampling.clone()));
+    let docs_cache = std::sync::Arc::new(DocsCache::default());
+    let image_cache = std::sync::Arc::new(ImageCache::new(
+        config.image_cache.clone(),
+        &config.proxy_data_path,
+    ));
+    #[cfg(feature = "cache")]
+    let page_archive = std::sync::Arc::new(PageArchive::new(
+        config.page_archive.clone(),
+        &config.proxy_data_path,
+    ));
+    let image_transform = std::sync::Arc::new(ImageTransformer::new(
+        config.image_transform.clone(),
+    ));
+    #[cfg(feature = "cache")]
+    let offline_cache = std::sync::Arc::new(OfflineCache::new(config.offline_cache.clone()));
+    let json_delta = std::sync::Arc::new(JsonDelta::new(config.json_delta.clone()));
+    let coalescer = std::sync::Arc::new(Coalescer::new(config.refresh_coalesce.clone()));
+    let events = backend.events();
+    let supervisor = std::sync::Arc::new(Supervisor::new(config.supervisor.clone()));
+    let probes = std::sync::Arc::new(Probes::new(config.probes.clone()));
+    let quota = std::sync::Arc::new(Quota::new(config.quota.clone(), &config.downloads_path));
+    let rate_limit = std::sync::Arc::new(RateLimiter::new(config.rate_limit.clone()));
+    let bandwidth = std::sync::Arc::new(Bandwidth::new(config.bandwidth.clone()));
+    #[cfg(feature = "transcode")]
+    let transcodes = std::sync::Arc::new(Transcodes::new(config.transcodes.clone()));
+    let skip_markers = std::sync::Arc::new(SkipMarkers::new(
+        config.skip_markers.clone(),
+        &config.proxy_data_path,
+    ));
+    let trash = std::sync::Arc::new(Trash::new(config.trash.clone(), &config.proxy_data_path));
+    let inbox = std::sync::Arc::new(Inbox::new(config.inbox.clone()));
+    #[cfg(feature = "notifications")]
+    let integrations = std::sync::Arc::new(Integrations::new(config.integrations.clone()));
+    #[cfg(feature = "notifications")]
+    let media_servers = std::sync::Arc::new(MediaServers::new(config.media_servers.clone()));
+
+    watchdog::spawn(
+        watchdog.clone(),
+        std::sync::Arc::downgrade(&backend),
+        client.clone(),
+        outbound.clone(),
+        backend_url.clone(),
+        metrics.clone(),
+    );
+    supervisor::spawn(
+        supervisor.clone(),
+        std::sync::Arc::downgrade(&backend),
+        client.clone(),
+        backend_url.clone(),
+        metrics.clone(),
+    );
+    #[cfg(feature = "metrics")]
+    metrics_push::spawn(
+        config.metrics_push.clone(),
+        config.instance_name.clone(),
+        metrics.clone(),
+        outbound.clone(),
+    );
+    stats::spawn_flusher(&stats);
+    devices::spawn_flusher(&devices);
+    #[cfg(feature = "auth")]
+    auth::spawn_flusher(&auth);
+
+    let state = AppState {
         config,
         backend_url,
-        client: Client::new(),
-        _server: std::sync::Arc::new(EmbeddedServer { handle }),
-    }
+        client,
+        backend_transport: addr.transport.clone(),
+        backend,
+        metrics,
+        watchdog,
+        content_filter,
+        stats,
+        devices,
+        relay_sync,
+        shares,
+        signed_urls,
+        cassette,
+        outbound,
+        well_known,
+        error_pages,
+        #[cfg(feature = "auth")]
+        auth,
+        #[cfg(feature = "auth")]
+        access_schedules,
+        signing,
+        workers,
+        jobs,
+        sampler,
+        docs_cache,
+        image_cache,
+        #[cfg(feature = "cache")]
+        page_archive,
+        image_transform,
+        #[cfg(feature = "cache")]
+        offline_cache,
+        json_delta,
+        coalescer,
+        events,
+        supervisor,
+        probes,
+        quota,
+        rate_limit,
+        bandwidth,
+        #[cfg(feature = "transcode")]
+        transcodes,
+        skip_markers,
+        trash,
+        inbox,
+        #[cfg(feature = "notifications")]
+        integrations,
+        #[cfg(feature = "notifications")]
+        media_servers,
+        discovery: None,
+        control: std::sync::Arc::new(Control::new()),
+        resumable: std::sync::Arc::new(ResumableUploads::default()),
+        restoring: std::sync::Arc::default(),
+        ws_bridges: std::sync::Arc::new(Bridges::default()),
+    };
+    inbox::spawn(state.clone());
+    jobs::spawn(state.clone());
+    #[cfg(feature = "notifications")]
+    media_servers::spawn(state.clone());
+    #[cfg(feature = "cache")]
+    page_archive::spawn(state.clone());
+    probes::spawn(state.clone());
+    quota::spawn(state.clone());
+    relay_sync::spawn(state.clone());
+    resources::spawn(state.clone());
+    resumable::spawn(state.clone());
+    retention::spawn(state.clone());
+    ssdp::spawn(state.clone());
+    trash::spawn(state.clone());
+    state
 }
 
-struct EmbeddedServer {
-    handle: *mut ffi::ManatanServerHandle,
+fn request_base_url(headers: &HeaderMap, tls: bool) -> String {
+    let host = headers
+        .get("x-forwarded-host")
+        .or_else(|| headers.get("host"))
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or(if tls { "https" } else { "http" });
+    format!("{scheme}://{host}")
 }
 
-unsafe impl Send for EmbeddedServer {}
-unsafe impl Sync for EmbeddedServer {}
+pub(crate) async fn proxy(state: AppState, req: Request) -> Response {
+    let (mut parts, body) = req.into_parts();
+    if WsBridge::is_upgrade(&parts.headers) {
+        let permit = parts.extensions.remove::<WebSocketPermit>();
+        return WsBridge::from_state(&state)
+            .with_permit(permit)
+            .upgrade(&mut parts)
+            .await;
+    }
+    if docs_cache::is_docs_path(parts.uri.path()) {
+        return docs_cache::serve(&state, Request::from_parts(parts, body)).await;
+    }
 
-impl Drop for EmbeddedServer {
-    fn drop(&mut self) {
-        if !self.handle.is_null() {
-            unsafe { ffi::manatan_server_stop(self.handle) };
-            self.handle = std::ptr::null_mut();
+    let safe_mode = state.content_filter.applies(&parts.headers, &parts.uri)
+        && content_filter::is_filtered_path(parts.uri.path());
+    if safe_mode {
+        if let Some(resp) = content_filter::block_nsfw(&state, &parts).await {
+            return resp;
         }
+        parts.headers.remove("accept-encoding");
     }
-}
 
-async fn proxy_handler(State(state): State<AppState>, req: Request) -> Response {
-    let (mut parts, body) = req.into_parts();
-    let is_ws = parts
-        .headers
-        .get("upgrade")
-        .and_then(|v| v.to_str().ok())
-        .map(|v| v.eq_ignore_ascii_case("websocket"))
-        .unwrap_or(false);
-
-    if is_ws {
-        let path_query = parts
-            .uri
-            .path_and_query()
-            .map(|v| v.as_str())
-            .unwrap_or(parts.uri.path());
-        let backend_ws = backend_ws_url(&state.backend_url);
-        let backend_url = format!("{backend_ws}{path_query}");
-        let headers = parts.headers.clone();
-        let protocols: Vec<String> = parts
-            .headers
-            .get("sec-websocket-protocol")
-            .and_then(|v| v.to_str().ok())
-            .map(|v| v.split(',').map(|s| s.trim().to_string()).collect())
-            .unwrap_or_default();
-
-        match WebSocketUpgrade::from_request_parts(&mut parts, &()).await {
-            Ok(ws) => {
-                return ws
-                    .protocols(protocols)
-                    .on_upgrade(move |socket| handle_socket(socket, headers, backend_url))
-                    .into_response();
-            }
-            Err(err) => return err.into_response(),
-        }
+    if let Some(resp) = quota::check(&state, &parts).await {
+        return resp;
+    }
+    let consumer = bandwidth::client_label(&parts);
+    if let Some(resp) = rate_limit::bandwidth_cap(&state, &consumer) {
+        return resp;
     }
+    #[cfg(feature = "auth")]
+    if let Some(resp) = rate_limit::token_quota(&state, &parts) {
+        return resp;
+    }
+    let path = parts.uri.path().to_string();
 
-    let req = Request::from_parts(parts, body);
-    proxy_request(state.client, req, &state.backend_url, "").await
-}
+    let client = client_id(&parts);
+    let delta = state.json_delta.request(&mut parts, &client);
+    let progress = stats::progress_series(&parts.method, parts.uri.path())
+        .map(|series| (client.clone(), series));
+    let (body, device_update) = match devices::capture(&state, &parts, &client, body).await {
+        Ok(captured) => captured,
+        Err(resp) => return resp,
+    };
+
+    let permit = match workers::classify(&parts.method, &parts.uri, &parts.headers) {
+        Some(class) if state.workers.enabled() => Some(state.workers.acquire(class).await),
+        _ => None,
+    };
 
-async fn handle_socket(client_socket: WebSocket, headers: HeaderMap, backend_url: String) {
-    let mut request = match backend_url.clone().into_client_request() {
-        Ok(req) => req,
-        Err(e) => {
-            error!("invalid backend URL {}: {}", backend_url, e);
-            return;
+    #[cfg(feature = "cache")]
+    let offline_key = state.offline_cache.key(&parts);
+    if state.supervisor.lifecycle() == Lifecycle::Restarting {
+        #[cfg(feature = "cache")]
+        if let Some(resp) = offline_key
+            .as_deref()
+            .and_then(|key| state.offline_cache.stale(key))
+        {
+            let resp = shape(&state, safe_mode, delta, resp).await;
+            return state.bandwidth.track(&state, consumer, &path, resp);
         }
+        return Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header("retry-after", "5")
+            .body(Body::from("backend is restarting"))
+            .unwrap();
+    }
+
+    #[cfg(feature = "transcode")]
+    let transcode = match state.transcodes.admit(&parts, &consumer) {
+        Some(Err(resp)) => return resp,
+        admitted => admitted.and_then(Result::ok),
+    };
+
+    let held = trash::hold(&state, &parts).await;
+    let image = image_cache::is_image_path(parts.uri.path());
+    let req = Request::from_parts(parts, body);
+    let resp = if image {
+        image_cache::serve(&state, req).await
+    } else {
+        coalesce::forward(&state, req).await
+    };
+    if let Some(held) = held {
+        state.trash.settle(held, resp.status().is_success()).await;
+    }
+    drop(permit);
+    if resp.extensions().get::<BackendUnreachable>().is_some() {
+        state.supervisor.record_refusal();
+    } else {
+        state.supervisor.record_ok();
+    }
+    #[cfg(feature = "cache")]
+    let resp = match offline_key {
+        Some(key) => state.offline_cache.settle(&key, resp).await,
+        None => resp,
     };
-    for &name in &[
-        "cookie",
-        "authorization",
-        "user-agent",
-        "sec-websocket-protocol",
-        "origin",
-    ] {
-        if let Some(value) = headers.get(name) {
-            request.headers_mut().insert(name, value.clone());
+    if let Some((client, series)) = progress {
+        if resp.status().is_success() {
+            state.stats.record(&client, series, crate::unix_now());
         }
     }
-    let (backend_socket, _) = match connect_async(request).await {
-        Ok(conn) => conn,
-        Err(e) => {
-            error!("backend ws connect failed: {}", e);
-            return;
+    if let Some(update) = device_update {
+        if resp.status().is_success() {
+            state.devices.record(update);
         }
+    }
+    let resp = shape(&state, safe_mode, delta, resp).await;
+    #[cfg(feature = "transcode")]
+    let resp = match transcode {
+        Some(transcode) => state.transcodes.track(transcode, resp),
+        None => resp,
     };
-    let (mut client_sender, mut client_receiver) = client_socket.split();
-    let (mut backend_sender, mut backend_receiver) = backend_socket.split();
-    loop {
-        tokio::select! {
-            msg = client_receiver.next() => match msg {
-                Some(Ok(msg)) => if let Some(t_msg) = axum_to_tungstenite(msg) {
-                    if backend_sender.send(t_msg).await.is_err() { break; }
-                },
-                _ => break,
-            },
-            msg = backend_receiver.next() => match msg {
-                Some(Ok(msg)) => if client_sender.send(tungstenite_to_axum(msg)).await.is_err() { break; },
-                _ => break,
-            }
-        }
+    state.bandwidth.track(&state, consumer, &path, resp)
+}
+
+// Filtering and delta encoding apply alike to live responses and to stale copies served
+// while the backend restarts; the offline cache stores bodies as the backend sent them.
+async fn shape(
+    state: &AppState,
+    safe_mode: bool,
+    delta: Option<DeltaRequest>,
+    resp: Response,
+) -> Response {
+    let resp = if safe_mode {
+        content_filter::filter_response(state, resp).await
+    } else {
+        resp
+    };
+    match delta {
+        Some(delta) => state.json_delta.respond(state, delta, resp).await,
+        None => resp,
     }
 }
 
-async fn proxy_request(
+fn client_id(parts: &Parts) -> String {
+    let credentials = Credentials::from_request(&parts.headers, &parts.uri);
+    parts
+        .extensions
+        .get::<Principal>()
+        .map(|principal| principal.id.clone())
+        .or(credentials.token)
+        .or(credentials.user)
+        .or_else(|| {
+            parts
+                .headers
+                .get("user-agent")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) async fn forward(state: &AppState, req: Request) -> Response {
+    if uploads::is_multipart(req.headers()) {
+        return uploads::forward(state, req).await;
+    }
+
+    let mode = state.cassette.mode();
+    if mode == CassetteMode::Off {
+        return proxy_request(
+            state.client.clone(),
+            req,
+            &state.backend_url,
+            "",
+            &state.config.routes,
+        )
+        .await;
+    }
+
+    let (req, recording) = match Cassette::buffer_request(req).await {
+        Ok(buffered) => buffered,
+        Err(resp) => return resp,
+    };
+    if mode == CassetteMode::Replay {
+        return state.cassette.replay(&recording).await;
+    }
+    let resp = proxy_request(
+        state.client.clone(),
+        req,
+        &state.backend_url,
+        "",
+        &state.config.routes,
+    )
+    .await;
+    state.cassette.record(recording, resp).await
+}
+
+const MAX_RETRY_BODY: usize = 1024 * 1024;
+const RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(250);
+
+pub(crate) async fn proxy_request(
     client: Client,
     req: Request,
     base_url: &str,
     strip_prefix: &str,
+    routes: &RoutesConfig,
 ) -> Response {
     let path_query = req
         .uri()
@@ -181,23 +763,110 @@ async fn proxy_request(
 
     let target_url = format!("{base_url}{target_path}");
     let icon_path = is_extension_icon_path(path_query);
+    let policy = if icon_path {
+        &routes.icons
+    } else if docs_cache::is_docs_path(req.uri().path()) {
+        &routes.docs
+    } else {
+        &routes.api
+    };
     let method = req.method().clone();
+    let retries = if matches!(method, Method::GET | Method::HEAD) {
+        policy.retries
+    } else {
+        0
+    };
     let headers = req.headers().clone();
-    let body = reqwest::Body::wrap_stream(req.into_body().into_data_stream());
+    let request = |url: &str, body: reqwest::Body| {
+        let mut builder = client.request(method.clone(), url).body(body);
+        for (key, value) in headers.iter() {
+            if key.as_str() != "host" {
+                builder = builder.header(key, value);
+            }
+        }
+        if policy.timeout_seconds > 0 {
+            builder = builder.timeout(std::time::Duration::from_secs(policy.timeout_seconds));
+        }
+        builder
+    };
+
+    let body = req.into_body();
+    let result = if retries == 0 {
+        request(
+            &target_url,
+            reqwest::Body::wrap_stream(body.into_data_stream()),
+        )
+        .send()
+        .await
+    } else {
+        // Retried requests need their body again, so it is read up front.
+        let Ok(bytes) = axum::body::to_bytes(body, MAX_RETRY_BODY).await else {
+            return Response::builder()
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .body(Body::empty())
+                .unwrap();
+        };
+        let mut attempt = 0;
+        loop {
+            let result = request(&target_url, reqwest::Body::from(bytes.clone()))
+                .send()
+                .await;
+            let retry = match &result {
+                Ok(resp) => matches!(
+                    resp.status(),
+                    StatusCode::BAD_GATEWAY
+                        | StatusCode::SERVICE_UNAVAILABLE
+                        | StatusCode::GATEWAY_TIMEOUT
+                ),
+                Err(err) => err.is_connect() || err.is_timeout(),
+            };
+            if !retry || attempt == retries {
+                break result;
+            }
+            attempt += 1;
+            debug!("retrying {} (attempt {})", target_url, attempt + 1);
+            tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+        }
+    };
 
-    let mut builder = client.request(method, &target_url).body(body);
-    for (key, value) in headers.iter() {
-        if key.as_str() != "host" {
-            builder = builder.header(key, value);
+    let result = match result {
+        Ok(resp)
+            if routes.redirects == RedirectPolicy::Follow
+                && matches!(method, Method::GET | Method::HEAD) =>
+        {
+            follow_redirects(resp, &request, base_url).await
         }
-    }
+        other => other,
+    };
 
-    match builder.send().await {
+    match result {
         Ok(resp) => {
+            let icon_cache =
+                icon_path && resp.status().is_success() && routes.icon_cache_seconds > 0;
             let mut response_builder = Response::builder().status(resp.status());
             for (key, value) in resp.headers() {
+                if icon_cache && matches!(key.as_str(), "cache-control" | "expires" | "pragma") {
+                    continue;
+                }
+                if key == header::LOCATION && resp.status().is_redirection() {
+                    let internal = value
+                        .to_str()
+                        .ok()
+                        .and_then(|location| backend_path(resp.url(), location, base_url));
+                    if let Some(path) = internal {
+                        response_builder =
+                            response_builder.header(key, format!("{strip_prefix}{path}"));
+                        continue;
+                    }
+                }
                 response_builder = response_builder.header(key, value);
             }
+            if icon_cache {
+                response_builder = response_builder.header(
+                    "cache-control",
+                    format!("public, max-age={}", routes.icon_cache_seconds),
+                );
+            }
             if icon_path && resp.status() == StatusCode::NOT_FOUND {
                 response_builder = response_builder
                     .header("cache-control", "no-store, no-cache, must-revalidate")
@@ -211,58 +880,87 @@ async fn proxy_request(
                     .body(Body::empty())
                     .unwrap())
         }
-        Err(_err) => Response::builder()
-            .status(StatusCode::BAD_GATEWAY)
-            .body(Body::empty())
-            .unwrap(),
+        Err(err) => {
+            let status = if err.is_timeout() {
+                StatusCode::GATEWAY_TIMEOUT
+            } else {
+                StatusCode::BAD_GATEWAY
+            };
+            let mut resp = Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap();
+            if err.is_connect() {
+                resp.extensions_mut().insert(BackendUnreachable);
+            }
+            resp
+        }
     }
 }
 
-fn is_extension_icon_path(path_query: &str) -> bool {
-    path_query.starts_with("/api/v1/extension/icon/")
-        || path_query.starts_with("/api/v1/anime/extension/icon/")
-        || path_query.starts_with("/extension/icon/")
-}
+const MAX_REDIRECTS: usize = 5;
 
-fn axum_to_tungstenite(msg: Message) -> Option<TungsteniteMessage> {
-    match msg {
-        Message::Text(t) => Some(TungsteniteMessage::Text(t.as_str().into())),
-        Message::Binary(b) => Some(TungsteniteMessage::Binary(b.to_vec())),
-        Message::Ping(p) => Some(TungsteniteMessage::Ping(p.to_vec())),
-        Message::Pong(p) => Some(TungsteniteMessage::Pong(p.to_vec())),
-        Message::Close(c) => {
-            let frame = c.map(|cf| tokio_tungstenite::tungstenite::protocol::CloseFrame {
-                code: CloseCode::from(cf.code),
-                reason: cf.reason.to_string().into(),
-            });
-            Some(TungsteniteMessage::Close(frame))
+// Chases redirects that stay on the backend so the client gets where they lead. One
+// pointing anywhere else, or one too many, is handed back as it is.
+async fn follow_redirects(
+    mut resp: reqwest::Response,
+    request: impl Fn(&str, reqwest::Body) -> reqwest::RequestBuilder,
+    base_url: &str,
+) -> reqwest::Result<reqwest::Response> {
+    for _ in 0..MAX_REDIRECTS {
+        if !matches!(
+            resp.status(),
+            StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT
+        ) {
+            break;
         }
+        let Some(path) = resp
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|location| backend_path(resp.url(), location, base_url))
+        else {
+            break;
+        };
+        debug!("following backend redirect to {}", path);
+        let url = format!("{base_url}{path}");
+        resp = request(&url, reqwest::Body::from(Vec::new()))
+            .send()
+            .await?;
     }
+    Ok(resp)
 }
 
-fn tungstenite_to_axum(msg: TungsteniteMessage) -> Message {
-    match msg {
-        TungsteniteMessage::Text(t) => Message::Text(t.as_str().into()),
-        TungsteniteMessage::Binary(b) => Message::Binary(b.into()),
-        TungsteniteMessage::Ping(p) => Message::Ping(p.into()),
-        TungsteniteMessage::Pong(p) => Message::Pong(p.into()),
-        TungsteniteMessage::Close(c) => {
-            let frame = c.map(|cf| axum::extract::ws::CloseFrame {
-                code: u16::from(cf.code),
-                reason: cf.reason.to_string().into(),
-            });
-            Message::Close(frame)
-        }
-        TungsteniteMessage::Frame(_) => Message::Binary(Bytes::new()),
+// The path and query of a redirect target on the backend itself, whether it names the
+// backend's address, another loopback spelling of it, or is relative.
+fn backend_path(from: &reqwest::Url, location: &str, base_url: &str) -> Option<String> {
+    let target = from.join(location).ok()?;
+    let base = reqwest::Url::parse(base_url).ok()?;
+    let host = target.host_str()?;
+    let loopback = host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback());
+    let internal = target.scheme() == base.scheme()
+        && target.port_or_known_default() == base.port_or_known_default()
+        && (base.host_str() == Some(host) || loopback);
+    if !internal {
+        return None;
     }
+    Some(match target.query() {
+        Some(query) => format!("{}?{query}", target.path()),
+        None => target.path().to_string(),
+    })
 }
 
-fn backend_ws_url(base: &str) -> String {
-    if let Some(stripped) = base.strip_prefix("https://") {
-        format!("wss://{}", stripped)
-    } else if let Some(stripped) = base.strip_prefix("http://") {
-        format!("ws://{}", stripped)
-    } else {
-        format!("ws://{}", base)
-    }
+fn is_extension_icon_path(path_query: &str) -> bool {
+    path_query.starts_with("/api/v1/extension/icon/")
+        || path_query.starts_with("/api/v1/anime/extension/icon/")
+        || path_query.starts_with("/extension/icon/")
 }
@@ -0,0 +1,390 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::app::AppState;
+use crate::jobs::{JobContext, JobResult};
+use crate::trash::Trash;
+
+const MAX_ARCHIVE_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const MAX_REPORTED: usize = 500;
+const ARCHIVE_EXTENSIONS: [&str; 4] = ["cbz", "cbr", "zip", "rar"];
+const IMAGE_EXTENSIONS: [&str; 8] = ["jpg", "jpeg", "png", "webp", "gif", "avif", "jxl", "bmp"];
+const COMIC_INFO: &str = "comicinfo.xml";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Rar,
+}
+
+struct Entry {
+    name: String,
+    data: Vec<u8>,
+}
+
+struct Contents {
+    format: Format,
+    entries: Vec<Entry>,
+    lost: usize,
+}
+
+pub(crate) async fn repair_job(state: AppState, ctx: JobContext) -> JobResult {
+    let roots = match ctx.payload.get("paths").and_then(Value::as_array) {
+        Some(paths) => paths
+            .iter()
+            .filter_map(Value::as_str)
+            .map(PathBuf::from)
+            .collect::<Vec<_>>(),
+        None => vec![PathBuf::from(&state.config.local_manga_path)],
+    };
+    let dry_run = ctx
+        .payload
+        .get("dry_run")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let trash = state.trash.clone();
+    tokio::task::spawn_blocking(move || repair_all(&roots, dry_run, &ctx, &trash))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+fn repair_all(roots: &[PathBuf], dry_run: bool, ctx: &JobContext, trash: &Trash) -> JobResult {
+    let archives = find_archives(roots);
+    let total = archives.len();
+    let mut clean = 0usize;
+    let mut repaired = Vec::new();
+    let mut failed = Vec::new();
+    for (done, path) in archives.iter().enumerate() {
+        if ctx.is_cancelled() {
+            return Err("cancelled".to_string());
+        }
+        ctx.progress(
+            done as f64 / total.max(1) as f64,
+            &format!("checked {done} of {total} archives"),
+        );
+        match repair(path, dry_run, trash) {
+            Ok(None) => clean += 1,
+            Ok(Some(report)) => repaired.push(report),
+            Err(err) => {
+                warn!("archive repair skipped {}: {}", path.display(), err);
+                failed.push(json!({ "path": path.display().to_string(), "error": err }));
+            }
+        }
+    }
+
+    if !dry_run && !repaired.is_empty() {
+        info!("normalized {} archives", repaired.len());
+    }
+    let (repaired_count, failed_count) = (repaired.len(), failed.len());
+    repaired.truncate(MAX_REPORTED);
+    failed.truncate(MAX_REPORTED);
+    Ok(json!({
+        "dry_run": dry_run,
+        "archives": total,
+        "clean": clean,
+        "repaired_count": repaired_count,
+        "failed_count": failed_count,
+        "repaired": repaired,
+        "failed": failed,
+    }))
+}
+
+fn find_archives(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut archives = Vec::new();
+    let mut pending = roots.to_vec();
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file()
+                && extension(&path.to_string_lossy())
+                    .is_some_and(|ext| ARCHIVE_EXTENSIONS.contains(&ext.as_str()))
+            {
+                archives.push(path);
+            }
+        }
+    }
+    archives.sort();
+    archives
+}
+
+fn repair(path: &Path, dry_run: bool, trash: &Trash) -> Result<Option<Value>, String> {
+    let size = std::fs::metadata(path)
+        .map_err(|err| err.to_string())?
+        .len();
+    if size > MAX_ARCHIVE_BYTES {
+        return Err(format!("archive is larger than {MAX_ARCHIVE_BYTES} bytes"));
+    }
+    let contents = read_archive(path)?;
+
+    let mut pages = Vec::new();
+    let mut comic_info = None;
+    let mut extra = 0usize;
+    for entry in contents.entries {
+        let base = entry.name.rsplit(['/', '\\']).next().unwrap_or(&entry.name);
+        if base.eq_ignore_ascii_case(COMIC_INFO) {
+            comic_info = Some(entry);
+        } else if extension(base).is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str())) {
+            pages.push(entry);
+        } else {
+            extra += 1;
+        }
+    }
+    if pages.is_empty() {
+        return Err("archive has no readable pages".to_string());
+    }
+
+    let mut issues = Vec::new();
+    let named_extension = extension(&path.to_string_lossy()).unwrap_or_default();
+    if contents.format == Format::Rar {
+        issues.push("rar");
+    } else if named_extension != "cbz" {
+        issues.push("misnamed");
+    }
+    if contents.lost > 0 {
+        issues.push("corrupt");
+    }
+    if pages
+        .iter()
+        .any(|page| page.name.contains('/') || page.name.contains('\\'))
+    {
+        issues.push("nested_folders");
+    }
+    let formats = pages
+        .iter()
+        .filter_map(|page| extension(&page.name))
+        .map(|ext| {
+            if ext == "jpeg" {
+                "jpg".to_string()
+            } else {
+                ext
+            }
+        })
+        .collect::<BTreeSet<_>>();
+    if formats.len() > 1 {
+        issues.push("mixed_formats");
+    }
+    if extra > 0 {
+        issues.push("extra_files");
+    }
+    // Readers that sort names byte-wise would show "10.jpg" before "2.jpg".
+    let mut lexical = pages
+        .iter()
+        .map(|page| page.name.clone())
+        .collect::<Vec<_>>();
+    lexical.sort();
+    pages.sort_by(|a, b| natural_cmp(&a.name, &b.name));
+    if pages.iter().map(|page| &page.name).ne(lexical.iter()) {
+        issues.push("unsorted");
+    }
+    if issues.is_empty() {
+        return Ok(None);
+    }
+
+    let output = path.with_extension("cbz");
+    let mut report = json!({
+        "path": path.display().to_string(),
+        "output": output.display().to_string(),
+        "issues": issues,
+        "pages": pages.len(),
+        "formats": formats,
+        "lost_entries": contents.lost,
+    });
+    if dry_run {
+        return Ok(Some(report));
+    }
+    if output != path && output.exists() {
+        return Err(format!("{} already exists", output.display()));
+    }
+
+    let partial = output.with_file_name(format!(
+        ".{}.repair",
+        output.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    if let Err(err) = write_cbz(&partial, &pages, comic_info.as_ref()) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(err);
+    }
+    // Pages that failed to read are gone from the rewrite, so keep the original.
+    if contents.lost > 0 {
+        let backup = path.with_file_name(format!(
+            "{}.bak",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        std::fs::rename(path, &backup).map_err(|err| err.to_string())?;
+        report["backup"] = json!(backup.display().to_string());
+    } else if output != path {
+        trash
+            .discard(path, "archive replaced by repair")
+            .map_err(|err| err.to_string())?;
+    }
+    std::fs::rename(&partial, &output).map_err(|err| err.to_string())?;
+    Ok(Some(report))
+}
+
+fn read_archive(path: &Path) -> Result<Contents, String> {
+    let mut magic = [0u8; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .map_err(|err| err.to_string())?;
+    if magic.starts_with(b"PK") {
+        read_zip(path)
+    } else if &magic == b"Rar!" {
+        read_rar(path)
+    } else {
+        Err("unrecognized archive format".to_string())
+    }
+}
+
+fn read_zip(path: &Path) -> Result<Contents, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|err| err.to_string())?;
+    let mut entries = Vec::new();
+    let mut lost = 0usize;
+    for index in 0..archive.len() {
+        let Ok(mut entry) = archive.by_index(index) else {
+            lost += 1;
+            continue;
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        // The CRC is only checked once the entry has been read to the end.
+        if entry.read_to_end(&mut data).is_err() {
+            lost += 1;
+            continue;
+        }
+        entries.push(Entry { name, data });
+    }
+    Ok(Contents {
+        format: Format::Zip,
+        entries,
+        lost,
+    })
+}
+
+fn read_rar(path: &Path) -> Result<Contents, String> {
+    let mut archive = unrar::Archive::new(path)
+        .open_for_processing()
+        .map_err(|err| err.to_string())?;
+    let mut entries = Vec::new();
+    let mut lost = 0usize;
+    loop {
+        let header = match archive.read_header() {
+            Ok(Some(header)) => header,
+            Ok(None) => break,
+            Err(_) if !entries.is_empty() => {
+                lost += 1;
+                break;
+            }
+            Err(err) => return Err(err.to_string()),
+        };
+        if !header.entry().is_file() {
+            archive = header.skip().map_err(|err| err.to_string())?;
+            continue;
+        }
+        let name = header.entry().filename.to_string_lossy().into_owned();
+        match header.read() {
+            Ok((data, next)) => {
+                entries.push(Entry { name, data });
+                archive = next;
+            }
+            Err(_) => {
+                lost += 1;
+                break;
+            }
+        }
+    }
+    Ok(Contents {
+        format: Format::Rar,
+        entries,
+        lost,
+    })
+}
+
+fn write_cbz(path: &Path, pages: &[Entry], comic_info: Option<&Entry>) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| err.to_string())?;
+    let mut writer = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let width = pages.len().to_string().len().max(3);
+    for (index, page) in pages.iter().enumerate() {
+        let ext = extension(&page.name).unwrap_or_default();
+        writer
+            .start_file(format!("{:0width$}.{ext}", index + 1), options)
+            .and_then(|_| writer.write_all(&page.data).map_err(Into::into))
+            .map_err(|err| err.to_string())?;
+    }
+    if let Some(comic_info) = comic_info {
+        writer
+            .start_file(
+                "ComicInfo.xml",
+                options.compression_method(CompressionMethod::Deflated),
+            )
+            .and_then(|_| writer.write_all(&comic_info.data).map_err(Into::into))
+            .map_err(|err| err.to_string())?;
+    }
+    let file = writer.finish().map_err(|err| err.to_string())?;
+    file.sync_all().map_err(|err| err.to_string())
+}
+
+fn extension(name: &str) -> Option<String> {
+    let (_, ext) = name.rsplit_once('.')?;
+    (!ext.contains(['/', '\\'])).then(|| ext.to_ascii_lowercase())
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_digits(&mut a);
+                let y = take_digits(&mut b);
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let order = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let order = x.to_lowercase().cmp(y.to_lowercase());
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
@@ -0,0 +1,860 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{header, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, patch},
+    Json, Router,
+};
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, TimeZone};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::app::AppState;
+use crate::config::AuthConfig;
+use crate::credentials::Credentials;
+use crate::error_pages;
+use crate::keys::random_id;
+use crate::principal::{Principal, Scope};
+use crate::store::{load_json, save_json};
+use crate::unix_now;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Serialize, Deserialize)]
+struct TokenRecord {
+    id: String,
+    name: String,
+    hash: String,
+    scopes: BTreeSet<Scope>,
+    created_at: u64,
+    expires_at: Option<u64>,
+    last_used_at: Option<u64>,
+    // Conversion profile for pages fetched with this token, e.g. `eink` for a Kobo.
+    #[serde(default)]
+    image_profile: Option<String>,
+    #[serde(default)]
+    quota: TokenQuota,
+    #[serde(default)]
+    usage: TokenUsage,
+}
+
+// Fair-use limits for a shared token; unset fields are unlimited. Bytes are what the
+// proxy sent back, as counted by bandwidth accounting.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+struct TokenQuota {
+    #[serde(default)]
+    daily_requests: Option<u64>,
+    #[serde(default)]
+    daily_bytes: Option<u64>,
+    #[serde(default)]
+    monthly_requests: Option<u64>,
+    #[serde(default)]
+    monthly_bytes: Option<u64>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Usage {
+    period: String,
+    requests: u64,
+    bytes: u64,
+}
+
+impl Usage {
+    // Counters start over once the local day or month has moved on.
+    fn roll(&mut self, period: &str) {
+        if self.period != period {
+            *self = Usage {
+                period: period.to_string(),
+                ..Usage::default()
+            };
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct TokenUsage {
+    day: Usage,
+    month: Usage,
+}
+
+impl TokenUsage {
+    fn roll(&mut self, periods: &Periods) {
+        self.day.roll(&periods.day);
+        self.month.roll(&periods.month);
+    }
+}
+
+struct Periods {
+    day: String,
+    month: String,
+    day_reset: u64,
+    month_reset: u64,
+}
+
+impl Periods {
+    fn now() -> Self {
+        let now = Local::now();
+        let today = now.date_naive();
+        let next_month = if today.month() == 12 {
+            NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)
+        } else {
+            NaiveDate::from_ymd_opt(today.year(), today.month() + 1, 1)
+        };
+        let until = |date: Option<NaiveDate>| {
+            date.and_then(|date| {
+                Local
+                    .from_local_datetime(&date.and_time(NaiveTime::MIN))
+                    .earliest()
+            })
+            .map_or(1, |start| (start - now).num_seconds().max(1) as u64)
+        };
+        Self {
+            day: today.format("%Y-%m-%d").to_string(),
+            month: today.format("%Y-%m").to_string(),
+            day_reset: until(today.succ_opt()),
+            month_reset: until(next_month),
+        }
+    }
+}
+
+impl TokenRecord {
+    fn view(&self) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "scopes": self.scopes,
+            "created_at": self.created_at,
+            "expires_at": self.expires_at,
+            "last_used_at": self.last_used_at,
+            "image_profile": self.image_profile,
+            "quota": self.quota,
+            "expired": self.is_expired(unix_now()),
+        })
+    }
+
+    fn usage_view(&self, periods: &Periods) -> serde_json::Value {
+        let mut usage = self.usage.clone();
+        usage.roll(periods);
+        json!({
+            "id": self.id,
+            "day": {
+                "date": usage.day.period,
+                "requests": usage.day.requests,
+                "bytes": usage.day.bytes,
+                "request_limit": self.quota.daily_requests,
+                "byte_limit": self.quota.daily_bytes,
+                "resets_in_seconds": periods.day_reset,
+            },
+            "month": {
+                "month": usage.month.period,
+                "requests": usage.month.requests,
+                "bytes": usage.month.bytes,
+                "request_limit": self.quota.monthly_requests,
+                "byte_limit": self.quota.monthly_bytes,
+                "resets_in_seconds": periods.month_reset,
+            },
+        })
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires| expires <= now)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct TokenData {
+    tokens: BTreeMap<String, TokenRecord>,
+}
+
+struct Inner {
+    data: TokenData,
+    dirty: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Requirement {
+    Public,
+    Read,
+    Write,
+    Admin,
+    Downloads { safe: bool },
+}
+
+impl Principal {
+    fn permits(&self, requirement: Requirement) -> bool {
+        if self.scopes.contains(&Scope::Admin) {
+            return true;
+        }
+        match requirement {
+            Requirement::Public => true,
+            Requirement::Read => self.scopes.contains(&Scope::Read),
+            Requirement::Write => self.scopes.contains(&Scope::Write),
+            Requirement::Admin => false,
+            Requirement::Downloads { safe } => {
+                self.scopes.contains(&Scope::Downloads)
+                    || self.scopes.contains(&Scope::Write)
+                    || (safe && self.scopes.contains(&Scope::Read))
+            }
+        }
+    }
+}
+
+pub(crate) struct Auth {
+    config: AuthConfig,
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+impl Auth {
+    pub(crate) fn new(config: AuthConfig, data_path: &str) -> Self {
+        let dir = PathBuf::from(data_path);
+        let path = dir.join("tokens.json");
+        let data: TokenData = if config.tokens_enabled {
+            load_json(&path)
+        } else {
+            TokenData::default()
+        };
+        let auth = Self {
+            config,
+            path,
+            inner: Mutex::new(Inner { data, dirty: false }),
+        };
+        auth.bootstrap(&dir);
+        auth
+    }
+
+    fn bootstrap(&self, dir: &std::path::Path) {
+        if !self.config.tokens_enabled || self.config.token.is_some() {
+            return;
+        }
+        if !self.lock().data.tokens.is_empty() {
+            return;
+        }
+
+        let (token, _) = self.create(
+            "bootstrap".to_string(),
+            BTreeSet::from([Scope::Admin]),
+            None,
+            None,
+            TokenQuota::default(),
+        );
+        self.flush();
+        let token_path = dir.join("admin-token.txt");
+        match std::fs::write(&token_path, format!("{token}\n")) {
+            Ok(()) => {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let _ = std::fs::set_permissions(
+                        &token_path,
+                        std::fs::Permissions::from_mode(0o600),
+                    );
+                }
+                warn!(
+                    "token auth enabled with no tokens; bootstrap admin token written to {}",
+                    token_path.display()
+                );
+            }
+            Err(err) => warn!(
+                "failed to write bootstrap admin token to {}: {}",
+                token_path.display(),
+                err
+            ),
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.config.is_enabled()
+    }
+
+    // Every principal id a request can authenticate as, for `/admin/users`.
+    pub(crate) fn users(&self) -> Vec<serde_json::Value> {
+        let mut users = Vec::new();
+        if let Some(user) = self.config.user.as_deref() {
+            users.push(json!({ "id": format!("basic:{user}"), "kind": "user", "name": user }));
+        }
+        if self.config.token.is_some() {
+            users.push(json!({ "id": "static-token", "kind": "token", "name": "static token" }));
+        }
+        users.extend(self.lock().data.tokens.values().map(|record| {
+            json!({
+                "id": record.id,
+                "kind": "token",
+                "name": record.name,
+                "scopes": record.scopes,
+            })
+        }));
+        users
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn authenticate(&self, credentials: &Credentials) -> Option<Principal> {
+        if let (Some(user), Some(password)) =
+            (credentials.user.as_deref(), credentials.password.as_deref())
+        {
+            let (Some(expected_user), Some(expected_password)) =
+                (self.config.user.as_deref(), self.config.password.as_deref())
+            else {
+                return None;
+            };
+            let matches = constant_time_eq(user.as_bytes(), expected_user.as_bytes())
+                & constant_time_eq(password.as_bytes(), expected_password.as_bytes());
+            return matches.then(|| Principal {
+                id: format!("basic:{user}"),
+                scopes: BTreeSet::from([Scope::Admin]),
+                image_profile: None,
+            });
+        }
+
+        let token = credentials.token.as_deref()?;
+        if self
+            .config
+            .token
+            .as_deref()
+            .is_some_and(|expected| constant_time_eq(expected.as_bytes(), token.as_bytes()))
+        {
+            return Some(Principal {
+                id: "static-token".to_string(),
+                scopes: BTreeSet::from([Scope::Admin]),
+                image_profile: None,
+            });
+        }
+        if !self.config.tokens_enabled {
+            return None;
+        }
+
+        let hash = hash_token(token);
+        let now = unix_now();
+        let mut inner = self.lock();
+        let record = inner
+            .data
+            .tokens
+            .values_mut()
+            .find(|record| record.hash == hash)?;
+        if record.is_expired(now) {
+            return None;
+        }
+        record.last_used_at = Some(now);
+        let principal = Principal {
+            id: record.id.clone(),
+            scopes: record.scopes.clone(),
+            image_profile: record.image_profile.clone(),
+        };
+        inner.dirty = true;
+        Some(principal)
+    }
+
+    // Counts a proxied request against the token's usage. `Err` names the exhausted
+    // quota and the seconds until it resets; other principals are never limited.
+    pub(crate) fn charge_request(&self, id: &str) -> Result<(), (&'static str, u64)> {
+        let periods = Periods::now();
+        let mut inner = self.lock();
+        let Some(record) = inner.data.tokens.get_mut(id) else {
+            return Ok(());
+        };
+        record.usage.roll(&periods);
+        let (quota, usage) = (&record.quota, &record.usage);
+        let over = |limit: Option<u64>, used: u64| limit.is_some_and(|limit| used >= limit);
+        if over(quota.monthly_requests, usage.month.requests) {
+            return Err(("monthly request quota exceeded", periods.month_reset));
+        }
+        if over(quota.monthly_bytes, usage.month.bytes) {
+            return Err(("monthly bandwidth quota exceeded", periods.month_reset));
+        }
+        if over(quota.daily_requests, usage.day.requests) {
+            return Err(("daily request quota exceeded", periods.day_reset));
+        }
+        if over(quota.daily_bytes, usage.day.bytes) {
+            return Err(("daily bandwidth quota exceeded", periods.day_reset));
+        }
+        record.usage.day.requests += 1;
+        record.usage.month.requests += 1;
+        inner.dirty = true;
+        Ok(())
+    }
+
+    pub(crate) fn charge_bytes(&self, id: &str, bytes: u64) {
+        let periods = Periods::now();
+        let mut inner = self.lock();
+        let Some(record) = inner.data.tokens.get_mut(id) else {
+            return;
+        };
+        record.usage.roll(&periods);
+        record.usage.day.bytes += bytes;
+        record.usage.month.bytes += bytes;
+        inner.dirty = true;
+    }
+
+    fn create(
+        &self,
+        name: String,
+        scopes: BTreeSet<Scope>,
+        expires_at: Option<u64>,
+        image_profile: Option<String>,
+        quota: TokenQuota,
+    ) -> (String, TokenRecord) {
+        let token = format!("mt_{}{}", random_id(), random_id());
+        let record = TokenRecord {
+            id: random_id(),
+            name,
+            hash: hash_token(&token),
+            scopes,
+            created_at: unix_now(),
+            expires_at,
+            last_used_at: None,
+            image_profile,
+            quota,
+            usage: TokenUsage::default(),
+        };
+        let mut inner = self.lock();
+        inner.data.tokens.insert(record.id.clone(), record.clone());
+        inner.dirty = true;
+        (token, record)
+    }
+
+    // A PATCH is checked in full before any of it lands, so one that is turned away
+    // leaves the record as it was. `Ok(None)` when there is no such token.
+    fn update(
+        &self,
+        id: &str,
+        update: UpdateToken,
+        known_profile: impl Fn(&str) -> bool,
+    ) -> Result<Option<serde_json::Value>, String> {
+        update.validate(known_profile)?;
+        let mut inner = self.lock();
+        let Some(record) = inner.data.tokens.get_mut(id) else {
+            return Ok(None);
+        };
+        update.apply(record);
+        let view = record.view();
+        inner.dirty = true;
+        Ok(Some(view))
+    }
+
+    fn persist(&self, inner: &mut Inner) {
+        match save_json(&self.path, &inner.data) {
+            Ok(()) => inner.dirty = false,
+            Err(err) => warn!(
+                "failed to persist tokens to {}: {}",
+                self.path.display(),
+                err
+            ),
+        }
+    }
+
+    fn flush(&self) {
+        let mut inner = self.lock();
+        if inner.dirty {
+            self.persist(&mut inner);
+        }
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn requirement(config: &AuthConfig, method: &Method, path: &str) -> Requirement {
+    let safe = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    if (path == "/health" || path == "/readyz") && config.exempt_health {
+        return Requirement::Public;
+    }
+    if path == "/favicon.ico"
+        || path == "/robots.txt"
+        || path == "/capabilities"
+        || path.starts_with("/.well-known/")
+        || path.starts_with("/s/")
+        || path.starts_with("/m/")
+        || path.starts_with("/relay/")
+        || path == "/ssdp/device.xml"
+    {
+        return Requirement::Public;
+    }
+    if path == "/admin"
+        || path.starts_with("/admin/")
+        || path == "/metrics"
+        || path.starts_with("/api/proxy/backup")
+        || path == "/api/proxy/config"
+    {
+        return Requirement::Admin;
+    }
+    if path.starts_with("/api/v1/settings") && !safe {
+        return Requirement::Admin;
+    }
+    if path.starts_with("/api/v1/download") {
+        return Requirement::Downloads { safe };
+    }
+    if safe {
+        Requirement::Read
+    } else {
+        Requirement::Write
+    }
+}
+
+pub(crate) fn check(state: &AppState, req: &mut Request) -> Option<Response> {
+    if !state.auth.enabled() {
+        return None;
+    }
+
+    let requirement = requirement(&state.auth.config, req.method(), req.uri().path());
+    if requirement == Requirement::Public {
+        return None;
+    }
+    if let Some(principal) = req.extensions().get::<Principal>() {
+        if principal.permits(requirement) {
+            return outside_schedule(state, req, principal, requirement);
+        }
+        return Some((StatusCode::FORBIDDEN, "token lacks the required scope").into_response());
+    }
+
+    let credentials = Credentials::from_request(req.headers(), req.uri());
+    let Some(principal) = state.auth.authenticate(&credentials) else {
+        let mut builder = Response::builder().status(StatusCode::UNAUTHORIZED);
+        if state.auth.config.basic_enabled() {
+            builder = builder.header(
+                header::WWW_AUTHENTICATE,
+                "Basic realm=\"Manatan\", charset=\"UTF-8\"",
+            );
+        }
+        if state.auth.config.token.is_some() || state.auth.config.tokens_enabled {
+            builder = builder.header(header::WWW_AUTHENTICATE, "Bearer realm=\"Manatan\"");
+        }
+        return Some(builder.body(Body::empty()).unwrap());
+    };
+    if !principal.permits(requirement) {
+        return Some((StatusCode::FORBIDDEN, "token lacks the required scope").into_response());
+    }
+    if let Some(resp) = outside_schedule(state, req, &principal, requirement) {
+        return Some(resp);
+    }
+
+    req.extensions_mut().insert(principal);
+    None
+}
+
+// Admin routes stay reachable, so a schedule can't lock out whoever manages it.
+fn outside_schedule(
+    state: &AppState,
+    req: &Request,
+    principal: &Principal,
+    requirement: Requirement,
+) -> Option<Response> {
+    if requirement == Requirement::Admin {
+        return None;
+    }
+    let until = state.access_schedules.blocked_until(&principal.id)?;
+    let detail = format!("Access is paused right now. Come back after {until}.");
+    let page = error_pages::prefers_html(req.headers())
+        .then(|| {
+            state
+                .error_pages
+                .page(&state.config.instance_name, StatusCode::FORBIDDEN, &detail)
+        })
+        .flatten();
+    let resp = match page {
+        Some(page) => (
+            StatusCode::FORBIDDEN,
+            [
+                (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                (header::CACHE_CONTROL, "no-store"),
+            ],
+            page,
+        )
+            .into_response(),
+        None => (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "error": "outside_access_schedule", "until": until })),
+        )
+            .into_response(),
+    };
+    Some(resp)
+}
+
+// Writes pending token changes on a blocking thread, keeping the file IO (and the lock
+// held across it) off the async workers.
+async fn save(auth: &Arc<Auth>) {
+    let auth = auth.clone();
+    let _ = tokio::task::spawn_blocking(move || auth.flush()).await;
+}
+
+pub(crate) fn spawn_flusher(auth: &Arc<Auth>) {
+    if !auth.enabled() {
+        return;
+    }
+    let auth = Arc::downgrade(auth);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let Some(auth) = auth.upgrade() else {
+                break;
+            };
+            let _ = tokio::task::spawn_blocking(move || auth.flush()).await;
+        }
+    });
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/tokens", get(list_tokens).post(create_token))
+        .route(
+            "/admin/tokens/{id}",
+            patch(update_token).delete(delete_token),
+        )
+        .route("/admin/tokens/{id}/usage", get(token_usage))
+}
+
+#[derive(Deserialize)]
+struct CreateToken {
+    name: String,
+    scopes: BTreeSet<Scope>,
+    expires_in_seconds: Option<u64>,
+    image_profile: Option<String>,
+    #[serde(default)]
+    quota: TokenQuota,
+}
+
+#[derive(Deserialize)]
+struct UpdateToken {
+    name: Option<String>,
+    scopes: Option<BTreeSet<Scope>>,
+    #[serde(default, deserialize_with = "double_option")]
+    expires_at: Option<Option<u64>>,
+    #[serde(default, deserialize_with = "double_option")]
+    image_profile: Option<Option<String>>,
+    quota: Option<TokenQuota>,
+}
+
+impl UpdateToken {
+    fn validate(&self, known_profile: impl Fn(&str) -> bool) -> Result<(), String> {
+        if self.scopes.as_ref().is_some_and(BTreeSet::is_empty) {
+            return Err("at least one scope is required".to_string());
+        }
+        if let Some(Some(name)) = &self.image_profile {
+            if !known_profile(name) {
+                return Err(format!("unknown image profile: {name}"));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(self, record: &mut TokenRecord) {
+        if let Some(name) = self.name {
+            record.name = name;
+        }
+        if let Some(scopes) = self.scopes {
+            record.scopes = scopes;
+        }
+        if let Some(expires_at) = self.expires_at {
+            record.expires_at = expires_at;
+        }
+        if let Some(image_profile) = self.image_profile {
+            record.image_profile = image_profile;
+        }
+        if let Some(quota) = self.quota {
+            record.quota = quota;
+        }
+    }
+}
+
+fn double_option<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<T>>, D::Error> {
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+async fn list_tokens(State(state): State<AppState>) -> Json<Vec<serde_json::Value>> {
+    let inner = state.auth.lock();
+    Json(inner.data.tokens.values().map(TokenRecord::view).collect())
+}
+
+async fn create_token(State(state): State<AppState>, Json(body): Json<CreateToken>) -> Response {
+    if body.scopes.is_empty() {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "at least one scope is required",
+        )
+            .into_response();
+    }
+    let expires_at = body
+        .expires_in_seconds
+        .map(|seconds| unix_now().saturating_add(seconds));
+    if let Some(response) = unknown_profile(&state, body.image_profile.as_deref()) {
+        return response;
+    }
+    let (token, record) = state.auth.create(
+        body.name,
+        body.scopes,
+        expires_at,
+        body.image_profile,
+        body.quota,
+    );
+    save(&state.auth).await;
+    (
+        StatusCode::CREATED,
+        Json(json!({ "token": token, "record": record.view() })),
+    )
+        .into_response()
+}
+
+async fn update_token(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(body): Json<UpdateToken>,
+) -> Response {
+    let profiles = state.image_transform.profiles();
+    match state
+        .auth
+        .update(&id, body, |name| profiles.contains_key(name))
+    {
+        Ok(Some(view)) => {
+            save(&state.auth).await;
+            Json(view).into_response()
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(reason) => (StatusCode::UNPROCESSABLE_ENTITY, reason).into_response(),
+    }
+}
+
+async fn token_usage(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let inner = state.auth.lock();
+    match inner.data.tokens.get(&id) {
+        Some(record) => Json(record.usage_view(&Periods::now())).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+fn unknown_profile(state: &AppState, name: Option<&str>) -> Option<Response> {
+    let name = name?;
+    if state.image_transform.profiles().contains_key(name) {
+        return None;
+    }
+    Some(
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("unknown image profile: {name}"),
+        )
+            .into_response(),
+    )
+}
+
+async fn delete_token(State(state): State<AppState>, Path(id): Path<String>) -> StatusCode {
+    {
+        let mut inner = state.auth.lock();
+        if inner.data.tokens.remove(&id).is_none() {
+            return StatusCode::NOT_FOUND;
+        }
+        inner.dirty = true;
+    }
+    save(&state.auth).await;
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> Auth {
+        let config = AuthConfig {
+            tokens_enabled: false,
+            token: None,
+            user: None,
+            password: None,
+            exempt_health: false,
+        };
+        let dir = std::env::temp_dir().join(format!("manatan-auth-{}", random_id()));
+        Auth::new(config, &dir.display().to_string())
+    }
+
+    fn patch(body: serde_json::Value) -> UpdateToken {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn rejected_patch_changes_nothing() {
+        let auth = auth();
+        let scopes = BTreeSet::from([Scope::Read]);
+        let (_, record) = auth.create(
+            "reader".to_string(),
+            scopes.clone(),
+            None,
+            None,
+            TokenQuota::default(),
+        );
+        let known = |name: &str| name == "eink";
+        for body in [
+            json!({ "name": "renamed", "expires_at": 10, "scopes": [] }),
+            json!({ "name": "renamed", "scopes": ["write"], "image_profile": "missing" }),
+        ] {
+            assert!(auth.update(&record.id, patch(body), known).is_err());
+            let inner = auth.lock();
+            let stored = &inner.data.tokens[&record.id];
+            assert_eq!(stored.name, "reader");
+            assert_eq!(stored.scopes, scopes);
+            assert_eq!(stored.expires_at, None);
+            assert_eq!(stored.image_profile, None);
+        }
+    }
+
+    #[test]
+    fn accepted_patch_applies_every_field() {
+        let auth = auth();
+        let (_, record) = auth.create(
+            "reader".to_string(),
+            BTreeSet::from([Scope::Read]),
+            Some(10),
+            None,
+            TokenQuota::default(),
+        );
+        let body = json!({
+            "name": "kobo",
+            "scopes": ["read", "downloads"],
+            "expires_at": null,
+            "image_profile": "eink",
+        });
+        let view = auth
+            .update(&record.id, patch(body), |name| name == "eink")
+            .unwrap()
+            .unwrap();
+        assert_eq!(view["name"], "kobo");
+        let inner = auth.lock();
+        let stored = &inner.data.tokens[&record.id];
+        assert_eq!(
+            stored.scopes,
+            BTreeSet::from([Scope::Read, Scope::Downloads])
+        );
+        assert_eq!(stored.expires_at, None);
+        assert_eq!(stored.image_profile.as_deref(), Some("eink"));
+        assert!(inner.dirty);
+    }
+
+    #[test]
+    fn patch_of_unknown_token_is_not_found() {
+        let auth = auth();
+        let body = patch(json!({ "name": "anything" }));
+        assert_eq!(auth.update("missing", body, |_| true), Ok(None));
+    }
+}
@@ -0,0 +1,141 @@
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::path::{Path, PathBuf};
+
+use reqwest::Client;
+use tracing::warn;
+
+use crate::Error;
+
+// URLs cannot carry an IPv6 zone id or a socket path, so those backends are
+// addressed by this name and the connection goes straight to the transport.
+const PLACEHOLDER_HOST: &str = "manatan-backend";
+const HOST_VAR: &str = "MANATAN_BACKEND_HOST";
+
+#[derive(Clone, Debug, Default)]
+pub(crate) enum Transport {
+    #[default]
+    Url,
+    Scoped(SocketAddr),
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Unix(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct BackendAddr {
+    // What the backend binds to: no brackets, zone id kept as `%zone`.
+    pub host: String,
+    pub url: String,
+    pub transport: Transport,
+    client: Client,
+}
+
+impl BackendAddr {
+    pub(crate) fn new(host: &str, port: u16, socket: Option<&str>) -> Result<Self, Error> {
+        let (host, mut url, mut transport) = Self::tcp(host, port)?;
+        if let Some(path) = socket {
+            if cfg!(unix) {
+                url = format!("http://{PLACEHOLDER_HOST}");
+                transport = Transport::Unix(PathBuf::from(path));
+            } else {
+                warn!("unix sockets are not available on this platform, using {url}");
+            }
+        }
+        let client = client(&transport)?;
+        Ok(Self {
+            host,
+            url,
+            transport,
+            client,
+        })
+    }
+
+    // The bind host, the URL requests use, and how to reach it.
+    fn tcp(host: &str, port: u16) -> Result<(String, String, Transport), Error> {
+        let host = host.trim();
+        // The bracketed URL form escapes the zone separator as `%25`.
+        let host = match host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+        {
+            Some(literal) => literal.replacen("%25", "%", 1),
+            None => host.to_string(),
+        };
+        let (ip, zone) = match host.split_once('%') {
+            Some((ip, zone)) => (ip, Some(zone)),
+            None => (host.as_str(), None),
+        };
+        let invalid = |reason: String| Error::InvalidArgument {
+            name: HOST_VAR.to_string(),
+            reason,
+        };
+        match (ip.parse::<IpAddr>(), zone) {
+            (Ok(IpAddr::V6(ip)), Some(zone)) => {
+                let scope_id = scope_id(zone)
+                    .ok_or_else(|| invalid(format!("has an unknown zone id {zone:?}")))?;
+                Ok((
+                    format!("{ip}%{zone}"),
+                    format!("http://{PLACEHOLDER_HOST}:{port}"),
+                    Transport::Scoped(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id))),
+                ))
+            }
+            (Ok(IpAddr::V6(ip)), None) => Ok((
+                ip.to_string(),
+                format!("http://[{ip}]:{port}"),
+                Transport::Url,
+            )),
+            (_, Some(_)) => Err(invalid(
+                "zone ids are only valid on IPv6 addresses".to_string(),
+            )),
+            (Ok(IpAddr::V4(ip)), None) => Ok((
+                ip.to_string(),
+                format!("http://{ip}:{port}"),
+                Transport::Url,
+            )),
+            (Err(_), None) => Ok((
+                host.clone(),
+                format!("http://{host}:{port}"),
+                Transport::Url,
+            )),
+        }
+    }
+
+    pub(crate) fn socket_path(&self) -> Option<&Path> {
+        match &self.transport {
+            Transport::Unix(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+// A builder failure (TLS backend, socket path) is reported rather than replaced by a
+// default client, which would quietly talk TCP to the placeholder host instead.
+fn client(transport: &Transport) -> Result<Client, Error> {
+    // Redirects are handled by the proxy, which knows the address clients see.
+    let builder = Client::builder().redirect(reqwest::redirect::Policy::none());
+    let builder = match transport {
+        Transport::Url => builder,
+        Transport::Scoped(addr) => builder.resolve(PLACEHOLDER_HOST, *addr),
+        #[cfg(unix)]
+        Transport::Unix(path) => builder.unix_socket(path.clone()),
+        #[cfg(not(unix))]
+        Transport::Unix(_) => builder,
+    };
+    builder
+        .build()
+        .map_err(|err| Error::BackendUnavailable(format!("cannot build backend client: {err}")))
+}
+
+fn scope_id(zone: &str) -> Option<u32> {
+    if let Ok(index) = zone.parse() {
+        return Some(index);
+    }
+    if_addrs::get_if_addrs()
+        .ok()?
+        .into_iter()
+        .find(|interface| interface.name == zone)
+        .and_then(|interface| interface.index)
+}
@@ -0,0 +1,596 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::app::AppState;
+use crate::config::Config;
+use crate::keys::random_id;
+use crate::supervisor::{self, Lifecycle};
+use crate::uploads;
+use crate::{unix_now, Error};
+
+const FORMAT: &str = "manatan-backup";
+const VERSION: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+const CHUNK_BYTES: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    format: String,
+    version: u32,
+    created_at: u64,
+    instance_name: String,
+    db: Option<String>,
+    directories: Vec<String>,
+}
+
+// The archive keeps fixed top-level names so a backup restores onto an install
+// whose paths differ.
+fn directories(config: &Config) -> [(&'static str, PathBuf); 4] {
+    [
+        ("downloads", PathBuf::from(&config.downloads_path)),
+        ("local-manga", PathBuf::from(&config.local_manga_path)),
+        ("local-anime", PathBuf::from(&config.local_anime_path)),
+        ("aidoku", PathBuf::from(&config.aidoku_cache_path)),
+    ]
+}
+
+fn io_error(context: &str, err: std::io::Error) -> Error {
+    Error::io(context, err)
+}
+
+fn backup_error(context: &str, err: impl std::fmt::Display) -> Error {
+    Error::Backup(format!("{context}: {err}"))
+}
+
+pub fn create<W: Write>(state: &AppState, writer: W) -> Result<W, Error> {
+    let config = &state.config;
+    let _pause = state.backend.pause_writes()?;
+    let db_path = PathBuf::from(&config.db_path);
+    let snapshot = PathBuf::from(&config.proxy_data_path).join(format!(
+        ".backup-{}-{}.sqlite",
+        std::process::id(),
+        unix_now()
+    ));
+    let db_name = if db_path.is_file() {
+        snapshot_db(&db_path, &snapshot)?;
+        db_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+    let result = write_archive(config, db_name.as_deref(), &snapshot, writer);
+    let _ = std::fs::remove_file(&snapshot);
+    result
+}
+
+// VACUUM INTO gives a consistent copy without the WAL even if a write slips past the pause.
+fn snapshot_db(db_path: &Path, snapshot: &Path) -> Result<(), Error> {
+    if let Some(parent) = snapshot.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| io_error("create backup dir", err))?;
+    }
+    let conn =
+        rusqlite::Connection::open_with_flags(db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|err| backup_error("open database", err))?;
+    conn.execute("VACUUM INTO ?1", [snapshot.to_string_lossy().as_ref()])
+        .map_err(|err| backup_error("snapshot database", err))?;
+    Ok(())
+}
+
+fn write_archive<W: Write>(
+    config: &Config,
+    db_name: Option<&str>,
+    snapshot: &Path,
+    writer: W,
+) -> Result<W, Error> {
+    let mut tar = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    tar.follow_symlinks(false);
+    let included = directories(config)
+        .into_iter()
+        .filter(|(_, path)| path.is_dir())
+        .collect::<Vec<_>>();
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        created_at: unix_now(),
+        instance_name: config.instance_name.clone(),
+        db: db_name.map(str::to_string),
+        directories: included.iter().map(|(name, _)| name.to_string()).collect(),
+    };
+    let manifest =
+        serde_json::to_vec_pretty(&manifest).map_err(|err| backup_error(MANIFEST, err))?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(unix_now());
+    header.set_cksum();
+    tar.append_data(&mut header, MANIFEST, manifest.as_slice())
+        .map_err(|err| io_error("write manifest", err))?;
+
+    if let Some(name) = db_name {
+        tar.append_path_with_name(snapshot, format!("db/{name}"))
+            .map_err(|err| io_error("write database", err))?;
+    }
+    for (name, path) in &included {
+        tar.append_dir_all(name, path)
+            .map_err(|err| io_error(&format!("write {}", path.display()), err))?;
+    }
+    let encoder = tar
+        .into_inner()
+        .map_err(|err| io_error("finish archive", err))?;
+    encoder
+        .finish()
+        .map_err(|err| io_error("finish archive", err))
+}
+
+// The backend is stopped while files are swapped; whatever was replaced is kept
+// next to it as `<path>.pre-restore` until the next restore.
+pub fn restore<R: Read>(state: &AppState, reader: R) -> Result<Value, Error> {
+    let config = &state.config;
+    let staging = PathBuf::from(&config.proxy_data_path).join(format!("restore-{}", random_id()));
+    let result = unpack_and_swap(state, reader, &staging);
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+fn unpack_and_swap<R: Read>(state: &AppState, reader: R, staging: &Path) -> Result<Value, Error> {
+    std::fs::create_dir_all(staging).map_err(|err| io_error("create staging dir", err))?;
+    tar::Archive::new(GzDecoder::new(reader))
+        .unpack(staging)
+        .map_err(|err| io_error("unpack backup", err))?;
+    let manifest = std::fs::read(staging.join(MANIFEST))
+        .map_err(|err| io_error("read manifest", err))
+        .and_then(|bytes| {
+            serde_json::from_slice::<Manifest>(&bytes)
+                .map_err(|err| backup_error("parse manifest", err))
+        })?;
+    let swaps = plan(&manifest, staging, &state.config)?;
+
+    state.backend.stop();
+    let swapped = swap_all(&swaps);
+    let restarted = state.backend.restart();
+    swapped?;
+    restarted?;
+    let restored = swaps
+        .iter()
+        .filter(|swap| swap.staged.is_some())
+        .map(|swap| swap.name)
+        .collect::<Vec<_>>();
+    info!(
+        "restored backup from {} ({})",
+        manifest.created_at,
+        restored.join(", ")
+    );
+    Ok(json!({
+        "created_at": manifest.created_at,
+        "instance_name": manifest.instance_name,
+        "restored": restored,
+    }))
+}
+
+// One path a restore replaces. Without `staged` the target is only moved aside, as the
+// database's WAL and shared-memory files are.
+struct Swap {
+    name: &'static str,
+    staged: Option<PathBuf>,
+    target: PathBuf,
+}
+
+// Everything the manifest promises is checked against the unpacked archive before the
+// backend is stopped, so a broken backup is turned away with the server untouched.
+fn plan(manifest: &Manifest, staging: &Path, config: &Config) -> Result<Vec<Swap>, Error> {
+    if manifest.format != FORMAT || manifest.version > VERSION {
+        return Err(Error::Backup(format!(
+            "unsupported backup {} v{}",
+            manifest.format, manifest.version
+        )));
+    }
+    let mut swaps = Vec::new();
+    if let Some(name) = &manifest.db {
+        if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+            return Err(Error::Backup(
+                "backup manifest has an invalid db name".to_string(),
+            ));
+        }
+        let staged = staging.join("db").join(name);
+        if !staged.is_file() {
+            return Err(Error::Backup(format!(
+                "backup manifest lists database {name}, which the archive does not contain"
+            )));
+        }
+        let db_path = PathBuf::from(&config.db_path);
+        for suffix in ["-wal", "-shm"] {
+            swaps.push(Swap {
+                name: "db",
+                staged: None,
+                target: PathBuf::from(format!("{}{suffix}", db_path.display())),
+            });
+        }
+        swaps.push(Swap {
+            name: "db",
+            staged: Some(staged),
+            target: db_path,
+        });
+    }
+    let known = directories(config);
+    for listed in &manifest.directories {
+        let Some((name, path)) = known.iter().find(|(name, _)| name == listed) else {
+            return Err(Error::Backup(format!(
+                "backup manifest lists unknown directory {listed}"
+            )));
+        };
+        let staged = staging.join(name);
+        if !staged.is_dir() {
+            return Err(Error::Backup(format!(
+                "backup manifest lists directory {name}, which the archive does not contain"
+            )));
+        }
+        swaps.push(Swap {
+            name,
+            staged: Some(staged),
+            target: path.clone(),
+        });
+    }
+    Ok(swaps)
+}
+
+// All or nothing: when one swap fails, the ones before it are put back.
+fn swap_all(swaps: &[Swap]) -> Result<(), Error> {
+    let mut done = Vec::new();
+    for swap in swaps {
+        match replace(swap.staged.as_deref(), &swap.target) {
+            Ok(moved) => done.push((swap, moved)),
+            Err(err) => {
+                for (swap, moved) in done.into_iter().rev() {
+                    undo(&swap.target, swap.staged.is_some(), moved);
+                }
+                return Err(err);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pre_restore(target: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.pre-restore", target.display()))
+}
+
+// Moves `target` aside and `staged` into its place, undoing its own move if the second
+// step fails. Returns whether there was a target to move aside.
+fn replace(staged: Option<&Path>, target: &Path) -> Result<bool, Error> {
+    let previous = pre_restore(target);
+    remove_path(&previous)
+        .map_err(|err| io_error(&format!("remove {}", previous.display()), err))?;
+    let moved = std::fs::symlink_metadata(target).is_ok();
+    if moved {
+        move_path(target, &previous)
+            .map_err(|err| io_error(&format!("move {} aside", target.display()), err))?;
+    }
+    let Some(staged) = staged else {
+        return Ok(moved);
+    };
+    let placed = target
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| move_path(staged, target));
+    if let Err(err) = placed {
+        undo(target, true, moved);
+        return Err(io_error(&format!("restore {}", target.display()), err));
+    }
+    Ok(moved)
+}
+
+fn undo(target: &Path, placed: bool, moved: bool) {
+    let mut result = Ok(());
+    if placed {
+        result = remove_path(target);
+    }
+    if moved {
+        result = result.and_then(|()| move_path(&pre_restore(target), target));
+    }
+    if let Err(err) = result {
+        error!("could not roll back {}: {}", target.display(), err);
+    }
+}
+
+// Falls back to copying when the staging dir is on another filesystem.
+pub(crate) fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_path(from, to)?;
+    remove_path(from)
+}
+
+fn copy_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    if !from.is_dir() {
+        return std::fs::copy(from, to).map(|_| ());
+    }
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        copy_path(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn remove_path(path: &Path) -> std::io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/proxy/backup", get(download))
+        .route(
+            "/api/proxy/backup/restore",
+            post(upload).layer(DefaultBodyLimit::disable()),
+        )
+}
+
+// Bridges the blocking tar writer onto the response body.
+struct ChannelWriter {
+    tx: mpsc::Sender<std::io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_BYTES {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::take(&mut self.buf));
+        self.tx.blocking_send(Ok(chunk)).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "backup download closed")
+        })
+    }
+}
+
+async fn download(State(state): State<AppState>) -> Response {
+    let (tx, rx) = mpsc::channel(8);
+    let errors = tx.clone();
+    tokio::task::spawn_blocking(move || {
+        let writer = ChannelWriter {
+            tx,
+            buf: Vec::new(),
+        };
+        match create(&state, writer)
+            .and_then(|mut writer| writer.flush().map_err(|err| io_error("send backup", err)))
+        {
+            Ok(()) => info!("backup download finished"),
+            Err(err) => {
+                warn!("backup failed: {}", err);
+                let _ = errors.blocking_send(Err(std::io::Error::other(err.to_string())));
+            }
+        }
+    });
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    let file_name = format!("manatan-backup-{}.tar.gz", unix_now());
+    (
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
+
+async fn upload(State(state): State<AppState>, headers: HeaderMap, body: Body) -> Response {
+    let Ok(_restoring) = state.restoring.clone().try_lock_owned() else {
+        return in_progress();
+    };
+    let max_bytes = state.config.uploads.max_bytes;
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    if declared.is_some_and(|length| length > max_bytes) {
+        return uploads::too_large(max_bytes);
+    }
+    let path = PathBuf::from(&state.config.proxy_data_path)
+        .join(format!(".restore-{}.tar.gz", random_id()));
+    let received = receive(&path, body, max_bytes).await;
+    if !matches!(received, Ok(true)) {
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+    match received {
+        Ok(true) => restore_received(&state, path).await,
+        Ok(false) => uploads::too_large(max_bytes),
+        Err(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    }
+}
+
+fn in_progress() -> Response {
+    (StatusCode::CONFLICT, "a restore is already in progress").into_response()
+}
+
+// Restores from an archive received in full and removes it afterwards.
+pub(crate) async fn restore_upload(state: &AppState, path: PathBuf) -> Response {
+    let Ok(_restoring) = state.restoring.clone().try_lock_owned() else {
+        let _ = tokio::fs::remove_file(&path).await;
+        return in_progress();
+    };
+    restore_received(state, path).await
+}
+
+async fn restore_received(state: &AppState, path: PathBuf) -> Response {
+    state.supervisor.set(Lifecycle::Restarting, None);
+    let restore_state = state.clone();
+    let archive = path.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let file = File::open(&archive).map_err(|err| io_error("open upload", err))?;
+        restore(&restore_state, file)
+    })
+    .await
+    .map_err(|err| Error::Backup(err.to_string()))
+    .and_then(|result| result);
+    let _ = tokio::fs::remove_file(&path).await;
+
+    let timeout = Duration::from_secs(state.config.supervisor.ready_timeout_seconds);
+    let ready = supervisor::wait_ready(&state.client, &state.backend_url, timeout).await;
+    match &ready {
+        Ok(()) => state.supervisor.set(Lifecycle::Ready, None),
+        Err(err) => state
+            .supervisor
+            .set(Lifecycle::Failed, Some(err.to_string())),
+    }
+    match (result, ready) {
+        (Ok(summary), Ok(())) => Json(summary).into_response(),
+        (Ok(_), Err(err)) | (Err(err), _) => {
+            warn!("restore failed: {}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+        }
+    }
+}
+
+// Returns false, with the file left partial, once the upload outgrows `max_bytes`.
+async fn receive(path: &Path, body: Body, max_bytes: u64) -> std::io::Result<bool> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut stream = body.into_data_stream();
+    let mut received = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        received += chunk.len() as u64;
+        if received > max_bytes {
+            return Ok(false);
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        root: PathBuf,
+        staging: PathBuf,
+        config: Config,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("manatan-backup-{}", random_id()));
+            let staging = root.join("staging");
+            std::fs::create_dir_all(staging.join("db")).unwrap();
+            let mut config = Config::builder().without_env().build().unwrap();
+            let path = |name: &str| root.join(name).display().to_string();
+            config.db_path = path("manatan.db");
+            config.downloads_path = path("downloads");
+            config.local_manga_path = path("local-manga");
+            config.local_anime_path = path("local-anime");
+            config.aidoku_cache_path = path("aidoku");
+            Self {
+                root,
+                staging,
+                config,
+            }
+        }
+
+        fn plan(&self, db: Option<&str>, directories: &[&str]) -> Result<Vec<Swap>, Error> {
+            let manifest = Manifest {
+                format: FORMAT.to_string(),
+                version: VERSION,
+                created_at: 0,
+                instance_name: "test".to_string(),
+                db: db.map(str::to_string),
+                directories: directories.iter().map(|dir| dir.to_string()).collect(),
+            };
+            plan(&manifest, &self.staging, &self.config)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn manifest_entries_must_be_in_the_archive() {
+        let fixture = Fixture::new();
+        assert!(fixture.plan(Some("manatan.db"), &[]).is_err());
+        assert!(fixture.plan(None, &["downloads"]).is_err());
+
+        std::fs::write(fixture.staging.join("db/manatan.db"), b"db").unwrap();
+        std::fs::create_dir(fixture.staging.join("downloads")).unwrap();
+        let swaps = fixture.plan(Some("manatan.db"), &["downloads"]).unwrap();
+        let staged = swaps.iter().filter(|swap| swap.staged.is_some()).count();
+        assert_eq!(staged, 2);
+    }
+
+    #[test]
+    fn unknown_directories_and_unsafe_db_names_are_rejected() {
+        let fixture = Fixture::new();
+        std::fs::create_dir(fixture.staging.join("elsewhere")).unwrap();
+        assert!(fixture.plan(None, &["elsewhere"]).is_err());
+        for name in ["", ".", "..", "../manatan.db", "db\\manatan.db"] {
+            assert!(fixture.plan(Some(name), &[]).is_err(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn failed_swap_restores_what_was_already_replaced() {
+        let fixture = Fixture::new();
+        let first = fixture.root.join("first");
+        std::fs::write(&first, b"old").unwrap();
+        let staged = fixture.staging.join("first");
+        std::fs::write(&staged, b"new").unwrap();
+        let swaps = [
+            Swap {
+                name: "first",
+                staged: Some(staged),
+                target: first.clone(),
+            },
+            Swap {
+                name: "second",
+                staged: Some(fixture.staging.join("missing")),
+                target: fixture.root.join("second"),
+            },
+        ];
+        assert!(swap_all(&swaps).is_err());
+        assert_eq!(std::fs::read(&first).unwrap(), b"old");
+        assert!(!pre_restore(&first).exists());
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Query, State},
+    http::request::Parts,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::app::AppState;
+#[cfg(feature = "auth")]
+use crate::auth::Auth;
+use crate::config::BandwidthConfig;
+use crate::credentials::Credentials;
+use crate::metrics::Metrics;
+use crate::principal::Principal;
+
+const PRUNE_AT: usize = 10_000;
+
+#[derive(Default)]
+struct Counter {
+    bytes: u64,
+    requests: u64,
+    window_bytes: u64,
+    window_start: u64,
+}
+
+impl Counter {
+    fn add(&mut self, bytes: u64, now: u64, window: u64) {
+        if now.saturating_sub(self.window_start) >= window {
+            self.window_start = now;
+            self.window_bytes = 0;
+        }
+        self.bytes += bytes;
+        self.requests += 1;
+        self.window_bytes += bytes;
+    }
+
+    fn current(&self, now: u64, window: u64) -> u64 {
+        if now.saturating_sub(self.window_start) >= window {
+            0
+        } else {
+            self.window_bytes
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    clients: HashMap<String, Counter>,
+    series: HashMap<String, Counter>,
+    sources: HashMap<String, Counter>,
+    total: u64,
+}
+
+pub(crate) struct Bandwidth {
+    config: BandwidthConfig,
+    started_at: u64,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Clone, Copy)]
+enum Subject {
+    Series,
+    Source,
+}
+
+impl Subject {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Series => "series",
+            Self::Source => "source",
+        }
+    }
+}
+
+// Counts what actually went out; dropped with the body, so aborted downloads
+// are charged for the part that was sent.
+struct Recorder {
+    bandwidth: Arc<Bandwidth>,
+    #[cfg(feature = "auth")]
+    auth: Arc<Auth>,
+    metrics: Option<Arc<Metrics>>,
+    client: String,
+    subject: Option<(Subject, String)>,
+    bytes: u64,
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.bandwidth
+            .record(&self.client, self.subject.as_ref(), self.bytes);
+        // Token quotas count the same bytes; other clients are ignored there.
+        #[cfg(feature = "auth")]
+        self.auth.charge_bytes(&self.client, self.bytes);
+        if let Some(metrics) = &self.metrics {
+            let kind = self
+                .subject
+                .as_ref()
+                .map_or("other", |(kind, _)| kind.as_str());
+            metrics.add(
+                "manatan_bandwidth_bytes_total",
+                "Response bytes sent to clients.",
+                &[("kind", kind)],
+                self.bytes as f64,
+            );
+        }
+    }
+}
+
+impl Bandwidth {
+    pub(crate) fn new(config: BandwidthConfig) -> Self {
+        Self {
+            config,
+            started_at: crate::unix_now(),
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    // Bytes the client has used in the current window and the seconds until it rolls over.
+    pub(crate) fn client_usage(&self, client: &str) -> (u64, u64) {
+        let now = crate::unix_now();
+        let window = self.config.window_seconds;
+        let inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        inner.clients.get(client).map_or((0, window), |counter| {
+            let used = counter.current(now, window);
+            let reset = (counter.window_start + window).saturating_sub(now).max(1);
+            (used, reset)
+        })
+    }
+
+    pub(crate) fn track(
+        self: &Arc<Self>,
+        state: &AppState,
+        client: String,
+        path: &str,
+        resp: Response,
+    ) -> Response {
+        if !self.config.enabled {
+            return resp;
+        }
+        let mut recorder = Recorder {
+            bandwidth: self.clone(),
+            #[cfg(feature = "auth")]
+            auth: state.auth.clone(),
+            metrics: state.config.metrics.enabled.then(|| state.metrics.clone()),
+            client,
+            subject: subject(path),
+            bytes: 0,
+        };
+        let (parts, body) = resp.into_parts();
+        let stream = body.into_data_stream().map(move |chunk| {
+            // Borrow the whole recorder so the closure owns it, not just the count.
+            let recorder = &mut recorder;
+            if let Ok(chunk) = &chunk {
+                recorder.bytes += chunk.len() as u64;
+            }
+            chunk
+        });
+        Response::from_parts(parts, Body::from_stream(stream))
+    }
+
+    fn record(&self, client: &str, subject: Option<&(Subject, String)>, bytes: u64) {
+        let now = crate::unix_now();
+        let window = self.config.window_seconds;
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        inner.total += bytes;
+        add(&mut inner.clients, client, bytes, now, window);
+        match subject {
+            Some((Subject::Series, key)) => add(&mut inner.series, key, bytes, now, window),
+            Some((Subject::Source, key)) => add(&mut inner.sources, key, bytes, now, window),
+            None => {}
+        }
+    }
+
+    fn report(&self, limit: usize) -> Value {
+        let now = crate::unix_now();
+        let window = self.config.window_seconds;
+        let inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        json!({
+            "since": self.started_at,
+            "window_seconds": window,
+            "total_bytes": inner.total,
+            "clients": top(&inner.clients, "client", limit, now, window),
+            "series": top(&inner.series, "series", limit, now, window),
+            "sources": top(&inner.sources, "source", limit, now, window),
+        })
+    }
+}
+
+fn add(counters: &mut HashMap<String, Counter>, key: &str, bytes: u64, now: u64, window: u64) {
+    if counters.len() >= PRUNE_AT && !counters.contains_key(key) {
+        // Keep the heavy hitters; anything idle for a whole window only loses its history.
+        counters.retain(|_, counter| counter.current(now, window) > 0);
+    }
+    counters
+        .entry(key.to_string())
+        .or_default()
+        .add(bytes, now, window);
+}
+
+fn top(
+    counters: &HashMap<String, Counter>,
+    label: &str,
+    limit: usize,
+    now: u64,
+    window: u64,
+) -> Vec<Value> {
+    let mut entries = counters.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+    entries
+        .into_iter()
+        .take(limit)
+        .map(|(key, counter)| {
+            json!({
+                label: key,
+                "bytes": counter.bytes,
+                "requests": counter.requests,
+                "window_bytes": counter.current(now, window),
+            })
+        })
+        .collect()
+}
+
+fn subject(path: &str) -> Option<(Subject, String)> {
+    let segments = path
+        .strip_prefix("/api/v1/")?
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let numeric = |id: &str| !id.is_empty() && id.bytes().all(|byte| byte.is_ascii_digit());
+    match segments.as_slice() {
+        ["source", id, ..] | ["anime", "source", id, ..] if numeric(id) => {
+            Some((Subject::Source, id.to_string()))
+        }
+        [kind @ ("manga" | "anime"), id, ..] if numeric(id) => {
+            Some((Subject::Series, format!("{kind}:{id}")))
+        }
+        _ => None,
+    }
+}
+
+// Tokens never show up in the report; they are reduced to a short digest.
+pub(crate) fn client_label(parts: &Parts) -> String {
+    if let Some(principal) = parts.extensions.get::<Principal>() {
+        return principal.id.clone();
+    }
+    let credentials = Credentials::from_request(&parts.headers, &parts.uri);
+    if let Some(user) = credentials.user {
+        return format!("user:{user}");
+    }
+    if let Some(token) = credentials.token {
+        let digest = Sha256::digest(token.as_bytes());
+        let short = digest[..4]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        return format!("token:{short}");
+    }
+    parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| format!("ip:{}", addr.ip()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[derive(Deserialize)]
+struct ReportQuery {
+    limit: Option<usize>,
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/admin/bandwidth", get(report))
+}
+
+async fn report(State(state): State<AppState>, Query(query): Query<ReportQuery>) -> Json<Value> {
+    let limit = query.limit.unwrap_or(20).clamp(1, 1000);
+    Json(state.bandwidth.report(limit))
+}
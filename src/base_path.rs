@@ -0,0 +1,95 @@
+use axum::{
+    body::Bytes,
+    extract::{OriginalUri, Request, State},
+    http::{header, uri::PathAndQuery, HeaderValue, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+
+use crate::app::AppState;
+
+const DOC_ROOTS: [&str; 3] = ["/openapi.json", "/docs", "/api/v1"];
+
+pub(crate) async fn middleware(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    // This is the outermost layer, so the URI the client sent is kept here before this
+    // and the path normalization behind it rewrite the request.
+    let original = OriginalUri(req.uri().clone());
+    req.extensions_mut().insert(original);
+    let base = state.config.base_path.as_str();
+    if base.is_empty() {
+        return next.run(req).await;
+    }
+    let stripped = match req.uri().path().strip_prefix(base) {
+        Some("") => "/",
+        Some(rest) if rest.starts_with('/') => rest,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("{stripped}?{query}"),
+        None => stripped.to_string(),
+    };
+    let mut parts = req.uri().clone().into_parts();
+    match PathAndQuery::try_from(path_and_query) {
+        Ok(value) => parts.path_and_query = Some(value),
+        Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+    }
+    match Uri::from_parts(parts) {
+        Ok(uri) => *req.uri_mut() = uri,
+        Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+    }
+
+    let mut resp = next.run(req).await;
+    let location = resp
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.starts_with('/') && !value.starts_with("//"))
+        .and_then(|value| HeaderValue::from_str(&format!("{base}{value}")).ok());
+    if let Some(location) = location {
+        resp.headers_mut().insert(header::LOCATION, location);
+    }
+    resp
+}
+
+// The backend renders its docs for the root, so point the spec's server and the
+// page's absolute asset and spec URLs back under the prefix.
+pub(crate) fn rewrite_docs(
+    base: &str,
+    path: &str,
+    content_type: Option<&str>,
+    body: Bytes,
+) -> Bytes {
+    if base.is_empty() {
+        return body;
+    }
+    if path == "/openapi.json" {
+        let Ok(mut spec) = serde_json::from_slice::<Value>(&body) else {
+            return body;
+        };
+        if let Some(spec) = spec.as_object_mut() {
+            spec.insert("servers".to_string(), json!([{ "url": base }]));
+        }
+        return serde_json::to_vec(&spec).map(Bytes::from).unwrap_or(body);
+    }
+    let textual = content_type.is_some_and(|value| {
+        value.starts_with("text/") || value.contains("javascript") || value.contains("json")
+    });
+    if !textual {
+        return body;
+    }
+    let Ok(text) = std::str::from_utf8(&body) else {
+        return body;
+    };
+    let mut text = text.to_string();
+    for quote in ['"', '\''] {
+        for root in DOC_ROOTS {
+            text = text.replace(&format!("{quote}{root}"), &format!("{quote}{base}{root}"));
+        }
+    }
+    Bytes::from(text)
+}
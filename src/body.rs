@@ -0,0 +1,28 @@
+use axum::body::{Body, Bytes};
+use futures::StreamExt;
+
+// Reads a body to cache or rewrite it. Past `limit`, or when the stream fails, the body
+// is handed back untouched (already-read chunks first) so the caller can pass it through.
+pub(crate) async fn buffer(body: Body, limit: usize) -> Result<Bytes, Body> {
+    let mut stream = body.into_data_stream();
+    let mut chunks = Vec::new();
+    let mut size = 0usize;
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                let head = futures::stream::iter(chunks.into_iter().map(Ok));
+                return Err(Body::from_stream(
+                    head.chain(futures::stream::once(async { Err(err) })),
+                ));
+            }
+        };
+        size += chunk.len();
+        chunks.push(chunk);
+        if size > limit {
+            let head = futures::stream::iter(chunks.into_iter().map(Ok));
+            return Err(Body::from_stream(head.chain(stream)));
+        }
+    }
+    Ok(Bytes::from(chunks.concat()))
+}
@@ -0,0 +1,515 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use axum::body::Bytes;
+use futures::future::BoxFuture;
+use tracing::{info, warn};
+
+use crate::config::{CacheBackend, ImageCacheConfig};
+use crate::image_cache::Meta;
+
+// Where cached images are kept, keyed by a hex digest. Size limits and eviction are up to
+// the store; expiry by `Meta::stored_at` is checked by the caller. Stores log their own
+// failures, so a broken store only ever costs a cache miss.
+pub(crate) trait CacheStore: Send + Sync {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<(Meta, Bytes)>>;
+
+    fn put<'a>(&'a self, key: &'a str, meta: &'a Meta, body: &'a Bytes) -> BoxFuture<'a, ()>;
+
+    fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()>;
+
+    // Where entries go, for logs and the self-test.
+    fn describe(&self) -> String;
+}
+
+pub(crate) fn open(config: &ImageCacheConfig, data_path: &str) -> Box<dyn CacheStore> {
+    // Never consulted while the cache is off, so don't scan a directory for it.
+    if !config.enabled {
+        return Box::new(MemoryStore::new(0));
+    }
+    let dir = config
+        .path
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(data_path).join("image-cache"));
+    match config.backend {
+        CacheBackend::Disk => Box::new(DiskStore::new(dir, config.max_bytes)),
+        CacheBackend::Memory => Box::new(MemoryStore::new(config.max_bytes)),
+        CacheBackend::Redis => {
+            redis_store(config).unwrap_or_else(|| Box::new(DiskStore::new(dir, config.max_bytes)))
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+fn redis_store(config: &ImageCacheConfig) -> Option<Box<dyn CacheStore>> {
+    let Some(url) = config.redis_url.as_deref() else {
+        warn!("MANATAN_IMAGE_CACHE_REDIS_URL is not set; using the disk cache");
+        return None;
+    };
+    match redis::RedisStore::new(url, config.ttl_seconds) {
+        Ok(store) => {
+            info!("image cache in {}", store.describe());
+            Some(Box::new(store))
+        }
+        Err(err) => {
+            warn!(
+                "invalid MANATAN_IMAGE_CACHE_REDIS_URL ({}); using the disk cache",
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+fn redis_store(_config: &ImageCacheConfig) -> Option<Box<dyn CacheStore>> {
+    warn!("this build has no redis feature; using the disk image cache");
+    None
+}
+
+struct Entry {
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    total: u64,
+}
+
+impl Index {
+    fn insert(&mut self, key: &str, size: u64) {
+        let entry = Entry {
+            size,
+            last_used: crate::unix_now(),
+        };
+        if let Some(previous) = self.entries.insert(key.to_string(), entry) {
+            self.total -= previous.size;
+        }
+        self.total += size;
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.total -= entry.size;
+        }
+    }
+
+    // Least recently used entries until the total fits, sparing the one just added.
+    fn evict(&mut self, max_bytes: u64, keep: &str) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total > max_bytes {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .filter(|(key, _)| key.as_str() != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+            evicted.push(oldest);
+        }
+        evicted
+    }
+}
+
+// `{key}.bin` and `{key}.json` per entry; the index is rebuilt from the directory at start.
+struct DiskStore {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Index>,
+}
+
+impl DiskStore {
+    fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        let index = scan(&dir);
+        if index.total > 0 {
+            info!(
+                "image cache at {} holds {} entries ({} bytes)",
+                dir.display(),
+                index.entries.len(),
+                index.total
+            );
+        }
+        Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Index> {
+        self.index.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn data_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.bin"))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    async fn delete(&self, key: &str) {
+        let _ = tokio::fs::remove_file(self.data_path(key)).await;
+        let _ = tokio::fs::remove_file(self.meta_path(key)).await;
+    }
+}
+
+impl CacheStore for DiskStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<(Meta, Bytes)>> {
+        Box::pin(async move {
+            {
+                let mut index = self.lock();
+                let entry = index.entries.get_mut(key)?;
+                entry.last_used = crate::unix_now();
+            }
+            let loaded = async {
+                let meta = tokio::fs::read(self.meta_path(key)).await.ok()?;
+                let meta: Meta = serde_json::from_slice(&meta).ok()?;
+                let body = tokio::fs::read(self.data_path(key)).await.ok()?;
+                Some((meta, Bytes::from(body)))
+            }
+            .await;
+            if loaded.is_none() {
+                self.remove(key).await;
+            }
+            loaded
+        })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, meta: &'a Meta, body: &'a Bytes) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let size = body.len() as u64;
+            if size > self.max_bytes {
+                return;
+            }
+            let written = async {
+                tokio::fs::create_dir_all(&self.dir).await?;
+                tokio::fs::write(self.data_path(key), body).await?;
+                tokio::fs::write(self.meta_path(key), serde_json::to_vec(meta)?).await
+            }
+            .await;
+            if let Err(err) = written {
+                warn!(
+                    "image cache write to {} failed: {}",
+                    self.dir.display(),
+                    err
+                );
+                self.remove(key).await;
+                return;
+            }
+            let evicted = {
+                let mut index = self.lock();
+                index.insert(key, size);
+                index.evict(self.max_bytes, key)
+            };
+            for key in evicted {
+                self.delete(&key).await;
+            }
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            self.lock().remove(key);
+            self.delete(key).await;
+        })
+    }
+
+    fn describe(&self) -> String {
+        self.dir.display().to_string()
+    }
+}
+
+fn scan(dir: &Path) -> Index {
+    let mut index = Index::default();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return index;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("bin") {
+            continue;
+        }
+        let (Some(key), Ok(metadata)) = (
+            path.file_stem().and_then(|stem| stem.to_str()),
+            entry.metadata(),
+        ) else {
+            continue;
+        };
+        let last_used = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        index.total += metadata.len();
+        index.entries.insert(
+            key.to_string(),
+            Entry {
+                size: metadata.len(),
+                last_used,
+            },
+        );
+    }
+    index
+}
+
+// Gone on restart, but no disk writes; for hosts with RAM to spare and slow storage.
+struct MemoryStore {
+    max_bytes: u64,
+    inner: Mutex<Memory>,
+}
+
+#[derive(Default)]
+struct Memory {
+    index: Index,
+    entries: HashMap<String, (Meta, Bytes)>,
+}
+
+impl MemoryStore {
+    fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            inner: Mutex::new(Memory::default()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Memory> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<(Meta, Bytes)>> {
+        let mut memory = self.lock();
+        let Memory { index, entries } = &mut *memory;
+        let found = index.entries.get_mut(key).and_then(|entry| {
+            entry.last_used = crate::unix_now();
+            entries.get(key).cloned()
+        });
+        Box::pin(std::future::ready(found))
+    }
+
+    fn put<'a>(&'a self, key: &'a str, meta: &'a Meta, body: &'a Bytes) -> BoxFuture<'a, ()> {
+        let size = body.len() as u64;
+        if size <= self.max_bytes {
+            let mut memory = self.lock();
+            let Memory { index, entries } = &mut *memory;
+            index.insert(key, size);
+            entries.insert(key.to_string(), (meta.clone(), body.clone()));
+            for key in index.evict(self.max_bytes, key) {
+                entries.remove(&key);
+            }
+        }
+        Box::pin(std::future::ready(()))
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()> {
+        let mut memory = self.lock();
+        memory.index.remove(key);
+        memory.entries.remove(key);
+        Box::pin(std::future::ready(()))
+    }
+
+    fn describe(&self) -> String {
+        "memory".to_string()
+    }
+}
+
+// A shared tier for several instances. Speaks just enough RESP for GET, SET and DEL, and
+// leaves size limits to the server's `maxmemory` policy; entries expire with the cache TTL.
+#[cfg(feature = "redis")]
+mod redis {
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    use axum::body::Bytes;
+    use futures::future::BoxFuture;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpStream;
+    use tokio::sync::Mutex;
+    use tracing::warn;
+
+    use super::CacheStore;
+    use crate::image_cache::Meta;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+    const PREFIX: &str = "manatan:image:";
+
+    type Connection = BufReader<TcpStream>;
+
+    pub(super) struct RedisStore {
+        addr: String,
+        auth: Option<(String, String)>,
+        db: Option<u32>,
+        ttl_seconds: u64,
+        conn: Mutex<Option<Connection>>,
+        warned: AtomicBool,
+    }
+
+    impl RedisStore {
+        pub(super) fn new(url: &str, ttl_seconds: u64) -> Result<Self, String> {
+            let url = reqwest::Url::parse(url).map_err(|err| err.to_string())?;
+            if url.scheme() != "redis" {
+                return Err("only redis:// URLs are supported".to_string());
+            }
+            let host = url.host_str().ok_or("the URL has no host")?;
+            let db = match url.path().trim_matches('/') {
+                "" => None,
+                db => Some(
+                    db.parse()
+                        .map_err(|_| format!("{db:?} is not a database number"))?,
+                ),
+            };
+            Ok(Self {
+                addr: format!("{}:{}", host, url.port().unwrap_or(6379)),
+                auth: url
+                    .password()
+                    .map(|password| (url.username().to_string(), password.to_string())),
+                db,
+                ttl_seconds: ttl_seconds.max(1),
+                conn: Mutex::new(None),
+                warned: AtomicBool::new(false),
+            })
+        }
+
+        async fn connect(&self) -> io::Result<Connection> {
+            let mut conn = BufReader::new(TcpStream::connect(&self.addr).await?);
+            if let Some((user, password)) = &self.auth {
+                if user.is_empty() {
+                    command(&mut conn, &[b"AUTH", password.as_bytes()]).await?;
+                } else {
+                    command(&mut conn, &[b"AUTH", user.as_bytes(), password.as_bytes()]).await?;
+                }
+            }
+            if let Some(db) = self.db {
+                command(&mut conn, &[b"SELECT", db.to_string().as_bytes()]).await?;
+            }
+            Ok(conn)
+        }
+
+        // One shared connection; a failed command drops it and the next one reconnects.
+        // Outages are logged once rather than per request.
+        async fn run(&self, args: &[&[u8]]) -> Option<Option<Vec<u8>>> {
+            let mut guard = self.conn.lock().await;
+            let result = tokio::time::timeout(TIMEOUT, async {
+                let conn = match guard.as_mut() {
+                    Some(conn) => conn,
+                    None => guard.insert(self.connect().await?),
+                };
+                command(conn, args).await
+            })
+            .await
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")));
+            match result {
+                Ok(reply) => {
+                    self.warned.store(false, Ordering::Relaxed);
+                    Some(reply)
+                }
+                Err(err) => {
+                    *guard = None;
+                    if !self.warned.swap(true, Ordering::Relaxed) {
+                        warn!("redis image cache at {} is unavailable: {}", self.addr, err);
+                    }
+                    None
+                }
+            }
+        }
+    }
+
+    impl CacheStore for RedisStore {
+        fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<(Meta, Bytes)>> {
+            Box::pin(async move {
+                let value = self
+                    .run(&[b"GET", format!("{PREFIX}{key}").as_bytes()])
+                    .await??;
+                // Meta as JSON, a newline, then the body; compact JSON has no raw newlines.
+                let split = value.iter().position(|&byte| byte == b'\n')?;
+                let meta = serde_json::from_slice(&value[..split]).ok()?;
+                Some((meta, Bytes::copy_from_slice(&value[split + 1..])))
+            })
+        }
+
+        fn put<'a>(&'a self, key: &'a str, meta: &'a Meta, body: &'a Bytes) -> BoxFuture<'a, ()> {
+            Box::pin(async move {
+                let Ok(mut value) = serde_json::to_vec(meta) else {
+                    return;
+                };
+                value.push(b'\n');
+                value.extend_from_slice(body);
+                let ttl = self.ttl_seconds.to_string();
+                self.run(&[
+                    b"SET",
+                    format!("{PREFIX}{key}").as_bytes(),
+                    &value,
+                    b"EX",
+                    ttl.as_bytes(),
+                ])
+                .await;
+            })
+        }
+
+        fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()> {
+            Box::pin(async move {
+                self.run(&[b"DEL", format!("{PREFIX}{key}").as_bytes()])
+                    .await;
+            })
+        }
+
+        fn describe(&self) -> String {
+            format!("redis at {}", self.addr)
+        }
+    }
+
+    async fn command(conn: &mut Connection, args: &[&[u8]]) -> io::Result<Option<Vec<u8>>> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend_from_slice(arg);
+            request.extend_from_slice(b"\r\n");
+        }
+        conn.get_mut().write_all(&request).await?;
+        read_reply(conn).await
+    }
+
+    // Simple strings, integers and bulk strings; `None` is a nil reply.
+    async fn read_reply(conn: &mut Connection) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        conn.read_until(b'\n', &mut line).await?;
+        let Some(line) = line.strip_suffix(b"\r\n") else {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed",
+            ));
+        };
+        let Some((&kind, rest)) = line.split_first() else {
+            return Err(io::Error::other("empty reply"));
+        };
+        match kind {
+            b'+' | b':' => Ok(Some(rest.to_vec())),
+            b'-' => Err(io::Error::other(String::from_utf8_lossy(rest).into_owned())),
+            b'$' => {
+                let len = std::str::from_utf8(rest)
+                    .ok()
+                    .and_then(|len| len.parse::<i64>().ok())
+                    .ok_or_else(|| io::Error::other("bad bulk length"))?;
+                let Ok(len) = usize::try_from(len) else {
+                    return Ok(None);
+                };
+                let mut data = vec![0; len + 2];
+                conn.read_exact(&mut data).await?;
+                data.truncate(len);
+                Ok(Some(data))
+            }
+            _ => Err(io::Error::other("unexpected reply type")),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::app::AppState;
+use crate::unix_now;
+use crate::updates::{self, RecentChapter};
+
+const DAY: i64 = 86_400;
+const DEFAULT_PAGES: u32 = 5;
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/calendar.ics", get(calendar))
+}
+
+#[derive(Deserialize)]
+struct CalendarQuery {
+    pages: Option<u32>,
+}
+
+struct Forecast {
+    manga_id: i64,
+    title: String,
+    next: i64,
+    interval_days: i64,
+    last_chapter: String,
+}
+
+async fn calendar(
+    State(state): State<AppState>,
+    Query(query): Query<CalendarQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let chapters = match updates::recent_chapters(
+        &state,
+        &headers,
+        query.pages.unwrap_or(DEFAULT_PAGES),
+    )
+    .await
+    {
+        Ok(chapters) => chapters,
+        Err(err) => {
+            warn!("calendar: failed to load recent chapters: {}", err);
+            return Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+
+    let now = unix_now() as i64;
+    let body = render(&forecast(chapters, now), now);
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/calendar; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "inline; filename=\"manatan.ics\"",
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn forecast(chapters: Vec<RecentChapter>, now: i64) -> Vec<Forecast> {
+    let mut by_manga: BTreeMap<i64, Vec<RecentChapter>> = BTreeMap::new();
+    for chapter in chapters {
+        if chapter.manga_status.eq_ignore_ascii_case("completed")
+            || chapter.manga_status.eq_ignore_ascii_case("cancelled")
+        {
+            continue;
+        }
+        by_manga.entry(chapter.manga_id).or_default().push(chapter);
+    }
+
+    let mut forecasts = Vec::new();
+    for (manga_id, mut chapters) in by_manga {
+        chapters.sort_by_key(RecentChapter::released_at);
+        chapters.dedup_by_key(|chapter| chapter.released_at() / DAY);
+        if chapters.len() < 2 {
+            continue;
+        }
+
+        let mut intervals = chapters
+            .windows(2)
+            .map(|pair| pair[1].released_at() - pair[0].released_at())
+            .filter(|interval| *interval > 0)
+            .collect::<Vec<_>>();
+        if intervals.is_empty() {
+            continue;
+        }
+        intervals.sort_unstable();
+        let interval = intervals[intervals.len() / 2].max(DAY);
+
+        let last = chapters.last().unwrap();
+        let mut next = last.released_at() + interval;
+        while next < now - DAY {
+            next += interval;
+        }
+        forecasts.push(Forecast {
+            manga_id,
+            title: last.manga_title.clone(),
+            next,
+            interval_days: interval / DAY,
+            last_chapter: last.chapter_name.clone(),
+        });
+    }
+    forecasts.sort_by_key(|forecast| forecast.next);
+    forecasts
+}
+
+fn render(forecasts: &[Forecast], now: i64) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//Manatan//Release Calendar//EN");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, "X-WR-CALNAME:Manatan releases");
+    for forecast in forecasts {
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(
+            &mut out,
+            &format!(
+                "UID:manga-{}-{}@manatan",
+                forecast.manga_id,
+                forecast.next / DAY
+            ),
+        );
+        push_line(&mut out, &format!("DTSTAMP:{}", format_time(now)));
+        push_line(
+            &mut out,
+            &format!("DTSTART;VALUE=DATE:{}", format_date(forecast.next)),
+        );
+        push_line(
+            &mut out,
+            &format!("DTEND;VALUE=DATE:{}", format_date(forecast.next + DAY)),
+        );
+        push_line(
+            &mut out,
+            &format!(
+                "SUMMARY:{}",
+                escape(&format!("{}: next release", forecast.title))
+            ),
+        );
+        push_line(
+            &mut out,
+            &format!(
+                "DESCRIPTION:{}",
+                escape(&format!(
+                    "Estimated from a typical {}-day cadence. Last: {}",
+                    forecast.interval_days, forecast.last_chapter
+                ))
+            ),
+        );
+        push_line(&mut out, "TRANSP:TRANSPARENT");
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for ch in line.chars() {
+        if width + ch.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(ch);
+        width += ch.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn format_date(unix: i64) -> String {
+    DateTime::<Utc>::from_timestamp(unix, 0)
+        .map(|time| time.format("%Y%m%d").to_string())
+        .unwrap_or_default()
+}
+
+fn format_time(unix: i64) -> String {
+    DateTime::<Utc>::from_timestamp(unix, 0)
+        .map(|time| time.format("%Y%m%dT%H%M%SZ").to_string())
+        .unwrap_or_default()
+}
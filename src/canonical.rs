@@ -0,0 +1,68 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use reqwest::Url;
+
+use crate::app::AppState;
+
+pub(crate) async fn middleware(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !state.config.canonical_redirect {
+        return next.run(req).await;
+    }
+    let Some(external) = state.config.external_url.as_deref() else {
+        return next.run(req).await;
+    };
+    let Some(canonical) = Url::parse(external).ok().and_then(|url| authority(&url)) else {
+        return next.run(req).await;
+    };
+
+    let host = req
+        .headers()
+        .get("x-forwarded-host")
+        .or_else(|| req.headers().get(header::HOST))
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase());
+    let Some(host) = host else {
+        return next.run(req).await;
+    };
+    if host == canonical || is_loopback(&host) || req.uri().path() == "/health" {
+        return next.run(req).await;
+    }
+
+    let path_query = req
+        .uri()
+        .path_and_query()
+        .map(|value| value.as_str())
+        .unwrap_or("/");
+    (
+        StatusCode::PERMANENT_REDIRECT,
+        [(header::LOCATION, format!("{external}{path_query}"))],
+    )
+        .into_response()
+}
+
+fn authority(url: &Url) -> Option<String> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host,
+    })
+}
+
+fn is_loopback(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(rest),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name == "localhost"
+        || name
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|addr| addr.is_loopback())
+}
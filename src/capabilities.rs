@@ -0,0 +1,93 @@
+use axum::{extract::State, routing::get, Json, Router};
+use serde_json::{json, Value};
+
+use crate::app::AppState;
+use crate::config::AuthConfig;
+
+// Cargo features this binary was built with; see the Building section of the README.
+const FEATURES: &[(&str, bool)] = &[
+    ("auth", cfg!(feature = "auth")),
+    ("cache", cfg!(feature = "cache")),
+    ("metrics", cfg!(feature = "metrics")),
+    ("notifications", cfg!(feature = "notifications")),
+    ("opds", cfg!(feature = "opds")),
+    ("redis", cfg!(feature = "redis")),
+    ("transcode", cfg!(feature = "transcode")),
+    ("webui", cfg!(feature = "webui")),
+];
+
+// Config validation refuses auth settings in a build without the `auth` feature, so
+// the config alone says what a client will be asked for.
+fn auth_modes(config: &AuthConfig) -> Vec<&'static str> {
+    let mut modes = Vec::new();
+    if config.basic_enabled() {
+        modes.push("basic");
+    }
+    if config.token.is_some() {
+        modes.push("token");
+    }
+    if config.tokens_enabled {
+        modes.push("tokens");
+    }
+    modes
+}
+
+// What this server build and its config offer, so clients can shape their UI up front
+// instead of probing endpoints and handling 404s. Readable without credentials.
+pub(crate) fn describe(state: &AppState) -> Value {
+    let config = &state.config;
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "instance": config.instance_name,
+        "auth": {
+            "enabled": config.auth.is_enabled(),
+            "modes": auth_modes(&config.auth),
+        },
+        "opds": cfg!(feature = "opds"),
+        "transcoding": cfg!(feature = "transcode"),
+        "webview": state.backend.webview_enabled(),
+        "remote_backend": config.backend.socket.is_none() && !config.backend.is_loopback(),
+        "features": FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>(),
+    })
+}
+
+// One line for the startup log naming what is switched on.
+pub(crate) fn banner(state: &AppState) -> String {
+    let capabilities = describe(state);
+    let mut enabled = Vec::new();
+    if let Some(modes) = capabilities["auth"]["modes"].as_array() {
+        if !modes.is_empty() {
+            let modes = modes
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join("+");
+            enabled.push(format!("auth ({modes})"));
+        }
+    }
+    for name in ["opds", "transcoding", "webview", "remote_backend"] {
+        if capabilities[name].as_bool() == Some(true) {
+            enabled.push(name.replace('_', " "));
+        }
+    }
+    if enabled.is_empty() {
+        enabled.push("none".to_string());
+    }
+    format!(
+        "manatan {} capabilities: {}",
+        env!("CARGO_PKG_VERSION"),
+        enabled.join(", ")
+    )
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/capabilities", get(capabilities))
+}
+
+async fn capabilities(State(state): State<AppState>) -> Json<Value> {
+    Json(describe(&state))
+}
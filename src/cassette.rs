@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{HeaderName, HeaderValue, StatusCode},
+    response::Response,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::config::{CassetteConfig, CassetteMode};
+
+const MAX_RECORDED_BODY: usize = 64 * 1024 * 1024;
+const SKIPPED_HEADERS: [&str; 3] = ["connection", "transfer-encoding", "content-length"];
+
+#[derive(Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    uri: String,
+    request_body_sha256: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+pub(crate) struct Recording {
+    key: String,
+    method: String,
+    uri: String,
+    body_sha256: String,
+}
+
+pub(crate) struct Cassette {
+    mode: CassetteMode,
+    dir: PathBuf,
+}
+
+impl Cassette {
+    pub(crate) fn new(config: &CassetteConfig, data_path: &str) -> Self {
+        let dir = config
+            .path
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(data_path).join("cassettes"));
+        if config.mode != CassetteMode::Off {
+            info!("cassette {:?} mode using {}", config.mode, dir.display());
+        }
+        Self {
+            mode: config.mode,
+            dir,
+        }
+    }
+
+    pub(crate) fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub(crate) async fn buffer_request(req: Request) -> Result<(Request, Recording), Response> {
+        let (parts, body) = req.into_parts();
+        let bytes = axum::body::to_bytes(body, MAX_RECORDED_BODY)
+            .await
+            .map_err(|_| {
+                status_response(StatusCode::PAYLOAD_TOO_LARGE, "cassette: request too large")
+            })?;
+        let method = parts.method.to_string();
+        let uri = parts
+            .uri
+            .path_and_query()
+            .map(|value| value.as_str().to_string())
+            .unwrap_or_else(|| parts.uri.path().to_string());
+        let body_sha256 = hex_digest(&bytes);
+        let recording = Recording {
+            key: hex_digest(format!("{method} {uri}\n{body_sha256}").as_bytes()),
+            method,
+            uri,
+            body_sha256,
+        };
+        Ok((Request::from_parts(parts, Body::from(bytes)), recording))
+    }
+
+    pub(crate) async fn replay(&self, recording: &Recording) -> Response {
+        let path = self.dir.join(format!("{}.json", recording.key));
+        let interaction = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice::<Interaction>(&bytes).ok(),
+            Err(_) => None,
+        };
+        let Some(interaction) = interaction else {
+            let mut resp =
+                status_response(StatusCode::NOT_FOUND, "cassette: no recorded interaction");
+            resp.headers_mut()
+                .insert("x-manatan-cassette", HeaderValue::from_static("miss"));
+            return resp;
+        };
+
+        let mut builder = Response::builder().status(interaction.status);
+        for (name, value) in &interaction.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::from_str(value),
+            ) {
+                builder = builder.header(name, value);
+            }
+        }
+        let body = STANDARD.decode(&interaction.body).unwrap_or_default();
+        builder
+            .header("x-manatan-cassette", "hit")
+            .body(Body::from(body))
+            .unwrap_or_else(|_| status_response(StatusCode::BAD_GATEWAY, "cassette: corrupt entry"))
+    }
+
+    pub(crate) async fn record(&self, recording: Recording, resp: Response) -> Response {
+        let (parts, body) = resp.into_parts();
+        let bytes = match axum::body::to_bytes(body, MAX_RECORDED_BODY).await {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                return status_response(StatusCode::BAD_GATEWAY, "cassette: response too large")
+            }
+        };
+
+        let interaction = Interaction {
+            method: recording.method,
+            uri: recording.uri,
+            request_body_sha256: recording.body_sha256,
+            status: parts.status.as_u16(),
+            headers: parts
+                .headers
+                .iter()
+                .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+                .filter_map(|(name, value)| {
+                    value
+                        .to_str()
+                        .ok()
+                        .map(|value| (name.to_string(), value.to_string()))
+                })
+                .collect(),
+            body: STANDARD.encode(&bytes),
+        };
+        let path = self.dir.join(format!("{}.json", recording.key));
+        if let Err(err) = write_interaction(&path, &interaction).await {
+            warn!("cassette: failed to record {}: {}", path.display(), err);
+        }
+
+        Response::from_parts(parts, Body::from(bytes))
+    }
+}
+
+async fn write_interaction(
+    path: &std::path::Path,
+    interaction: &Interaction,
+) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let bytes = serde_json::to_vec_pretty(interaction).map_err(std::io::Error::other)?;
+    tokio::fs::write(path, bytes).await
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn status_response(status: StatusCode, message: &'static str) -> Response {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .unwrap()
+}
@@ -1,3 +1,101 @@
+use std::ffi::CString;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::embedded::{ffi_error, take_string};
+use crate::{ffi, to_cstring, Error};
+
+// Settings for the webview that solves Cloudflare challenges; `None` keeps the library default.
+#[derive(Clone, Debug, Default)]
+pub struct CefOptions {
+    pub cache_dir: Option<PathBuf>,
+    pub user_agent: Option<String>,
+    pub proxy_server: Option<String>,
+    pub headless: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub expires: Option<i64>,
+    #[serde(default)]
+    pub secure: bool,
+    #[serde(default)]
+    pub http_only: bool,
+}
+
 pub fn try_handle_subprocess() -> bool {
-    unsafe { crate::ffi::manatan_server_try_handle_subprocess() }
+    unsafe { ffi::manatan_server_try_handle_subprocess() }
+}
+
+// Call before starting the server; later calls fail once the webview is up.
+pub fn init(options: &CefOptions) -> Result<(), Error> {
+    let cache_dir = options
+        .cache_dir
+        .as_ref()
+        .map(|path| to_cstring(&path.to_string_lossy(), "cache_dir"))
+        .transpose()?;
+    let user_agent = optional(options.user_agent.as_deref(), "user_agent")?;
+    let proxy_server = optional(options.proxy_server.as_deref(), "proxy_server")?;
+    let raw = ffi::ManatanCefOptions {
+        cache_dir: pointer(&cache_dir),
+        user_agent: pointer(&user_agent),
+        proxy_server: pointer(&proxy_server),
+        headless: if options.headless { 1 } else { 0 },
+    };
+    if unsafe { ffi::manatan_cef_init(&raw) } {
+        Ok(())
+    } else {
+        Err(ffi_error("manatan_cef_init"))
+    }
+}
+
+// Every cookie in the webview store, or only those for `domain` and its subdomains.
+pub fn cookies(domain: Option<&str>) -> Result<Vec<Cookie>, Error> {
+    let domain = optional(domain, "domain")?;
+    let text = unsafe { take_string(ffi::manatan_cef_cookies(pointer(&domain))) }
+        .ok_or_else(|| ffi_error("manatan_cef_cookies"))?;
+    serde_json::from_str(&text).map_err(|err| Error::Ffi {
+        call: "manatan_cef_cookies",
+        detail: Some(format!("invalid cookie list: {err}")),
+    })
+}
+
+// Drops challenge tokens so the next request to the source solves a fresh one.
+pub fn clear_cookies(domain: Option<&str>) -> Result<(), Error> {
+    let domain = optional(domain, "domain")?;
+    if unsafe { ffi::manatan_cef_clear_cookies(pointer(&domain)) } {
+        Ok(())
+    } else {
+        Err(ffi_error("manatan_cef_clear_cookies"))
+    }
+}
+
+// Writes pending cookie changes to the cache dir.
+pub fn flush_cookies() -> Result<(), Error> {
+    if unsafe { ffi::manatan_cef_flush_cookies() } {
+        Ok(())
+    } else {
+        Err(ffi_error("manatan_cef_flush_cookies"))
+    }
+}
+
+fn optional(value: Option<&str>, label: &str) -> Result<Option<CString>, Error> {
+    value
+        .filter(|value| !value.is_empty())
+        .map(|value| to_cstring(value, label))
+        .transpose()
+}
+
+fn pointer(value: &Option<CString>) -> *const std::os::raw::c_char {
+    value
+        .as_ref()
+        .map(|value| value.as_ptr())
+        .unwrap_or(std::ptr::null())
 }
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use serde_json::{json, Value};
+
+use crate::app::{self, AppState};
+use crate::config::RefreshCoalesceConfig;
+use crate::image_cache::hex_digest;
+use crate::keys::random_id;
+use crate::supervisor::BackendUnreachable;
+
+const MAX_REQUEST: usize = 1024 * 1024;
+const MAX_RESPONSE: usize = 32 * 1024 * 1024;
+const JOB_HEADER: &str = "x-manatan-refresh-job";
+const COALESCED_HEADER: &str = "x-manatan-coalesced";
+
+// The backend's answer, kept so every caller of a flight can be sent a copy.
+#[derive(Clone)]
+struct Snapshot {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    unreachable: bool,
+}
+
+impl Snapshot {
+    async fn take(resp: Response) -> Self {
+        let unreachable = resp.extensions().get::<BackendUnreachable>().is_some();
+        let (parts, body) = resp.into_parts();
+        match axum::body::to_bytes(body, MAX_RESPONSE).await {
+            Ok(body) => Self {
+                status: parts.status,
+                headers: parts.headers,
+                body,
+                unreachable,
+            },
+            Err(_) => Self {
+                status: StatusCode::BAD_GATEWAY,
+                headers: HeaderMap::new(),
+                body: Bytes::new(),
+                unreachable,
+            },
+        }
+    }
+
+    fn response(&self, job: &str, coalesced: bool) -> Response {
+        let mut resp = Response::new(Body::from(self.body.clone()));
+        *resp.status_mut() = self.status;
+        *resp.headers_mut() = self.headers.clone();
+        if let Ok(value) = HeaderValue::from_str(job) {
+            resp.headers_mut().insert(JOB_HEADER, value);
+        }
+        resp.headers_mut().insert(
+            COALESCED_HEADER,
+            HeaderValue::from_static(if coalesced { "true" } else { "false" }),
+        );
+        if self.unreachable {
+            resp.extensions_mut().insert(BackendUnreachable);
+        }
+        resp
+    }
+}
+
+struct Flight {
+    id: String,
+    target: String,
+    started: Instant,
+    callers: Arc<AtomicUsize>,
+    result: Shared<BoxFuture<'static, Snapshot>>,
+}
+
+impl Flight {
+    fn done(&self) -> bool {
+        self.result.peek().is_some()
+    }
+}
+
+// Folds the library refresh and online-fetch triggers that several clients fire at once
+// (typically every open app on start-up) into one backend call per window.
+pub(crate) struct Coalescer {
+    config: RefreshCoalesceConfig,
+    flights: Mutex<HashMap<String, Flight>>,
+}
+
+impl Coalescer {
+    pub(crate) fn new(config: RefreshCoalesceConfig) -> Self {
+        Self {
+            config,
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Flight>> {
+        self.flights.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.config.window_seconds)
+    }
+
+    // A flight is joined while it is still running or was started within the window.
+    fn live(&self, flight: &Flight) -> bool {
+        !flight.done() || flight.started.elapsed() < self.window()
+    }
+
+    // Failed calls are not shared past their end, so a retry reaches the backend.
+    fn settle(&self, key: &str, id: &str, status: StatusCode) {
+        if status.is_success() {
+            return;
+        }
+        let mut flights = self.lock();
+        if flights.get(key).is_some_and(|flight| flight.id == id) {
+            flights.remove(key);
+        }
+    }
+}
+
+fn is_trigger(method: &Method, uri: &Uri) -> bool {
+    let Some(rest) = uri.path().strip_prefix("/api/v1/") else {
+        return false;
+    };
+    let segments = rest
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let online_fetch = uri.query().is_some_and(|query| {
+        query
+            .split('&')
+            .any(|pair| pair.eq_ignore_ascii_case("onlineFetch=true"))
+    });
+    match segments.as_slice() {
+        ["update", "fetch"] => method == Method::POST,
+        ["manga" | "anime", _] | ["manga" | "anime", _, "full" | "chapters" | "episodes"] => {
+            method == Method::GET && online_fetch
+        }
+        _ => false,
+    }
+}
+
+pub(crate) async fn forward(state: &AppState, req: Request) -> Response {
+    if state.coalescer.config.window_seconds == 0 || !is_trigger(req.method(), req.uri()) {
+        return app::forward(state, req).await;
+    }
+    let (parts, body) = req.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, MAX_REQUEST).await else {
+        return Response::builder()
+            .status(StatusCode::PAYLOAD_TOO_LARGE)
+            .body(Body::empty())
+            .unwrap();
+    };
+    let target = format!(
+        "{} {}",
+        parts.method,
+        parts
+            .uri
+            .path_and_query()
+            .map(|value| value.as_str())
+            .unwrap_or(parts.uri.path())
+    );
+    let key = format!("{target}\n{}", hex_digest(&body));
+
+    let (id, result, coalesced) = {
+        let mut flights = state.coalescer.lock();
+        flights.retain(|_, flight| state.coalescer.live(flight));
+        match flights.get(&key) {
+            Some(flight) => {
+                flight.callers.fetch_add(1, Ordering::Relaxed);
+                (flight.id.clone(), flight.result.clone(), true)
+            }
+            None => {
+                let id = random_id();
+                let req = Request::from_parts(parts, Body::from(body));
+                let task_state = state.clone();
+                // The call runs on its own task so it finishes even if every caller
+                // hangs up.
+                let task = tokio::spawn(async move {
+                    Snapshot::take(app::forward(&task_state, req).await).await
+                });
+                let result = async move {
+                    task.await.unwrap_or_else(|_| Snapshot {
+                        status: StatusCode::BAD_GATEWAY,
+                        headers: HeaderMap::new(),
+                        body: Bytes::new(),
+                        unreachable: false,
+                    })
+                }
+                .boxed()
+                .shared();
+                flights.insert(
+                    key.clone(),
+                    Flight {
+                        id: id.clone(),
+                        target,
+                        started: Instant::now(),
+                        callers: Arc::new(AtomicUsize::new(1)),
+                        result: result.clone(),
+                    },
+                );
+                (id, result, false)
+            }
+        }
+    };
+    record(state, coalesced);
+    let snapshot = result.await;
+    if !coalesced {
+        state.coalescer.settle(&key, &id, snapshot.status);
+    }
+    snapshot.response(&id, coalesced)
+}
+
+fn record(state: &AppState, coalesced: bool) {
+    if !state.config.metrics.enabled {
+        return;
+    }
+    state.metrics.inc(
+        "manatan_refresh_triggers_total",
+        "Library refresh triggers by whether they joined a running call.",
+        &[("coalesced", if coalesced { "true" } else { "false" })],
+    );
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/admin/refresh-jobs", get(status))
+}
+
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    let coalescer = &state.coalescer;
+    let flights = coalescer.lock();
+    let jobs = flights
+        .values()
+        .filter(|flight| coalescer.live(flight))
+        .map(|flight| {
+            json!({
+                "id": flight.id,
+                "request": flight.target,
+                "age_seconds": flight.started.elapsed().as_secs(),
+                "callers": flight.callers.load(Ordering::Relaxed),
+                "done": flight.done(),
+                "status": flight.result.peek().map(|snapshot| snapshot.status.as_u16()),
+            })
+        })
+        .collect::<Vec<_>>();
+    Json(json!({
+        "window_seconds": coalescer.config.window_seconds,
+        "jobs": jobs,
+    }))
+}
@@ -1,33 +1,1884 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub java_runtime_url: String,
+    pub webview_enabled: bool,
+    pub aidoku_index_url: String,
+    pub aidoku_enabled: bool,
+    pub aidoku_cache_path: String,
+    pub db_path: String,
+    pub migrate_path: Option<String>,
+    pub tracker_remote_search: bool,
+    pub tracker_search_ttl_seconds: i64,
+    pub downloads_path: String,
+    pub local_manga_path: String,
+    pub local_anime_path: String,
+    pub backend_stdout: OutputTarget,
+    pub backend_stderr: OutputTarget,
+    pub runtime: RuntimeConfig,
+    pub backend: BackendConfig,
+    pub proxy_data_path: String,
+    pub instance_name: String,
+    pub external_url: Option<String>,
+    pub base_path: String,
+    pub canonical_redirect: bool,
+    pub webui_path: Option<String>,
+    pub discovery_enabled: bool,
+    pub control: ControlConfig,
+    pub cors: CorsPolicy,
+    pub compression: CompressionConfig,
+    pub watchdog: WatchdogConfig,
+    pub supervisor: SupervisorConfig,
+    pub probes: ProbesConfig,
+    pub content_filter: ContentFilterConfig,
+    pub stats: StatsConfig,
+    pub devices: DeviceProgressConfig,
+    pub relay_sync: RelaySyncConfig,
+    pub share: ShareConfig,
+    pub signed_urls: SignedUrlConfig,
+    pub cassette: CassetteConfig,
+    pub image_cache: ImageCacheConfig,
+    pub page_archive: PageArchiveConfig,
+    pub image_transform: ImageTransformConfig,
+    pub offline_cache: OfflineCacheConfig,
+    pub json_delta: JsonDeltaConfig,
+    pub refresh_coalesce: RefreshCoalesceConfig,
+    pub outbound: OutboundConfig,
+    pub routes: RoutesConfig,
+    pub well_known: WellKnownConfig,
+    pub ssdp: SsdpConfig,
+    pub wol: WolConfig,
+    pub error_pages: ErrorPagesConfig,
+    pub auth: AuthConfig,
+    pub signing: SigningConfig,
+    pub workers: WorkersConfig,
+    pub jobs: JobsConfig,
+    pub metrics: MetricsConfig,
+    pub metrics_push: MetricsPushConfig,
+    pub logging: LoggingConfig,
+    pub sampling: SamplingConfig,
+    pub uploads: UploadsConfig,
+    pub quota: QuotaConfig,
+    pub rate_limit: RateLimitConfig,
+    pub bandwidth: BandwidthConfig,
+    pub transcodes: TranscodeConfig,
+    pub hwaccel: HwAccelConfig,
+    pub skip_markers: SkipMarkersConfig,
+    pub retention: RetentionConfig,
+    pub trash: TrashConfig,
+    pub inbox: InboxConfig,
+    pub integrations: IntegrationsConfig,
+    pub media_servers: MediaServersConfig,
+    pub paths: PathsConfig,
+    // The file `ConfigBuilder::file` read, so tools that change settings can write them back.
+    pub config_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "target", content = "path", rename_all = "lowercase")]
+pub enum OutputTarget {
+    Inherit,
+    Log,
+    File(String),
+}
+
+impl OutputTarget {
+    fn load(vars: &Vars, key: &str) -> Self {
+        match vars.get(key).unwrap_or_default().trim() {
+            "" | "inherit" => Self::Inherit,
+            "log" => Self::Log,
+            path => Self::File(path.to_string()),
+        }
+    }
+}
+
+fn base_path(vars: &Vars) -> String {
+    let Some(value) = vars.non_empty("MANATAN_BASE_PATH") else {
+        return String::new();
+    };
+    let trimmed = value.trim().trim_matches('/');
+    if trimmed
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '?' | '#' | '%'))
+    {
+        vars.invalid(
+            "MANATAN_BASE_PATH",
+            &value,
+            "must be a plain path prefix such as /manatan".to_string(),
+        );
+        return String::new();
+    }
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{trimmed}")
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CorsPolicy {
+    pub allowed_origins: Vec<String>,
+    pub allow_credentials: bool,
+    pub allowed_headers: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub max_age_seconds: Option<u64>,
+}
+
+impl CorsPolicy {
+    fn load(vars: &Vars) -> Self {
+        let allowed_origins = vars
+            .list("MANATAN_CORS_ALLOWED_ORIGINS")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|origin| {
+                let trimmed = origin.trim_end_matches('/');
+                if trimmed == "*" {
+                    return None;
+                }
+                if !(trimmed.starts_with("http://") || trimmed.starts_with("https://")) {
+                    vars.invalid(
+                        "MANATAN_CORS_ALLOWED_ORIGINS",
+                        &origin,
+                        "origins must start with http:// or https://".to_string(),
+                    );
+                    return None;
+                }
+                Some(trimmed.to_string())
+            })
+            .collect();
+        Self {
+            allowed_origins,
+            allow_credentials: vars.bool("MANATAN_CORS_ALLOW_CREDENTIALS", false),
+            allowed_headers: vars
+                .list("MANATAN_CORS_ALLOWED_HEADERS")
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|header| header != "*")
+                .map(|header| header.to_lowercase())
+                .collect(),
+            allowed_methods: vars
+                .list("MANATAN_CORS_ALLOWED_METHODS")
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|method| method != "*")
+                .map(|method| method.to_uppercase())
+                .collect(),
+            max_age_seconds: vars.parse_opt("MANATAN_CORS_MAX_AGE_SECONDS"),
+        }
+    }
+
+    pub fn is_permissive(&self) -> bool {
+        self.allowed_origins.is_empty()
+    }
+}
+
+// Settings the backend takes without a restart; `None` leaves a value alone.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webview_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracker_remote_search: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracker_search_ttl_seconds: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downloads_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_manga_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_anime_path: Option<String>,
+}
+
+// The embedded backend has no auth of its own; only the proxy should face the network.
+#[derive(Clone, Debug)]
+pub struct BackendConfig {
+    pub host: String,
+    pub port: u16,
+    pub socket: Option<String>,
+    pub allow_remote: bool,
+    pub loopback_only: bool,
+}
+
+impl BackendConfig {
+    fn load(vars: &Vars, proxy_port: u16) -> Self {
+        let allow_remote = vars.bool("MANATAN_BACKEND_ALLOW_REMOTE", false);
+        Self {
+            host: vars
+                .non_empty("MANATAN_BACKEND_HOST")
+                .unwrap_or_else(|| "127.0.0.1".to_string()),
+            port: vars.parse("MANATAN_BACKEND_PORT", proxy_port.saturating_add(1)),
+            socket: vars.non_empty("MANATAN_BACKEND_SOCKET"),
+            allow_remote,
+            loopback_only: vars.bool("MANATAN_BACKEND_LOOPBACK_ONLY", !allow_remote),
+        }
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        let host = self.host.trim();
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        let host = host.split('%').next().unwrap_or(host);
+        match host.parse::<std::net::IpAddr>() {
+            Ok(ip) => ip.is_loopback(),
+            Err(_) => {
+                let host = host.trim_end_matches('.').to_ascii_lowercase();
+                host == "localhost" || host.ends_with(".localhost")
+            }
+        }
+    }
+
+    pub fn validate(&self, proxy_port: u16) -> Result<(), ConfigError> {
+        if self.loopback_only && self.allow_remote {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_BACKEND_LOOPBACK_ONLY".to_string(),
+                value: "true".to_string(),
+                reason: "conflicts with MANATAN_BACKEND_ALLOW_REMOTE=true".to_string(),
+            });
+        }
+        if self.socket.is_none() && !self.allow_remote && !self.is_loopback() {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_BACKEND_HOST".to_string(),
+                value: self.host.clone(),
+                reason: "exposes the unauthenticated backend beyond loopback; \
+                         set MANATAN_BACKEND_ALLOW_REMOTE=true to allow it"
+                    .to_string(),
+            });
+        }
+        if self.socket.is_none() && self.port == proxy_port {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_BACKEND_PORT".to_string(),
+                value: self.port.to_string(),
+                reason: "collides with MANATAN_PORT".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RuntimeConfig {
+    pub heap_min_mb: u32,
+    pub heap_max_mb: u32,
+    pub gc: Option<String>,
+    pub jvm_args: Vec<String>,
+    pub worker_threads: u32,
+    pub io_threads: u32,
+}
+
+impl RuntimeConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            heap_min_mb: vars.parse("MANATAN_JAVA_HEAP_MIN_MB", 0),
+            heap_max_mb: vars.parse("MANATAN_JAVA_HEAP_MAX_MB", 0),
+            gc: vars
+                .get("MANATAN_JAVA_GC")
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty()),
+            jvm_args: vars
+                .get("MANATAN_JAVA_OPTS")
+                .map(|value| value.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            worker_threads: vars.parse("MANATAN_BACKEND_WORKER_THREADS", 0),
+            io_threads: vars.parse("MANATAN_BACKEND_IO_THREADS", 0),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WatchdogConfig {
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    pub timeout_seconds: u64,
+    pub failure_threshold: u32,
+    pub endpoints: Vec<String>,
+    pub actions: Vec<RecoveryAction>,
+    pub notify_url: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecoveryAction {
+    Log,
+    Notify,
+    RestartBackend,
+    RestartProcess,
+}
+
+impl RecoveryAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Log => "log",
+            Self::Notify => "notify",
+            Self::RestartBackend => "restart_backend",
+            Self::RestartProcess => "restart_process",
+        }
+    }
+}
+
+impl std::str::FromStr for RecoveryAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().replace('-', "_").as_str() {
+            "log" => Ok(Self::Log),
+            "notify" => Ok(Self::Notify),
+            "restart_backend" => Ok(Self::RestartBackend),
+            "restart_process" => Ok(Self::RestartProcess),
+            other => Err(format!("unknown recovery action: {other}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SupervisorConfig {
+    pub enabled: bool,
+    pub ready_timeout_seconds: u64,
+    pub refusal_threshold: u32,
+}
+
+impl SupervisorConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_SUPERVISOR_ENABLED", true),
+            ready_timeout_seconds: vars.parse("MANATAN_BACKEND_READY_TIMEOUT_SECONDS", 120),
+            refusal_threshold: vars.parse("MANATAN_SUPERVISOR_REFUSAL_THRESHOLD", 5).max(1),
+        }
+    }
+}
+
+// What a health probe checks: `http://` and `https://` URLs must answer below 500,
+// `tcp:host:port` must accept a connection, `path:/mnt/nas` must be a listable directory
+// and `exec:command args..` must exit 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeTarget {
+    Http(String),
+    Tcp(String),
+    Path(String),
+    Exec(Vec<String>),
+}
+
+impl std::str::FromStr for ProbeTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if value.starts_with("http://") || value.starts_with("https://") {
+            return Ok(Self::Http(value.to_string()));
+        }
+        match value.split_once(':') {
+            Some(("tcp", addr)) if !addr.is_empty() => Ok(Self::Tcp(addr.to_string())),
+            Some(("path", path)) if !path.is_empty() => Ok(Self::Path(path.to_string())),
+            Some(("exec", command)) if !command.trim().is_empty() => Ok(Self::Exec(
+                command.split_whitespace().map(str::to_string).collect(),
+            )),
+            _ => Err("expected an http(s) URL, tcp:host:port, path:/dir or exec:command".into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HealthProbe {
+    pub name: String,
+    pub target: ProbeTarget,
+    // Reported, but not held against readiness.
+    pub optional: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProbesConfig {
+    pub probes: Vec<HealthProbe>,
+    pub interval_seconds: u64,
+    pub timeout_seconds: u64,
+}
+
+impl ProbesConfig {
+    fn load(vars: &Vars) -> Self {
+        let optional = vars
+            .list("MANATAN_HEALTH_PROBES_OPTIONAL")
+            .unwrap_or_default();
+        let probes = vars
+            .list("MANATAN_HEALTH_PROBES")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                let Some((name, target)) = entry.split_once('=') else {
+                    vars.invalid(
+                        "MANATAN_HEALTH_PROBES",
+                        &entry,
+                        "expected name=target".to_string(),
+                    );
+                    return None;
+                };
+                let name = name.trim().to_string();
+                match target.parse() {
+                    Ok(target) => Some(HealthProbe {
+                        optional: optional.contains(&name),
+                        name,
+                        target,
+                    }),
+                    Err(err) => {
+                        vars.invalid("MANATAN_HEALTH_PROBES", &entry, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self {
+            probes,
+            interval_seconds: vars
+                .parse("MANATAN_HEALTH_PROBE_INTERVAL_SECONDS", 30)
+                .max(1),
+            timeout_seconds: vars.parse("MANATAN_HEALTH_PROBE_TIMEOUT_SECONDS", 5).max(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkersConfig {
+    pub enabled: bool,
+    pub interactive: usize,
+    pub prefetch: usize,
+    pub thumbnail: usize,
+    pub background: usize,
+    pub priority: Vec<JobClass>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobClass {
+    Interactive,
+    Prefetch,
+    Thumbnail,
+    Background,
+}
+
+impl JobClass {
+    pub const ALL: [JobClass; 4] = [
+        Self::Interactive,
+        Self::Prefetch,
+        Self::Thumbnail,
+        Self::Background,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Interactive => "interactive",
+            Self::Prefetch => "prefetch",
+            Self::Thumbnail => "thumbnail",
+            Self::Background => "background",
+        }
+    }
+}
+
+impl std::str::FromStr for JobClass {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "interactive" => Ok(Self::Interactive),
+            "prefetch" => Ok(Self::Prefetch),
+            "thumbnail" => Ok(Self::Thumbnail),
+            "background" => Ok(Self::Background),
+            other => Err(format!("unknown job class: {other}")),
+        }
+    }
+}
+
+impl WorkersConfig {
+    fn load(vars: &Vars) -> Self {
+        let mut priority = vars
+            .parse_list::<JobClass>("MANATAN_WORKERS_PRIORITY")
+            .unwrap_or_default()
+            .into_iter()
+            .fold(Vec::new(), |mut order, class| {
+                if !order.contains(&class) {
+                    order.push(class);
+                }
+                order
+            });
+        for class in JobClass::ALL {
+            if !priority.contains(&class) {
+                priority.push(class);
+            }
+        }
+
+        Self {
+            enabled: vars.bool("MANATAN_WORKERS_ENABLED", true),
+            interactive: vars.parse("MANATAN_WORKERS_INTERACTIVE", 16).max(1),
+            prefetch: vars.parse("MANATAN_WORKERS_PREFETCH", 4).max(1),
+            thumbnail: vars.parse("MANATAN_WORKERS_THUMBNAIL", 2).max(1),
+            background: vars.parse("MANATAN_WORKERS_BACKGROUND", 2).max(1),
+            priority,
+        }
+    }
+
+    pub fn limit(&self, class: JobClass) -> usize {
+        match class {
+            JobClass::Interactive => self.interactive,
+            JobClass::Prefetch => self.prefetch,
+            JobClass::Thumbnail => self.thumbnail,
+            JobClass::Background => self.background,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct JobsConfig {
+    pub enabled: bool,
+    pub concurrency: usize,
+    pub max_attempts: u32,
+    pub retry_delay_seconds: u64,
+    pub history_days: u64,
+}
+
+impl JobsConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_JOBS_ENABLED", true),
+            concurrency: vars.parse("MANATAN_JOBS_CONCURRENCY", 1).max(1),
+            max_attempts: vars.parse("MANATAN_JOBS_MAX_ATTEMPTS", 3).max(1),
+            retry_delay_seconds: vars.parse("MANATAN_JOBS_RETRY_DELAY_SECONDS", 30),
+            history_days: vars.parse("MANATAN_JOBS_HISTORY_DAYS", 30),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub request_log: bool,
+}
+
+impl MetricsConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            // A build without the `metrics` feature has no exposition to turn on.
+            enabled: cfg!(feature = "metrics") && vars.bool("MANATAN_METRICS_ENABLED", true),
+            request_log: vars.bool("MANATAN_REQUEST_LOG", false),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MetricsPushConfig {
+    pub pushgateway_url: Option<String>,
+    pub remote_write_url: Option<String>,
+    pub interval_seconds: u64,
+    pub job: String,
+    pub bearer_token: Option<String>,
+}
+
+impl MetricsPushConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            pushgateway_url: vars.non_empty("MANATAN_METRICS_PUSHGATEWAY_URL"),
+            remote_write_url: vars.non_empty("MANATAN_METRICS_REMOTE_WRITE_URL"),
+            interval_seconds: vars
+                .parse("MANATAN_METRICS_PUSH_INTERVAL_SECONDS", 60)
+                .max(5),
+            job: vars
+                .non_empty("MANATAN_METRICS_PUSH_JOB")
+                .unwrap_or_else(|| "manatan".to_string()),
+            bearer_token: vars.non_empty("MANATAN_METRICS_PUSH_TOKEN"),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.pushgateway_url.is_some() || self.remote_write_url.is_some()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LoggingConfig {
+    pub target: LogTarget,
+    pub filter: String,
+    pub identifier: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogTarget {
+    Stderr,
+    Journald,
+    EventLog,
+}
+
+impl std::str::FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().replace(['-', '_'], "").as_str() {
+            "stderr" | "console" => Ok(Self::Stderr),
+            "journald" | "journal" => Ok(Self::Journald),
+            "eventlog" | "windowseventlog" => Ok(Self::EventLog),
+            other => Err(format!("unknown log target: {other}")),
+        }
+    }
+}
+
+impl LoggingConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            target: vars.parse("MANATAN_LOG_TARGET", LogTarget::Stderr),
+            filter: vars
+                .non_empty("MANATAN_LOG")
+                .unwrap_or_else(|| "info".to_string()),
+            identifier: vars
+                .non_empty("MANATAN_LOG_IDENTIFIER")
+                .unwrap_or_else(|| "manatan".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SamplingConfig {
+    pub sample_rate: f64,
+    pub always_sample_errors: bool,
+    pub route_rates: Vec<(String, f64)>,
+}
+
+impl SamplingConfig {
+    fn load(vars: &Vars) -> Self {
+        let route_rates = vars
+            .list("MANATAN_TRACE_SAMPLE_ROUTES")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                let Some((route, rate)) = entry.rsplit_once('=') else {
+                    vars.invalid(
+                        "MANATAN_TRACE_SAMPLE_ROUTES",
+                        &entry,
+                        "expected route=rate".to_string(),
+                    );
+                    return None;
+                };
+                match rate.trim().parse::<f64>() {
+                    Ok(rate) => Some((route.trim().to_string(), rate.clamp(0.0, 1.0))),
+                    Err(err) => {
+                        vars.invalid("MANATAN_TRACE_SAMPLE_ROUTES", &entry, err.to_string());
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self {
+            sample_rate: vars
+                .parse("MANATAN_TRACE_SAMPLE_RATE", 1.0_f64)
+                .clamp(0.0, 1.0),
+            always_sample_errors: vars.bool("MANATAN_TRACE_SAMPLE_ERRORS", true),
+            route_rates,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UploadsConfig {
+    pub max_bytes: u64,
+    // How long an unfinished resumable upload is kept after its last chunk.
+    pub resume_expiry_seconds: u64,
+}
+
+impl UploadsConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            max_bytes: vars.parse("MANATAN_UPLOAD_MAX_BYTES", 8 * 1024 * 1024 * 1024),
+            resume_expiry_seconds: vars.parse("MANATAN_UPLOAD_RESUME_EXPIRY_SECONDS", 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ControlConfig {
+    pub enabled: bool,
+    pub path: Option<String>,
+}
+
+impl ControlConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_CONTROL_ENABLED", true),
+            path: vars.non_empty("MANATAN_CONTROL_PATH"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    pub level: CompressionLevel,
+    pub min_bytes: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionLevel {
+    Fastest,
+    Default,
+    Best,
+    Precise(i32),
+}
+
+impl std::str::FromStr for CompressionLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "fastest" => Ok(Self::Fastest),
+            "default" => Ok(Self::Default),
+            "best" => Ok(Self::Best),
+            other => other
+                .parse()
+                .map(Self::Precise)
+                .map_err(|_| format!("unknown compression level: {other}")),
+        }
+    }
+}
+
+impl CompressionConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_COMPRESSION_ENABLED", true),
+            level: vars.parse("MANATAN_COMPRESSION_LEVEL", CompressionLevel::Default),
+            min_bytes: vars.parse("MANATAN_COMPRESSION_MIN_BYTES", 1024),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl std::str::FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim().to_uppercase();
+        let value = value
+            .strip_suffix("IB")
+            .or(value.strip_suffix('B'))
+            .unwrap_or(&value);
+        let (number, unit) = match value.char_indices().last() {
+            Some((index, unit @ ('K' | 'M' | 'G' | 'T'))) => (&value[..index], unit),
+            _ => (value, ' '),
+        };
+        let number = number
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| format!("invalid size: {value}"))?;
+        let scale = match unit {
+            'K' => 1u64 << 10,
+            'M' => 1 << 20,
+            'G' => 1 << 30,
+            'T' => 1 << 40,
+            _ => 1,
+        };
+        if number < 0.0 {
+            return Err(format!("invalid size: {value}"));
+        }
+        Ok(Self((number * scale as f64) as u64))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct QuotaConfig {
+    pub global_bytes: Option<u64>,
+    pub category_bytes: Vec<(String, u64)>,
+    pub auto_prune: bool,
+    pub scan_interval_seconds: u64,
+}
+
+impl QuotaConfig {
+    fn load(vars: &Vars) -> Self {
+        let category_bytes = vars
+            .list("MANATAN_DOWNLOADS_CATEGORY_QUOTAS")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                let Some((category, size)) = entry.rsplit_once('=') else {
+                    vars.invalid(
+                        "MANATAN_DOWNLOADS_CATEGORY_QUOTAS",
+                        &entry,
+                        "expected category=size".to_string(),
+                    );
+                    return None;
+                };
+                match size.parse::<ByteSize>() {
+                    Ok(size) => Some((category.trim().to_lowercase(), size.0)),
+                    Err(err) => {
+                        vars.invalid("MANATAN_DOWNLOADS_CATEGORY_QUOTAS", &entry, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self {
+            global_bytes: vars
+                .parse_opt::<ByteSize>("MANATAN_DOWNLOADS_QUOTA")
+                .map(|size| size.0),
+            category_bytes,
+            auto_prune: vars.bool("MANATAN_DOWNLOADS_QUOTA_AUTO_PRUNE", false),
+            scan_interval_seconds: vars
+                .parse("MANATAN_DOWNLOADS_QUOTA_SCAN_SECONDS", 300)
+                .max(10),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.global_bytes.is_some() || !self.category_bytes.is_empty()
+    }
+
+    pub fn category_limit(&self, category: &str) -> Option<u64> {
+        let category = category.to_lowercase();
+        self.category_bytes
+            .iter()
+            .find(|(name, _)| *name == category)
+            .map(|(_, limit)| *limit)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    pub requests_per_second: Option<f64>,
+    pub burst: u32,
+    pub max_concurrent: Option<usize>,
+    pub max_websockets: Option<usize>,
+    pub trust_forwarded: bool,
+    // Peers whose `X-Forwarded-For` is believed; loopback when none are listed.
+    pub trusted_proxies: Vec<IpRange>,
+    // Proxies in front of the server; the client is that many entries from the right.
+    pub trusted_hops: usize,
+    pub exempt_loopback: bool,
+    pub client_bytes: Option<u64>,
+}
+
+// An address or a CIDR block, `10.0.0.0/8` or `fd00::/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpRange {
+    pub addr: std::net::IpAddr,
+    pub prefix: u8,
+}
+
+impl IpRange {
+    pub fn contains(&self, ip: std::net::IpAddr) -> bool {
+        let (addr, ip, bits) = match (self.addr, ip.to_canonical()) {
+            (std::net::IpAddr::V4(addr), std::net::IpAddr::V4(ip)) => {
+                (u32::from(addr) as u128, u32::from(ip) as u128, 32)
+            }
+            (std::net::IpAddr::V6(addr), std::net::IpAddr::V6(ip)) => {
+                (u128::from(addr), u128::from(ip), 128)
+            }
+            _ => return false,
+        };
+        let shift = bits - u32::from(self.prefix);
+        shift >= bits || addr >> shift == ip >> shift
+    }
+}
+
+impl std::str::FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match value.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let addr = addr
+            .parse::<std::net::IpAddr>()
+            .map_err(|_| format!("invalid address: {addr}"))?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| format!("invalid prefix length: {prefix}"))?,
+            None => bits,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl RateLimitConfig {
+    fn load(vars: &Vars) -> Self {
+        let requests_per_second = vars.parse_opt::<f64>("MANATAN_RATE_LIMIT_RPS");
+        let default_burst = requests_per_second.map_or(1.0, |rps| (rps * 2.0).ceil()) as u32;
+        Self {
+            requests_per_second,
+            burst: vars.parse("MANATAN_RATE_LIMIT_BURST", default_burst).max(1),
+            max_concurrent: vars.parse_opt("MANATAN_MAX_CONCURRENT_REQUESTS"),
+            max_websockets: vars.parse_opt("MANATAN_MAX_WEBSOCKETS"),
+            trust_forwarded: vars.bool("MANATAN_RATE_LIMIT_TRUST_FORWARDED", false),
+            trusted_proxies: vars
+                .parse_list("MANATAN_RATE_LIMIT_TRUSTED_PROXIES")
+                .filter(|proxies: &Vec<IpRange>| !proxies.is_empty())
+                .unwrap_or_else(|| {
+                    ["127.0.0.0/8", "::1/128"]
+                        .iter()
+                        .filter_map(|range| range.parse().ok())
+                        .collect()
+                }),
+            trusted_hops: vars.parse("MANATAN_RATE_LIMIT_TRUSTED_HOPS", 1).max(1),
+            exempt_loopback: vars.bool("MANATAN_RATE_LIMIT_EXEMPT_LOOPBACK", true),
+            client_bytes: vars
+                .parse_opt::<ByteSize>("MANATAN_RATE_LIMIT_CLIENT_BYTES")
+                .map(|size| size.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BandwidthConfig {
+    pub enabled: bool,
+    pub window_seconds: u64,
+}
+
+impl BandwidthConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_BANDWIDTH_ENABLED", true),
+            window_seconds: vars
+                .parse("MANATAN_BANDWIDTH_WINDOW_SECONDS", 86_400)
+                .max(60),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TranscodeConfig {
+    // 0 lets any number of episodes stream at once.
+    pub max_concurrent: usize,
+    pub idle_seconds: u64,
+}
+
+impl TranscodeConfig {
+    fn load(vars: &Vars) -> Self {
+        let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+        Self {
+            max_concurrent: vars.parse("MANATAN_MAX_TRANSCODES", cpus.div_ceil(2)),
+            idle_seconds: vars.parse("MANATAN_TRANSCODE_IDLE_SECONDS", 60).max(5),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HwAccel {
+    #[serde(rename = "none")]
+    Software,
+    Vaapi,
+    Nvenc,
+    VideoToolbox,
+    Qsv,
+}
+
+impl std::str::FromStr for HwAccel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().replace(['-', '_'], "").as_str() {
+            "none" | "off" | "software" => Ok(Self::Software),
+            "vaapi" => Ok(Self::Vaapi),
+            "nvenc" | "cuda" | "nvidia" => Ok(Self::Nvenc),
+            "videotoolbox" | "vt" => Ok(Self::VideoToolbox),
+            "qsv" | "quicksync" => Ok(Self::Qsv),
+            other => Err(format!("unknown hardware acceleration: {other}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HwAccelConfig {
+    // None picks the best backend found at startup.
+    pub backend: Option<HwAccel>,
+    pub device: Option<String>,
+}
+
+impl HwAccelConfig {
+    fn load(vars: &Vars) -> Self {
+        let backend = match vars.non_empty("MANATAN_HWACCEL") {
+            Some(value) if value.trim().eq_ignore_ascii_case("auto") => None,
+            _ => vars.parse_opt("MANATAN_HWACCEL"),
+        };
+        Self {
+            backend,
+            device: vars.non_empty("MANATAN_HWACCEL_DEVICE"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SkipMarkersConfig {
+    pub ffmpeg_path: String,
+    pub intro_window_seconds: u32,
+    pub outro_window_seconds: u32,
+    pub min_seconds: u32,
+}
+
+impl SkipMarkersConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            ffmpeg_path: vars
+                .non_empty("MANATAN_FFMPEG_PATH")
+                .unwrap_or_else(|| "ffmpeg".to_string()),
+            intro_window_seconds: vars.parse("MANATAN_SKIP_INTRO_WINDOW_SECONDS", 600).max(30),
+            outro_window_seconds: vars.parse("MANATAN_SKIP_OUTRO_WINDOW_SECONDS", 300).max(30),
+            min_seconds: vars.parse("MANATAN_SKIP_MIN_SECONDS", 15).max(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RetentionConfig {
+    pub enabled: bool,
+    pub read_days: Option<u64>,
+    pub max_unread_per_series: Option<usize>,
+    pub categories: Vec<String>,
+    pub interval_seconds: u64,
+}
+
+impl RetentionConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_RETENTION_ENABLED", false),
+            read_days: vars.parse_opt("MANATAN_RETENTION_READ_DAYS"),
+            max_unread_per_series: vars.parse_opt("MANATAN_RETENTION_MAX_UNREAD"),
+            categories: vars
+                .list("MANATAN_RETENTION_CATEGORIES")
+                .unwrap_or_default(),
+            interval_seconds: vars
+                .parse("MANATAN_RETENTION_INTERVAL_SECONDS", 86_400)
+                .max(60),
+        }
+    }
+
+    pub fn has_rules(&self) -> bool {
+        self.read_days.is_some() || self.max_unread_per_series.is_some()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PathsConfig {
+    pub collapse_slashes: bool,
+    pub remove_dot_segments: bool,
+    pub trailing_slash: TrailingSlash,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailingSlash {
+    Keep,
+    Strip,
+    Redirect,
+}
+
+impl std::str::FromStr for TrailingSlash {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "keep" => Ok(Self::Keep),
+            "strip" => Ok(Self::Strip),
+            "redirect" => Ok(Self::Redirect),
+            other => Err(format!("unknown trailing slash policy: {other}")),
+        }
+    }
+}
+
+impl PathsConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            collapse_slashes: vars.bool("MANATAN_PATH_COLLAPSE_SLASHES", true),
+            remove_dot_segments: vars.bool("MANATAN_PATH_REMOVE_DOT_SEGMENTS", true),
+            trailing_slash: vars.parse("MANATAN_PATH_TRAILING_SLASH", TrailingSlash::Keep),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AuthConfig {
+    pub tokens_enabled: bool,
+    pub token: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub exempt_health: bool,
+}
+
+impl AuthConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            tokens_enabled: vars.bool("MANATAN_AUTH_TOKENS", false),
+            token: vars
+                .non_empty("MANATAN_AUTH_TOKEN")
+                .or(vars.non_empty("MANATAN_AUTH_ADMIN_TOKEN")),
+            user: vars.non_empty("MANATAN_AUTH_USER"),
+            password: vars.non_empty("MANATAN_AUTH_PASSWORD"),
+            exempt_health: vars.bool("MANATAN_AUTH_EXEMPT_HEALTH", false),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tokens_enabled || self.token.is_some() || self.basic_enabled()
+    }
+
+    pub fn basic_enabled(&self) -> bool {
+        self.user.is_some() && self.password.is_some()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SigningConfig {
+    pub secret: Option<String>,
+    pub routes: Vec<String>,
+    pub max_skew_seconds: u64,
+}
+
+impl SigningConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            secret: vars.non_empty("MANATAN_SIGNING_SECRET"),
+            routes: vars.list("MANATAN_SIGNING_ROUTES").unwrap_or_default(),
+            max_skew_seconds: vars.parse("MANATAN_SIGNING_MAX_SKEW_SECONDS", 300),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.secret.is_some() && !self.routes.is_empty()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ErrorPagesConfig {
+    pub enabled: bool,
+    pub template_path: Option<String>,
+}
+
+impl ErrorPagesConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_ERROR_PAGES_ENABLED", true),
+            template_path: vars.non_empty("MANATAN_ERROR_PAGE_TEMPLATE"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WellKnownConfig {
+    pub favicon_path: Option<String>,
+    pub robots_txt_path: Option<String>,
+    pub security_txt_path: Option<String>,
+    pub security_contact: Option<String>,
+}
+
+impl WellKnownConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            favicon_path: vars.non_empty("MANATAN_FAVICON_PATH"),
+            robots_txt_path: vars.non_empty("MANATAN_ROBOTS_TXT_PATH"),
+            security_txt_path: vars.non_empty("MANATAN_SECURITY_TXT_PATH"),
+            security_contact: vars.non_empty("MANATAN_SECURITY_CONTACT"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SsdpConfig {
+    pub enabled: bool,
+    pub max_age_seconds: u64,
+    pub ttl: u32,
+}
+
+impl SsdpConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_SSDP_ENABLED", false),
+            max_age_seconds: vars.parse("MANATAN_SSDP_MAX_AGE_SECONDS", 1800).max(60),
+            ttl: vars.parse("MANATAN_SSDP_TTL", 2).clamp(1, 255),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl std::str::FromStr for MacAddress {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let digits = value
+            .trim()
+            .chars()
+            .filter(|c| !matches!(c, ':' | '-' | '.'))
+            .collect::<String>();
+        if digits.len() != 12 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid mac address: {value}"));
+        }
+        let mut bytes = [0u8; 6];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16)
+                .map_err(|_| format!("invalid mac address: {value}"))?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl std::fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WolConfig {
+    pub targets: Vec<(String, MacAddress)>,
+    pub broadcast: String,
+    pub port: u16,
+}
+
+impl WolConfig {
+    fn load(vars: &Vars) -> Self {
+        let targets = vars
+            .list("MANATAN_WOL_TARGETS")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                let (name, mac) = entry.split_once('=').unwrap_or((&entry, &entry));
+                match mac.parse::<MacAddress>() {
+                    Ok(mac) => Some((name.trim().to_string(), mac)),
+                    Err(err) => {
+                        vars.invalid("MANATAN_WOL_TARGETS", &entry, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self {
+            targets,
+            broadcast: vars
+                .non_empty("MANATAN_WOL_BROADCAST")
+                .unwrap_or_else(|| "255.255.255.255".to_string()),
+            port: vars.parse("MANATAN_WOL_PORT", 9),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OutboundConfig {
+    pub default_profile: Option<String>,
+    pub domain_profiles: Vec<(String, String)>,
+    pub profiles_file: Option<String>,
+    pub bind_address: Option<std::net::IpAddr>,
+    pub interface: Option<String>,
+    pub ip_family: IpFamily,
+    pub log: bool,
+    pub log_entries: usize,
+}
+
+// Which address family outbound connections try first when a host has both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+    Any,
+    Ipv4,
+    Ipv6,
+}
+
+impl std::str::FromStr for IpFamily {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "any" | "auto" => Ok(Self::Any),
+            "ipv4" | "v4" | "4" => Ok(Self::Ipv4),
+            "ipv6" | "v6" | "6" => Ok(Self::Ipv6),
+            other => Err(format!("unknown ip family: {other}")),
+        }
+    }
+}
+
+impl OutboundConfig {
+    fn load(vars: &Vars) -> Self {
+        let domain_profiles = vars
+            .list("MANATAN_OUTBOUND_PROFILES")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                let Some((domain, profile)) = entry.split_once('=') else {
+                    vars.invalid(
+                        "MANATAN_OUTBOUND_PROFILES",
+                        &entry,
+                        "expected domain=profile".to_string(),
+                    );
+                    return None;
+                };
+                Some((
+                    domain.trim().trim_start_matches("*.").to_lowercase(),
+                    profile.trim().to_string(),
+                ))
+            })
+            .collect();
+        Self {
+            default_profile: vars.non_empty("MANATAN_OUTBOUND_DEFAULT_PROFILE"),
+            domain_profiles,
+            profiles_file: vars.non_empty("MANATAN_OUTBOUND_PROFILES_FILE"),
+            bind_address: vars.parse_opt("MANATAN_OUTBOUND_BIND_ADDRESS"),
+            interface: vars.non_empty("MANATAN_OUTBOUND_INTERFACE"),
+            ip_family: vars.parse("MANATAN_OUTBOUND_IP_FAMILY", IpFamily::Any),
+            log: vars.bool("MANATAN_OUTBOUND_LOG", false),
+            log_entries: vars.parse("MANATAN_OUTBOUND_LOG_ENTRIES", 500),
+        }
+    }
+}
+
+// How the proxy calls the backend for one class of route. A zero timeout means none;
+// retries only apply to GET and HEAD and follow connection failures, timeouts and
+// 502/503/504 answers.
+#[derive(Clone, Debug)]
+pub struct RoutePolicy {
+    pub timeout_seconds: u64,
+    pub retries: u32,
+}
+
+impl RoutePolicy {
+    fn load(vars: &Vars, class: &str, timeout_seconds: u64, retries: u32) -> Self {
+        Self {
+            timeout_seconds: vars
+                .parse(&format!("MANATAN_{class}_TIMEOUT_SECONDS"), timeout_seconds),
+            retries: vars.parse(&format!("MANATAN_{class}_RETRIES"), retries),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RoutesConfig {
+    pub api: RoutePolicy,
+    pub docs: RoutePolicy,
+    pub icons: RoutePolicy,
+    pub icon_cache_seconds: u64,
+    pub redirects: RedirectPolicy,
+}
+
+// What the proxy does with a 3xx from the backend. Either way a `Location` pointing at
+// the backend's own address is rewritten to a path on the proxy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    // GET and HEAD redirects back into the backend are followed before answering.
+    Follow,
+    Pass,
+}
+
+impl std::str::FromStr for RedirectPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "follow" => Ok(Self::Follow),
+            "pass" => Ok(Self::Pass),
+            other => Err(format!("unknown redirect policy: {other}")),
+        }
+    }
+}
+
+impl RoutesConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            api: RoutePolicy::load(vars, "API", 0, 0),
+            docs: RoutePolicy::load(vars, "DOCS", 20, 2),
+            icons: RoutePolicy::load(vars, "ICON", 5, 0),
+            icon_cache_seconds: vars.parse("MANATAN_ICON_CACHE_SECONDS", 86400),
+            redirects: vars.parse("MANATAN_BACKEND_REDIRECTS", RedirectPolicy::Follow),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CassetteConfig {
+    pub mode: CassetteMode,
+    pub path: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CassetteMode {
+    Off,
+    Record,
+    Replay,
+}
+
+impl std::str::FromStr for CassetteMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            other => Err(format!("unknown cassette mode: {other}")),
+        }
+    }
+}
+
+impl CassetteConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            mode: vars.parse("MANATAN_CASSETTE_MODE", CassetteMode::Off),
+            path: vars.non_empty("MANATAN_CASSETTE_PATH"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheBackend {
+    Disk,
+    Memory,
+    Redis,
+}
+
+impl std::str::FromStr for CacheBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "disk" => Ok(Self::Disk),
+            "memory" => Ok(Self::Memory),
+            "redis" => Ok(Self::Redis),
+            other => Err(format!("unknown cache backend: {other}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageCacheConfig {
+    pub enabled: bool,
+    pub backend: CacheBackend,
+    pub path: Option<String>,
+    // `redis://[user:password@]host[:port][/db]`, for the `redis` backend.
+    pub redis_url: Option<String>,
+    pub max_bytes: u64,
+    pub ttl_seconds: u64,
+}
+
+impl ImageCacheConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: cfg!(feature = "cache") && vars.bool("MANATAN_IMAGE_CACHE_ENABLED", true),
+            backend: vars.parse("MANATAN_IMAGE_CACHE_BACKEND", CacheBackend::Disk),
+            path: vars.non_empty("MANATAN_IMAGE_CACHE_PATH"),
+            redis_url: vars.non_empty("MANATAN_IMAGE_CACHE_REDIS_URL"),
+            max_bytes: vars
+                .parse("MANATAN_IMAGE_CACHE_SIZE", ByteSize(512 << 20))
+                .0,
+            ttl_seconds: vars.parse("MANATAN_IMAGE_CACHE_TTL_SECONDS", 7 * 24 * 3600),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PageArchiveConfig {
+    pub enabled: bool,
+    pub path: Option<String>,
+    // 0 lets the archive grow without limit.
+    pub max_bytes: u64,
+    pub refresh_seconds: u64,
+}
+
+impl PageArchiveConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_PAGE_ARCHIVE_ENABLED", false),
+            path: vars.non_empty("MANATAN_PAGE_ARCHIVE_PATH"),
+            max_bytes: vars.parse("MANATAN_PAGE_ARCHIVE_SIZE", ByteSize(0)).0,
+            refresh_seconds: vars
+                .parse("MANATAN_PAGE_ARCHIVE_REFRESH_SECONDS", 600)
+                .max(30),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    Hardlink,
+    Copy,
+    Symlink,
+}
+
+impl std::str::FromStr for LinkMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "hardlink" => Ok(Self::Hardlink),
+            "copy" => Ok(Self::Copy),
+            "symlink" => Ok(Self::Symlink),
+            other => Err(format!("unknown link mode: {other}")),
+        }
+    }
+}
+
+// Webhook receivers for *arr tools. `path_map` rewrites the paths they report
+// (`/tv=/mnt/media/tv`) when they see the disk under another mount.
+#[derive(Clone, Debug)]
+pub struct IntegrationsConfig {
+    pub enabled: bool,
+    pub link_mode: LinkMode,
+    pub path_map: Vec<(String, String)>,
+}
+
+impl IntegrationsConfig {
+    fn load(vars: &Vars) -> Self {
+        let path_map = vars
+            .list("MANATAN_INTEGRATIONS_PATH_MAP")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                let Some((from, to)) = entry.split_once('=') else {
+                    vars.invalid(
+                        "MANATAN_INTEGRATIONS_PATH_MAP",
+                        &entry,
+                        "expected from=to".to_string(),
+                    );
+                    return None;
+                };
+                Some((from.trim().to_string(), to.trim().to_string()))
+            })
+            .collect();
+        Self {
+            enabled: vars.bool("MANATAN_INTEGRATIONS_ENABLED", false),
+            link_mode: vars.parse("MANATAN_INTEGRATIONS_LINK_MODE", LinkMode::Hardlink),
+            path_map,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MediaServersConfig {
+    pub jellyfin_url: Option<String>,
+    pub jellyfin_api_key: Option<String>,
+    pub plex_url: Option<String>,
+    pub plex_token: Option<String>,
+    pub plex_sections: Vec<String>,
+    pub delay_seconds: u64,
+}
+
+impl MediaServersConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            jellyfin_url: vars.non_empty("MANATAN_JELLYFIN_URL"),
+            jellyfin_api_key: vars.non_empty("MANATAN_JELLYFIN_API_KEY"),
+            plex_url: vars.non_empty("MANATAN_PLEX_URL"),
+            plex_token: vars.non_empty("MANATAN_PLEX_TOKEN"),
+            plex_sections: vars.list("MANATAN_PLEX_SECTIONS").unwrap_or_default(),
+            delay_seconds: vars.parse("MANATAN_MEDIA_SERVER_DELAY_SECONDS", 30),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.jellyfin_url.is_some() || self.plex_url.is_some()
+    }
+}
+
 #[derive(Clone, Debug)]
-pub struct Config {
-    pub host: String,
-    pub port: u16,
-    pub java_runtime_url: String,
-    pub webview_enabled: bool,
-    pub aidoku_index_url: String,
-    pub aidoku_enabled: bool,
-    pub aidoku_cache_path: String,
-    pub db_path: String,
-    pub migrate_path: Option<String>,
-    pub tracker_remote_search: bool,
-    pub tracker_search_ttl_seconds: i64,
-    pub downloads_path: String,
-    pub local_manga_path: String,
-    pub local_anime_path: String,
+pub struct InboxConfig {
+    pub path: Option<String>,
+    pub interval_seconds: u64,
+}
+
+impl InboxConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            path: vars.non_empty("MANATAN_INBOX_PATH"),
+            interval_seconds: vars.parse("MANATAN_INBOX_INTERVAL_SECONDS", 30).max(5),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrashConfig {
+    pub enabled: bool,
+    pub path: Option<String>,
+    pub retention_days: u64,
+}
+
+impl TrashConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_TRASH_ENABLED", true),
+            path: vars.non_empty("MANATAN_TRASH_PATH"),
+            retention_days: vars.parse("MANATAN_TRASH_RETENTION_DAYS", 7),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageTransformConfig {
+    pub enabled: bool,
+    pub max_width: u32,
+    pub quality: u8,
+    pub concurrency: usize,
+    pub profiles_file: Option<String>,
+}
+
+impl ImageTransformConfig {
+    fn load(vars: &Vars) -> Self {
+        let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+        Self {
+            enabled: vars.bool("MANATAN_IMAGE_TRANSFORM_ENABLED", true),
+            max_width: vars.parse("MANATAN_IMAGE_TRANSFORM_MAX_WIDTH", 4096),
+            quality: vars
+                .parse::<u8>("MANATAN_IMAGE_TRANSFORM_QUALITY", 80)
+                .clamp(1, 100),
+            concurrency: vars
+                .parse("MANATAN_IMAGE_TRANSFORM_CONCURRENCY", cpus.div_ceil(2))
+                .max(1),
+            profiles_file: vars.non_empty("MANATAN_IMAGE_PROFILES_FILE"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OfflineCacheConfig {
+    pub enabled: bool,
+    pub max_bytes: u64,
+    pub max_stale_seconds: u64,
+    pub paths: Vec<String>,
+}
+
+impl OfflineCacheConfig {
+    fn load(vars: &Vars) -> Self {
+        let paths = vars.list("MANATAN_OFFLINE_CACHE_PATHS").unwrap_or_else(|| {
+            [
+                "/api/v1/category",
+                "/api/v1/manga/",
+                "/api/v1/source/list",
+                "/api/v1/extension/list",
+                "/api/v1/update/recentChapters/",
+                "/api/v1/settings/about",
+            ]
+            .map(str::to_string)
+            .to_vec()
+        });
+        Self {
+            enabled: vars.bool("MANATAN_OFFLINE_CACHE_ENABLED", false),
+            max_bytes: vars
+                .parse("MANATAN_OFFLINE_CACHE_SIZE", ByteSize(64 << 20))
+                .0,
+            max_stale_seconds: vars.parse("MANATAN_OFFLINE_CACHE_MAX_STALE_SECONDS", 86_400),
+            paths,
+        }
+    }
+}
+
+// 0 sends every refresh trigger to the backend.
+#[derive(Clone, Debug)]
+pub struct RefreshCoalesceConfig {
+    pub window_seconds: u64,
+}
+
+impl RefreshCoalesceConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            window_seconds: vars.parse("MANATAN_REFRESH_COALESCE_SECONDS", 10),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct JsonDeltaConfig {
+    pub enabled: bool,
+    pub max_bytes: u64,
+    pub paths: Vec<String>,
+}
+
+impl JsonDeltaConfig {
+    fn load(vars: &Vars) -> Self {
+        let paths = vars.list("MANATAN_JSON_DELTA_PATHS").unwrap_or_else(|| {
+            [
+                "/api/v1/category",
+                "/api/v1/manga/",
+                "/api/v1/update/recentChapters/",
+            ]
+            .map(str::to_string)
+            .to_vec()
+        });
+        Self {
+            enabled: vars.bool("MANATAN_JSON_DELTA_ENABLED", false),
+            max_bytes: vars.parse("MANATAN_JSON_DELTA_SIZE", ByteSize(64 << 20)).0,
+            paths,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ShareConfig {
+    pub enabled: bool,
+    pub default_ttl_seconds: u64,
+    pub max_ttl_seconds: u64,
+}
+
+impl ShareConfig {
+    fn load(vars: &Vars) -> Self {
+        let max_ttl_seconds = vars
+            .parse("MANATAN_SHARE_MAX_TTL_SECONDS", 30 * 86_400)
+            .max(60);
+        Self {
+            enabled: vars.bool("MANATAN_SHARE_ENABLED", true),
+            default_ttl_seconds: vars
+                .parse("MANATAN_SHARE_DEFAULT_TTL_SECONDS", 7 * 86_400)
+                .clamp(60, max_ttl_seconds),
+            max_ttl_seconds,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SignedUrlConfig {
+    pub enabled: bool,
+    pub default_ttl_seconds: u64,
+    pub max_ttl_seconds: u64,
+}
+
+impl SignedUrlConfig {
+    fn load(vars: &Vars) -> Self {
+        let max_ttl_seconds = vars
+            .parse("MANATAN_SIGNED_URL_MAX_TTL_SECONDS", 7 * 86_400)
+            .max(60);
+        Self {
+            enabled: vars.bool("MANATAN_SIGNED_URLS_ENABLED", true),
+            default_ttl_seconds: vars
+                .parse("MANATAN_SIGNED_URL_TTL_SECONDS", 6 * 60 * 60)
+                .clamp(60, max_ttl_seconds),
+            max_ttl_seconds,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StatsConfig {
+    pub enabled: bool,
+    pub session_gap_seconds: u64,
+}
+
+impl StatsConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_STATS_ENABLED", true),
+            session_gap_seconds: vars.parse("MANATAN_STATS_SESSION_GAP_SECONDS", 300).max(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceProgressConfig {
+    pub enabled: bool,
+}
+
+impl DeviceProgressConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            enabled: vars.bool("MANATAN_DEVICE_PROGRESS_ENABLED", false),
+        }
+    }
+}
+
+// Syncing runs when both `url` and `phrase` are set; `serve` makes this instance a relay
+// for others, whether or not it syncs itself.
+#[derive(Clone, Debug)]
+pub struct RelaySyncConfig {
+    pub url: Option<String>,
+    pub phrase: Option<String>,
+    pub interval_seconds: u64,
+    pub serve: bool,
+}
+
+impl RelaySyncConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            url: vars
+                .non_empty("MANATAN_RELAY_URL")
+                .map(|url| url.trim_end_matches('/').to_string()),
+            phrase: vars.non_empty("MANATAN_RELAY_PHRASE"),
+            interval_seconds: vars.parse("MANATAN_RELAY_INTERVAL_SECONDS", 300).max(10),
+            serve: vars.bool("MANATAN_RELAY_SERVE", false),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.url.is_some() && self.phrase.is_some()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ContentFilterConfig {
+    pub safe_mode: bool,
+    pub tokens: Vec<String>,
+    pub users: Vec<String>,
+    pub nsfw_tags: Vec<String>,
+}
+
+impl ContentFilterConfig {
+    fn load(vars: &Vars) -> Self {
+        Self {
+            safe_mode: vars.bool("MANATAN_SAFE_MODE", false),
+            tokens: vars.list("MANATAN_SAFE_MODE_TOKENS").unwrap_or_default(),
+            users: vars.list("MANATAN_SAFE_MODE_USERS").unwrap_or_default(),
+            nsfw_tags: vars
+                .list("MANATAN_SAFE_MODE_TAGS")
+                .unwrap_or_else(|| {
+                    ["nsfw", "hentai", "adult", "smut", "pornographic", "erotica"]
+                        .iter()
+                        .map(|tag| tag.to_string())
+                        .collect()
+                })
+                .into_iter()
+                .map(|tag| tag.to_lowercase())
+                .collect(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.safe_mode || !self.tokens.is_empty() || !self.users.is_empty()
+    }
+}
+
+impl WatchdogConfig {
+    fn load(vars: &Vars) -> Self {
+        let endpoints = vars
+            .list("MANATAN_WATCHDOG_ENDPOINTS")
+            .unwrap_or_else(|| vec!["/health".to_string()]);
+        let actions = vars
+            .parse_list::<RecoveryAction>("MANATAN_WATCHDOG_ACTIONS")
+            .filter(|actions| !actions.is_empty())
+            .unwrap_or_else(|| vec![RecoveryAction::Log, RecoveryAction::RestartBackend]);
+
+        Self {
+            enabled: vars.bool("MANATAN_WATCHDOG_ENABLED", true),
+            interval_seconds: vars.parse("MANATAN_WATCHDOG_INTERVAL_SECONDS", 30).max(1),
+            timeout_seconds: vars.parse("MANATAN_WATCHDOG_TIMEOUT_SECONDS", 5).max(1),
+            failure_threshold: vars.parse("MANATAN_WATCHDOG_FAILURE_THRESHOLD", 3).max(1),
+            endpoints,
+            actions,
+            notify_url: vars.non_empty("MANATAN_WATCHDOG_NOTIFY_URL"),
+        }
+    }
 }
 
 impl Config {
-    pub fn from_env() -> Self {
-        let host = std::env::var("MANATAN_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-        let port = std::env::var("MANATAN_PORT")
-            .ok()
-            .and_then(|v| v.parse::<u16>().ok())
-            .unwrap_or(4568);
-        let java_runtime_url = std::env::var("MANATAN_JAVA_URL")
-            .unwrap_or_else(|_| "http://127.0.0.1:4566".to_string());
-        let webview_enabled = env_bool("MANATAN_WEBVIEW_ENABLED", false);
-        let db_path =
-            std::env::var("MANATAN_DB_PATH").unwrap_or_else(|_| "manatan.sqlite".to_string());
+    fn load(vars: &Vars) -> Self {
+        let host = vars
+            .get("MANATAN_HOST")
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let port = vars.parse("MANATAN_PORT", 4568);
+        let java_runtime_url = vars
+            .get("MANATAN_JAVA_URL")
+            .unwrap_or_else(|| "http://127.0.0.1:4566".to_string());
+        let webview_enabled = vars.bool("MANATAN_WEBVIEW_ENABLED", false);
+        let db_path = vars
+            .get("MANATAN_DB_PATH")
+            .unwrap_or_else(|| "manatan.sqlite".to_string());
         let db_parent = std::path::PathBuf::from(&db_path)
             .parent()
             .map(|path| {
@@ -38,26 +1889,32 @@ impl Config {
                 }
             })
             .unwrap_or_else(|| std::path::PathBuf::from("."));
-        let aidoku_index_url = std::env::var("MANATAN_AIDOKU_INDEX").unwrap_or_default();
-        let aidoku_enabled = env_bool("MANATAN_AIDOKU_ENABLED", true);
-        let migrate_path = std::env::var("MANATAN_MIGRATE_PATH").ok();
-        let tracker_remote_search = env_bool("MANATAN_TRACKER_REMOTE_SEARCH", true);
-        let tracker_search_ttl_seconds = std::env::var("MANATAN_TRACKER_SEARCH_TTL_SECONDS")
-            .ok()
-            .and_then(|v| v.parse::<i64>().ok())
-            .unwrap_or(3600);
-        let downloads_path = std::env::var("MANATAN_DOWNLOADS_PATH")
-            .unwrap_or_else(|_| db_parent.join("downloads").to_string_lossy().to_string());
-        let local_manga_path = std::env::var("MANATAN_LOCAL_MANGA_PATH")
-            .unwrap_or_else(|_| db_parent.join("local-manga").to_string_lossy().to_string());
-        let local_anime_path = std::env::var("MANATAN_LOCAL_ANIME_PATH")
-            .unwrap_or_else(|_| db_parent.join("local-anime").to_string_lossy().to_string());
-        let aidoku_cache_path = std::env::var("MANATAN_AIDOKU_CACHE")
-            .unwrap_or_else(|_| db_parent.join("aidoku").to_string_lossy().to_string());
+        let aidoku_index_url = vars.get("MANATAN_AIDOKU_INDEX").unwrap_or_default();
+        let aidoku_enabled = vars.bool("MANATAN_AIDOKU_ENABLED", true);
+        let migrate_path = vars.get("MANATAN_MIGRATE_PATH");
+        let tracker_remote_search = vars.bool("MANATAN_TRACKER_REMOTE_SEARCH", true);
+        let tracker_search_ttl_seconds = vars.parse("MANATAN_TRACKER_SEARCH_TTL_SECONDS", 3600);
+        let downloads_path = vars
+            .get("MANATAN_DOWNLOADS_PATH")
+            .unwrap_or_else(|| db_parent.join("downloads").to_string_lossy().to_string());
+        let local_manga_path = vars
+            .get("MANATAN_LOCAL_MANGA_PATH")
+            .unwrap_or_else(|| db_parent.join("local-manga").to_string_lossy().to_string());
+        let local_anime_path = vars
+            .get("MANATAN_LOCAL_ANIME_PATH")
+            .unwrap_or_else(|| db_parent.join("local-anime").to_string_lossy().to_string());
+        let aidoku_cache_path = vars
+            .get("MANATAN_AIDOKU_CACHE")
+            .unwrap_or_else(|| db_parent.join("aidoku").to_string_lossy().to_string());
+        let proxy_data_path = vars
+            .get("MANATAN_PROXY_DATA_PATH")
+            .unwrap_or_else(|| db_parent.join("proxy").to_string_lossy().to_string());
 
         Self {
             host,
             port,
+            tls_cert_path: vars.non_empty("MANATAN_TLS_CERT_PATH"),
+            tls_key_path: vars.non_empty("MANATAN_TLS_KEY_PATH"),
             java_runtime_url,
             webview_enabled,
             aidoku_index_url,
@@ -70,21 +1927,661 @@ impl Config {
             downloads_path,
             local_manga_path,
             local_anime_path,
+            backend_stdout: OutputTarget::load(vars, "MANATAN_BACKEND_STDOUT"),
+            backend_stderr: OutputTarget::load(vars, "MANATAN_BACKEND_STDERR"),
+            runtime: RuntimeConfig::load(vars),
+            backend: BackendConfig::load(vars, port),
+            proxy_data_path,
+            instance_name: vars
+                .non_empty("MANATAN_INSTANCE_NAME")
+                .unwrap_or_else(|| "Manatan".to_string()),
+            external_url: vars
+                .get("MANATAN_EXTERNAL_URL")
+                .map(|value| value.trim().trim_end_matches('/').to_string())
+                .filter(|value| !value.is_empty()),
+            base_path: base_path(vars),
+            canonical_redirect: vars.bool("MANATAN_CANONICAL_REDIRECT", false),
+            webui_path: vars.non_empty("MANATAN_WEBUI_PATH"),
+            discovery_enabled: vars.bool("MANATAN_DISCOVERY_ENABLED", false),
+            control: ControlConfig::load(vars),
+            cors: CorsPolicy::load(vars),
+            compression: CompressionConfig::load(vars),
+            watchdog: WatchdogConfig::load(vars),
+            supervisor: SupervisorConfig::load(vars),
+            probes: ProbesConfig::load(vars),
+            content_filter: ContentFilterConfig::load(vars),
+            stats: StatsConfig::load(vars),
+            devices: DeviceProgressConfig::load(vars),
+            relay_sync: RelaySyncConfig::load(vars),
+            share: ShareConfig::load(vars),
+            signed_urls: SignedUrlConfig::load(vars),
+            cassette: CassetteConfig::load(vars),
+            image_cache: ImageCacheConfig::load(vars),
+            page_archive: PageArchiveConfig::load(vars),
+            image_transform: ImageTransformConfig::load(vars),
+            offline_cache: OfflineCacheConfig::load(vars),
+            json_delta: JsonDeltaConfig::load(vars),
+            refresh_coalesce: RefreshCoalesceConfig::load(vars),
+            outbound: OutboundConfig::load(vars),
+            routes: RoutesConfig::load(vars),
+            well_known: WellKnownConfig::load(vars),
+            ssdp: SsdpConfig::load(vars),
+            wol: WolConfig::load(vars),
+            error_pages: ErrorPagesConfig::load(vars),
+            auth: AuthConfig::load(vars),
+            signing: SigningConfig::load(vars),
+            workers: WorkersConfig::load(vars),
+            jobs: JobsConfig::load(vars),
+            metrics: MetricsConfig::load(vars),
+            metrics_push: MetricsPushConfig::load(vars),
+            logging: LoggingConfig::load(vars),
+            sampling: SamplingConfig::load(vars),
+            uploads: UploadsConfig::load(vars),
+            quota: QuotaConfig::load(vars),
+            rate_limit: RateLimitConfig::load(vars),
+            bandwidth: BandwidthConfig::load(vars),
+            transcodes: TranscodeConfig::load(vars),
+            hwaccel: HwAccelConfig::load(vars),
+            skip_markers: SkipMarkersConfig::load(vars),
+            retention: RetentionConfig::load(vars),
+            trash: TrashConfig::load(vars),
+            inbox: InboxConfig::load(vars),
+            integrations: IntegrationsConfig::load(vars),
+            media_servers: MediaServersConfig::load(vars),
+            paths: PathsConfig::load(vars),
+            config_file: None,
         }
     }
 
     pub fn addr(&self) -> String {
-        format!("{}:{}", self.host, self.port)
+        if self.host.contains(':') && !self.host.starts_with('[') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        self.tls_cert_path.is_some() && self.tls_key_path.is_some()
+    }
+
+    pub fn from_env() -> Self {
+        Self::load(&Vars::env())
+    }
+
+    pub fn from_file(path: impl Into<PathBuf>) -> Result<Self, ConfigError> {
+        ConfigBuilder::new().file(path).build()
+    }
+
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.tls_cert_path.is_some() != self.tls_key_path.is_some() {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_TLS_KEY_PATH".to_string(),
+                value: self.tls_key_path.clone().unwrap_or_default(),
+                reason: "tls_cert_path and tls_key_path must be set together".to_string(),
+            });
+        }
+        self.backend.validate(self.port)?;
+        let runtime = &self.runtime;
+        if runtime.heap_min_mb > 0
+            && runtime.heap_max_mb > 0
+            && runtime.heap_min_mb > runtime.heap_max_mb
+        {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_JAVA_HEAP_MIN_MB".to_string(),
+                value: runtime.heap_min_mb.to_string(),
+                reason: format!("exceeds MANATAN_JAVA_HEAP_MAX_MB ({})", runtime.heap_max_mb),
+            });
+        }
+        if self.cors.allow_credentials && self.cors.is_permissive() {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_CORS_ALLOW_CREDENTIALS".to_string(),
+                value: "true".to_string(),
+                reason: "requires an explicit cors allowed_origins list".to_string(),
+            });
+        }
+        if let Some(rps) = self.rate_limit.requests_per_second {
+            if !(rps.is_finite() && rps > 0.0) {
+                return Err(ConfigError::Invalid {
+                    key: "MANATAN_RATE_LIMIT_RPS".to_string(),
+                    value: rps.to_string(),
+                    reason: "must be a positive number".to_string(),
+                });
+            }
+        }
+        if self.rate_limit.client_bytes.is_some() && !self.bandwidth.enabled {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_RATE_LIMIT_CLIENT_BYTES".to_string(),
+                value: self.rate_limit.client_bytes.unwrap_or(0).to_string(),
+                reason: "requires MANATAN_BANDWIDTH_ENABLED".to_string(),
+            });
+        }
+        if let (Some(address), Some(_)) = (self.outbound.bind_address, &self.outbound.interface) {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_OUTBOUND_BIND_ADDRESS".to_string(),
+                value: address.to_string(),
+                reason: "cannot be combined with MANATAN_OUTBOUND_INTERFACE".to_string(),
+            });
+        }
+        if self.relay_sync.is_enabled() && !self.devices.enabled {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_RELAY_URL".to_string(),
+                value: self.relay_sync.url.clone().unwrap_or_default(),
+                reason: "requires MANATAN_DEVICE_PROGRESS_ENABLED".to_string(),
+            });
+        }
+        self.validate_features()?;
+        if self.canonical_redirect && self.external_url.is_none() {
+            return Err(ConfigError::Invalid {
+                key: "MANATAN_CANONICAL_REDIRECT".to_string(),
+                value: "true".to_string(),
+                reason: "requires external_url".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    // Settings that opt into a subsystem the build left out are refused rather than
+    // ignored; a server that silently drops its auth settings would run open.
+    fn validate_features(&self) -> Result<(), ConfigError> {
+        let missing = |key: &str, value: String, feature: &str| ConfigError::Invalid {
+            key: key.to_string(),
+            value,
+            reason: format!("this build was compiled without the `{feature}` feature"),
+        };
+        if !cfg!(feature = "auth") {
+            if self.auth.tokens_enabled {
+                return Err(missing("MANATAN_AUTH_TOKENS", "true".to_string(), "auth"));
+            }
+            if self.auth.token.is_some() {
+                return Err(missing(
+                    "MANATAN_AUTH_TOKEN",
+                    "<redacted>".to_string(),
+                    "auth",
+                ));
+            }
+            if let Some(user) = &self.auth.user {
+                return Err(missing("MANATAN_AUTH_USER", user.clone(), "auth"));
+            }
+        }
+        if !cfg!(feature = "cache") {
+            if self.page_archive.enabled {
+                let key = "MANATAN_PAGE_ARCHIVE_ENABLED";
+                return Err(missing(key, "true".to_string(), "cache"));
+            }
+            if self.offline_cache.enabled {
+                let key = "MANATAN_OFFLINE_CACHE_ENABLED";
+                return Err(missing(key, "true".to_string(), "cache"));
+            }
+        }
+        if !cfg!(feature = "notifications") {
+            if self.integrations.enabled {
+                let key = "MANATAN_INTEGRATIONS_ENABLED";
+                return Err(missing(key, "true".to_string(), "notifications"));
+            }
+            let media = &self.media_servers;
+            if let Some(url) = media.jellyfin_url.as_ref().or(media.plex_url.as_ref()) {
+                let key = if media.jellyfin_url.is_some() {
+                    "MANATAN_JELLYFIN_URL"
+                } else {
+                    "MANATAN_PLEX_URL"
+                };
+                return Err(missing(key, url.clone(), "notifications"));
+            }
+        }
+        if !cfg!(feature = "metrics") {
+            let push = &self.metrics_push;
+            if let Some(url) = push
+                .pushgateway_url
+                .as_ref()
+                .or(push.remote_write_url.as_ref())
+            {
+                let key = if push.pushgateway_url.is_some() {
+                    "MANATAN_METRICS_PUSHGATEWAY_URL"
+                } else {
+                    "MANATAN_METRICS_REMOTE_WRITE_URL"
+                };
+                return Err(missing(key, url.clone(), "metrics"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    Invalid {
+        key: String,
+        value: String,
+        reason: String,
+    },
+    UnknownKey(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "failed to read {}: {source}", path.display()),
+            Self::Parse { path, message } => {
+                write!(f, "failed to parse {}: {message}", path.display())
+            }
+            Self::Invalid { key, value, reason } => write!(f, "invalid {key}={value:?}: {reason}"),
+            Self::UnknownKey(key) => write!(f, "unknown config key {key}"),
+        }
     }
 }
 
-fn env_bool(key: &str, default: bool) -> bool {
-    std::env::var(key)
-        .ok()
-        .and_then(|value| match value.to_lowercase().as_str() {
-            "1" | "true" | "yes" | "on" => Some(true),
-            "0" | "false" | "no" | "off" => Some(false),
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
             _ => None,
+        }
+    }
+}
+
+// Keys everywhere use the environment variable names. File keys drop the
+// `MANATAN_` prefix, tables join with `_` (`[watchdog] interval_seconds`), and
+// builder keys accept either form (`watchdog.interval_seconds` or
+// `MANATAN_WATCHDOG_INTERVAL_SECONDS`). Precedence: builder > env > file > embedded
+// defaults > defaults.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigBuilder {
+    file: Option<PathBuf>,
+    suwayomi: Option<PathBuf>,
+    skip_env: bool,
+    overrides: HashMap<String, String>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    // Layers the settings of a Suwayomi install under everything else, even the file.
+    pub fn suwayomi(mut self, data_dir: impl Into<PathBuf>) -> Self {
+        self.suwayomi = Some(data_dir.into());
+        self
+    }
+
+    pub fn without_env(mut self) -> Self {
+        self.skip_env = true;
+        self
+    }
+
+    pub fn set(mut self, key: &str, value: impl ToString) -> Self {
+        self.overrides.insert(env_key(key), value.to_string());
+        self
+    }
+
+    pub fn host(self, host: impl Into<String>) -> Self {
+        self.set("host", host.into())
+    }
+
+    pub fn port(self, port: u16) -> Self {
+        self.set("port", port)
+    }
+
+    pub fn db_path(self, path: impl Into<String>) -> Self {
+        self.set("db_path", path.into())
+    }
+
+    pub fn tls(self, cert_path: impl Into<String>, key_path: impl Into<String>) -> Self {
+        self.set("tls_cert_path", cert_path.into())
+            .set("tls_key_path", key_path.into())
+    }
+
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let mut vars = Vars {
+            overrides: self.overrides,
+            env: !self.skip_env,
+            defaults: embedded_defaults(),
+            ..Vars::default()
+        };
+        if let Some(path) = &self.file {
+            vars.file = read_file(path)?;
+        }
+        if let Some(dir) = &self.suwayomi {
+            for (key, value) in crate::suwayomi::import(dir)?.vars() {
+                vars.file.entry(key).or_insert(value);
+            }
+        }
+        let mut config = Config::load(&vars);
+        config.config_file = self.file;
+        vars.finish()?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+fn env_key(key: &str) -> String {
+    let key = key.trim().replace(['.', '-'], "_").to_uppercase();
+    if key.starts_with("MANATAN_") {
+        key
+    } else {
+        format!("MANATAN_{key}")
+    }
+}
+
+fn read_file(path: &Path) -> Result<HashMap<String, String>, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let table = text
+        .parse::<toml::Table>()
+        .map_err(|err| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: err.message().to_string(),
+        })?;
+    let mut values = HashMap::new();
+    flatten("MANATAN", &table, &mut values);
+    Ok(values)
+}
+
+// Sets top-level string keys in a config file, editing lines in place so comments and
+// layout survive; keys the file lacks go above its first table.
+pub(crate) fn set_file_keys(path: &Path, values: &[(&str, &str)]) -> std::io::Result<()> {
+    let text = std::fs::read_to_string(path)?;
+    let mut lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+    let first_table = lines
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let mut missing = Vec::new();
+    for (key, value) in values {
+        let line = format!("{key} = {}", toml::Value::String(value.to_string()));
+        let existing = lines[..first_table].iter().position(|current| {
+            current
+                .trim_start()
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with('='))
+        });
+        match existing {
+            Some(index) => lines[index] = line,
+            None => missing.push(line),
+        }
+    }
+    lines.splice(first_table..first_table, missing);
+    let mut text = lines.join("\n");
+    text.push('\n');
+    let staged = path.with_extension("toml.tmp");
+    std::fs::write(&staged, text)?;
+    std::fs::rename(&staged, path)
+}
+
+fn flatten(prefix: &str, table: &toml::Table, values: &mut HashMap<String, String>) {
+    for (name, value) in table {
+        let key = format!("{prefix}_{}", name.replace(['.', '-'], "_").to_uppercase());
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, values),
+            toml::Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(scalar).collect();
+                values.insert(key, items.join(","));
+            }
+            value => {
+                values.insert(key, scalar(value));
+            }
+        }
+    }
+}
+
+// A config file baked in at build time from `MANATAN_DEFAULT_CONFIG`, so a distribution
+// can ship its own defaults; empty otherwise. build.rs has already checked that it parses.
+const EMBEDDED_DEFAULTS: &str = include_str!(concat!(env!("OUT_DIR"), "/default-config.toml"));
+
+fn embedded_defaults() -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    if let Ok(table) = EMBEDDED_DEFAULTS.parse::<toml::Table>() {
+        flatten("MANATAN", &table, &mut values);
+    }
+    values
+}
+
+fn scalar(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[derive(Default)]
+struct Vars {
+    overrides: HashMap<String, String>,
+    env: bool,
+    file: HashMap<String, String>,
+    defaults: HashMap<String, String>,
+    seen: RefCell<HashSet<String>>,
+    errors: RefCell<Vec<ConfigError>>,
+}
+
+impl Vars {
+    fn env() -> Self {
+        Self {
+            env: true,
+            defaults: embedded_defaults(),
+            ..Self::default()
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.seen.borrow_mut().insert(key.to_string());
+        if let Some(value) = self.overrides.get(key) {
+            return Some(value.clone());
+        }
+        if self.env {
+            if let Ok(value) = std::env::var(key) {
+                return Some(value);
+            }
+        }
+        self.file
+            .get(key)
+            .or_else(|| self.defaults.get(key))
+            .cloned()
+    }
+
+    fn non_empty(&self, key: &str) -> Option<String> {
+        self.get(key).filter(|value| !value.is_empty())
+    }
+
+    fn bool(&self, key: &str, default: bool) -> bool {
+        let Some(value) = self.get(key).filter(|value| !value.trim().is_empty()) else {
+            return default;
+        };
+        match value.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "0" | "false" | "no" | "off" => false,
+            _ => {
+                self.invalid(key, &value, "expected a boolean".to_string());
+                default
+            }
+        }
+    }
+
+    fn parse<T>(&self, key: &str, default: T) -> T
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let Some(value) = self.get(key).filter(|value| !value.trim().is_empty()) else {
+            return default;
+        };
+        match value.trim().parse::<T>() {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                self.invalid(key, &value, err.to_string());
+                default
+            }
+        }
+    }
+
+    fn list(&self, key: &str) -> Option<Vec<String>> {
+        self.get(key).map(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+    }
+
+    fn parse_opt<T>(&self, key: &str) -> Option<T>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let value = self.non_empty(key)?;
+        match value.trim().parse::<T>() {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                self.invalid(key, &value, err.to_string());
+                None
+            }
+        }
+    }
+
+    fn parse_list<T>(&self, key: &str) -> Option<Vec<T>>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.list(key).map(|items| {
+            items
+                .iter()
+                .filter_map(|item| match item.parse::<T>() {
+                    Ok(parsed) => Some(parsed),
+                    Err(err) => {
+                        self.invalid(key, item, err.to_string());
+                        None
+                    }
+                })
+                .collect()
         })
-        .unwrap_or(default)
+    }
+
+    fn invalid(&self, key: &str, value: &str, reason: String) {
+        self.errors.borrow_mut().push(ConfigError::Invalid {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason,
+        });
+    }
+
+    fn finish(self) -> Result<(), ConfigError> {
+        if let Some(err) = self.errors.into_inner().into_iter().next() {
+            return Err(err);
+        }
+        let seen = self.seen.into_inner();
+        let mut unknown: Vec<&String> = self
+            .overrides
+            .keys()
+            .chain(self.file.keys())
+            .chain(self.defaults.keys())
+            .filter(|key| !seen.contains(*key))
+            .collect();
+        unknown.sort();
+        match unknown.first() {
+            Some(key) => Err(ConfigError::UnknownKey((*key).clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(pairs: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        pairs
+            .iter()
+            .fold(Config::builder().without_env(), |builder, (key, value)| {
+                builder.set(key, value)
+            })
+            .build()
+    }
+
+    #[test]
+    fn auth_settings_need_the_auth_feature() {
+        for pairs in [
+            &[("auth_user", "reader"), ("auth_password", "secret")][..],
+            &[("auth_token", "secret")][..],
+            &[("auth_tokens", "true")][..],
+        ] {
+            let result = build(pairs);
+            assert_eq!(result.is_ok(), cfg!(feature = "auth"), "{pairs:?}");
+        }
+    }
+
+    #[test]
+    fn subsystem_settings_need_their_feature() {
+        let cases = [
+            ("page_archive_enabled", "true", cfg!(feature = "cache")),
+            ("offline_cache_enabled", "true", cfg!(feature = "cache")),
+            (
+                "integrations_enabled",
+                "true",
+                cfg!(feature = "notifications"),
+            ),
+            (
+                "jellyfin_url",
+                "http://127.0.0.1:8096",
+                cfg!(feature = "notifications"),
+            ),
+            (
+                "plex_url",
+                "http://127.0.0.1:32400",
+                cfg!(feature = "notifications"),
+            ),
+            (
+                "metrics_pushgateway_url",
+                "http://127.0.0.1:9091",
+                cfg!(feature = "metrics"),
+            ),
+        ];
+        for (key, value, compiled) in cases {
+            assert_eq!(build(&[(key, value)]).is_ok(), compiled, "{key}");
+        }
+    }
+
+    #[test]
+    fn malformed_list_entries_are_reported() {
+        let cases = [
+            ("trace_sample_routes", "/api/v1/manga=0.5", "/api/v1/manga"),
+            (
+                "trace_sample_routes",
+                "/api/v1/manga=0.5",
+                "/api/v1/manga=half",
+            ),
+            ("outbound_profiles", "mangadex.org=slow", "mangadex.org"),
+            ("downloads_category_quotas", "Reading=10GB", "Reading"),
+        ];
+        for (key, valid, malformed) in cases {
+            assert!(build(&[(key, valid)]).is_ok(), "{key}={valid}");
+            assert!(build(&[(key, malformed)]).is_err(), "{key}={malformed}");
+        }
+    }
+
+    #[test]
+    fn trimmed_builds_switch_off_exposition_and_caching() {
+        let config = build(&[]).unwrap();
+        assert_eq!(config.metrics.enabled, cfg!(feature = "metrics"));
+        assert_eq!(config.image_cache.enabled, cfg!(feature = "cache"));
+    }
 }
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Body,
+    http::{header, request::Parts, HeaderMap, StatusCode, Uri},
+    response::Response,
+};
+use serde_json::Value;
+
+use crate::app::AppState;
+use crate::body::buffer;
+use crate::config::ContentFilterConfig;
+use crate::credentials::Credentials;
+
+const LOOKUP_TTL: Duration = Duration::from_secs(300);
+const MAX_FILTER_BODY: usize = 32 * 1024 * 1024;
+
+pub(crate) struct ContentFilter {
+    config: ContentFilterConfig,
+    lookups: Mutex<HashMap<String, (bool, Instant)>>,
+}
+
+impl ContentFilter {
+    pub(crate) fn new(config: ContentFilterConfig) -> Self {
+        Self {
+            config,
+            lookups: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn applies(&self, headers: &HeaderMap, uri: &Uri) -> bool {
+        if !self.config.is_active() {
+            return false;
+        }
+        if self.config.safe_mode {
+            return true;
+        }
+        let credentials = Credentials::from_request(headers, uri);
+        credentials
+            .token
+            .as_ref()
+            .is_some_and(|token| self.config.tokens.contains(token))
+            || credentials
+                .user
+                .as_ref()
+                .is_some_and(|user| self.config.users.contains(user))
+    }
+
+    pub(crate) fn is_nsfw(&self, value: &Value) -> bool {
+        let Some(object) = value.as_object() else {
+            return false;
+        };
+        if object.get("isNsfw").and_then(Value::as_bool) == Some(true) {
+            return true;
+        }
+        if let Some(source) = object.get("source") {
+            if self.is_nsfw(source) {
+                return true;
+            }
+        }
+        if let Some(manga) = object.get("manga").or_else(|| object.get("anime")) {
+            if self.is_nsfw(manga) {
+                return true;
+            }
+        }
+        object
+            .get("genre")
+            .and_then(Value::as_array)
+            .is_some_and(|genres| {
+                genres.iter().filter_map(Value::as_str).any(|genre| {
+                    let genre = genre.trim().to_lowercase();
+                    self.config.nsfw_tags.contains(&genre)
+                })
+            })
+    }
+
+    fn strip(&self, value: &mut Value) {
+        match value {
+            Value::Array(items) => {
+                items.retain(|item| !self.is_nsfw(item));
+                for item in items {
+                    self.strip(item);
+                }
+            }
+            Value::Object(object) => {
+                for item in object.values_mut() {
+                    self.strip(item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn cached(&self, key: &str) -> Option<bool> {
+        let lookups = self.lookups.lock().unwrap_or_else(|err| err.into_inner());
+        lookups
+            .get(key)
+            .filter(|(_, at)| at.elapsed() < LOOKUP_TTL)
+            .map(|(nsfw, _)| *nsfw)
+    }
+
+    fn remember(&self, key: String, nsfw: bool) {
+        let mut lookups = self.lookups.lock().unwrap_or_else(|err| err.into_inner());
+        lookups.retain(|_, (_, at)| at.elapsed() < LOOKUP_TTL);
+        lookups.insert(key, (nsfw, Instant::now()));
+    }
+}
+
+pub(crate) fn is_filtered_path(path: &str) -> bool {
+    path.starts_with("/api/v1/")
+}
+
+fn entry_path(path: &str) -> Option<String> {
+    let rest = path.strip_prefix("/api/v1/")?;
+    let mut segments = rest.split('/');
+    let kind = segments.next()?;
+    if kind != "manga" && kind != "anime" {
+        return None;
+    }
+    let id = segments.next()?;
+    if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!("/api/v1/{kind}/{id}"))
+}
+
+pub(crate) async fn block_nsfw(state: &AppState, parts: &Parts) -> Option<Response> {
+    block_entry(state, parts.uri.path(), &parts.headers).await
+}
+
+// Share and signed media links are served without the caller's credentials, so the
+// per-token and per-user filter is applied when they are minted instead.
+pub(crate) async fn block_link(
+    state: &AppState,
+    headers: &HeaderMap,
+    uri: &Uri,
+    path: &str,
+) -> Option<Response> {
+    if !state.content_filter.applies(headers, uri) {
+        return None;
+    }
+    block_entry(state, path, headers).await
+}
+
+async fn block_entry(state: &AppState, path: &str, headers: &HeaderMap) -> Option<Response> {
+    let entry = entry_path(path)?;
+    let filter = &state.content_filter;
+
+    let nsfw = match filter.cached(&entry) {
+        Some(nsfw) => nsfw,
+        None => {
+            // An entry that cannot be looked up is blocked rather than let through.
+            let Ok(value) = state.backend_json(&entry, headers).await else {
+                return Some(
+                    Response::builder()
+                        .status(StatusCode::SERVICE_UNAVAILABLE)
+                        .header(header::RETRY_AFTER, "5")
+                        .body(Body::from("content filter could not check this entry"))
+                        .unwrap(),
+                );
+            };
+            let nsfw = filter.is_nsfw(&value);
+            filter.remember(entry, nsfw);
+            nsfw
+        }
+    };
+
+    if nsfw {
+        Some(
+            Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::from("blocked by content filter"))
+                .unwrap(),
+        )
+    } else {
+        None
+    }
+}
+
+pub(crate) async fn filter_response(state: &AppState, resp: Response) -> Response {
+    let is_json = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("json"));
+    if !is_json || !resp.status().is_success() {
+        return resp;
+    }
+
+    // Safe mode fails closed: a body that cannot be read or parsed is never sent
+    // through unfiltered.
+    let (mut parts, body) = resp.into_parts();
+    let Ok(bytes) = buffer(body, MAX_FILTER_BODY).await else {
+        return unfiltered();
+    };
+    let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) else {
+        return unfiltered();
+    };
+    state.content_filter.strip(&mut value);
+    let Ok(body) = serde_json::to_vec(&value) else {
+        return unfiltered();
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::ETAG);
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn unfiltered() -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_GATEWAY)
+        .body(Body::from(
+            "response could not be checked by the content filter",
+        ))
+        .unwrap()
+}
@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, Notify};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::Config;
+use crate::Error;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_LINE: usize = 64 * 1024;
+
+// A command the proxy does not handle itself (`show`, `open`, ...), passed on to
+// whoever embeds it: the tray icon or the window of the first instance.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ControlMessage {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+pub(crate) struct Control {
+    messages: broadcast::Sender<ControlMessage>,
+    shutdown: Notify,
+}
+
+impl Control {
+    pub(crate) fn new() -> Self {
+        Self {
+            messages: broadcast::channel(16).0,
+            shutdown: Notify::new(),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<ControlMessage> {
+        self.messages.subscribe()
+    }
+
+    pub(crate) async fn shutdown_requested(&self) {
+        self.shutdown.notified().await
+    }
+}
+
+// A socket in the data directory on Unix, a pipe named after the port on Windows.
+fn endpoint(config: &Config) -> Option<String> {
+    if !config.control.enabled {
+        return None;
+    }
+    if let Some(path) = &config.control.path {
+        return Some(path.clone());
+    }
+    if cfg!(windows) {
+        Some(format!(r"\\.\pipe\manatan-{}", config.port))
+    } else {
+        Some(
+            std::path::Path::new(&config.proxy_data_path)
+                .join("control.sock")
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
+
+// Pipes go away with their handles; a socket file has to be removed.
+pub(crate) fn close(config: &Config) {
+    if cfg!(unix) {
+        if let Some(endpoint) = endpoint(config) {
+            let _ = std::fs::remove_file(endpoint);
+        }
+    }
+}
+
+pub(crate) async fn serve(state: AppState) {
+    let Some(endpoint) = endpoint(&state.config) else {
+        return;
+    };
+    if let Err(err) = listen(&state, &endpoint).await {
+        warn!("control channel disabled: {}", err);
+    }
+}
+
+#[cfg(unix)]
+async fn listen(state: &AppState, endpoint: &str) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::path::Path::new(endpoint);
+    if tokio::net::UnixStream::connect(path).await.is_ok() {
+        return Err(Error::InvalidArgument {
+            name: "MANATAN_CONTROL_PATH".to_string(),
+            reason: format!("{endpoint} is owned by another running instance"),
+        });
+    }
+    let _ = std::fs::remove_file(path);
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| Error::io(format!("failed to create {}", parent.display()), err))?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|err| Error::io(format!("failed to bind {endpoint}"), err))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|err| Error::io(format!("failed to restrict {endpoint}"), err))?;
+    info!("control channel on {}", endpoint);
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle(state.clone(), stream));
+            }
+            Err(err) => warn!("control accept failed: {}", err),
+        }
+    }
+}
+
+#[cfg(windows)]
+async fn listen(state: &AppState, endpoint: &str) -> Result<(), Error> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    // Creating the first instance fails when another process already owns the name.
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .reject_remote_clients(true)
+        .create(endpoint)
+        .map_err(|err| Error::io(format!("failed to create {endpoint}"), err))?;
+    info!("control channel on {}", endpoint);
+    loop {
+        if let Err(err) = server.connect().await {
+            warn!("control accept failed: {}", err);
+            continue;
+        }
+        let next = ServerOptions::new()
+            .reject_remote_clients(true)
+            .create(endpoint)
+            .map_err(|err| Error::io(format!("failed to create {endpoint}"), err))?;
+        let connected = std::mem::replace(&mut server, next);
+        tokio::spawn(handle(state.clone(), connected));
+    }
+}
+
+async fn handle<S>(state: AppState, stream: S)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let reply = if line.len() > MAX_LINE {
+            json!({ "ok": false, "error": "request too large" })
+        } else {
+            match serde_json::from_str::<ControlMessage>(&line) {
+                Ok(message) => dispatch(&state, message),
+                Err(err) => json!({ "ok": false, "error": err.to_string() }),
+            }
+        };
+        let mut reply = reply.to_string();
+        reply.push('\n');
+        if writer.write_all(reply.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn dispatch(state: &AppState, message: ControlMessage) -> Value {
+    let control = &state.control;
+    match message.command.as_str() {
+        "ping" => json!({ "ok": true, "version": env!("CARGO_PKG_VERSION") }),
+        "status" => json!({
+            "ok": true,
+            "pid": std::process::id(),
+            "port": state.config.port,
+            "url": format!(
+                "{}://{}{}",
+                if state.config.tls_enabled() { "https" } else { "http" },
+                state.config.addr(),
+                state.config.base_path
+            ),
+        }),
+        "shutdown" => {
+            info!("shutdown requested over the control channel");
+            control.shutdown.notify_one();
+            json!({ "ok": true })
+        }
+        _ => match control.messages.send(message) {
+            Ok(_) => json!({ "ok": true }),
+            Err(_) => json!({ "ok": false, "error": "nothing is listening for that command" }),
+        },
+    }
+}
+
+// Hands a command to the instance that owns the channel, e.g. `show` from a second
+// launch before it exits. Fails when no instance is running.
+pub async fn send(config: &Config, message: &ControlMessage) -> Result<Value, Error> {
+    let endpoint = endpoint(config).ok_or_else(|| Error::InvalidArgument {
+        name: "MANATAN_CONTROL_ENABLED".to_string(),
+        reason: "is false".to_string(),
+    })?;
+    let exchange = async {
+        let stream = connect(&endpoint).await?;
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut request = serde_json::to_string(message).unwrap_or_default();
+        request.push('\n');
+        writer.write_all(request.as_bytes()).await?;
+        let mut reply = String::new();
+        BufReader::new(reader).read_line(&mut reply).await?;
+        serde_json::from_str::<Value>(&reply).map_err(std::io::Error::other)
+    };
+    match tokio::time::timeout(SEND_TIMEOUT, exchange).await {
+        Ok(result) => result.map_err(|err| Error::io(format!("control {endpoint}"), err)),
+        Err(_) => Err(Error::io(
+            format!("control {endpoint}"),
+            std::io::ErrorKind::TimedOut.into(),
+        )),
+    }
+}
+
+#[cfg(unix)]
+async fn connect(endpoint: &str) -> std::io::Result<tokio::net::UnixStream> {
+    tokio::net::UnixStream::connect(endpoint).await
+}
+
+#[cfg(windows)]
+async fn connect(
+    endpoint: &str,
+) -> std::io::Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+
+    const ERROR_PIPE_BUSY: i32 = 231;
+    loop {
+        match ClientOptions::new().open(endpoint) {
+            Err(err) if err.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            result => return result,
+        }
+    }
+}
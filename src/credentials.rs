@@ -0,0 +1,91 @@
+use axum::http::{header, HeaderMap, Uri};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Credentials {
+    pub token: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Credentials {
+    pub(crate) fn from_request(headers: &HeaderMap, uri: &Uri) -> Self {
+        let mut credentials = Self::default();
+
+        if let Some(value) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+        {
+            if let Some(token) = strip_scheme(value, "bearer") {
+                credentials.token = Some(token.to_string());
+            } else if let Some(encoded) = strip_scheme(value, "basic") {
+                if let Some((user, password)) = STANDARD
+                    .decode(encoded)
+                    .ok()
+                    .and_then(|decoded| String::from_utf8(decoded).ok())
+                    .and_then(|decoded| {
+                        decoded
+                            .split_once(':')
+                            .map(|(user, password)| (user.to_string(), password.to_string()))
+                    })
+                {
+                    credentials.user = Some(user);
+                    credentials.password = Some(password);
+                }
+            }
+        }
+
+        if credentials.token.is_none() {
+            credentials.token = query_param(uri, "token");
+        }
+
+        credentials
+    }
+}
+
+fn strip_scheme<'a>(value: &'a str, scheme: &str) -> Option<&'a str> {
+    let (prefix, rest) = value.trim().split_once(' ')?;
+    if prefix.eq_ignore_ascii_case(scheme) {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
+
+pub(crate) fn query_param(uri: &Uri, name: &str) -> Option<String> {
+    uri.query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if key == name {
+            Some(percent_decode(value))
+        } else {
+            None
+        }
+    })
+}
+
+pub(crate) fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' if index + 2 < bytes.len() => {
+                let decoded = std::str::from_utf8(&bytes[index + 1..index + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match decoded {
+                    Some(byte) => {
+                        out.push(byte);
+                        index += 3;
+                        continue;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b'+' => out.push(b' '),
+            byte => out.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,369 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, request::Parts, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::DeviceProgressConfig;
+use crate::credentials::{percent_decode, query_param};
+use crate::store::{load_json, save_json};
+use crate::unix_now;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_FORM_BYTES: usize = 64 * 1024;
+const DEVICE_HEADER: &str = "x-manatan-device";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Position {
+    pub chapter: i64,
+    pub page: Option<i64>,
+    pub read: bool,
+    pub updated_at: u64,
+}
+
+impl Position {
+    fn rank(&self) -> (i64, i64) {
+        (
+            self.chapter,
+            if self.read {
+                i64::MAX
+            } else {
+                self.page.unwrap_or(0)
+            },
+        )
+    }
+}
+
+pub(crate) struct Update {
+    device: String,
+    manga_id: i64,
+    chapter: i64,
+    page: Option<i64>,
+    read: Option<bool>,
+    updated_at: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct DeviceData {
+    series: BTreeMap<i64, BTreeMap<String, Position>>,
+}
+
+struct Inner {
+    data: DeviceData,
+    dirty: bool,
+}
+
+pub(crate) struct DeviceProgress {
+    config: DeviceProgressConfig,
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+impl DeviceProgress {
+    pub(crate) fn new(config: DeviceProgressConfig, data_path: &str) -> Self {
+        let path = PathBuf::from(data_path).join("device-progress.json");
+        let data = if config.enabled {
+            load_json(&path)
+        } else {
+            DeviceData::default()
+        };
+        Self {
+            config,
+            path,
+            inner: Mutex::new(Inner { data, dirty: false }),
+        }
+    }
+
+    pub(crate) fn record(&self, update: Update) {
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let devices = inner.data.series.entry(update.manga_id).or_default();
+        // A bare "read" toggle keeps the page the device last reported for that chapter.
+        let same_chapter = devices
+            .get(&update.device)
+            .filter(|previous| previous.chapter == update.chapter);
+        let position = Position {
+            chapter: update.chapter,
+            page: update
+                .page
+                .or(same_chapter.and_then(|previous| previous.page)),
+            read: update
+                .read
+                .unwrap_or(same_chapter.is_some_and(|previous| previous.read)),
+            updated_at: update.updated_at,
+        };
+        devices.insert(update.device, position);
+        inner.dirty = true;
+    }
+
+    fn devices(&self, manga_id: i64) -> BTreeMap<String, Position> {
+        let inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        inner
+            .data
+            .series
+            .get(&manga_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn snapshot(&self) -> BTreeMap<i64, BTreeMap<String, Position>> {
+        let inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        inner.data.series.clone()
+    }
+
+    // Takes positions reported elsewhere, keeping whichever of each device's is newer.
+    // Returns how many changed.
+    pub(crate) fn merge(&self, series: BTreeMap<i64, BTreeMap<String, Position>>) -> usize {
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let mut changed = 0;
+        for (manga_id, positions) in series {
+            let devices = inner.data.series.entry(manga_id).or_default();
+            for (device, position) in positions {
+                let newer = devices
+                    .get(&device)
+                    .is_none_or(|current| position.updated_at > current.updated_at);
+                if newer {
+                    devices.insert(device, position);
+                    changed += 1;
+                }
+            }
+        }
+        if changed > 0 {
+            inner.dirty = true;
+        }
+        changed
+    }
+
+    fn flush(&self) {
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        if !inner.dirty {
+            return;
+        }
+        if let Err(err) = save_json(&self.path, &inner.data) {
+            warn!(
+                "failed to persist device progress to {}: {}",
+                self.path.display(),
+                err
+            );
+            return;
+        }
+        inner.dirty = false;
+    }
+}
+
+fn furthest(devices: &BTreeMap<String, Position>) -> Option<(&String, &Position)> {
+    devices
+        .iter()
+        .max_by_key(|(_, position)| (position.rank(), position.updated_at))
+}
+
+pub(crate) fn spawn_flusher(devices: &Arc<DeviceProgress>) {
+    if !devices.config.enabled {
+        return;
+    }
+    let devices = Arc::downgrade(devices);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let Some(devices) = devices.upgrade() else {
+                break;
+            };
+            let _ = tokio::task::spawn_blocking(move || devices.flush()).await;
+        }
+    });
+}
+
+fn chapter_target(method: &Method, path: &str) -> Option<(i64, i64)> {
+    if !matches!(method.as_str(), "PATCH" | "PUT" | "POST") {
+        return None;
+    }
+    let segments = path
+        .strip_prefix("/api/v1/manga/")?
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    match segments.as_slice() {
+        [id, "chapter", index] => Some((id.parse().ok()?, index.parse().ok()?)),
+        _ => None,
+    }
+}
+
+// Progress updates are small url-encoded forms, so they are buffered to read
+// `lastPageRead`/`read` before being handed on to the backend unchanged.
+pub(crate) async fn capture(
+    state: &AppState,
+    parts: &Parts,
+    client: &str,
+    body: Body,
+) -> Result<(Body, Option<Update>), Response> {
+    if !state.devices.config.enabled {
+        return Ok((body, None));
+    }
+    let Some((manga_id, chapter)) = chapter_target(&parts.method, parts.uri.path()) else {
+        return Ok((body, None));
+    };
+    let form = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if !form {
+        return Ok((body, None));
+    }
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_FORM_BYTES).await else {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "progress update too large").into_response());
+    };
+
+    let text = String::from_utf8_lossy(&bytes);
+    let field = |name: &str| {
+        text.split('&')
+            .find_map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (key == name).then(|| percent_decode(value))
+            })
+            .or_else(|| query_param(&parts.uri, name))
+    };
+    let page = field("lastPageRead").and_then(|value| value.trim().parse().ok());
+    let read = field("read").and_then(|value| value.trim().parse().ok());
+    let update = (page.is_some() || read.is_some()).then(|| Update {
+        device: device_id(parts, client),
+        manga_id,
+        chapter,
+        page,
+        read,
+        updated_at: unix_now(),
+    });
+    Ok((Body::from(bytes), update))
+}
+
+fn device_id(parts: &Parts, client: &str) -> String {
+    parts
+        .headers
+        .get(DEVICE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| query_param(&parts.uri, "device"))
+        .map(|device| device.trim().to_string())
+        .filter(|device| !device.is_empty())
+        .unwrap_or_else(|| client.to_string())
+}
+
+#[derive(Deserialize)]
+struct ProgressQuery {
+    manga: i64,
+}
+
+#[derive(Deserialize)]
+struct ReconcileRequest {
+    manga: i64,
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/devices/progress", get(progress))
+        .route("/devices/progress/reconcile", post(reconcile))
+}
+
+fn disabled(state: &AppState) -> Option<Response> {
+    if state.devices.config.enabled {
+        return None;
+    }
+    Some(
+        (
+            StatusCode::NOT_FOUND,
+            "device progress tracking is disabled",
+        )
+            .into_response(),
+    )
+}
+
+async fn progress(State(state): State<AppState>, Query(query): Query<ProgressQuery>) -> Response {
+    if let Some(resp) = disabled(&state) {
+        return resp;
+    }
+    let devices = state.devices.devices(query.manga);
+    let furthest = furthest(&devices).map(|(device, position)| position_json(device, position));
+    Json(json!({
+        "manga": query.manga,
+        "devices": devices,
+        "furthest": furthest,
+    }))
+    .into_response()
+}
+
+async fn reconcile(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<ReconcileRequest>,
+) -> Response {
+    if let Some(resp) = disabled(&state) {
+        return resp;
+    }
+    let devices = state.devices.devices(body.manga);
+    let Some((device, position)) = furthest(&devices) else {
+        return (StatusCode::NOT_FOUND, "no device progress for this manga").into_response();
+    };
+
+    let mut form = Vec::new();
+    if let Some(page) = position.page {
+        form.push(format!("lastPageRead={page}"));
+    }
+    if position.read {
+        form.push("read=true".to_string());
+    }
+    let url = format!(
+        "{}/api/v1/manga/{}/chapter/{}",
+        state.backend_url, body.manga, position.chapter
+    );
+    let mut request = state
+        .client
+        .patch(url)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(form.join("&"));
+    for name in [header::AUTHORIZATION, header::COOKIE] {
+        if let Some(value) = headers.get(&name) {
+            request = request.header(name, value);
+        }
+    }
+    match request.send().await {
+        Ok(resp) if resp.status().is_success() => {
+            info!(
+                "reconciled manga {} to chapter {} from device {}",
+                body.manga, position.chapter, device
+            );
+            Json(json!({
+                "manga": body.manga,
+                "applied": position_json(device, position),
+            }))
+            .into_response()
+        }
+        Ok(resp) => (
+            StatusCode::BAD_GATEWAY,
+            format!("backend returned {}", resp.status()),
+        )
+            .into_response(),
+        Err(err) => (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+    }
+}
+
+fn position_json(device: &str, position: &Position) -> Value {
+    json!({
+        "device": device,
+        "chapter": position.chapter,
+        "page": position.page,
+        "read": position.read,
+        "updated_at": position.updated_at,
+    })
+}
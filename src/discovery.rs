@@ -0,0 +1,128 @@
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use tracing::{info, warn};
+
+use crate::config::Config;
+
+const SERVICE_TYPE: &str = "_manatan._tcp.local.";
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub(crate) struct Discovery {
+    daemon: ServiceDaemon,
+    fullname: String,
+    stopped: AtomicBool,
+}
+
+impl Discovery {
+    pub(crate) fn start(config: &Config) -> Option<Self> {
+        if !config.discovery_enabled {
+            return None;
+        }
+        // Advertise the bound address when there is one; a wildcard bind follows
+        // whatever interfaces the host has.
+        let bound = config.host.parse::<IpAddr>().ok();
+        if bound.is_some_and(|ip| ip.is_loopback()) {
+            warn!(
+                "mdns discovery disabled: {} is only reachable from this machine",
+                config.host
+            );
+            return None;
+        }
+        let instance = config.instance_name.replace('.', "-");
+        let host_name = format!("{}-{}.local.", label(&config.instance_name), config.port);
+        let tls = if config.tls_cert_path.is_some() {
+            "true"
+        } else {
+            "false"
+        };
+        let port = config.port.to_string();
+        let properties = [
+            ("name", config.instance_name.as_str()),
+            ("port", port.as_str()),
+            ("tls", tls),
+            ("path", config.base_path.as_str()),
+            ("version", env!("CARGO_PKG_VERSION")),
+        ];
+        let service = match bound.filter(|ip| !ip.is_unspecified()) {
+            Some(ip) => ServiceInfo::new(
+                SERVICE_TYPE,
+                &instance,
+                &host_name,
+                ip,
+                config.port,
+                &properties[..],
+            ),
+            None => ServiceInfo::new(
+                SERVICE_TYPE,
+                &instance,
+                &host_name,
+                (),
+                config.port,
+                &properties[..],
+            )
+            .map(ServiceInfo::enable_addr_auto),
+        };
+        let result = ServiceDaemon::new().and_then(|daemon| {
+            let service = service?;
+            let fullname = service.get_fullname().to_string();
+            daemon.register(service)?;
+            Ok((daemon, fullname))
+        });
+        match result {
+            Ok((daemon, fullname)) => {
+                info!("advertising {} over mdns", fullname);
+                Some(Self {
+                    daemon,
+                    fullname,
+                    stopped: AtomicBool::new(false),
+                })
+            }
+            Err(err) => {
+                warn!("mdns discovery disabled: {}", err);
+                None
+            }
+        }
+    }
+
+    // Sends the goodbye so clients drop the entry right away instead of waiting out the TTL.
+    pub(crate) fn stop(&self) {
+        if self.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(status) = self.daemon.unregister(&self.fullname) {
+            let _ = status.recv_timeout(GOODBYE_TIMEOUT);
+        }
+        if let Ok(status) = self.daemon.shutdown() {
+            let _ = status.recv_timeout(GOODBYE_TIMEOUT);
+        }
+        info!("stopped advertising {} over mdns", self.fullname);
+    }
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn label(name: &str) -> String {
+    let label = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    let label = label.trim_matches('-');
+    if label.is_empty() {
+        "manatan".to_string()
+    } else {
+        label.chars().take(48).collect()
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{header, HeaderValue, Method, StatusCode},
+    response::Response,
+};
+use sha2::{Digest, Sha256};
+
+use crate::app::{forward, AppState};
+use crate::base_path::rewrite_docs;
+use crate::body::buffer;
+
+const MAX_CACHED_BODY: usize = 16 * 1024 * 1024;
+const ASSET_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+const ENTRY_CACHE_CONTROL: &str = "public, no-cache";
+
+struct Cached {
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+    etag: String,
+    restarts: u32,
+}
+
+#[derive(Default)]
+pub(crate) struct DocsCache {
+    entries: Mutex<HashMap<String, Cached>>,
+}
+
+pub(crate) fn is_docs_path(path: &str) -> bool {
+    path == "/openapi.json" || path == "/docs" || path.starts_with("/docs/")
+}
+
+// Entry points can change when the backend is upgraded, so only hashed-looking
+// static assets get the immutable policy; the rest revalidate against the ETag.
+fn is_static_asset(path: &str) -> bool {
+    path.starts_with("/docs/")
+        && [
+            ".js", ".css", ".png", ".svg", ".map", ".woff", ".woff2", ".ico",
+        ]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+impl DocsCache {
+    fn lookup(&self, key: &str, restarts: u32) -> Option<Response> {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        match entries.get(key) {
+            Some(cached) if cached.restarts == restarts => Some(respond(key, cached)),
+            Some(_) => {
+                entries.clear();
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+pub(crate) async fn serve(state: &AppState, mut req: Request) -> Response {
+    if req.method() != Method::GET {
+        return forward(state, req).await;
+    }
+    let key = req
+        .uri()
+        .path_and_query()
+        .map(|value| value.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let if_none_match = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let restarts = state.backend.restarts();
+
+    if let Some(resp) = state.docs_cache.lookup(&key, restarts) {
+        return revalidate(resp, if_none_match.as_deref());
+    }
+
+    for name in [
+        header::ACCEPT_ENCODING,
+        header::IF_NONE_MATCH,
+        header::IF_MODIFIED_SINCE,
+    ] {
+        req.headers_mut().remove(name);
+    }
+    let resp = forward(state, req).await;
+    if resp.status() != StatusCode::OK {
+        return resp;
+    }
+
+    let (parts, body) = resp.into_parts();
+    let bytes = match buffer(body, MAX_CACHED_BODY).await {
+        Ok(bytes) => bytes,
+        Err(body) => return Response::from_parts(parts, body),
+    };
+    let content_type = parts.headers.get(header::CONTENT_TYPE).cloned();
+    let path = key.split('?').next().unwrap_or(&key);
+    let bytes = rewrite_docs(
+        &state.config.base_path,
+        path,
+        content_type.as_ref().and_then(|value| value.to_str().ok()),
+        bytes,
+    );
+    let cached = Cached {
+        content_type,
+        etag: format!(
+            "\"{}\"",
+            Sha256::digest(&bytes)
+                .iter()
+                .take(12)
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>()
+        ),
+        body: bytes,
+        restarts,
+    };
+    let resp = respond(&key, &cached);
+    state
+        .docs_cache
+        .entries
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(key, cached);
+    revalidate(resp, if_none_match.as_deref())
+}
+
+fn respond(key: &str, cached: &Cached) -> Response {
+    let path = key.split('?').next().unwrap_or(key);
+    let cache_control = if is_static_asset(path) {
+        ASSET_CACHE_CONTROL
+    } else {
+        ENTRY_CACHE_CONTROL
+    };
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CACHE_CONTROL, cache_control)
+        .header(header::ETAG, &cached.etag)
+        .header("x-manatan-cache", "docs");
+    if let Some(content_type) = &cached.content_type {
+        builder = builder.header(header::CONTENT_TYPE, content_type);
+    }
+    builder.body(Body::from(cached.body.clone())).unwrap()
+}
+
+fn revalidate(resp: Response, if_none_match: Option<&str>) -> Response {
+    let Some(etag) = resp.headers().get(header::ETAG).cloned() else {
+        return resp;
+    };
+    let matches = if_none_match.is_some_and(|value| {
+        value.split(',').any(|candidate| {
+            candidate.trim() == "*" || candidate.trim().as_bytes() == etag.as_bytes()
+        })
+    });
+    if !matches {
+        return resp;
+    }
+    let (mut parts, _) = resp.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(header::CONTENT_TYPE);
+    Response::from_parts(parts, Body::empty())
+}
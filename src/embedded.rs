@@ -0,0 +1,707 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+use crate::config::{Config, ConfigError, ConfigUpdate, HwAccel, OutputTarget};
+use crate::events::{self, BackendEvent};
+use crate::ffi_guard::{self, SendHandle};
+use crate::hwaccel::{self, Selection};
+use crate::{ffi, to_cstring, Error};
+
+const CONFIG_VERSIONS: &[u32] = &[1];
+const RESTART_BACKOFF: Duration = Duration::from_secs(30);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(600);
+
+pub(crate) struct EmbeddedServer {
+    launch: Mutex<LaunchConfig>,
+    capabilities: Capabilities,
+    hwaccel: Selection,
+    handle: Mutex<Option<Arc<BackendHandle>>>,
+    restarts: AtomicU32,
+    restart_gate: Mutex<RestartGate>,
+    webview_enabled: AtomicBool,
+    events: Box<broadcast::Sender<BackendEvent>>,
+}
+
+// Shared by every restart, so recovery paths that notice the same outage do not each
+// restart the backend. `streak` counts automatic restarts that came close together.
+#[derive(Default)]
+struct RestartGate {
+    running: u32,
+    last: Option<Instant>,
+    streak: u32,
+}
+
+impl RestartGate {
+    fn backoff(&self) -> Duration {
+        RESTART_BACKOFF
+            .saturating_mul(1 << self.streak.min(5))
+            .min(RESTART_BACKOFF_MAX)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RestartOutcome {
+    Restarted,
+    InProgress,
+    BackingOff(Duration),
+}
+
+impl std::fmt::Display for RestartOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Restarted => write!(f, "restarted"),
+            Self::InProgress => write!(f, "a restart is already under way"),
+            Self::BackingOff(wait) => {
+                write!(f, "backing off for another {}s", wait.as_secs().max(1))
+            }
+        }
+    }
+}
+
+unsafe impl Send for EmbeddedServer {}
+unsafe impl Sync for EmbeddedServer {}
+
+// A started backend. Every library call holds a clone while it runs, so one abandoned
+// past its deadline keeps the handle alive: the backend is stopped and freed when the
+// last holder lets go, never under a thread that is still using it.
+pub(crate) struct BackendHandle(SendHandle);
+
+// The library allows calls on a handle from any thread.
+unsafe impl Sync for BackendHandle {}
+
+impl BackendHandle {
+    fn raw(&self) -> *mut ffi::ManatanServerHandle {
+        self.0.get()
+    }
+}
+
+impl Drop for BackendHandle {
+    fn drop(&mut self) {
+        stop_handle(self.raw());
+    }
+}
+
+// Detaches the event callback right away, since the sender it points at may be gone by
+// the time a stuck call returns and the handle is actually stopped.
+fn release(handle: Arc<BackendHandle>) {
+    events::unregister(handle.raw());
+    if Arc::strong_count(&handle) > 1 {
+        warn!(
+            "an abandoned backend call still holds the handle; stopping it once that call returns"
+        );
+    }
+}
+
+// Sent to the library as `{"version": N, "config": {..}}`; the field names are the JSON keys.
+#[derive(Serialize)]
+struct LaunchConfig {
+    host: String,
+    port: u16,
+    java_runtime_url: String,
+    webview_enabled: bool,
+    aidoku_index_url: String,
+    aidoku_enabled: bool,
+    aidoku_cache_path: String,
+    db_path: String,
+    migrate_path: Option<String>,
+    tracker_remote_search: bool,
+    tracker_search_ttl_seconds: i64,
+    downloads_path: String,
+    local_manga_path: String,
+    local_anime_path: String,
+    stdout: OutputTarget,
+    stderr: OutputTarget,
+    java_heap_min_mb: u32,
+    java_heap_max_mb: u32,
+    java_gc: Option<String>,
+    java_args: Vec<String>,
+    worker_threads: u32,
+    io_threads: u32,
+    socket_path: Option<PathBuf>,
+    proxy_host: String,
+    proxy_port: u16,
+    #[serde(rename = "backend_loopback_only")]
+    loopback_only: bool,
+    hwaccel: HwAccel,
+    hwaccel_device: Option<String>,
+}
+
+impl LaunchConfig {
+    fn new(
+        config: &Config,
+        hwaccel: &Selection,
+        host: &str,
+        port: u16,
+        socket_path: Option<&Path>,
+    ) -> Result<Self, Error> {
+        let runtime = &config.runtime;
+        if runtime.heap_min_mb > 0
+            && runtime.heap_max_mb > 0
+            && runtime.heap_min_mb > runtime.heap_max_mb
+        {
+            return Err(Error::Config(ConfigError::Invalid {
+                key: "MANATAN_JAVA_HEAP_MIN_MB".to_string(),
+                value: runtime.heap_min_mb.to_string(),
+                reason: format!("exceeds MANATAN_JAVA_HEAP_MAX_MB ({})", runtime.heap_max_mb),
+            }));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            java_runtime_url: config.java_runtime_url.clone(),
+            webview_enabled: config.webview_enabled,
+            aidoku_index_url: config.aidoku_index_url.clone(),
+            aidoku_enabled: config.aidoku_enabled,
+            aidoku_cache_path: config.aidoku_cache_path.clone(),
+            db_path: config.db_path.clone(),
+            migrate_path: config
+                .migrate_path
+                .clone()
+                .filter(|value| !value.is_empty()),
+            tracker_remote_search: config.tracker_remote_search,
+            tracker_search_ttl_seconds: config.tracker_search_ttl_seconds,
+            downloads_path: config.downloads_path.clone(),
+            local_manga_path: config.local_manga_path.clone(),
+            local_anime_path: config.local_anime_path.clone(),
+            stdout: config.backend_stdout.clone(),
+            stderr: config.backend_stderr.clone(),
+            java_heap_min_mb: runtime.heap_min_mb,
+            java_heap_max_mb: runtime.heap_max_mb,
+            java_gc: runtime.gc.clone(),
+            java_args: runtime.jvm_args.clone(),
+            worker_threads: runtime.worker_threads,
+            io_threads: runtime.io_threads,
+            socket_path: socket_path.map(Path::to_path_buf),
+            proxy_host: config.host.clone(),
+            proxy_port: config.port,
+            loopback_only: config.backend.loopback_only,
+            hwaccel: hwaccel.selected,
+            hwaccel_device: hwaccel.device.clone(),
+        })
+    }
+
+    // Keys the library does not list are left out rather than failing the start.
+    fn blob(&self, capabilities: &Capabilities, webview_enabled: bool) -> Result<CString, Error> {
+        let Ok(serde_json::Value::Object(mut config)) = serde_json::to_value(self) else {
+            return Err(Error::InvalidArgument {
+                name: "config".to_string(),
+                reason: "could not be serialized".to_string(),
+            });
+        };
+        config.insert("webview_enabled".to_string(), webview_enabled.into());
+        if let Some(keys) = &capabilities.config_keys {
+            config.retain(|key, value| {
+                let known = keys.iter().any(|known| known == key);
+                if !known && !value.is_null() {
+                    warn!("backend library does not support {}, ignoring it", key);
+                }
+                known
+            });
+        }
+        let blob = serde_json::json!({
+            "version": capabilities.version,
+            "config": config,
+        });
+        to_cstring(&blob.to_string(), "config")
+    }
+}
+
+// What the library reports from `manatan_server_capabilities`: the config versions it
+// parses and, optionally, the keys it understands.
+#[derive(Deserialize)]
+struct Capabilities {
+    #[serde(default, rename = "config_versions")]
+    versions: Vec<u32>,
+    #[serde(default)]
+    config_keys: Option<Vec<String>>,
+    #[serde(skip)]
+    version: u32,
+}
+
+impl Capabilities {
+    fn negotiate() -> Result<Self, Error> {
+        let text = unsafe { take_string(ffi::manatan_server_capabilities()) }
+            .ok_or_else(|| ffi_error("manatan_server_capabilities"))?;
+        let mut capabilities: Self = serde_json::from_str(&text).map_err(|err| Error::Ffi {
+            call: "manatan_server_capabilities",
+            detail: Some(format!("invalid capabilities: {err}")),
+        })?;
+        capabilities.version = capabilities
+            .versions
+            .iter()
+            .copied()
+            .filter(|version| CONFIG_VERSIONS.contains(version))
+            .max()
+            .ok_or_else(|| Error::Ffi {
+                call: "manatan_server_capabilities",
+                detail: Some(format!(
+                    "library reads config versions {:?}, this build writes {:?}",
+                    capabilities.versions, CONFIG_VERSIONS
+                )),
+            })?;
+        Ok(capabilities)
+    }
+}
+
+unsafe extern "C" fn backend_log(stream: u8, line: *const c_char, len: usize) {
+    if line.is_null() {
+        return;
+    }
+    ffi_guard::callback("log", || {
+        let bytes = unsafe { std::slice::from_raw_parts(line.cast::<u8>(), len) };
+        let line = String::from_utf8_lossy(bytes);
+        let line = line.trim_end();
+        match stream {
+            ffi::MANATAN_STREAM_STDOUT => {
+                info!(target: "manatan::backend", stream = "stdout", "{}", line)
+            }
+            ffi::MANATAN_STREAM_STDERR => {
+                warn!(target: "manatan::backend", stream = "stderr", "{}", line)
+            }
+            _ => info!(target: "manatan::backend", "{}", line),
+        }
+    });
+}
+
+impl EmbeddedServer {
+    pub(crate) fn start(
+        config: &Config,
+        host: &str,
+        port: u16,
+        socket_path: Option<&Path>,
+    ) -> Result<Self, Error> {
+        let hwaccel = hwaccel::select(&config.hwaccel);
+        let launch = LaunchConfig::new(config, &hwaccel, host, port, socket_path)?;
+        let capabilities = Capabilities::negotiate()?;
+        let events = Box::new(broadcast::channel(events::CHANNEL_CAPACITY).0);
+        let handle = launch_backend(&launch, &capabilities, launch.webview_enabled)?;
+        events::register(handle, &events);
+        Ok(Self {
+            capabilities,
+            hwaccel,
+            events,
+            webview_enabled: AtomicBool::new(launch.webview_enabled),
+            launch: Mutex::new(launch),
+            handle: Mutex::new(Some(Arc::new(BackendHandle(SendHandle::new(handle))))),
+            restarts: AtomicU32::new(0),
+            restart_gate: Mutex::new(RestartGate::default()),
+        })
+    }
+
+    // A deliberate restart, e.g. after a restore: it always happens, and automatic
+    // recovery holds off while it runs and for the backoff after.
+    pub(crate) fn restart(&self) -> Result<(), Error> {
+        self.lock_gate().running += 1;
+        self.finish_restart(false)
+    }
+
+    // The one way automatic recovery (the supervisor and the watchdog) restarts the
+    // backend. A restart already under way is not repeated, and automatic restarts that
+    // follow each other back off from 30s up to 10 minutes.
+    pub(crate) fn recover(&self) -> Result<RestartOutcome, Error> {
+        {
+            let mut gate = self.lock_gate();
+            if gate.running > 0 {
+                return Ok(RestartOutcome::InProgress);
+            }
+            if let Some(last) = gate.last {
+                let wait = gate.backoff().saturating_sub(last.elapsed());
+                if !wait.is_zero() {
+                    return Ok(RestartOutcome::BackingOff(wait));
+                }
+            }
+            gate.running += 1;
+        }
+        self.finish_restart(true)?;
+        Ok(RestartOutcome::Restarted)
+    }
+
+    fn lock_gate(&self) -> std::sync::MutexGuard<'_, RestartGate> {
+        self.restart_gate
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn finish_restart(&self, automatic: bool) -> Result<(), Error> {
+        let result = self.relaunch();
+        let mut gate = self.lock_gate();
+        gate.running -= 1;
+        if automatic {
+            gate.streak = match gate.last {
+                Some(last) if last.elapsed() < RESTART_BACKOFF_MAX => gate.streak + 1,
+                _ => 0,
+            };
+        }
+        gate.last = Some(Instant::now());
+        result
+    }
+
+    fn relaunch(&self) -> Result<(), Error> {
+        let mut handle = self.handle.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(old) = handle.take() {
+            release(old);
+        }
+        let launch = self.launch.lock().unwrap_or_else(|err| err.into_inner());
+        let raw = launch_backend(&launch, &self.capabilities, self.webview_enabled())?;
+        events::register(raw, &self.events);
+        *handle = Some(Arc::new(BackendHandle(SendHandle::new(raw))));
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub(crate) fn stop(&self) {
+        let mut handle = self.handle.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(old) = handle.take() {
+            release(old);
+        }
+    }
+
+    pub(crate) fn port(&self) -> Option<u16> {
+        let handle = self.handle.lock().unwrap_or_else(|err| err.into_inner());
+        let handle = handle.as_ref()?;
+        Some(unsafe { ffi::manatan_server_port(handle.raw()) })
+    }
+
+    pub(crate) fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn events(&self) -> broadcast::Sender<BackendEvent> {
+        (*self.events).clone()
+    }
+
+    pub(crate) fn hwaccel(&self) -> &Selection {
+        &self.hwaccel
+    }
+
+    pub(crate) fn webview_enabled(&self) -> bool {
+        self.webview_enabled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_webview_enabled(&self, enabled: bool) -> Result<(), Error> {
+        let call = "manatan_server_set_webview_enabled";
+        let handle = self.handle.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(handle) = handle.clone() {
+            ffi_guard::call(call, ffi_guard::CALL_TIMEOUT, move || {
+                if unsafe {
+                    ffi::manatan_server_set_webview_enabled(handle.raw(), u8::from(enabled))
+                } {
+                    Ok(())
+                } else {
+                    Err(ffi_error(call))
+                }
+            })?;
+        }
+        self.webview_enabled.store(enabled, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl EmbeddedServer {
+    pub(crate) fn settings(&self) -> ConfigUpdate {
+        let launch = self.launch.lock().unwrap_or_else(|err| err.into_inner());
+        ConfigUpdate {
+            webview_enabled: Some(self.webview_enabled()),
+            tracker_remote_search: Some(launch.tracker_remote_search),
+            tracker_search_ttl_seconds: Some(launch.tracker_search_ttl_seconds),
+            downloads_path: Some(launch.downloads_path.clone()),
+            local_manga_path: Some(launch.local_manga_path.clone()),
+            local_anime_path: Some(launch.local_anime_path.clone()),
+        }
+    }
+
+    // Hands the running backend only what changed, then keeps it for the next
+    // restart. Returns the keys that changed.
+    pub(crate) fn apply(&self, update: &ConfigUpdate) -> Result<Vec<String>, Error> {
+        if update.tracker_search_ttl_seconds.is_some_and(|ttl| ttl < 0) {
+            return Err(Error::InvalidArgument {
+                name: "tracker_search_ttl_seconds".to_string(),
+                reason: "must not be negative".to_string(),
+            });
+        }
+        let current = self.settings();
+        let mut changes = serde_json::Map::new();
+        if let Some(value) = update
+            .tracker_remote_search
+            .filter(|value| Some(*value) != current.tracker_remote_search)
+        {
+            changes.insert("tracker_remote_search".to_string(), value.into());
+        }
+        if let Some(value) = update
+            .tracker_search_ttl_seconds
+            .filter(|value| Some(*value) != current.tracker_search_ttl_seconds)
+        {
+            changes.insert("tracker_search_ttl_seconds".to_string(), value.into());
+        }
+        let mut paths = Vec::new();
+        for (key, new, old) in [
+            (
+                "downloads_path",
+                &update.downloads_path,
+                &current.downloads_path,
+            ),
+            (
+                "local_manga_path",
+                &update.local_manga_path,
+                &current.local_manga_path,
+            ),
+            (
+                "local_anime_path",
+                &update.local_anime_path,
+                &current.local_anime_path,
+            ),
+        ] {
+            let Some(path) = new.as_deref().filter(|path| Some(*path) != old.as_deref()) else {
+                continue;
+            };
+            if path.trim().is_empty() {
+                return Err(Error::InvalidArgument {
+                    name: key.to_string(),
+                    reason: "must not be empty".to_string(),
+                });
+            }
+            to_cstring(path, key)?;
+            paths.push((key, path.to_string()));
+            changes.insert(key.to_string(), path.into());
+        }
+        let mut changed = changes.keys().cloned().collect::<Vec<_>>();
+
+        if !changes.is_empty() {
+            let json = to_cstring(&serde_json::Value::Object(changes).to_string(), "settings")?;
+            let call = "manatan_server_update_config";
+            let handle = self.handle.lock().unwrap_or_else(|err| err.into_inner());
+            if let Some(handle) = handle.clone() {
+                ffi_guard::call(call, ffi_guard::CALL_TIMEOUT, move || {
+                    if unsafe { ffi::manatan_server_update_config(handle.raw(), json.as_ptr()) } {
+                        Ok(())
+                    } else {
+                        Err(ffi_error(call))
+                    }
+                })?;
+            }
+            drop(handle);
+            let mut launch = self.launch.lock().unwrap_or_else(|err| err.into_inner());
+            if let Some(value) = update.tracker_remote_search {
+                launch.tracker_remote_search = value;
+            }
+            if let Some(value) = update.tracker_search_ttl_seconds {
+                launch.tracker_search_ttl_seconds = value;
+            }
+            for (key, path) in paths {
+                match key {
+                    "downloads_path" => launch.downloads_path = path,
+                    "local_manga_path" => launch.local_manga_path = path,
+                    _ => launch.local_anime_path = path,
+                }
+            }
+        }
+        if let Some(enabled) = update
+            .webview_enabled
+            .filter(|enabled| *enabled != self.webview_enabled())
+        {
+            self.set_webview_enabled(enabled)?;
+            changed.push("webview_enabled".to_string());
+        }
+        Ok(changed)
+    }
+
+    pub(crate) fn aidoku_installed(&self) -> Result<serde_json::Value, Error> {
+        let call = "manatan_aidoku_list_installed";
+        let text = self.with_handle(call, ffi_guard::CALL_TIMEOUT, |handle| {
+            unsafe { take_string(ffi::manatan_aidoku_list_installed(handle)) }
+                .ok_or_else(|| ffi_error("manatan_aidoku_list_installed"))
+        })?;
+        serde_json::from_str(&text).map_err(|err| Error::Ffi {
+            call: "manatan_aidoku_list_installed",
+            detail: Some(format!("invalid extension list: {err}")),
+        })
+    }
+
+    pub(crate) fn aidoku_install(&self, package_path: &str) -> Result<(), Error> {
+        let package_path = to_cstring(package_path, "package_path")?;
+        self.with_handle(
+            "manatan_aidoku_install",
+            ffi_guard::INSTALL_TIMEOUT,
+            move |handle| match unsafe {
+                ffi::manatan_aidoku_install(handle, package_path.as_ptr())
+            } {
+                0 => Ok(()),
+                code => Err(ffi_error_code("manatan_aidoku_install", code)),
+            },
+        )
+    }
+
+    pub(crate) fn aidoku_uninstall(&self, id: &str) -> Result<(), Error> {
+        let id = to_cstring(id, "id")?;
+        self.with_handle(
+            "manatan_aidoku_uninstall",
+            ffi_guard::CALL_TIMEOUT,
+            move |handle| match unsafe { ffi::manatan_aidoku_uninstall(handle, id.as_ptr()) } {
+                0 => Ok(()),
+                code => Err(ffi_error_code("manatan_aidoku_uninstall", code)),
+            },
+        )
+    }
+
+    // The backend's own accounting, as JSON; `None` when the library does not report it.
+    pub(crate) fn resource_usage(&self) -> Option<serde_json::Value> {
+        let call = "manatan_server_resource_usage";
+        let text = self
+            .with_handle(call, ffi_guard::CALL_TIMEOUT, move |handle| {
+                unsafe { take_string(ffi::manatan_server_resource_usage(handle)) }
+                    .ok_or_else(|| ffi_error(call))
+            })
+            .ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    // Holds the backend's writers off until the guard drops; `None` when it is not running.
+    pub(crate) fn pause_writes(&self) -> Result<Option<WritePause<'_>>, Error> {
+        let handle = self.handle.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(handle) = handle.clone() else {
+            return Ok(None);
+        };
+        let call = "manatan_server_pause_writes";
+        ffi_guard::call(call, ffi_guard::CALL_TIMEOUT, move || {
+            if unsafe { ffi::manatan_server_pause_writes(handle.raw()) } {
+                Ok(())
+            } else {
+                Err(ffi_error(call))
+            }
+        })?;
+        Ok(Some(WritePause(self)))
+    }
+
+    fn with_handle<T, F>(&self, call: &'static str, timeout: Duration, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(*mut ffi::ManatanServerHandle) -> Result<T, Error> + Send + 'static,
+    {
+        let handle = self.handle.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(handle) = handle.clone() else {
+            return Err(Error::BackendUnavailable(
+                "backend is not running".to_string(),
+            ));
+        };
+        ffi_guard::call(call, timeout, move || f(handle.raw()))
+    }
+}
+
+pub(crate) struct WritePause<'a>(&'a EmbeddedServer);
+
+impl Drop for WritePause<'_> {
+    fn drop(&mut self) {
+        let handle = self.0.handle.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(handle) = handle.clone() else {
+            return;
+        };
+        let call = "manatan_server_resume_writes";
+        let resumed = ffi_guard::call(call, ffi_guard::CALL_TIMEOUT, move || {
+            unsafe { ffi::manatan_server_resume_writes(handle.raw()) };
+            Ok(())
+        });
+        if let Err(err) = resumed {
+            error!("{}", err);
+        }
+    }
+}
+
+impl Drop for EmbeddedServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// A stop that hangs is abandoned so a restart can still bring up a fresh backend.
+fn stop_handle(handle: *mut ffi::ManatanServerHandle) {
+    events::unregister(handle);
+    let handle = SendHandle::new(handle);
+    let stopped = ffi_guard::call("manatan_server_stop", ffi_guard::STOP_TIMEOUT, move || {
+        unsafe { ffi::manatan_server_stop(handle.get()) };
+        Ok(())
+    });
+    if let Err(err) = stopped {
+        error!("{}", err);
+    }
+}
+
+fn launch_backend(
+    launch: &LaunchConfig,
+    capabilities: &Capabilities,
+    webview_enabled: bool,
+) -> Result<*mut ffi::ManatanServerHandle, Error> {
+    if let Some(path) = &launch.socket_path {
+        prepare_socket(path)?;
+    }
+    let blob = launch.blob(capabilities, webview_enabled)?;
+    let handle = unsafe { ffi::manatan_server_start_json(blob.as_ptr(), Some(backend_log)) };
+    if handle.is_null() {
+        return Err(ffi_error("manatan_server_start_json"));
+    }
+    Ok(handle)
+}
+
+// A socket left behind by a previous run would make the bind fail.
+fn prepare_socket(path: &Path) -> Result<(), Error> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| Error::io(format!("create {}", parent.display()), err))?;
+    }
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(Error::io(
+            format!("remove stale socket {}", path.display()),
+            err,
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Copies a string the library allocated and hands it back to `manatan_string_free`.
+// Safety: `raw` is null or came from the library and is not used afterwards.
+pub(crate) unsafe fn take_string(raw: *mut c_char) -> Option<String> {
+    if raw.is_null() {
+        return None;
+    }
+    let text = unsafe { CStr::from_ptr(raw) }
+        .to_string_lossy()
+        .into_owned();
+    unsafe { ffi::manatan_string_free(raw) };
+    Some(text)
+}
+
+// The library keeps the reason for the last failed call on the calling thread, so
+// this has to run right after the failing call and before any other library call.
+fn last_error() -> Option<String> {
+    unsafe { take_string(ffi::manatan_server_last_error()) }
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+pub(crate) fn ffi_error(call: &'static str) -> Error {
+    Error::Ffi {
+        call,
+        detail: last_error(),
+    }
+}
+
+fn ffi_error_code(call: &'static str, code: i32) -> Error {
+    Error::Ffi {
+        call,
+        detail: Some(last_error().map_or_else(
+            || format!("code {code}"),
+            |detail| format!("{detail} (code {code})"),
+        )),
+    }
+}
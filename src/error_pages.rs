@@ -0,0 +1,158 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use tracing::warn;
+
+use crate::app::AppState;
+use crate::config::ErrorPagesConfig;
+
+const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{status}} {{title}} - {{instance}}</title>
+<style>
+body { font-family: system-ui, sans-serif; background: #111318; color: #e3e3e8; display: flex; align-items: center; justify-content: center; min-height: 100vh; margin: 0; }
+main { max-width: 32rem; padding: 2rem; text-align: center; }
+h1 { font-size: 3rem; margin: 0; }
+h2 { font-weight: 500; margin: 0.5rem 0 1.5rem; }
+p { color: #a9abb5; line-height: 1.5; }
+</style>
+</head>
+<body>
+<main>
+<h1>{{status}}</h1>
+<h2>{{title}}</h2>
+<p>{{detail}}</p>
+<p><small>{{instance}}</small></p>
+</main>
+</body>
+</html>
+"#;
+
+pub(crate) struct ErrorPages {
+    enabled: bool,
+    template: String,
+}
+
+impl ErrorPages {
+    pub(crate) fn new(config: &ErrorPagesConfig) -> Self {
+        let template = config
+            .template_path
+            .as_deref()
+            .and_then(|path| match std::fs::read_to_string(path) {
+                Ok(template) => Some(template),
+                Err(err) => {
+                    warn!("failed to read error page template {}: {}", path, err);
+                    None
+                }
+            })
+            .unwrap_or_else(|| DEFAULT_TEMPLATE.to_string());
+        Self {
+            enabled: config.enabled,
+            template,
+        }
+    }
+
+    pub(crate) fn render(&self, instance: &str, status: StatusCode) -> Option<String> {
+        let detail = match status {
+            StatusCode::BAD_GATEWAY => {
+                "The server could not reach its backend. It may still be starting up or restarting; try again in a moment."
+            }
+            StatusCode::SERVICE_UNAVAILABLE => {
+                "The server is temporarily unable to handle this request. Please try again shortly."
+            }
+            StatusCode::UNAUTHORIZED => {
+                "You need to sign in or provide a valid access token to view this page."
+            }
+            _ => return None,
+        };
+        Some(self.fill(instance, status, detail))
+    }
+
+    // For responses that explain themselves, e.g. an access schedule saying when it ends.
+    #[cfg(feature = "auth")]
+    pub(crate) fn page(&self, instance: &str, status: StatusCode, detail: &str) -> Option<String> {
+        self.enabled.then(|| self.fill(instance, status, detail))
+    }
+
+    fn fill(&self, instance: &str, status: StatusCode, detail: &str) -> String {
+        self.template
+            .replace("{{instance}}", &escape(instance))
+            .replace("{{status}}", status.as_str())
+            .replace(
+                "{{title}}",
+                &escape(status.canonical_reason().unwrap_or_default()),
+            )
+            .replace("{{detail}}", &escape(detail))
+    }
+}
+
+pub(crate) async fn middleware(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let wants_html = state.error_pages.enabled && prefers_html(req.headers());
+    let resp = next.run(req).await;
+    if !wants_html || resp.body().size_hint().exact() != Some(0) {
+        return resp;
+    }
+
+    let Some(page) = state
+        .error_pages
+        .render(&state.config.instance_name, resp.status())
+    else {
+        return resp;
+    };
+    let (mut parts, _) = resp.into_parts();
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("no-store"),
+    );
+    Response::from_parts(parts, Body::from(page))
+}
+
+pub(crate) fn prefers_html(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let mut html = 0.0f32;
+    let mut json = 0.0f32;
+    for item in accept.split(',') {
+        let mut params = item.split(';');
+        let media = params.next().unwrap_or_default().trim().to_lowercase();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|value| value.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match media.as_str() {
+            "text/html" | "application/xhtml+xml" => html = html.max(quality),
+            "application/json" => json = json.max(quality),
+            _ => {}
+        }
+    }
+    html > 0.0 && html >= json
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,127 @@
+use std::convert::Infallible;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use futures::{stream, Stream};
+use serde::Deserialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::app::AppState;
+use crate::{ffi, ffi_guard};
+
+pub(crate) const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone, Debug)]
+pub(crate) struct BackendEvent {
+    pub kind: String,
+    pub payload: String,
+}
+
+pub(crate) fn register(
+    handle: *mut ffi::ManatanServerHandle,
+    sender: &broadcast::Sender<BackendEvent>,
+) {
+    let user_data = sender as *const broadcast::Sender<BackendEvent> as *mut c_void;
+    unsafe { ffi::manatan_server_set_event_callback(handle, Some(on_event), user_data) };
+}
+
+pub(crate) fn unregister(handle: *mut ffi::ManatanServerHandle) {
+    unsafe { ffi::manatan_server_set_event_callback(handle, None, std::ptr::null_mut()) };
+}
+
+unsafe extern "C" fn on_event(user_data: *mut c_void, kind: *const c_char, payload: *const c_char) {
+    if user_data.is_null() || kind.is_null() {
+        return;
+    }
+    ffi_guard::callback("event", || {
+        let sender = unsafe { &*(user_data as *const broadcast::Sender<BackendEvent>) };
+        let kind = unsafe { CStr::from_ptr(kind) }
+            .to_string_lossy()
+            .into_owned();
+        // SSE frames are line based: a line break in the kind cannot be sent at all
+        // (axum panics on it), and a bare or paired `\r` in the payload would split it
+        // into extra data lines.
+        if kind.contains(['\n', '\r']) {
+            warn!("dropping backend event with a line break in its kind: {kind:?}");
+            return;
+        }
+        let payload = if payload.is_null() {
+            String::new()
+        } else {
+            normalize_newlines(&unsafe { CStr::from_ptr(payload) }.to_string_lossy())
+        };
+        let _ = sender.send(BackendEvent { kind, payload });
+    });
+}
+
+fn normalize_newlines(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/events", get(events))
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    kinds: Option<String>,
+}
+
+async fn events(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let kinds: Vec<String> = query
+        .kinds
+        .unwrap_or_default()
+        .split(',')
+        .map(|kind| kind.trim().to_string())
+        .filter(|kind| !kind.is_empty())
+        .collect();
+    let receiver = state.events.subscribe();
+    let stream = stream::unfold((receiver, kinds), |(mut receiver, kinds)| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    let event = Event::default().event("lagged").data(skipped.to_string());
+                    return Some((Ok(event), (receiver, kinds)));
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            if !kinds.is_empty() && !matches(&kinds, &event.kind) {
+                continue;
+            }
+            let event = Event::default().event(event.kind).data(event.payload);
+            return Some((Ok(event), (receiver, kinds)));
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
+
+fn matches(kinds: &[String], kind: &str) -> bool {
+    kinds.iter().any(|prefix| {
+        kind == prefix
+            || kind
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_line_breaks_become_newlines() {
+        assert_eq!(normalize_newlines("a\r\nb\rc\nd"), "a\nb\nc\nd");
+    }
+}
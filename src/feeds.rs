@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::app::AppState;
+use crate::unix_now;
+use crate::updates::{self, RecentChapter};
+
+const DEFAULT_PAGES: u32 = 2;
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/feeds/updates.atom", get(updates_feed))
+}
+
+#[derive(Deserialize)]
+struct FeedQuery {
+    category: Option<i64>,
+    token: Option<String>,
+    pages: Option<u32>,
+}
+
+async fn updates_feed(
+    State(state): State<AppState>,
+    Query(query): Query<FeedQuery>,
+    mut headers: HeaderMap,
+) -> Response {
+    if let Some(token) = query.token.as_deref() {
+        if !headers.contains_key(header::AUTHORIZATION) {
+            if let Ok(value) = HeaderValue::from_str(&format!("Bearer {token}")) {
+                headers.insert(header::AUTHORIZATION, value);
+            }
+        }
+    }
+
+    let pages = query.pages.unwrap_or(DEFAULT_PAGES);
+    let mut chapters = match updates::recent_chapters(&state, &headers, pages).await {
+        Ok(chapters) => chapters,
+        Err(err) => {
+            warn!("feeds: failed to load recent chapters: {}", err);
+            return bad_gateway();
+        }
+    };
+
+    let mut title = "Manatan: new chapters".to_string();
+    if let Some(category) = query.category {
+        let members = match state
+            .backend_json(&format!("/api/v1/category/{category}"), &headers)
+            .await
+        {
+            Ok(value) => value
+                .as_array()
+                .map(|items| {
+                    items
+                        .iter()
+                        .filter_map(|item| item.get("id").and_then(Value::as_i64))
+                        .collect::<HashSet<_>>()
+                })
+                .unwrap_or_default(),
+            Err(err) => {
+                warn!("feeds: failed to load category {}: {}", category, err);
+                return bad_gateway();
+            }
+        };
+        chapters.retain(|chapter| members.contains(&chapter.manga_id));
+        title = format!("{title} (category {category})");
+    }
+    chapters.sort_by_key(|chapter| std::cmp::Reverse(chapter.released_at()));
+
+    let base = state.external_base_url(&headers);
+    let body = render(&title, &base, &chapters);
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn render(title: &str, base: &str, chapters: &[RecentChapter]) -> String {
+    let updated = chapters
+        .first()
+        .map(RecentChapter::released_at)
+        .unwrap_or_else(|| unix_now() as i64);
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    out.push_str(&format!(
+        "  <id>urn:manatan:feed:updates</id>\n  <title>{}</title>\n",
+        escape(title)
+    ));
+    out.push_str(&format!("  <updated>{}</updated>\n", rfc3339(updated)));
+    out.push_str(&format!(
+        "  <link rel=\"alternate\" href=\"{}\"/>\n",
+        escape(&format!("{base}/updates"))
+    ));
+    out.push_str("  <generator>Manatan</generator>\n");
+
+    for chapter in chapters {
+        let link = format!(
+            "{base}/manga/{}/chapter/{}",
+            chapter.manga_id, chapter.chapter_index
+        );
+        out.push_str("  <entry>\n");
+        out.push_str(&format!(
+            "    <id>urn:manatan:chapter:{}</id>\n",
+            chapter.chapter_id
+        ));
+        out.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape(&format!(
+                "{}: {}",
+                chapter.manga_title, chapter.chapter_name
+            ))
+        ));
+        out.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            rfc3339(chapter.released_at())
+        ));
+        out.push_str(&format!(
+            "    <link rel=\"alternate\" href=\"{}\"/>\n",
+            escape(&link)
+        ));
+        out.push_str(&format!(
+            "    <author><name>{}</name></author>\n",
+            escape(&chapter.manga_title)
+        ));
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn rfc3339(unix: i64) -> String {
+    DateTime::<Utc>::from_timestamp(unix, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+fn bad_gateway() -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_GATEWAY)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -1,21 +1,19 @@
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
+
+pub type ManatanLogCallback = unsafe extern "C" fn(stream: u8, line: *const c_char, len: usize);
+
+pub type ManatanEventCallback =
+    unsafe extern "C" fn(user_data: *mut c_void, kind: *const c_char, payload: *const c_char);
+
+pub const MANATAN_STREAM_STDOUT: u8 = 1;
+pub const MANATAN_STREAM_STDERR: u8 = 2;
 
 #[repr(C)]
-pub struct ManatanServerConfig {
-    pub host: *const c_char,
-    pub port: u16,
-    pub java_runtime_url: *const c_char,
-    pub webview_enabled: u8,
-    pub aidoku_index_url: *const c_char,
-    pub aidoku_enabled: u8,
-    pub aidoku_cache_path: *const c_char,
-    pub db_path: *const c_char,
-    pub migrate_path: *const c_char,
-    pub tracker_remote_search: u8,
-    pub tracker_search_ttl_seconds: i64,
-    pub downloads_path: *const c_char,
-    pub local_manga_path: *const c_char,
-    pub local_anime_path: *const c_char,
+pub struct ManatanCefOptions {
+    pub cache_dir: *const c_char,
+    pub user_agent: *const c_char,
+    pub proxy_server: *const c_char,
+    pub headless: u8,
 }
 
 #[repr(C)]
@@ -24,8 +22,40 @@ pub struct ManatanServerHandle {
 }
 
 extern "C" {
-    pub fn manatan_server_start(config: *const ManatanServerConfig) -> *mut ManatanServerHandle;
+    pub fn manatan_server_capabilities() -> *mut c_char;
+    pub fn manatan_server_start_json(
+        config_json: *const c_char,
+        log_callback: Option<ManatanLogCallback>,
+    ) -> *mut ManatanServerHandle;
     pub fn manatan_server_stop(handle: *mut ManatanServerHandle);
     pub fn manatan_server_port(handle: *const ManatanServerHandle) -> u16;
+    pub fn manatan_server_set_webview_enabled(
+        handle: *mut ManatanServerHandle,
+        enabled: u8,
+    ) -> bool;
+    pub fn manatan_server_update_config(
+        handle: *mut ManatanServerHandle,
+        settings_json: *const c_char,
+    ) -> bool;
+    pub fn manatan_server_pause_writes(handle: *mut ManatanServerHandle) -> bool;
+    pub fn manatan_server_resume_writes(handle: *mut ManatanServerHandle);
     pub fn manatan_server_try_handle_subprocess() -> bool;
+    pub fn manatan_cef_init(options: *const ManatanCefOptions) -> bool;
+    pub fn manatan_cef_cookies(domain: *const c_char) -> *mut c_char;
+    pub fn manatan_cef_clear_cookies(domain: *const c_char) -> bool;
+    pub fn manatan_cef_flush_cookies() -> bool;
+    pub fn manatan_server_last_error() -> *mut c_char;
+    pub fn manatan_server_resource_usage(handle: *mut ManatanServerHandle) -> *mut c_char;
+    pub fn manatan_server_set_event_callback(
+        handle: *mut ManatanServerHandle,
+        callback: Option<ManatanEventCallback>,
+        user_data: *mut c_void,
+    );
+    pub fn manatan_aidoku_list_installed(handle: *mut ManatanServerHandle) -> *mut c_char;
+    pub fn manatan_aidoku_install(
+        handle: *mut ManatanServerHandle,
+        package_path: *const c_char,
+    ) -> i32;
+    pub fn manatan_aidoku_uninstall(handle: *mut ManatanServerHandle, id: *const c_char) -> i32;
+    pub fn manatan_string_free(value: *mut c_char);
 }
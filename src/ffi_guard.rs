@@ -0,0 +1,89 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use tracing::error;
+
+use crate::{ffi, Error};
+
+pub(crate) const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+pub(crate) const STOP_TIMEOUT: Duration = Duration::from_secs(60);
+pub(crate) const INSTALL_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Raw handles are not `Send`; the library allows calls on a handle from any thread.
+#[derive(Clone, Copy)]
+pub(crate) struct SendHandle(*mut ffi::ManatanServerHandle);
+
+unsafe impl Send for SendHandle {}
+
+impl SendHandle {
+    pub(crate) fn new(handle: *mut ffi::ManatanServerHandle) -> Self {
+        Self(handle)
+    }
+
+    // A method rather than `.0`, so closures capture the wrapper and stay `Send`.
+    pub(crate) fn get(self) -> *mut ffi::ManatanServerHandle {
+        self.0
+    }
+}
+
+// Callbacks run on library threads; a panic must not unwind into C.
+pub(crate) fn callback(name: &'static str, f: impl FnOnce()) {
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+        error!("{} callback panicked: {}", name, message(&*payload));
+    }
+}
+
+// Runs a library call on its own thread and gives up on it after `timeout`, so a hung
+// backend surfaces as an error instead of freezing the caller. The thread is left
+// behind if the call never returns, so `f` must own what it touches; backend calls
+// move in a clone of the `BackendHandle`. `f` also reads `manatan_server_last_error`,
+// which is per thread.
+pub(crate) fn call<T, F>(call: &'static str, timeout: Duration, f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let spawned = std::thread::Builder::new()
+        .name(format!("ffi-{call}"))
+        .spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+                Err(Error::Ffi {
+                    call,
+                    detail: Some(format!("panicked: {}", message(&*payload))),
+                })
+            });
+            let _ = sender.send(result);
+        });
+    if let Err(err) = spawned {
+        return Err(Error::io(format!("spawn thread for {call}"), err));
+    }
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            error!(
+                "{} has not returned after {}s, abandoning it",
+                call,
+                timeout.as_secs()
+            );
+            Err(Error::Ffi {
+                call,
+                detail: Some(format!("timed out after {}s", timeout.as_secs())),
+            })
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(Error::Ffi {
+            call,
+            detail: Some("call thread exited without a result".to_string()),
+        }),
+    }
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|text| text.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
@@ -0,0 +1,144 @@
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::{HwAccel, HwAccelConfig};
+
+// Best first: dedicated encoders before the generic VAAPI path.
+const PREFERENCE: &[HwAccel] = &[
+    HwAccel::Nvenc,
+    HwAccel::Qsv,
+    HwAccel::VideoToolbox,
+    HwAccel::Vaapi,
+];
+
+#[derive(Clone, Debug, Serialize)]
+struct Found {
+    backend: HwAccel,
+    device: Option<String>,
+}
+
+// What ffmpeg in the backend is told to use, and what the host looked like when it was picked.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Selection {
+    pub(crate) selected: HwAccel,
+    pub(crate) device: Option<String>,
+    source: &'static str,
+    available: Vec<Found>,
+}
+
+pub(crate) fn select(config: &HwAccelConfig) -> Selection {
+    let available = detect();
+    let found = |backend: HwAccel| available.iter().find(|found| found.backend == backend);
+    let (selected, source) = match config.backend {
+        Some(backend) => {
+            if backend != HwAccel::Software && found(backend).is_none() {
+                warn!(
+                    "MANATAN_HWACCEL={:?} was not detected on this host, using it anyway",
+                    backend
+                );
+            }
+            (backend, "config")
+        }
+        None => (
+            PREFERENCE
+                .iter()
+                .copied()
+                .find(|backend| found(*backend).is_some())
+                .unwrap_or(HwAccel::Software),
+            "auto",
+        ),
+    };
+    let device = config
+        .device
+        .clone()
+        .or_else(|| found(selected).and_then(|found| found.device.clone()));
+    info!(
+        "transcoding with {:?}{}",
+        selected,
+        device
+            .as_deref()
+            .map(|device| format!(" on {device}"))
+            .unwrap_or_default()
+    );
+    Selection {
+        selected,
+        device,
+        source,
+        available,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn detect() -> Vec<Found> {
+    use std::path::Path;
+
+    let mut found = Vec::new();
+    if Path::new("/dev/nvidiactl").exists() && Path::new("/proc/driver/nvidia/version").exists() {
+        found.push(Found {
+            backend: HwAccel::Nvenc,
+            device: None,
+        });
+    }
+    // Render nodes are named by the kernel; the PCI vendor says whose GPU is behind each.
+    let mut nodes = std::fs::read_dir("/sys/class/drm")
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| name.starts_with("renderD"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    nodes.sort();
+    for node in nodes {
+        let device = format!("/dev/dri/{node}");
+        if !Path::new(&device).exists() {
+            continue;
+        }
+        let vendor = std::fs::read_to_string(format!("/sys/class/drm/{node}/device/vendor"))
+            .unwrap_or_default();
+        let backends: &[HwAccel] = match vendor.trim() {
+            "0x8086" => &[HwAccel::Qsv, HwAccel::Vaapi],
+            "0x1002" => &[HwAccel::Vaapi],
+            _ => &[],
+        };
+        for backend in backends {
+            if !found.iter().any(|found| found.backend == *backend) {
+                found.push(Found {
+                    backend: *backend,
+                    device: Some(device.clone()),
+                });
+            }
+        }
+    }
+    found
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn detect() -> Vec<Found> {
+    vec![Found {
+        backend: HwAccel::VideoToolbox,
+        device: None,
+    }]
+}
+
+#[cfg(windows)]
+fn detect() -> Vec<Found> {
+    let system = std::env::var_os("SystemRoot").unwrap_or_else(|| "C:\\Windows".into());
+    let nvenc = std::path::Path::new(&system)
+        .join("System32")
+        .join("nvEncodeAPI64.dll");
+    if nvenc.exists() {
+        vec![Found {
+            backend: HwAccel::Nvenc,
+            device: None,
+        }]
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "ios", windows)))]
+fn detect() -> Vec<Found> {
+    Vec::new()
+}
@@ -0,0 +1,315 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{header, HeaderValue, Method, StatusCode},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::app::{forward, AppState};
+use crate::body::buffer;
+#[cfg(feature = "cache")]
+use crate::cache_store::{self, CacheStore};
+use crate::config::ImageCacheConfig;
+use crate::principal::Principal;
+
+pub(crate) const MAX_CACHED_BODY: usize = 32 * 1024 * 1024;
+#[cfg(feature = "cache")]
+const ARCHIVE_MAX_AGE_SECONDS: u64 = 30 * 24 * 3600;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Meta {
+    pub content_type: Option<String>,
+    pub etag: String,
+    pub stored_at: u64,
+}
+
+pub(crate) struct ImageCache {
+    config: ImageCacheConfig,
+    #[cfg(feature = "cache")]
+    backend: Box<dyn CacheStore>,
+}
+
+pub(crate) fn is_image_path(path: &str) -> bool {
+    if path.starts_with("/extension/icon/") || path.starts_with("/api/v1/extension/icon/") {
+        return true;
+    }
+    let Some(rest) = path.strip_prefix("/api/v1/manga/") else {
+        return false;
+    };
+    let segments: Vec<&str> = rest.split('/').collect();
+    matches!(
+        segments.as_slice(),
+        [_, "thumbnail"] | [_, "chapter", _, "page", _]
+    )
+}
+
+impl ImageCache {
+    #[cfg(feature = "cache")]
+    pub(crate) fn new(config: ImageCacheConfig, data_path: &str) -> Self {
+        Self {
+            backend: cache_store::open(&config, data_path),
+            config,
+        }
+    }
+
+    // Without the `cache` feature there is nowhere to keep entries; the config comes
+    // through disabled, so nothing below is reached.
+    #[cfg(not(feature = "cache"))]
+    pub(crate) fn new(config: ImageCacheConfig, _data_path: &str) -> Self {
+        Self { config }
+    }
+
+    #[cfg(feature = "cache")]
+    async fn lookup(&self, hash: &str) -> Option<(Meta, Bytes)> {
+        let (meta, body) = self.backend.get(hash).await?;
+        if crate::unix_now().saturating_sub(meta.stored_at) >= self.config.ttl_seconds {
+            self.backend.remove(hash).await;
+            return None;
+        }
+        Some((meta, body))
+    }
+
+    #[cfg(feature = "cache")]
+    async fn store(&self, hash: &str, meta: &Meta, body: &Bytes) {
+        self.backend.put(hash, meta, body).await;
+    }
+
+    // Round-trips a throwaway entry through the cache store; `None` when disabled.
+    #[cfg(feature = "cache")]
+    pub(crate) async fn self_test(&self) -> Option<Result<(), String>> {
+        if !self.config.enabled {
+            return None;
+        }
+        let hash = format!("selftest-{}", std::process::id());
+        let body = Bytes::from_static(b"manatan-selftest");
+        let meta = Meta {
+            content_type: Some("text/plain".to_string()),
+            etag: "\"selftest\"".to_string(),
+            stored_at: crate::unix_now(),
+        };
+        self.store(&hash, &meta, &body).await;
+        let found = self.lookup(&hash).await;
+        self.backend.remove(&hash).await;
+        Some(match found {
+            Some((_, read)) if read == body => Ok(()),
+            Some(_) => Err("read back different bytes".to_string()),
+            None => Err(format!("could not write to {}", self.backend.describe())),
+        })
+    }
+
+    #[cfg(not(feature = "cache"))]
+    async fn lookup(&self, _hash: &str) -> Option<(Meta, Bytes)> {
+        None
+    }
+
+    #[cfg(not(feature = "cache"))]
+    async fn store(&self, _hash: &str, _meta: &Meta, _body: &Bytes) {}
+
+    #[cfg(not(feature = "cache"))]
+    pub(crate) async fn self_test(&self) -> Option<Result<(), String>> {
+        None
+    }
+}
+
+// Resized or re-encoded variants are cached under their own URL, next to the original.
+pub(crate) async fn serve(state: &AppState, req: Request) -> Response {
+    let token_profile = req
+        .extensions()
+        .get::<Principal>()
+        .and_then(|principal| principal.image_profile.clone());
+    let Some((transform, profile, upstream)) = state
+        .image_transform
+        .requested(req.uri(), token_profile.as_deref())
+    else {
+        return serve_original(state, req).await;
+    };
+    let cache = &state.image_cache;
+    let caching = cache.config.enabled && req.method() == Method::GET;
+    let key = match &profile {
+        Some(profile) => format!("{}#{profile}", req.uri()),
+        None => req.uri().to_string(),
+    };
+    let hash = hex_digest(key.as_bytes());
+    let if_none_match = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    if caching {
+        if let Some((meta, body)) = cache.lookup(&hash).await {
+            let resp = respond(&meta, body, cache.config.ttl_seconds, "hit");
+            return revalidate(resp, if_none_match.as_deref());
+        }
+    }
+
+    let (mut parts, body) = req.into_parts();
+    parts.uri = upstream;
+    for name in [header::IF_NONE_MATCH, header::IF_MODIFIED_SINCE] {
+        parts.headers.remove(name);
+    }
+    let resp = serve_original(state, Request::from_parts(parts, body)).await;
+    if !is_cacheable(&resp) {
+        return resp;
+    }
+    let (parts, body) = resp.into_parts();
+    let source = match buffer(body, MAX_CACHED_BODY).await {
+        Ok(source) => source,
+        Err(body) => return Response::from_parts(parts, body),
+    };
+    let (bytes, content_type) = match state.image_transform.apply(transform, source.clone()).await {
+        Ok(transformed) => transformed,
+        Err(err) => {
+            warn!("image transform failed, serving the original: {}", err);
+            return Response::from_parts(parts, Body::from(source));
+        }
+    };
+    let meta = Meta {
+        content_type: Some(content_type.to_string()),
+        etag: format!("\"{}\"", &hex_digest(&bytes)[..24]),
+        stored_at: crate::unix_now(),
+    };
+    if caching {
+        cache.store(&hash, &meta, &bytes).await;
+    }
+    let resp = respond(&meta, bytes, cache.config.ttl_seconds, "miss");
+    revalidate(resp, if_none_match.as_deref())
+}
+
+// Archived pages are answered first, even with the backend down; fresh ones are archived
+// on the way out when their series is in the library.
+#[cfg(feature = "cache")]
+async fn serve_original(state: &AppState, req: Request) -> Response {
+    let Some(key) = state.page_archive.key(&req) else {
+        return serve_cached(state, req).await;
+    };
+    if let Some((meta, body)) = state.page_archive.lookup(&key).await {
+        let if_none_match = req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok());
+        // Archived pages do not go stale, so the age counts from now.
+        let meta = Meta {
+            stored_at: crate::unix_now(),
+            ..meta
+        };
+        let resp = respond(&meta, body, ARCHIVE_MAX_AGE_SECONDS, "archive");
+        return revalidate(resp, if_none_match);
+    }
+    let resp = serve_cached(state, req).await;
+    state.page_archive.keep(key, resp).await
+}
+
+#[cfg(not(feature = "cache"))]
+async fn serve_original(state: &AppState, req: Request) -> Response {
+    serve_cached(state, req).await
+}
+
+async fn serve_cached(state: &AppState, mut req: Request) -> Response {
+    let cache = &state.image_cache;
+    if !cache.config.enabled || req.method() != Method::GET {
+        return forward(state, req).await;
+    }
+    let key = req
+        .uri()
+        .path_and_query()
+        .map(|value| value.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let hash = hex_digest(key.as_bytes());
+    let if_none_match = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    if let Some((meta, body)) = cache.lookup(&hash).await {
+        let resp = respond(&meta, body, cache.config.ttl_seconds, "hit");
+        return revalidate(resp, if_none_match.as_deref());
+    }
+
+    for name in [header::IF_NONE_MATCH, header::IF_MODIFIED_SINCE] {
+        req.headers_mut().remove(name);
+    }
+    let resp = forward(state, req).await;
+    if !is_cacheable(&resp) {
+        return resp;
+    }
+
+    let (parts, body) = resp.into_parts();
+    let bytes = match buffer(body, MAX_CACHED_BODY).await {
+        Ok(bytes) => bytes,
+        Err(body) => return Response::from_parts(parts, body),
+    };
+    let meta = Meta {
+        content_type: parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        etag: parts
+            .headers
+            .get(header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("\"{}\"", &hex_digest(&bytes)[..24])),
+        stored_at: crate::unix_now(),
+    };
+    cache.store(&hash, &meta, &bytes).await;
+    let resp = respond(&meta, bytes, cache.config.ttl_seconds, "miss");
+    revalidate(resp, if_none_match.as_deref())
+}
+
+pub(crate) fn is_cacheable(resp: &Response) -> bool {
+    let headers = resp.headers();
+    let image = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("image/"));
+    let no_store = headers
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.to_lowercase().contains("no-store"));
+    resp.status() == StatusCode::OK && image && !no_store
+}
+
+fn respond(meta: &Meta, body: Bytes, ttl_seconds: u64, status: &'static str) -> Response {
+    let max_age = ttl_seconds.saturating_sub(crate::unix_now().saturating_sub(meta.stored_at));
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CACHE_CONTROL, format!("private, max-age={max_age}"))
+        .header("x-manatan-cache", status);
+    if let Ok(etag) = HeaderValue::from_str(&meta.etag) {
+        builder = builder.header(header::ETAG, etag);
+    }
+    if let Some(content_type) = &meta.content_type {
+        builder = builder.header(header::CONTENT_TYPE, content_type);
+    }
+    builder.body(Body::from(body)).unwrap()
+}
+
+fn revalidate(resp: Response, if_none_match: Option<&str>) -> Response {
+    let Some(etag) = resp.headers().get(header::ETAG).cloned() else {
+        return resp;
+    };
+    let matches = if_none_match.is_some_and(|value| {
+        value.split(',').any(|candidate| {
+            candidate.trim() == "*" || candidate.trim().as_bytes() == etag.as_bytes()
+        })
+    });
+    if !matches {
+        return resp;
+    }
+    let (mut parts, _) = resp.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(header::CONTENT_TYPE);
+    Response::from_parts(parts, Body::empty())
+}
+
+pub(crate) fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use axum::body::Bytes;
+use axum::http::Uri;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::config::ImageTransformConfig;
+
+const MAX_SOURCE_DIMENSION: u32 = 16_384;
+const AVIF_SPEED: u8 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Format {
+    Webp,
+    Avif,
+    #[serde(alias = "jpg")]
+    Jpeg,
+    Png,
+}
+
+impl Format {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "webp" => Some(Self::Webp),
+            "avif" => Some(Self::Avif),
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Webp => "image/webp",
+            Self::Avif => "image/avif",
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Transform {
+    width: Option<u32>,
+    height: Option<u32>,
+    format: Option<Format>,
+    quality: u8,
+    grayscale: bool,
+    contrast: f32,
+}
+
+// A named set of conversions applied to every page a device fetches, picked by the
+// token's `image_profile` or a `profile` query parameter.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct ConversionProfile {
+    #[serde(default)]
+    pub grayscale: bool,
+    // Percent, as for `DynamicImage::adjust_contrast`; negative values soften.
+    #[serde(default)]
+    pub contrast: f32,
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    #[serde(default)]
+    pub format: Option<Format>,
+    #[serde(default)]
+    pub quality: Option<u8>,
+}
+
+pub(crate) struct ImageTransformer {
+    config: ImageTransformConfig,
+    permits: Semaphore,
+    profiles: BTreeMap<String, ConversionProfile>,
+}
+
+impl ImageTransformer {
+    pub(crate) fn new(config: ImageTransformConfig) -> Self {
+        let mut profiles = builtin_profiles();
+        if let Some(path) = config.profiles_file.as_deref() {
+            match std::fs::read(path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| {
+                    serde_json::from_slice::<BTreeMap<String, ConversionProfile>>(&bytes)
+                        .map_err(|err| err.to_string())
+                }) {
+                Ok(custom) => profiles.extend(custom),
+                Err(err) => warn!("ignoring image profiles file {}: {}", path, err),
+            }
+        }
+        Self {
+            permits: Semaphore::new(config.concurrency),
+            config,
+            profiles,
+        }
+    }
+
+    pub(crate) fn profiles(&self) -> &BTreeMap<String, ConversionProfile> {
+        &self.profiles
+    }
+
+    // Splits `width`, `height`, `format`, `quality` and `profile` off the query; the rest
+    // goes upstream. A profile, from the query or the caller's token, only applies to
+    // chapter pages and sets the defaults the other parameters can still narrow.
+    // Returns the profile's name with the transform so the variant is cached apart.
+    pub(crate) fn requested(
+        &self,
+        uri: &Uri,
+        token_profile: Option<&str>,
+    ) -> Option<(Transform, Option<String>, Uri)> {
+        if !self.config.enabled {
+            return None;
+        }
+        let mut transform = Transform {
+            width: None,
+            height: None,
+            format: None,
+            quality: self.config.quality,
+            grayscale: false,
+            contrast: 0.0,
+        };
+        let mut profile = token_profile.map(str::to_string);
+        let mut quality = None;
+        let mut rest = Vec::new();
+        let query = uri.query().unwrap_or_default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let dimension = || {
+                value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|value| *value > 0)
+                    .map(|value| value.min(self.config.max_width))
+            };
+            match key {
+                "width" => transform.width = dimension(),
+                "height" => transform.height = dimension(),
+                "format" => transform.format = Format::parse(value),
+                "profile" => profile = Some(value.to_string()).filter(|value| !value.is_empty()),
+                "quality" => quality = value.parse::<u8>().ok(),
+                _ => rest.push(pair),
+            }
+        }
+        let profile = profile
+            .filter(|_| is_page_path(uri.path()))
+            .and_then(|name| match self.profiles.get(&name) {
+                Some(found) => Some((name, found)),
+                None => {
+                    warn!("unknown image profile: {}", name);
+                    None
+                }
+            });
+        if let Some((_, found)) = profile {
+            let narrow = |requested: Option<u32>, limit: Option<u32>| match (requested, limit) {
+                (Some(requested), Some(limit)) => Some(requested.min(limit)),
+                (requested, limit) => requested.or(limit),
+            };
+            transform.width = narrow(transform.width, found.max_width);
+            transform.height = narrow(transform.height, found.max_height);
+            transform.format = transform.format.or(found.format);
+            transform.quality = found.quality.unwrap_or(transform.quality);
+            transform.grayscale = found.grayscale;
+            transform.contrast = found.contrast;
+        } else if (transform.width, transform.height, transform.format) == (None, None, None) {
+            return None;
+        }
+        if let Some(quality) = quality {
+            transform.quality = quality;
+        }
+        transform.quality = transform.quality.clamp(1, 100);
+        let path = if rest.is_empty() {
+            uri.path().to_string()
+        } else {
+            format!("{}?{}", uri.path(), rest.join("&"))
+        };
+        let mut parts = uri.clone().into_parts();
+        parts.path_and_query = path.parse().ok();
+        Some((
+            transform,
+            profile.map(|(name, _)| name),
+            Uri::from_parts(parts).ok()?,
+        ))
+    }
+
+    // Decoding and encoding run on the blocking pool, a few at a time, so a burst of
+    // page requests cannot starve the proxy.
+    pub(crate) async fn apply(
+        &self,
+        transform: Transform,
+        source: Bytes,
+    ) -> Result<(Bytes, &'static str), String> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|err| err.to_string())?;
+        tokio::task::spawn_blocking(move || convert(transform, &source))
+            .await
+            .map_err(|err| err.to_string())?
+    }
+}
+
+fn convert(transform: Transform, source: &[u8]) -> Result<(Bytes, &'static str), String> {
+    let mut reader = ImageReader::new(Cursor::new(source))
+        .with_guessed_format()
+        .map_err(|err| err.to_string())?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    reader.limits(limits);
+    let source_format = reader.format();
+    let mut image = reader.decode().map_err(|err| err.to_string())?;
+
+    // Fit inside the requested box; never upscale.
+    let width = transform.width.unwrap_or(u32::MAX).min(image.width());
+    let height = transform.height.unwrap_or(u32::MAX).min(image.height());
+    if width < image.width() || height < image.height() {
+        image = image.resize(width, height, FilterType::CatmullRom);
+    }
+    if transform.grayscale {
+        image = image.grayscale();
+    }
+    if transform.contrast != 0.0 {
+        image = image.adjust_contrast(transform.contrast);
+    }
+
+    let format = transform.format.unwrap_or(match source_format {
+        Some(ImageFormat::Png) => Format::Png,
+        Some(ImageFormat::WebP) => Format::Webp,
+        _ => Format::Jpeg,
+    });
+    let encoded = encode(&image, format, transform.quality)?;
+    Ok((Bytes::from(encoded), format.content_type()))
+}
+
+fn encode(image: &DynamicImage, format: Format, quality: u8) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    match format {
+        Format::Webp => {
+            let encoded = if image.color().has_alpha() {
+                let rgba = image.to_rgba8();
+                webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+                    .encode(f32::from(quality))
+                    .to_vec()
+            } else {
+                let rgb = image.to_rgb8();
+                webp::Encoder::from_rgb(&rgb, rgb.width(), rgb.height())
+                    .encode(f32::from(quality))
+                    .to_vec()
+            };
+            return Ok(encoded);
+        }
+        Format::Avif => image
+            .write_with_encoder(AvifEncoder::new_with_speed_quality(
+                &mut out, AVIF_SPEED, quality,
+            ))
+            .map_err(|err| err.to_string())?,
+        // JPEG has no alpha channel; transparent pixels come out black. Gray pages are
+        // written as single-channel JPEGs, a good deal smaller than the RGB equivalent.
+        Format::Jpeg => {
+            let flat = if image.color().has_color() {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            } else {
+                DynamicImage::ImageLuma8(image.to_luma8())
+            };
+            flat.write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality))
+                .map_err(|err| err.to_string())?
+        }
+        Format::Png => image
+            .write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .map_err(|err| err.to_string())?,
+    }
+    Ok(out)
+}
+
+fn is_page_path(path: &str) -> bool {
+    let Some(rest) = path.strip_prefix("/api/v1/manga/") else {
+        return false;
+    };
+    matches!(
+        rest.split('/').collect::<Vec<_>>().as_slice(),
+        [_, "chapter", _, "page", _]
+    )
+}
+
+// Sized for 6" and 7-8" e-readers; pages are flattened to gray JPEG, which their
+// browsers decode far faster than color PNG or WebP.
+fn builtin_profiles() -> BTreeMap<String, ConversionProfile> {
+    let eink = |max_width, max_height| ConversionProfile {
+        grayscale: true,
+        contrast: 15.0,
+        max_width: Some(max_width),
+        max_height: Some(max_height),
+        format: Some(Format::Jpeg),
+        quality: Some(70),
+    };
+    BTreeMap::from([
+        ("eink".to_string(), eink(1072, 1448)),
+        ("eink-hd".to_string(), eink(1264, 1680)),
+    ])
+}
@@ -0,0 +1,443 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::backup::move_path;
+use crate::config::InboxConfig;
+use crate::library;
+use crate::local_manga::{self, sanitize};
+use crate::unix_now;
+
+const REJECTED_DIR: &str = ".rejected";
+const MAX_RECENT: usize = 100;
+const MAX_COMIC_INFO_BYTES: u64 = 256 * 1024;
+const MANGA_EXTENSIONS: &[&str] = &["cbz", "cbr"];
+const ANIME_EXTENSIONS: &[&str] = &["mkv", "mp4", "webm", "avi", "m4v"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Manga,
+    Anime,
+}
+
+impl Kind {
+    fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        if MANGA_EXTENSIONS.contains(&extension.as_str()) {
+            Some(Self::Manga)
+        } else if ANIME_EXTENSIONS.contains(&extension.as_str()) {
+            Some(Self::Anime)
+        } else {
+            None
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Manga => "manga",
+            Self::Anime => "anime",
+        }
+    }
+}
+
+struct Candidate {
+    path: PathBuf,
+    kind: Kind,
+    // The series folder the file was dropped in, if any.
+    folder: Option<String>,
+}
+
+// Watches a drop folder and files what lands there into the local library. A file is
+// only taken once its size and modification time held still for a whole interval,
+// so half-copied files are left alone.
+pub(crate) struct Inbox {
+    config: InboxConfig,
+    seen: Mutex<HashMap<PathBuf, (u64, SystemTime)>>,
+    recent: Mutex<VecDeque<Value>>,
+    wake: Notify,
+}
+
+impl Inbox {
+    pub(crate) fn new(config: InboxConfig) -> Self {
+        Self {
+            config,
+            seen: Mutex::new(HashMap::new()),
+            recent: Mutex::new(VecDeque::new()),
+            wake: Notify::new(),
+        }
+    }
+
+    fn record(&self, outcome: Value) {
+        let mut recent = self.recent.lock().unwrap_or_else(|err| err.into_inner());
+        if recent.len() == MAX_RECENT {
+            recent.pop_back();
+        }
+        recent.push_front(outcome);
+    }
+
+    // Files that have not changed since the previous pass; everything else is
+    // remembered for the next one.
+    fn settled(&self, dir: &Path) -> Vec<Candidate> {
+        let mut found = Vec::new();
+        collect(dir, None, &mut found);
+        let mut seen = self.seen.lock().unwrap_or_else(|err| err.into_inner());
+        let mut next = HashMap::new();
+        let mut settled = Vec::new();
+        for (candidate, stamp) in found {
+            if seen.get(&candidate.path) == Some(&stamp) {
+                settled.push(candidate);
+            } else {
+                next.insert(candidate.path, stamp);
+            }
+        }
+        *seen = next;
+        settled
+    }
+}
+
+fn collect(dir: &Path, folder: Option<&str>, found: &mut Vec<(Candidate, (u64, SystemTime))>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let path = entry.path();
+        if meta.is_dir() {
+            // One level of series folders: `inbox/{series}/{file}`.
+            if folder.is_none() {
+                collect(&path, Some(&name), found);
+            }
+            continue;
+        }
+        let Some(kind) = Kind::of(&path) else {
+            continue;
+        };
+        let stamp = (
+            meta.len(),
+            meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        );
+        found.push((
+            Candidate {
+                path,
+                kind,
+                folder: folder.map(str::to_string),
+            },
+            stamp,
+        ));
+    }
+}
+
+// Lowercase letters and digits only, so "Kaguya-sama: Love is War" and
+// "kaguya_sama love is war" compare equal.
+fn fold(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Guesses the series from a release name such as
+// "[Group] Some Title - Vol. 02 Ch. 011 (Digital).cbz" or "Show.Name.S01E03.1080p.mkv".
+fn series_from_name(stem: &str) -> Option<String> {
+    let mut text = String::new();
+    let mut depth = 0usize;
+    for c in stem.chars() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth = depth.saturating_sub(1),
+            '_' | '.' if depth == 0 => text.push(' '),
+            c if depth == 0 => text.push(c),
+            _ => {}
+        }
+    }
+    let tokens = text.split_whitespace().collect::<Vec<_>>();
+    let numeric = |token: &str| !token.is_empty() && token.chars().all(|c| c.is_ascii_digit());
+    let end = tokens
+        .iter()
+        .enumerate()
+        .position(|(index, token)| {
+            let lower = token.to_lowercase();
+            let next_numeric = tokens
+                .get(index + 1)
+                .is_some_and(|next| numeric(next.trim_start_matches('#')));
+            let prefixed = [
+                "vol", "v", "ch", "c", "chapter", "volume", "ep", "e", "episode", "#",
+            ]
+            .iter()
+            .any(|prefix| {
+                lower
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| numeric(rest.trim_start_matches('.')))
+            });
+            let season = lower.strip_prefix('s').is_some_and(|rest| {
+                rest.split_once('e')
+                    .is_some_and(|(season, episode)| numeric(season) && numeric(episode))
+            });
+            let keyword = matches!(
+                lower.trim_end_matches('.'),
+                "vol" | "volume" | "ch" | "chapter" | "ep" | "episode" | "-"
+            ) && next_numeric;
+            index > 0 && (prefixed || season || keyword || numeric(&lower))
+        })
+        .unwrap_or(tokens.len());
+    let series = tokens[..end]
+        .join(" ")
+        .trim_matches(|c: char| c == '-' || c.is_whitespace())
+        .to_string();
+    (!series.is_empty()).then_some(series)
+}
+
+// `<Series>` from a `.cbz`'s ComicInfo.xml, the most reliable name a file carries.
+fn comic_info_series(path: &Path) -> Option<String> {
+    let mut archive = zip::ZipArchive::new(File::open(path).ok()?).ok()?;
+    let name = archive
+        .file_names()
+        .find(|name| name.eq_ignore_ascii_case("ComicInfo.xml"))?
+        .to_string();
+    let mut xml = String::new();
+    archive
+        .by_name(&name)
+        .ok()?
+        .take(MAX_COMIC_INFO_BYTES)
+        .read_to_string(&mut xml)
+        .ok()?;
+    let start = xml.find("<Series>")? + "<Series>".len();
+    let end = start + xml[start..].find("</Series>")?;
+    let series = xml[start..end]
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    let series = series.trim();
+    (!series.is_empty()).then(|| series.to_string())
+}
+
+// Files a settled drop into `{root}/{series}/{file}`. The series name is matched
+// against folders already in the library and the backend's library titles, so new
+// chapters join the series they belong to.
+fn import(candidate: &Candidate, root: &Path, titles: &[String]) -> Result<Value, String> {
+    let file = candidate
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or("no file name")?;
+    let stem = file.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&file);
+    let is_cbz = candidate
+        .path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cbz"));
+    let comic_info = is_cbz.then(|| comic_info_series(&candidate.path)).flatten();
+    let (guess, source) = match (comic_info, &candidate.folder) {
+        (Some(series), _) => (Some(series), "comicinfo"),
+        (None, Some(folder)) => (Some(folder.clone()), "folder"),
+        (None, None) => (series_from_name(stem), "filename"),
+    };
+    let guess = guess.ok_or("could not tell the series from the file name")?;
+    let existing = std::fs::read_dir(root)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let key = fold(&guess);
+    let (series, matched) = existing
+        .iter()
+        .find(|name| fold(name) == key)
+        .map(|name| (name.clone(), "folder"))
+        .or_else(|| {
+            titles
+                .iter()
+                .find(|title| fold(title) == key)
+                .and_then(|title| sanitize(title))
+                .map(|title| (title, "library"))
+        })
+        .or_else(|| sanitize(&guess).map(|name| (name, "new")))
+        .ok_or("series name is empty once sanitized")?;
+    let file = sanitize(&file).ok_or("file name is empty once sanitized")?;
+    let target = root.join(&series).join(&file);
+    if target.exists() {
+        return Err(format!("{series}/{file} already exists"));
+    }
+    std::fs::create_dir_all(root.join(&series)).map_err(|err| err.to_string())?;
+    move_path(&candidate.path, &target).map_err(|err| err.to_string())?;
+    Ok(json!({
+        "kind": candidate.kind.label(),
+        "series": series,
+        "file": file,
+        "identified_by": source,
+        "matched": matched,
+        "path": target.display().to_string(),
+    }))
+}
+
+// Drops that cannot be filed are set aside so they are not retried every pass.
+fn reject(inbox: &Path, path: &Path) -> Option<PathBuf> {
+    let dir = inbox.join(REJECTED_DIR);
+    std::fs::create_dir_all(&dir).ok()?;
+    let target = dir.join(path.file_name()?);
+    move_path(path, &target).ok()?;
+    Some(target)
+}
+
+async fn scan(state: &AppState, dir: &Path) {
+    let inbox = state.inbox.clone();
+    let scan_dir = dir.to_path_buf();
+    let Ok(candidates) = tokio::task::spawn_blocking(move || inbox.settled(&scan_dir)).await else {
+        return;
+    };
+    if candidates.is_empty() {
+        return;
+    }
+    let titles = if candidates
+        .iter()
+        .any(|candidate| candidate.kind == Kind::Manga)
+    {
+        match library::library(state).await {
+            Ok(manga) => manga.into_iter().map(|manga| manga.title).collect(),
+            Err(err) => {
+                warn!("inbox could not read the library: {}", err);
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    let settings = state.backend.settings();
+    let manga_root = PathBuf::from(settings.local_manga_path.unwrap_or_default());
+    let anime_root = PathBuf::from(settings.local_anime_path.unwrap_or_default());
+    let inbox_dir = dir.to_path_buf();
+    let outcomes = tokio::task::spawn_blocking(move || {
+        let mut outcomes = Vec::new();
+        for candidate in candidates {
+            let root = match candidate.kind {
+                Kind::Manga => &manga_root,
+                Kind::Anime => &anime_root,
+            };
+            let outcome = match import(&candidate, root, &titles) {
+                Ok(imported) => {
+                    info!(
+                        "inbox imported {} into {}",
+                        candidate.path.display(),
+                        imported["path"].as_str().unwrap_or_default()
+                    );
+                    imported
+                }
+                Err(err) => {
+                    warn!("inbox rejected {}: {}", candidate.path.display(), err);
+                    let moved = reject(&inbox_dir, &candidate.path);
+                    json!({
+                        "kind": candidate.kind.label(),
+                        "file": candidate.path.display().to_string(),
+                        "error": err,
+                        "moved_to": moved.map(|path| path.display().to_string()),
+                    })
+                }
+            };
+            outcomes.push((candidate.kind, outcome));
+        }
+        if let Ok(folders) = std::fs::read_dir(&inbox_dir) {
+            for folder in folders.flatten() {
+                if folder.file_name() != REJECTED_DIR {
+                    // Only succeeds for folders emptied by the imports.
+                    let _ = std::fs::remove_dir(folder.path());
+                }
+            }
+        }
+        outcomes
+    })
+    .await
+    .unwrap_or_default();
+
+    let (mut imported_manga, mut imported_anime) = (false, false);
+    for (kind, outcome) in outcomes {
+        if outcome.get("error").is_none() {
+            imported_manga |= kind == Kind::Manga;
+            imported_anime |= kind == Kind::Anime;
+        }
+        let mut outcome = outcome;
+        outcome["at"] = json!(unix_now());
+        state.inbox.record(outcome);
+    }
+    if imported_manga {
+        local_manga::rescan(state, HeaderMap::new());
+    }
+    if imported_anime {
+        local_manga::rescan_anime(state);
+        #[cfg(feature = "notifications")]
+        state.media_servers.notify();
+    }
+}
+
+pub(crate) fn spawn(state: AppState) {
+    let Some(dir) = state.inbox.config.path.clone().map(PathBuf::from) else {
+        return;
+    };
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        warn!("inbox {} is unusable: {}", dir.display(), err);
+        return;
+    }
+    info!("watching {} for imports", dir.display());
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(state.inbox.config.interval_seconds);
+        loop {
+            scan(&state, &dir).await;
+            let _ = tokio::time::timeout(interval, state.inbox.wake.notified()).await;
+        }
+    });
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/inbox", get(status))
+        .route("/admin/inbox/scan", post(scan_now))
+}
+
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    let inbox = &state.inbox;
+    let pending = inbox
+        .seen
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .keys()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>();
+    Json(json!({
+        "path": inbox.config.path,
+        "interval_seconds": inbox.config.interval_seconds,
+        "pending": pending,
+        "recent": *inbox.recent.lock().unwrap_or_else(|err| err.into_inner()),
+    }))
+}
+
+// Takes files that settled since the last pass without waiting for the next one.
+async fn scan_now(State(state): State<AppState>) -> StatusCode {
+    if state.inbox.config.path.is_none() {
+        return StatusCode::NOT_FOUND;
+    }
+    state.inbox.wake.notify_one();
+    StatusCode::ACCEPTED
+}
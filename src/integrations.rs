@@ -0,0 +1,456 @@
+use std::collections::VecDeque;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::{IntegrationsConfig, LinkMode};
+use crate::local_manga::{self, sanitize};
+use crate::unix_now;
+
+const MAX_RECENT: usize = 100;
+
+// What a webhook asks for, whichever tool sent it. Paths are as the sender sees them.
+enum Event {
+    Import {
+        series: String,
+        path: String,
+    },
+    Rename {
+        series: String,
+        from: String,
+        to: String,
+    },
+    Delete {
+        series: String,
+        path: String,
+    },
+}
+
+impl Event {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Import { .. } => "import",
+            Self::Rename { .. } => "rename",
+            Self::Delete { .. } => "delete",
+        }
+    }
+
+    fn series(&self) -> &str {
+        match self {
+            Self::Import { series, .. }
+            | Self::Rename { series, .. }
+            | Self::Delete { series, .. } => series,
+        }
+    }
+}
+
+// Mirrors episodes that *arr tools import into `{local anime}/{series}/{file}`, so
+// the backend's local anime source picks them up without a second download.
+pub(crate) struct Integrations {
+    config: IntegrationsConfig,
+    recent: Mutex<VecDeque<Value>>,
+}
+
+impl Integrations {
+    pub(crate) fn new(config: IntegrationsConfig) -> Self {
+        Self {
+            config,
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn record(&self, outcome: Value) {
+        let mut recent = self.recent.lock().unwrap_or_else(|err| err.into_inner());
+        if recent.len() == MAX_RECENT {
+            recent.pop_back();
+        }
+        recent.push_front(outcome);
+    }
+
+    // The longest matching `path_map` prefix wins. Paths outside every mapping are
+    // refused, so a webhook cannot have the proxy link arbitrary files into the library.
+    fn local_path(&self, path: &str) -> Result<PathBuf, String> {
+        if self.config.path_map.is_empty() {
+            return Err("MANATAN_INTEGRATIONS_PATH_MAP is not set".to_string());
+        }
+        let path = Path::new(path);
+        let (from, to) = self
+            .config
+            .path_map
+            .iter()
+            .filter(|(from, _)| path.starts_with(from))
+            .max_by_key(|(from, _)| from.len())
+            .ok_or_else(|| format!("{} is outside the mapped paths", path.display()))?;
+        let rest = path.strip_prefix(from).map_err(|err| err.to_string())?;
+        if !rest
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(format!("{} is outside the mapped paths", path.display()));
+        }
+        Ok(Path::new(to).join(rest))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SonarrSeries {
+    title: String,
+    #[serde(default)]
+    path: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SonarrFile {
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    relative_path: Option<String>,
+    #[serde(default)]
+    previous_path: Option<String>,
+}
+
+impl SonarrFile {
+    // Older Sonarr versions only send the path relative to the series folder.
+    fn full_path(&self, series: &SonarrSeries) -> Option<String> {
+        self.path.clone().or_else(|| {
+            let base = series.path.as_deref()?;
+            let relative = self.relative_path.as_deref()?;
+            Some(Path::new(base).join(relative).display().to_string())
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SonarrWebhook {
+    event_type: String,
+    #[serde(default)]
+    series: Option<SonarrSeries>,
+    #[serde(default)]
+    episode_file: Option<SonarrFile>,
+    #[serde(default)]
+    deleted_files: Vec<SonarrFile>,
+    #[serde(default)]
+    renamed_episode_files: Vec<SonarrFile>,
+}
+
+impl SonarrWebhook {
+    fn events(&self) -> Vec<Event> {
+        let Some(series) = &self.series else {
+            return Vec::new();
+        };
+        let title = || series.title.clone();
+        let mut events = Vec::new();
+        match self.event_type.as_str() {
+            // Upgrades list the files they replaced.
+            "Download" => {
+                events.extend(self.deleted_files.iter().filter_map(|file| {
+                    Some(Event::Delete {
+                        series: title(),
+                        path: file.full_path(series)?,
+                    })
+                }));
+                if let Some(path) = self.episode_file.as_ref().and_then(|f| f.full_path(series)) {
+                    events.push(Event::Import {
+                        series: title(),
+                        path,
+                    });
+                }
+            }
+            "Rename" => {
+                events.extend(self.renamed_episode_files.iter().filter_map(|file| {
+                    Some(Event::Rename {
+                        series: title(),
+                        from: file.previous_path.clone()?,
+                        to: file.full_path(series)?,
+                    })
+                }));
+            }
+            "EpisodeFileDelete" => {
+                if let Some(path) = self.episode_file.as_ref().and_then(|f| f.full_path(series)) {
+                    events.push(Event::Delete {
+                        series: title(),
+                        path,
+                    });
+                }
+            }
+            _ => {}
+        }
+        events
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum GenericKind {
+    Import,
+    Rename,
+    Delete,
+}
+
+// For tools without a Sonarr-style payload:
+// `{"event": "import", "series": "..", "path": ".."}`, plus `previous_path` for renames.
+#[derive(Deserialize)]
+struct GenericWebhook {
+    event: GenericKind,
+    series: String,
+    path: String,
+    #[serde(default)]
+    previous_path: Option<String>,
+}
+
+impl GenericWebhook {
+    fn event(self) -> Result<Event, &'static str> {
+        Ok(match self.event {
+            GenericKind::Import => Event::Import {
+                series: self.series,
+                path: self.path,
+            },
+            GenericKind::Delete => Event::Delete {
+                series: self.series,
+                path: self.path,
+            },
+            GenericKind::Rename => Event::Rename {
+                series: self.series,
+                from: self
+                    .previous_path
+                    .ok_or("rename events need previous_path")?,
+                to: self.path,
+            },
+        })
+    }
+}
+
+fn target(root: &Path, series: &str, source: &Path) -> Result<PathBuf, String> {
+    let series = sanitize(series).ok_or("empty series title")?;
+    let file = source
+        .file_name()
+        .and_then(|name| sanitize(&name.to_string_lossy()))
+        .ok_or("path has no file name")?;
+    Ok(root.join(series).join(file))
+}
+
+fn link(mode: LinkMode, source: &Path, target: &Path) -> std::io::Result<&'static str> {
+    match mode {
+        LinkMode::Copy => std::fs::copy(source, target).map(|_| "copy"),
+        // Hard links only work within one filesystem; elsewhere the file is copied.
+        LinkMode::Hardlink => std::fs::hard_link(source, target)
+            .map(|()| "hardlink")
+            .or_else(|_| std::fs::copy(source, target).map(|_| "copy")),
+        #[cfg(unix)]
+        LinkMode::Symlink => std::os::unix::fs::symlink(source, target).map(|()| "symlink"),
+        #[cfg(windows)]
+        LinkMode::Symlink => std::os::windows::fs::symlink_file(source, target).map(|()| "symlink"),
+        #[cfg(not(any(unix, windows)))]
+        LinkMode::Symlink => std::fs::copy(source, target).map(|_| "copy"),
+    }
+}
+
+fn remove(root: &Path, series: &str, path: &Path) -> Result<bool, String> {
+    let target = target(root, series, path)?;
+    match std::fs::remove_file(&target) {
+        Ok(()) => {
+            if let Some(dir) = target.parent() {
+                // Only succeeds once the series has no episodes left.
+                let _ = std::fs::remove_dir(dir);
+            }
+            Ok(true)
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn import(
+    root: &Path,
+    mode: LinkMode,
+    series: &str,
+    source: &Path,
+) -> Result<(PathBuf, &'static str), String> {
+    if !source.is_file() {
+        return Err(format!("{} is not a readable file", source.display()));
+    }
+    let target = target(root, series, source)?;
+    if let Some(dir) = target.parent() {
+        std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    // Re-imports and upgrades under the same name replace the old copy.
+    match std::fs::remove_file(&target) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.to_string()),
+        _ => {}
+    }
+    let how = link(mode, source, &target).map_err(|err| err.to_string())?;
+    Ok((target, how))
+}
+
+fn apply(integrations: &Integrations, root: &Path, event: &Event) -> Value {
+    let mode = integrations.config.link_mode;
+    let mut outcome = json!({ "event": event.kind(), "series": event.series() });
+    let linked = match event {
+        Event::Import { series, path } => integrations
+            .local_path(path)
+            .and_then(|source| import(root, mode, series, &source))
+            .map(Some),
+        Event::Rename { series, from, to } => integrations.local_path(from).and_then(|from| {
+            let to = integrations.local_path(to)?;
+            remove(root, series, &from)?;
+            import(root, mode, series, &to).map(Some)
+        }),
+        Event::Delete { series, path } => integrations
+            .local_path(path)
+            .and_then(|path| remove(root, series, &path))
+            .map(|removed| {
+                outcome["removed"] = json!(removed);
+                None
+            }),
+    };
+    match linked {
+        Ok(linked) => {
+            if let Some((target, how)) = linked {
+                outcome["path"] = json!(target.display().to_string());
+                outcome["how"] = json!(how);
+            }
+            info!("integration {}", outcome);
+        }
+        Err(err) => {
+            warn!(
+                "integration {} for {} failed: {}",
+                event.kind(),
+                event.series(),
+                err
+            );
+            outcome["error"] = json!(err);
+        }
+    }
+    outcome
+}
+
+async fn handle(state: &AppState, events: Vec<Event>) -> Response {
+    if !state.integrations.config.enabled {
+        return (
+            StatusCode::NOT_FOUND,
+            "integrations are disabled (MANATAN_INTEGRATIONS_ENABLED)",
+        )
+            .into_response();
+    }
+    // Sonarr's "Test" button and events that need no action get an empty success.
+    if events.is_empty() {
+        return Json(json!({ "results": [] })).into_response();
+    }
+    let root = PathBuf::from(
+        state
+            .backend
+            .settings()
+            .local_anime_path
+            .unwrap_or_default(),
+    );
+    let integrations = state.integrations.clone();
+    let outcomes = tokio::task::spawn_blocking(move || {
+        events
+            .iter()
+            .map(|event| apply(&integrations, &root, event))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+    if outcomes
+        .iter()
+        .any(|outcome| outcome.get("error").is_none())
+    {
+        local_manga::rescan_anime(state);
+        state.media_servers.notify();
+    }
+    for outcome in &outcomes {
+        let mut outcome = outcome.clone();
+        outcome["at"] = json!(unix_now());
+        state.integrations.record(outcome);
+    }
+    let status = if outcomes
+        .iter()
+        .any(|outcome| outcome.get("error").is_some())
+    {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(json!({ "results": outcomes }))).into_response()
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/integrations/sonarr", post(sonarr))
+        .route("/integrations/generic", post(generic))
+        .route("/admin/integrations", get(status))
+}
+
+async fn sonarr(State(state): State<AppState>, Json(body): Json<SonarrWebhook>) -> Response {
+    handle(&state, body.events()).await
+}
+
+async fn generic(State(state): State<AppState>, Json(body): Json<GenericWebhook>) -> Response {
+    match body.event() {
+        Ok(event) => handle(&state, vec![event]).await,
+        Err(err) => (StatusCode::UNPROCESSABLE_ENTITY, err).into_response(),
+    }
+}
+
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    let integrations = &state.integrations;
+    Json(json!({
+        "enabled": integrations.config.enabled,
+        "link_mode": integrations.config.link_mode,
+        "path_map": integrations
+            .config
+            .path_map
+            .iter()
+            .map(|(from, to)| json!({ "from": from, "to": to }))
+            .collect::<Vec<_>>(),
+        "recent": *integrations.recent.lock().unwrap_or_else(|err| err.into_inner()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integrations(path_map: &[(&str, &str)]) -> Integrations {
+        Integrations::new(IntegrationsConfig {
+            enabled: true,
+            link_mode: LinkMode::Hardlink,
+            path_map: path_map
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn reported_paths_must_fall_under_the_path_map() {
+        let mapped = integrations(&[("/tv", "/mnt/media/tv"), ("/tv/kids", "/mnt/kids")]);
+        assert_eq!(
+            mapped.local_path("/tv/Show/ep1.mkv"),
+            Ok(PathBuf::from("/mnt/media/tv/Show/ep1.mkv"))
+        );
+        assert_eq!(
+            mapped.local_path("/tv/kids/Show/ep1.mkv"),
+            Ok(PathBuf::from("/mnt/kids/Show/ep1.mkv"))
+        );
+        for path in ["/etc/shadow", "/tv/../etc/shadow", "/tvshows/ep1.mkv"] {
+            assert!(mapped.local_path(path).is_err(), "{path}");
+        }
+        assert!(integrations(&[]).local_path("/tv/Show/ep1.mkv").is_err());
+    }
+}
@@ -0,0 +1,670 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{Path as UrlPath, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use futures::future::BoxFuture;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::{Notify, Semaphore};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::archives;
+use crate::config::JobsConfig;
+use crate::reconcile;
+use crate::retention;
+use crate::skip_markers;
+use crate::storage;
+use crate::unix_now;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_LISTED_JOBS: usize = 500;
+const MAX_DUPLICATE_GROUPS: usize = 500;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    state TEXT NOT NULL,
+    progress REAL NOT NULL DEFAULT 0,
+    message TEXT,
+    result TEXT,
+    error TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    run_after INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    started_at INTEGER,
+    finished_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS jobs_state ON jobs (state, run_after);
+";
+
+const COLUMNS: &str = "id, kind, payload, state, progress, message, result, error, attempts, \
+                       max_attempts, run_after, created_at, updated_at, started_at, finished_at";
+
+pub(crate) type JobResult = Result<Value, String>;
+type Handler = Arc<dyn Fn(AppState, JobContext) -> BoxFuture<'static, JobResult> + Send + Sync>;
+
+pub(crate) struct JobQueue {
+    config: JobsConfig,
+    conn: Mutex<Connection>,
+    handlers: BTreeMap<&'static str, Handler>,
+    cancelled: Mutex<HashSet<i64>>,
+    notify: Notify,
+}
+
+#[derive(Clone)]
+pub(crate) struct JobContext {
+    pub id: i64,
+    pub payload: Value,
+    queue: Arc<JobQueue>,
+}
+
+impl JobContext {
+    pub(crate) fn progress(&self, fraction: f64, message: &str) {
+        self.queue.update_progress(self.id, fraction, message);
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.queue
+            .cancelled
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .contains(&self.id)
+    }
+}
+
+struct Claimed {
+    id: i64,
+    kind: String,
+    payload: Value,
+    attempts: u32,
+    max_attempts: u32,
+}
+
+impl JobQueue {
+    pub(crate) fn new(config: JobsConfig, data_path: &str) -> Self {
+        let path = PathBuf::from(data_path).join("jobs.sqlite3");
+        let conn = match open(&path) {
+            Ok(conn) => conn,
+            Err(err) => {
+                warn!(
+                    "job queue falling back to memory: cannot open {}: {}",
+                    path.display(),
+                    err
+                );
+                let conn = Connection::open_in_memory().expect("in-memory sqlite");
+                let _ = conn.execute_batch(SCHEMA);
+                conn
+            }
+        };
+
+        let mut handlers = BTreeMap::new();
+        handlers.insert("dedup_scan", handler(dedup_scan));
+        handlers.insert("export_library", handler(export_library));
+        handlers.insert("migrate_data", handler(storage::run_job));
+        handlers.insert("prune_downloads", handler(retention::run_job));
+        handlers.insert("reconcile_downloads", handler(reconcile::run_job));
+        handlers.insert("repair_archives", handler(archives::repair_job));
+        handlers.insert("skip_markers", handler(skip_markers::run_job));
+
+        let queue = Self {
+            config,
+            conn: Mutex::new(conn),
+            handlers,
+            cancelled: Mutex::new(HashSet::new()),
+            notify: Notify::new(),
+        };
+        queue.recover();
+        queue
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn recover(&self) {
+        let now = unix_now();
+        let conn = self.lock();
+        match conn.execute(
+            "UPDATE jobs SET state = 'queued', run_after = ?1, updated_at = ?1, \
+             message = 'interrupted by restart' WHERE state = 'running'",
+            params![now as i64],
+        ) {
+            Ok(0) => {}
+            Ok(count) => info!("requeued {} interrupted jobs", count),
+            Err(err) => warn!("failed to requeue interrupted jobs: {}", err),
+        }
+        self.prune(&conn, now);
+    }
+
+    fn prune(&self, conn: &Connection, now: u64) {
+        let cutoff = now.saturating_sub(self.config.history_days * 86_400) as i64;
+        if let Err(err) = conn.execute(
+            "DELETE FROM jobs WHERE state IN ('succeeded', 'failed', 'cancelled') \
+             AND finished_at < ?1",
+            params![cutoff],
+        ) {
+            warn!("failed to prune job history: {}", err);
+        }
+    }
+
+    pub(crate) fn enqueue(
+        &self,
+        kind: &str,
+        payload: Value,
+        max_attempts: Option<u32>,
+    ) -> Result<i64, String> {
+        if !self.handlers.contains_key(kind) {
+            return Err(format!("unknown job kind: {kind}"));
+        }
+        let now = unix_now() as i64;
+        let id = {
+            let conn = self.lock();
+            conn.execute(
+                "INSERT INTO jobs (kind, payload, state, max_attempts, run_after, created_at, \
+                 updated_at) VALUES (?1, ?2, 'queued', ?3, ?4, ?4, ?4)",
+                params![
+                    kind,
+                    payload.to_string(),
+                    max_attempts.unwrap_or(self.config.max_attempts).max(1),
+                    now
+                ],
+            )
+            .map_err(|err| err.to_string())?;
+            conn.last_insert_rowid()
+        };
+        self.notify.notify_one();
+        Ok(id)
+    }
+
+    pub(crate) fn has_pending(&self, kind: &str) -> bool {
+        self.lock()
+            .query_row(
+                "SELECT 1 FROM jobs WHERE kind = ?1 AND state IN ('queued', 'running') LIMIT 1",
+                params![kind],
+                |_| Ok(()),
+            )
+            .optional()
+            .ok()
+            .flatten()
+            .is_some()
+    }
+
+    fn claim_next(&self) -> Option<Claimed> {
+        let now = unix_now() as i64;
+        let conn = self.lock();
+        let claimed = conn
+            .query_row(
+                "SELECT id, kind, payload, attempts, max_attempts FROM jobs \
+                 WHERE state = 'queued' AND run_after <= ?1 ORDER BY id LIMIT 1",
+                params![now],
+                |row| {
+                    Ok(Claimed {
+                        id: row.get(0)?,
+                        kind: row.get(1)?,
+                        payload: serde_json::from_str(&row.get::<_, String>(2)?)
+                            .unwrap_or(Value::Null),
+                        attempts: row.get::<_, u32>(3)? + 1,
+                        max_attempts: row.get(4)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(|err| warn!("failed to poll job queue: {}", err))
+            .ok()
+            .flatten()?;
+        if let Err(err) = conn.execute(
+            "UPDATE jobs SET state = 'running', attempts = ?2, started_at = ?3, updated_at = ?3, \
+             progress = 0, error = NULL WHERE id = ?1",
+            params![claimed.id, claimed.attempts, now],
+        ) {
+            warn!("failed to claim job {}: {}", claimed.id, err);
+            return None;
+        }
+        Some(claimed)
+    }
+
+    fn update_progress(&self, id: i64, fraction: f64, message: &str) {
+        let conn = self.lock();
+        let _ = conn.execute(
+            "UPDATE jobs SET progress = ?2, message = ?3, updated_at = ?4 WHERE id = ?1",
+            params![id, fraction.clamp(0.0, 1.0), message, unix_now() as i64],
+        );
+    }
+
+    fn finish(&self, job: &Claimed, outcome: JobResult) {
+        let now = unix_now();
+        let cancelled = self
+            .cancelled
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&job.id);
+        let conn = self.lock();
+        let result = match outcome {
+            Ok(value) => conn.execute(
+                "UPDATE jobs SET state = 'succeeded', progress = 1, result = ?2, \
+                 finished_at = ?3, updated_at = ?3 WHERE id = ?1",
+                params![job.id, value.to_string(), now as i64],
+            ),
+            Err(error) if cancelled => conn.execute(
+                "UPDATE jobs SET state = 'cancelled', error = ?2, finished_at = ?3, \
+                 updated_at = ?3 WHERE id = ?1",
+                params![job.id, error, now as i64],
+            ),
+            Err(error) if job.attempts < job.max_attempts => {
+                let delay = self
+                    .config
+                    .retry_delay_seconds
+                    .saturating_mul(1 << (job.attempts - 1).min(10));
+                warn!(
+                    "job {} ({}) failed, retrying in {}s: {}",
+                    job.id, job.kind, delay, error
+                );
+                conn.execute(
+                    "UPDATE jobs SET state = 'queued', error = ?2, run_after = ?3, \
+                     updated_at = ?4 WHERE id = ?1",
+                    params![job.id, error, now.saturating_add(delay) as i64, now as i64],
+                )
+            }
+            Err(error) => {
+                warn!("job {} ({}) failed: {}", job.id, job.kind, error);
+                conn.execute(
+                    "UPDATE jobs SET state = 'failed', error = ?2, finished_at = ?3, \
+                     updated_at = ?3 WHERE id = ?1",
+                    params![job.id, error, now as i64],
+                )
+            }
+        };
+        if let Err(err) = result {
+            warn!("failed to record outcome of job {}: {}", job.id, err);
+        }
+        self.prune(&conn, now);
+    }
+
+    pub(crate) fn get(&self, id: i64) -> Option<Value> {
+        let conn = self.lock();
+        conn.query_row(
+            &format!("SELECT {COLUMNS} FROM jobs WHERE id = ?1"),
+            params![id],
+            job_json,
+        )
+        .optional()
+        .ok()
+        .flatten()
+    }
+
+    fn list(&self, state: Option<&str>, limit: usize) -> Result<Vec<Value>, String> {
+        let conn = self.lock();
+        let mut statement = conn
+            .prepare(&format!(
+                "SELECT {COLUMNS} FROM jobs WHERE (?1 IS NULL OR state = ?1) \
+                 ORDER BY id DESC LIMIT ?2"
+            ))
+            .map_err(|err| err.to_string())?;
+        let rows = statement
+            .query_map(params![state, limit as i64], job_json)
+            .map_err(|err| err.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|err| err.to_string())
+    }
+
+    fn cancel(&self, id: i64) -> Option<bool> {
+        let now = unix_now() as i64;
+        let conn = self.lock();
+        let state = conn
+            .query_row("SELECT state FROM jobs WHERE id = ?1", params![id], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()
+            .ok()
+            .flatten()?;
+        match state.as_str() {
+            "queued" => {
+                let _ = conn.execute(
+                    "UPDATE jobs SET state = 'cancelled', finished_at = ?2, updated_at = ?2 \
+                     WHERE id = ?1",
+                    params![id, now],
+                );
+                Some(true)
+            }
+            "running" => {
+                self.cancelled
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .insert(id);
+                Some(true)
+            }
+            _ => Some(false),
+        }
+    }
+
+    fn retry(&self, id: i64) -> Option<bool> {
+        let now = unix_now() as i64;
+        let updated = {
+            let conn = self.lock();
+            conn.execute(
+                "UPDATE jobs SET state = 'queued', attempts = 0, run_after = ?2, updated_at = ?2, \
+                 finished_at = NULL WHERE id = ?1 AND state IN ('failed', 'cancelled')",
+                params![id, now],
+            )
+            .ok()?
+        };
+        if updated > 0 {
+            self.notify.notify_one();
+            return Some(true);
+        }
+        self.get(id).map(|_| false)
+    }
+
+    pub(crate) fn kinds(&self) -> Vec<&'static str> {
+        self.handlers.keys().copied().collect()
+    }
+}
+
+fn open(path: &Path) -> rusqlite::Result<Connection> {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let conn = Connection::open(path)?;
+    conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+    conn.execute_batch(SCHEMA)?;
+    Ok(conn)
+}
+
+fn job_json(row: &Row<'_>) -> rusqlite::Result<Value> {
+    let parse = |text: Option<String>| {
+        text.and_then(|text| serde_json::from_str::<Value>(&text).ok())
+            .unwrap_or(Value::Null)
+    };
+    Ok(json!({
+        "id": row.get::<_, i64>(0)?,
+        "kind": row.get::<_, String>(1)?,
+        "payload": parse(row.get(2)?),
+        "state": row.get::<_, String>(3)?,
+        "progress": row.get::<_, f64>(4)?,
+        "message": row.get::<_, Option<String>>(5)?,
+        "result": parse(row.get(6)?),
+        "error": row.get::<_, Option<String>>(7)?,
+        "attempts": row.get::<_, u32>(8)?,
+        "max_attempts": row.get::<_, u32>(9)?,
+        "run_after": row.get::<_, i64>(10)?,
+        "created_at": row.get::<_, i64>(11)?,
+        "updated_at": row.get::<_, i64>(12)?,
+        "started_at": row.get::<_, Option<i64>>(13)?,
+        "finished_at": row.get::<_, Option<i64>>(14)?,
+    }))
+}
+
+fn handler<F, Fut>(run: F) -> Handler
+where
+    F: Fn(AppState, JobContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = JobResult> + Send + 'static,
+{
+    Arc::new(move |state, ctx| Box::pin(run(state, ctx)))
+}
+
+pub(crate) fn spawn(state: AppState) {
+    if !state.jobs.config.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        let slots = Arc::new(Semaphore::new(state.jobs.config.concurrency));
+        loop {
+            let Ok(slot) = slots.clone().acquire_owned().await else {
+                break;
+            };
+            let Some(job) = state.jobs.claim_next() else {
+                drop(slot);
+                let _ = tokio::time::timeout(POLL_INTERVAL, state.jobs.notify.notified()).await;
+                continue;
+            };
+
+            let state = state.clone();
+            tokio::spawn(async move {
+                let queue = state.jobs.clone();
+                let outcome = match queue.handlers.get(job.kind.as_str()).cloned() {
+                    Some(run) => {
+                        let ctx = JobContext {
+                            id: job.id,
+                            payload: job.payload.clone(),
+                            queue: queue.clone(),
+                        };
+                        match tokio::spawn(run(state, ctx)).await {
+                            Ok(outcome) => outcome,
+                            Err(err) => Err(format!("job panicked: {err}")),
+                        }
+                    }
+                    None => Err(format!("no handler for job kind: {}", job.kind)),
+                };
+                queue.finish(&job, outcome);
+                drop(slot);
+            });
+        }
+    });
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/jobs", get(list).post(create))
+        .route("/admin/jobs/{id}", get(show).delete(cancel))
+        .route("/admin/jobs/{id}/retry", post(retry))
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    state: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CreateJob {
+    kind: String,
+    #[serde(default)]
+    payload: Value,
+    max_attempts: Option<u32>,
+}
+
+async fn list(State(state): State<AppState>, Query(query): Query<ListQuery>) -> Response {
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_LISTED_JOBS);
+    match state.jobs.list(query.state.as_deref(), limit) {
+        Ok(jobs) => Json(json!({ "kinds": state.jobs.kinds(), "jobs": jobs })).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    }
+}
+
+async fn create(State(state): State<AppState>, Json(body): Json<CreateJob>) -> Response {
+    match state
+        .jobs
+        .enqueue(&body.kind, body.payload, body.max_attempts)
+    {
+        Ok(id) => (
+            StatusCode::ACCEPTED,
+            Json(state.jobs.get(id).unwrap_or_else(|| json!({ "id": id }))),
+        )
+            .into_response(),
+        Err(err) => (StatusCode::UNPROCESSABLE_ENTITY, err).into_response(),
+    }
+}
+
+async fn show(State(state): State<AppState>, UrlPath(id): UrlPath<i64>) -> Response {
+    match state.jobs.get(id) {
+        Some(job) => Json(job).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn cancel(State(state): State<AppState>, UrlPath(id): UrlPath<i64>) -> StatusCode {
+    match state.jobs.cancel(id) {
+        Some(true) => StatusCode::ACCEPTED,
+        Some(false) => StatusCode::CONFLICT,
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn retry(State(state): State<AppState>, UrlPath(id): UrlPath<i64>) -> StatusCode {
+    match state.jobs.retry(id) {
+        Some(true) => StatusCode::ACCEPTED,
+        Some(false) => StatusCode::CONFLICT,
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn dedup_scan(state: AppState, ctx: JobContext) -> JobResult {
+    let roots = match ctx.payload.get("paths").and_then(Value::as_array) {
+        Some(paths) => paths
+            .iter()
+            .filter_map(Value::as_str)
+            .map(PathBuf::from)
+            .collect::<Vec<_>>(),
+        None => vec![
+            PathBuf::from(&state.config.local_manga_path),
+            PathBuf::from(&state.config.local_anime_path),
+        ],
+    };
+    tokio::task::spawn_blocking(move || scan_duplicates(&roots, &ctx))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+fn scan_duplicates(roots: &[PathBuf], ctx: &JobContext) -> JobResult {
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    let mut pending = roots.to_vec();
+    let mut files = 0usize;
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                let size = entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+                if size > 0 {
+                    by_size.entry(size).or_default().push(entry.path());
+                    files += 1;
+                }
+            }
+        }
+    }
+
+    let candidates = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect::<Vec<_>>();
+    let total = candidates
+        .iter()
+        .map(|(_, paths)| paths.len())
+        .sum::<usize>();
+    let mut hashed = 0usize;
+    let mut groups = Vec::new();
+    for (size, paths) in candidates {
+        let mut by_hash: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for path in paths {
+            if ctx.is_cancelled() {
+                return Err("cancelled".to_string());
+            }
+            if let Ok(digest) = hash_file(&path) {
+                by_hash
+                    .entry(digest)
+                    .or_default()
+                    .push(path.display().to_string());
+            }
+            hashed += 1;
+            if hashed.is_multiple_of(50) {
+                ctx.progress(
+                    hashed as f64 / total as f64,
+                    &format!("hashed {hashed} of {total} candidate files"),
+                );
+            }
+        }
+        groups.extend(
+            by_hash
+                .into_iter()
+                .filter(|(_, paths)| paths.len() > 1)
+                .map(|(sha256, paths)| json!({ "size": size, "sha256": sha256, "paths": paths })),
+        );
+    }
+
+    let duplicate_groups = groups.len();
+    groups.truncate(MAX_DUPLICATE_GROUPS);
+    Ok(json!({
+        "files": files,
+        "duplicate_groups": duplicate_groups,
+        "groups": groups,
+    }))
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+async fn export_library(state: AppState, ctx: JobContext) -> JobResult {
+    let headers = HeaderMap::new();
+    let categories = state.backend_json("/api/v1/category", &headers).await?;
+    let categories = categories.as_array().cloned().unwrap_or_default();
+
+    let mut exported = Vec::new();
+    let mut entries = 0usize;
+    for (index, category) in categories.iter().enumerate() {
+        if ctx.is_cancelled() {
+            return Err("cancelled".to_string());
+        }
+        let Some(id) = category.get("id").and_then(Value::as_i64) else {
+            continue;
+        };
+        let manga = state
+            .backend_json(&format!("/api/v1/category/{id}"), &headers)
+            .await?;
+        entries += manga.as_array().map(Vec::len).unwrap_or(0);
+        exported.push(json!({ "category": category, "manga": manga }));
+        ctx.progress(
+            (index + 1) as f64 / categories.len() as f64,
+            &format!("exported {} of {} categories", index + 1, categories.len()),
+        );
+    }
+
+    let dir = PathBuf::from(&state.config.proxy_data_path).join("exports");
+    let path = dir.join(format!("library-{}.json", unix_now()));
+    let bytes = serde_json::to_vec_pretty(&json!({
+        "exported_at": unix_now(),
+        "categories": exported,
+    }))
+    .map_err(|err| err.to_string())?;
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|err| err.to_string())?;
+    tokio::fs::write(&path, bytes)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(json!({
+        "path": path.display().to_string(),
+        "categories": categories.len(),
+        "entries": entries,
+    }))
+}
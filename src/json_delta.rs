@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, request::Parts, HeaderValue, Method, StatusCode},
+    response::Response,
+};
+use serde_json::{json, Value};
+
+use crate::app::AppState;
+use crate::body::buffer;
+use crate::config::JsonDeltaConfig;
+use crate::image_cache::{self, hex_digest};
+
+const MAX_BODY: usize = 32 * 1024 * 1024;
+const PATCH_TYPE: &str = "application/json-patch+json";
+
+struct Entry {
+    etag: String,
+    body: Bytes,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    total: u64,
+}
+
+// RFC 3229 delta encoding for big library listings: the last payload each client got
+// per URL is remembered, and a client sending `A-IM: json-patch` with that payload's
+// ETag in `If-None-Match` gets a `226 IM Used` RFC 6902 patch against it.
+pub(crate) struct JsonDelta {
+    config: JsonDeltaConfig,
+    inner: Mutex<Inner>,
+}
+
+// What a delta-capable request asked for, taken before it is forwarded.
+pub(crate) struct DeltaRequest {
+    key: String,
+    bases: Vec<String>,
+}
+
+impl JsonDelta {
+    pub(crate) fn new(config: JsonDeltaConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // The conditional headers refer to our ETags, not the backend's, so they are taken
+    // off the request.
+    pub(crate) fn request(&self, parts: &mut Parts, client: &str) -> Option<DeltaRequest> {
+        let path = parts.uri.path();
+        if !self.config.enabled
+            || parts.method != Method::GET
+            || image_cache::is_image_path(path)
+            || !self
+                .config
+                .paths
+                .iter()
+                .any(|prefix| path.starts_with(prefix))
+            || !accepts_patch(parts)
+        {
+            return None;
+        }
+        let key = format!(
+            "{client}\n{}",
+            parts
+                .uri
+                .path_and_query()
+                .map(|value| value.as_str())
+                .unwrap_or(path)
+        );
+        let bases = parts
+            .headers
+            .remove(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok().map(str::to_string))
+            .map(|value| value.split(',').map(|tag| tag.trim().to_string()).collect())
+            .unwrap_or_default();
+        parts.headers.remove("a-im");
+        Some(DeltaRequest { key, bases })
+    }
+
+    // Keeps `body` as the client's new base and hands back the one it replaces.
+    fn remember(&self, key: &str, etag: &str, body: &Bytes) -> Option<(String, Bytes)> {
+        let now = crate::unix_now();
+        let size = body.len() as u64;
+        let mut inner = self.lock();
+        let previous = inner.entries.remove(key).map(|entry| {
+            inner.total -= entry.body.len() as u64;
+            (entry.etag, entry.body)
+        });
+        if size > self.config.max_bytes {
+            return previous;
+        }
+        while inner.total + size > self.config.max_bytes {
+            let Some(oldest) = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = inner.entries.remove(&oldest) {
+                inner.total -= entry.body.len() as u64;
+            }
+        }
+        inner.total += size;
+        inner.entries.insert(
+            key.to_string(),
+            Entry {
+                etag: etag.to_string(),
+                body: body.clone(),
+                last_used: now,
+            },
+        );
+        previous
+    }
+
+    pub(crate) async fn respond(
+        &self,
+        state: &AppState,
+        request: DeltaRequest,
+        resp: Response,
+    ) -> Response {
+        let is_json = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("json"));
+        if resp.status() != StatusCode::OK || !is_json {
+            return resp;
+        }
+        let (mut parts, body) = resp.into_parts();
+        let bytes = match buffer(body, MAX_BODY).await {
+            Ok(bytes) => bytes,
+            Err(body) => return Response::from_parts(parts, body),
+        };
+        let etag = format!("\"{}\"", &hex_digest(&bytes)[..24]);
+        let previous = self.remember(&request.key, &etag, &bytes);
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.insert(
+            header::VARY,
+            HeaderValue::from_static("A-IM, If-None-Match"),
+        );
+        if let Ok(value) = HeaderValue::from_str(&etag) {
+            parts.headers.insert(header::ETAG, value);
+        }
+        if request.bases.contains(&etag) {
+            self.record(state, "not_modified", bytes.len());
+            parts.status = StatusCode::NOT_MODIFIED;
+            parts.headers.remove(header::CONTENT_TYPE);
+            return Response::from_parts(parts, Body::empty());
+        }
+        let patch = previous
+            .filter(|(base, _)| request.bases.contains(base))
+            .and_then(|(base, base_body)| {
+                let from = serde_json::from_slice::<Value>(&base_body).ok()?;
+                let to = serde_json::from_slice::<Value>(&bytes).ok()?;
+                let mut ops = Vec::new();
+                diff(&from, &to, "", &mut ops);
+                let patch = serde_json::to_vec(&ops).ok()?;
+                // A patch that is no smaller than the payload is not worth applying.
+                (patch.len() < bytes.len()).then_some((base, patch))
+            });
+        let Some((base, patch)) = patch else {
+            self.record(state, "full", 0);
+            return Response::from_parts(parts, Body::from(bytes));
+        };
+        self.record(state, "patch", bytes.len() - patch.len());
+        parts.status = StatusCode::IM_USED;
+        parts
+            .headers
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PATCH_TYPE));
+        parts
+            .headers
+            .insert("im", HeaderValue::from_static("json-patch"));
+        if let Ok(value) = HeaderValue::from_str(&base) {
+            parts.headers.insert("delta-base", value);
+        }
+        Response::from_parts(parts, Body::from(patch))
+    }
+
+    fn record(&self, state: &AppState, kind: &str, saved: usize) {
+        if !state.config.metrics.enabled {
+            return;
+        }
+        state.metrics.inc(
+            "manatan_json_delta_responses_total",
+            "Delta-capable library responses by outcome.",
+            &[("kind", kind)],
+        );
+        state.metrics.add(
+            "manatan_json_delta_saved_bytes_total",
+            "Response bytes saved by sending patches and 304s.",
+            &[],
+            saved as f64,
+        );
+    }
+}
+
+fn accepts_patch(parts: &Parts) -> bool {
+    parts
+        .headers
+        .get_all("a-im")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            item.split(';')
+                .next()
+                .is_some_and(|name| name.trim().eq_ignore_ascii_case("json-patch"))
+        })
+}
+
+fn pointer(path: &str, key: &str) -> String {
+    format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"))
+}
+
+// RFC 6902 operations turning `from` into `to`. Arrays keep their common head and tail
+// and are patched element by element in between, so an entry added to or dropped from a
+// long list costs one operation rather than a rewrite of everything after it.
+fn diff(from: &Value, to: &Value, path: &str, ops: &mut Vec<Value>) {
+    if from == to {
+        return;
+    }
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            for key in from.keys().filter(|key| !to.contains_key(*key)) {
+                ops.push(json!({ "op": "remove", "path": pointer(path, key) }));
+            }
+            for (key, value) in to {
+                match from.get(key) {
+                    Some(old) => diff(old, value, &pointer(path, key), ops),
+                    None => ops.push(json!({
+                        "op": "add",
+                        "path": pointer(path, key),
+                        "value": value,
+                    })),
+                }
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            let head = from.iter().zip(to).take_while(|(a, b)| a == b).count();
+            let tail = from[head..]
+                .iter()
+                .rev()
+                .zip(to[head..].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let from = &from[head..from.len() - tail];
+            let to = &to[head..to.len() - tail];
+            let common = from.len().min(to.len());
+            for (offset, (a, b)) in from.iter().zip(to).enumerate() {
+                diff(a, b, &format!("{path}/{}", head + offset), ops);
+            }
+            for _ in common..from.len() {
+                ops.push(json!({ "op": "remove", "path": format!("{path}/{}", head + common) }));
+            }
+            for (offset, value) in to[common..].iter().enumerate() {
+                ops.push(json!({
+                    "op": "add",
+                    "path": format!("{path}/{}", head + common + offset),
+                    "value": value,
+                }));
+            }
+        }
+        _ => ops.push(json!({ "op": "replace", "path": path, "value": to })),
+    }
+}
@@ -0,0 +1,79 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub(crate) struct SigningKey {
+    key: Vec<u8>,
+}
+
+impl SigningKey {
+    pub(crate) fn from_secret(secret: &[u8]) -> Self {
+        Self {
+            key: secret.to_vec(),
+        }
+    }
+
+    pub(crate) fn load_or_create(path: &Path) -> io::Result<Self> {
+        if let Ok(key) = fs::read(path) {
+            if key.len() >= 32 {
+                return Ok(Self { key });
+            }
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let key = rand::random::<[u8; 32]>().to_vec();
+        fs::write(path, &key)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(path, fs::Permissions::from_mode(0o600));
+        }
+        Ok(Self { key })
+    }
+
+    pub(crate) fn sign(&self, message: &[u8]) -> String {
+        URL_SAFE_NO_PAD.encode(self.mac(message).finalize().into_bytes())
+    }
+
+    pub(crate) fn verify(&self, message: &[u8], signature: &str) -> bool {
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature.trim()) else {
+            return false;
+        };
+        self.mac(message).verify_slice(&signature).is_ok()
+    }
+
+    pub(crate) fn verify_hex(&self, message: &[u8], signature: &str) -> bool {
+        let signature = signature.trim();
+        if !signature.len().is_multiple_of(2) || !signature.is_ascii() {
+            return false;
+        }
+        let Ok(signature) = (0..signature.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&signature[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            return false;
+        };
+        self.mac(message).verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self, message: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac accepts any key length");
+        mac.update(message);
+        mac
+    }
+}
+
+pub(crate) fn random_id() -> String {
+    rand::random::<[u8; 16]>()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
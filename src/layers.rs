@@ -0,0 +1,240 @@
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::{CompressionLayer, CompressionLevel};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+use tracing::warn;
+
+use crate::app::{self, AppState};
+#[cfg(feature = "auth")]
+use crate::auth;
+use crate::config::{self, CompressionConfig, CorsPolicy};
+use crate::signing;
+
+// Browser tus clients read these off upload responses.
+const EXPOSED_HEADERS: [HeaderName; 5] = [
+    header::LOCATION,
+    HeaderName::from_static("tus-resumable"),
+    HeaderName::from_static("upload-offset"),
+    HeaderName::from_static("upload-length"),
+    HeaderName::from_static("upload-expires"),
+];
+
+pub fn cors_layer() -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers(EXPOSED_HEADERS)
+}
+
+pub fn compression_layer_for(config: &CompressionConfig) -> CompressionLayer<impl Predicate> {
+    let level = match config.level {
+        config::CompressionLevel::Fastest => CompressionLevel::Fastest,
+        config::CompressionLevel::Default => CompressionLevel::Default,
+        config::CompressionLevel::Best => CompressionLevel::Best,
+        config::CompressionLevel::Precise(level) => CompressionLevel::Precise(level),
+    };
+    // Images, video and archives are already compressed, and ranged or upgraded
+    // responses have to reach the client byte for byte.
+    let predicate = SizeAbove::new(config.min_bytes)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE)
+        .and(NotForContentType::const_new("video/"))
+        .and(NotForContentType::const_new("audio/"))
+        .and(NotForContentType::const_new("application/zip"))
+        .and(NotForContentType::const_new("application/x-rar"))
+        .and(NotForContentType::const_new("application/octet-stream"))
+        .and(
+            |status: StatusCode, _version, headers: &axum::http::HeaderMap, _extensions: &_| {
+                status != StatusCode::SWITCHING_PROTOCOLS
+                    && status != StatusCode::PARTIAL_CONTENT
+                    && !headers.contains_key(header::CONTENT_RANGE)
+            },
+        );
+    CompressionLayer::new()
+        .quality(level)
+        .compress_when(predicate)
+}
+
+pub fn cors_layer_for(policy: &CorsPolicy) -> CorsLayer {
+    if policy.is_permissive() {
+        if policy.allow_credentials {
+            warn!("ignoring cors allow_credentials without an allowed_origins list");
+        }
+        let mut layer = cors_layer();
+        if let Some(max_age) = policy.max_age_seconds {
+            layer = layer.max_age(std::time::Duration::from_secs(max_age));
+        }
+        return layer;
+    }
+
+    let origins = policy
+        .allowed_origins
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok())
+        .collect::<Vec<_>>();
+    // Wildcards are not allowed alongside credentials, so mirror the preflight instead.
+    let headers = if policy.allowed_headers.is_empty() {
+        AllowHeaders::mirror_request()
+    } else {
+        AllowHeaders::list(
+            policy
+                .allowed_headers
+                .iter()
+                .filter_map(|header| header.parse::<HeaderName>().ok()),
+        )
+    };
+    let methods = if policy.allowed_methods.is_empty() {
+        AllowMethods::mirror_request()
+    } else {
+        AllowMethods::list(
+            policy
+                .allowed_methods
+                .iter()
+                .filter_map(|method| method.parse::<Method>().ok()),
+        )
+    };
+    let mut layer = CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_headers(headers)
+        .allow_methods(methods)
+        .expose_headers(EXPOSED_HEADERS)
+        .allow_credentials(policy.allow_credentials);
+    if let Some(max_age) = policy.max_age_seconds {
+        layer = layer.max_age(std::time::Duration::from_secs(max_age));
+    }
+    layer
+}
+
+pub fn is_proxied_path(path: &str) -> bool {
+    path == "/health"
+        || path == "/openapi.json"
+        || path == "/api/v1"
+        || path.starts_with("/api/v1/")
+        || path == "/docs"
+        || path.starts_with("/docs/")
+        || path.starts_with("/extension/icon/")
+}
+
+#[derive(Clone)]
+pub struct ProxyLayer {
+    state: AppState,
+}
+
+impl ProxyLayer {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+}
+
+impl<S> Layer<S> for ProxyLayer {
+    type Service = ProxyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ProxyService {
+            state: self.state.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ProxyService<S> {
+    state: AppState,
+    inner: S,
+}
+
+impl<S> Service<Request> for ProxyService<S>
+where
+    S: Service<Request, Error = Infallible> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if is_proxied_path(req.uri().path()) {
+            let state = self.state.clone();
+            return Box::pin(async move { Ok(app::proxy(state, req).await) });
+        }
+        let future = self.inner.call(req);
+        Box::pin(async move { future.await.map(IntoResponse::into_response) })
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthLayer {
+    state: AppState,
+}
+
+impl AuthLayer {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService {
+            state: self.state.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthService<S> {
+    state: AppState,
+    inner: S,
+}
+
+impl<S> Service<Request> for AuthService<S>
+where
+    S: Service<Request, Error = Infallible> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let state = self.state.clone();
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            #[cfg_attr(not(feature = "auth"), allow(unused_mut))]
+            let mut req = match signing::check(&state, req).await {
+                Ok(req) => req,
+                Err(resp) => return Ok(resp),
+            };
+            #[cfg(feature = "auth")]
+            if let Some(resp) = auth::check(&state, &mut req) {
+                return Ok(resp);
+            }
+            inner.call(req).await.map(IntoResponse::into_response)
+        })
+    }
+}
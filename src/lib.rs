@@ -1,77 +1,187 @@
+#[cfg(feature = "auth")]
+mod access_schedule;
+mod admin;
+mod aidoku;
+mod archives;
+#[cfg(feature = "auth")]
+mod auth;
+mod backend_addr;
+mod bandwidth;
+mod base_path;
+mod body;
+#[cfg(feature = "cache")]
+mod cache_store;
+mod calendar;
+mod canonical;
+mod capabilities;
+mod cassette;
+mod coalesce;
+mod content_filter;
+mod control;
+mod credentials;
+mod devices;
+mod discovery;
+mod docs_cache;
+mod embedded;
+mod error_pages;
+mod events;
+#[cfg(feature = "opds")]
+mod feeds;
 mod ffi;
+mod ffi_guard;
+mod hwaccel;
+mod image_cache;
+mod image_transform;
+mod inbox;
+#[cfg(feature = "notifications")]
+mod integrations;
+mod jobs;
+mod json_delta;
+mod keys;
+mod library;
+mod local_manga;
+mod logging;
+#[cfg(feature = "notifications")]
+mod media_servers;
+mod metrics;
+#[cfg(feature = "metrics")]
+mod metrics_push;
+mod normalize;
+#[cfg(feature = "cache")]
+mod offline_cache;
+mod outbound;
+#[cfg(feature = "cache")]
+mod page_archive;
+mod principal;
+mod probes;
+mod quota;
+mod rate_limit;
+mod reconcile;
+mod relay_sync;
+mod resources;
+mod resumable;
+mod retention;
+mod runtime_config;
+mod sampling;
+mod selftest;
+mod share;
+mod signed_urls;
+mod signing;
+mod skip_markers;
+mod ssdp;
+mod stats;
+mod store;
+mod supervisor;
+mod tls;
+#[cfg(feature = "transcode")]
+mod transcodes;
+mod trash;
+mod updates;
+mod uploads;
+mod watchdog;
+#[cfg(feature = "webui")]
+mod webui;
+mod well_known;
+mod workers;
+mod wol;
 
 pub mod app;
+pub mod backup;
 pub mod cef_app;
 pub mod config;
+pub mod layers;
+pub mod storage;
+pub mod suwayomi;
+pub mod ws;
 
 use std::ffi::CString;
 
-pub use app::{build_router, build_router_without_cors, AppState};
-pub use config::Config;
+pub use app::{build_router, build_router_without_cors, serve, AppState};
+pub use config::{Config, ConfigUpdate};
+pub use control::{send as send_control, ControlMessage};
+pub use layers::{cors_layer, cors_layer_for, AuthLayer, ProxyLayer};
+pub use logging::init as init_logging;
+pub use ws::WsBridge;
 
-#[derive(Debug)]
-pub struct Error(String);
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    // `detail` is whatever `manatan_server_last_error` had to say about the failure.
+    #[error("{call} failed{}", detail_suffix(detail))]
+    Ffi {
+        call: &'static str,
+        detail: Option<String>,
+    },
+    #[error(transparent)]
+    Config(#[from] config::ConfigError),
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("backend unavailable: {0}")]
+    BackendUnavailable(String),
+    #[error("tls: {0}")]
+    Tls(String),
+    #[error("logging: {0}")]
+    Logging(String),
+    #[error("backup: {0}")]
+    Backup(String),
+    #[error("migration: {0}")]
+    Migration(String),
+    #[error("{name} {reason}")]
+    InvalidArgument { name: String, reason: String },
+}
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
+fn detail_suffix(detail: &Option<String>) -> String {
+    detail
+        .as_deref()
+        .map(|detail| format!(": {detail}"))
+        .unwrap_or_default()
 }
 
-impl std::error::Error for Error {}
+impl Error {
+    pub(crate) fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        Self::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
 
 pub async fn build_state(config: Config) -> Result<AppState, Error> {
-    let backend_host = std::env::var("MANATAN_BACKEND_HOST")
-        .unwrap_or_else(|_| "127.0.0.1".to_string());
-    let backend_port = std::env::var("MANATAN_BACKEND_PORT")
-        .ok()
-        .and_then(|value| value.parse::<u16>().ok())
-        .unwrap_or_else(|| config.port.saturating_add(1));
-
-    let backend_url = format!("http://{}:{}", backend_host, backend_port);
-
-    let host = to_cstring(&backend_host, "backend_host")?;
-    let java_runtime_url = to_cstring(&config.java_runtime_url, "java_runtime_url")?;
-    let aidoku_index_url = to_cstring(&config.aidoku_index_url, "aidoku_index_url")?;
-    let aidoku_cache_path = to_cstring(&config.aidoku_cache_path, "aidoku_cache_path")?;
-    let db_path = to_cstring(&config.db_path, "db_path")?;
-    let downloads_path = to_cstring(&config.downloads_path, "downloads_path")?;
-    let local_manga_path = to_cstring(&config.local_manga_path, "local_manga_path")?;
-    let local_anime_path = to_cstring(&config.local_anime_path, "local_anime_path")?;
+    // `Config::from_env` hands over an unchecked config; builder configs pass twice.
+    config.validate()?;
+    let backend = &config.backend;
+    let addr =
+        backend_addr::BackendAddr::new(&backend.host, backend.port, backend.socket.as_deref())?;
 
-    let migrate_path = match config.migrate_path.as_deref() {
-        Some(value) if !value.is_empty() => Some(to_cstring(value, "migrate_path")?),
-        _ => None,
-    };
+    let server =
+        embedded::EmbeddedServer::start(&config, &addr.host, backend.port, addr.socket_path())?;
+    supervisor::wait_ready(
+        addr.client(),
+        &addr.url,
+        std::time::Duration::from_secs(config.supervisor.ready_timeout_seconds),
+    )
+    .await?;
 
-    let ffi_config = ffi::ManatanServerConfig {
-        host: host.as_ptr(),
-        port: backend_port,
-        java_runtime_url: java_runtime_url.as_ptr(),
-        webview_enabled: if config.webview_enabled { 1 } else { 0 },
-        aidoku_index_url: aidoku_index_url.as_ptr(),
-        aidoku_enabled: if config.aidoku_enabled { 1 } else { 0 },
-        aidoku_cache_path: aidoku_cache_path.as_ptr(),
-        db_path: db_path.as_ptr(),
-        migrate_path: migrate_path
-            .as_ref()
-            .map(|value| value.as_ptr())
-            .unwrap_or(std::ptr::null()),
-        tracker_remote_search: if config.tracker_remote_search { 1 } else { 0 },
-        tracker_search_ttl_seconds: config.tracker_search_ttl_seconds,
-        downloads_path: downloads_path.as_ptr(),
-        local_manga_path: local_manga_path.as_ptr(),
-        local_anime_path: local_anime_path.as_ptr(),
-    };
-
-    let handle = unsafe { ffi::manatan_server_start(&ffi_config) };
-    if handle.is_null() {
-        return Err(Error("manatan_server_start failed".to_string()));
-    }
-
-    Ok(app::new_state(config, backend_url, handle))
+    let discovery = discovery::Discovery::start(&config);
+    let mut state = app::new_state(config, &addr, server);
+    state.discovery = discovery.map(std::sync::Arc::new);
+    Ok(state)
 }
 
 fn to_cstring(value: &str, label: &str) -> Result<CString, Error> {
-    CString::new(value).map_err(|_| Error(format!("{label} contains NUL bytes")))
+    CString::new(value).map_err(|_| Error::InvalidArgument {
+        name: label.to_string(),
+        reason: "contains NUL bytes".to_string(),
+    })
+}
+
+pub(crate) fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or(0)
 }
@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+
+use axum::http::HeaderMap;
+use serde_json::Value;
+
+use crate::app::AppState;
+
+#[derive(Clone, Debug)]
+pub(crate) struct LibraryManga {
+    pub id: i64,
+    pub title: String,
+    pub categories: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Chapter {
+    pub index: i64,
+    pub name: String,
+    pub scanlator: Option<String>,
+    pub read: bool,
+    pub downloaded: bool,
+    pub last_read_at: i64,
+}
+
+impl Chapter {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(Self {
+            index: value.get("index")?.as_i64()?,
+            name: value
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            scanlator: value
+                .get("scanlator")
+                .and_then(Value::as_str)
+                .filter(|scanlator| !scanlator.is_empty())
+                .map(str::to_string),
+            read: value.get("read").and_then(Value::as_bool).unwrap_or(false),
+            downloaded: value
+                .get("downloaded")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            last_read_at: value
+                .get("lastReadAt")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+pub(crate) async fn library(state: &AppState) -> Result<Vec<LibraryManga>, String> {
+    let headers = HeaderMap::new();
+    let categories = state.backend_json("/api/v1/category", &headers).await?;
+    let mut manga: BTreeMap<i64, LibraryManga> = BTreeMap::new();
+    for category in categories.as_array().map(Vec::as_slice).unwrap_or_default() {
+        let Some(id) = category.get("id").and_then(Value::as_i64) else {
+            continue;
+        };
+        let name = category
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let entries = state
+            .backend_json(&format!("/api/v1/category/{id}"), &headers)
+            .await?;
+        for entry in entries.as_array().map(Vec::as_slice).unwrap_or_default() {
+            let Some(manga_id) = entry.get("id").and_then(Value::as_i64) else {
+                continue;
+            };
+            let item = manga.entry(manga_id).or_insert_with(|| LibraryManga {
+                id: manga_id,
+                title: entry
+                    .get("title")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                categories: Vec::new(),
+            });
+            if !item.categories.contains(&name) {
+                item.categories.push(name.clone());
+            }
+        }
+    }
+    Ok(manga.into_values().collect())
+}
+
+pub(crate) async fn manga_categories(
+    state: &AppState,
+    manga_id: i64,
+    headers: &HeaderMap,
+) -> Result<Vec<String>, String> {
+    let value = state
+        .backend_json(&format!("/api/v1/manga/{manga_id}/category"), headers)
+        .await?;
+    Ok(value
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|category| category.get("name").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect())
+}
+
+pub(crate) async fn chapters(state: &AppState, manga_id: i64) -> Result<Vec<Chapter>, String> {
+    let value = state
+        .backend_json(
+            &format!("/api/v1/manga/{manga_id}/chapters"),
+            &HeaderMap::new(),
+        )
+        .await?;
+    Ok(value
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(Chapter::from_value)
+        .collect())
+}
+
+// Deleted chapters go through the trash, which keeps the files the backend removes.
+pub(crate) async fn delete_download(
+    state: &AppState,
+    manga_id: i64,
+    index: i64,
+) -> Result<(), String> {
+    let held = state.trash.hold_chapter(state, manga_id, index).await;
+    let deleted = forget_download(state, manga_id, index).await;
+    if let Some(held) = held {
+        state.trash.settle(held, deleted.is_ok()).await;
+    }
+    deleted
+}
+
+// Asks the backend to delete a chapter's download, which also clears its downloaded flag.
+pub(crate) async fn forget_download(
+    state: &AppState,
+    manga_id: i64,
+    index: i64,
+) -> Result<(), String> {
+    let path = format!("/api/v1/manga/{manga_id}/chapter/{index}");
+    let resp = state
+        .client
+        .delete(format!("{}{}", state.backend_url, path))
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("{path}: status {}", resp.status()));
+    }
+    Ok(())
+}
+
+pub(crate) async fn queue_download(
+    state: &AppState,
+    manga_id: i64,
+    index: i64,
+) -> Result<(), String> {
+    let path = format!("/api/v1/download/{manga_id}/chapter/{index}");
+    let resp = state
+        .client
+        .get(format!("{}{}", state.backend_url, path))
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("{path}: status {}", resp.status()));
+    }
+    Ok(())
+}
@@ -0,0 +1,333 @@
+use std::path::{Path, PathBuf};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Multipart, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::uploads;
+
+const MAX_NAME_CHARS: usize = 180;
+const LOCAL_SOURCE_ID: &str = "0";
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/local-manga/upload",
+            post(upload_multipart).put(upload_stream),
+        )
+        .layer(DefaultBodyLimit::disable())
+}
+
+#[derive(Deserialize)]
+struct UploadQuery {
+    series: Option<String>,
+    file: Option<String>,
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Serialize)]
+struct Stored {
+    series: String,
+    file: String,
+    bytes: u64,
+}
+
+async fn upload_multipart(
+    State(state): State<AppState>,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Response {
+    let mut series = query.series.clone();
+    let mut budget = state.config.uploads.max_bytes;
+    let mut stored = Vec::new();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+        };
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            if field.name() == Some("series") {
+                match field.text().await {
+                    Ok(text) => series = Some(text),
+                    Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+                }
+            }
+            continue;
+        };
+        match store(
+            &state,
+            series.as_deref(),
+            &file_name,
+            query.overwrite,
+            field,
+            &mut budget,
+        )
+        .await
+        {
+            Ok(file) => stored.push(file),
+            Err(resp) => return resp,
+        }
+    }
+    if stored.is_empty() {
+        return (StatusCode::BAD_REQUEST, "no archive files in upload").into_response();
+    }
+    finish(&state, headers, stored)
+}
+
+async fn upload_stream(
+    State(state): State<AppState>,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let Some(file_name) = query.file.as_deref() else {
+        return (StatusCode::BAD_REQUEST, "file query parameter is required").into_response();
+    };
+    let mut budget = state.config.uploads.max_bytes;
+    match store(
+        &state,
+        query.series.as_deref(),
+        file_name,
+        query.overwrite,
+        body.into_data_stream(),
+        &mut budget,
+    )
+    .await
+    {
+        Ok(file) => finish(&state, headers, vec![file]),
+        Err(resp) => resp,
+    }
+}
+
+// Imports an archive a resumable upload has assembled on disk.
+pub(crate) async fn import_file(
+    state: &AppState,
+    headers: HeaderMap,
+    series: Option<&str>,
+    file_name: &str,
+    overwrite: bool,
+    path: &Path,
+) -> Response {
+    let file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    let chunks = futures::stream::unfold(file, |mut file| async move {
+        let mut buf = vec![0; 256 * 1024];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buf.truncate(read);
+                Some((Ok::<_, std::io::Error>(Bytes::from(buf)), file))
+            }
+            Err(err) => Some((Err(err), file)),
+        }
+    });
+    let mut budget = state.config.uploads.max_bytes;
+    match store(
+        state,
+        series,
+        file_name,
+        overwrite,
+        Box::pin(chunks),
+        &mut budget,
+    )
+    .await
+    {
+        Ok(file) => finish(state, headers, vec![file]),
+        Err(resp) => resp,
+    }
+}
+
+fn finish(state: &AppState, headers: HeaderMap, stored: Vec<Stored>) -> Response {
+    rescan(state, headers);
+    (StatusCode::CREATED, Json(json!({ "files": stored }))).into_response()
+}
+
+// Browsing the local source makes the backend pick up new files.
+pub(crate) fn rescan(state: &AppState, headers: HeaderMap) {
+    browse(state, headers, format!("/api/v1/source/{LOCAL_SOURCE_ID}/popular/1"));
+}
+
+pub(crate) fn rescan_anime(state: &AppState) {
+    browse(
+        state,
+        HeaderMap::new(),
+        format!("/api/v1/anime/source/{LOCAL_SOURCE_ID}/popular/1"),
+    );
+}
+
+fn browse(state: &AppState, headers: HeaderMap, path: String) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(err) = state.backend_json(&path, &headers).await {
+            warn!("local library rescan failed: {}", err);
+        }
+    });
+}
+
+async fn store<S, E>(
+    state: &AppState,
+    series: Option<&str>,
+    file_name: &str,
+    overwrite: bool,
+    mut stream: S,
+    budget: &mut u64,
+) -> Result<Stored, Response>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let file = archive_name(file_name).ok_or_else(|| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("{file_name}: only .cbz and .cbr archives are accepted"),
+        )
+            .into_response()
+    })?;
+    let stem = file.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&file);
+    let series = series
+        .and_then(sanitize)
+        .or_else(|| sanitize(stem))
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "invalid series name").into_response())?;
+
+    let dir = PathBuf::from(&state.config.local_manga_path).join(&series);
+    let target = dir.join(&file);
+    if !overwrite && tokio::fs::try_exists(&target).await.unwrap_or(false) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("{series}/{file} already exists"),
+        )
+            .into_response());
+    }
+    let partial = dir.join(format!(".{file}.part"));
+    let written = write(
+        &dir,
+        &partial,
+        &file,
+        &mut stream,
+        state.config.uploads.max_bytes,
+        budget,
+    )
+    .await;
+    let bytes = match written {
+        Ok(bytes) => bytes,
+        Err(resp) => {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(resp);
+        }
+    };
+    if overwrite && tokio::fs::try_exists(&target).await.unwrap_or(false) {
+        let trash = state.trash.clone();
+        let replaced = target.clone();
+        let label = format!("{series}/{file} replaced by an upload");
+        let discarded = tokio::task::spawn_blocking(move || trash.discard(&replaced, &label))
+            .await
+            .map_err(std::io::Error::other)
+            .and_then(|result| result);
+        if let Err(err) = discarded {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response());
+        }
+    }
+    if let Err(err) = tokio::fs::rename(&partial, &target).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response());
+    }
+    info!("imported local manga {}/{} ({} bytes)", series, file, bytes);
+    Ok(Stored {
+        series,
+        file,
+        bytes,
+    })
+}
+
+async fn write<S, E>(
+    dir: &Path,
+    partial: &Path,
+    file: &str,
+    stream: &mut S,
+    limit: u64,
+    budget: &mut u64,
+) -> Result<u64, Response>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let io_error =
+        |err: std::io::Error| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+    tokio::fs::create_dir_all(dir).await.map_err(io_error)?;
+    let mut out = tokio::fs::File::create(partial).await.map_err(io_error)?;
+    let mut written = 0u64;
+    let mut head = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk =
+            chunk.map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()).into_response())?;
+        if chunk.len() as u64 > *budget {
+            return Err(uploads::too_large(limit));
+        }
+        *budget -= chunk.len() as u64;
+        if head.len() < 4 {
+            head.extend(chunk.iter().take(4 - head.len()));
+        }
+        out.write_all(&chunk).await.map_err(io_error)?;
+        written += chunk.len() as u64;
+    }
+    out.flush().await.map_err(io_error)?;
+    if !has_magic(file, &head) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("{file} is not a valid archive"),
+        )
+            .into_response());
+    }
+    Ok(written)
+}
+
+pub(crate) fn archive_name(file_name: &str) -> Option<String> {
+    let base = file_name.rsplit(['/', '\\']).next().unwrap_or(file_name);
+    let name = sanitize(base)?;
+    let (_, extension) = name.rsplit_once('.')?;
+    matches!(extension.to_ascii_lowercase().as_str(), "cbz" | "cbr").then_some(name)
+}
+
+fn has_magic(file: &str, head: &[u8]) -> bool {
+    if file.to_ascii_lowercase().ends_with(".cbz") {
+        head.starts_with(b"PK\x03\x04")
+    } else {
+        head.starts_with(b"Rar!")
+    }
+}
+
+pub(crate) fn sanitize(name: &str) -> Option<String> {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cleaned = cleaned.trim_matches(|c: char| c == '.' || c == ' ');
+    let cleaned: String = cleaned.chars().take(MAX_NAME_CHARS).collect();
+    (!cleaned.is_empty()).then_some(cleaned)
+}
@@ -0,0 +1,141 @@
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::config::{LogTarget, LoggingConfig};
+use crate::Error;
+
+pub fn init(config: &LoggingConfig) -> Result<(), Error> {
+    let filter = EnvFilter::try_new(&config.filter)
+        .map_err(|err| Error::Logging(format!("invalid log filter {:?}: {err}", config.filter)))?;
+    let registry = tracing_subscriber::registry().with(filter);
+
+    let result = match config.target {
+        LogTarget::Stderr => registry
+            .with(fmt::layer().with_writer(std::io::stderr))
+            .try_init(),
+        #[cfg(target_os = "linux")]
+        LogTarget::Journald => {
+            let layer = tracing_journald::layer()
+                .map_err(|err| Error::io("failed to connect to journald", err))?
+                .with_syslog_identifier(config.identifier.clone());
+            registry.with(layer).try_init()
+        }
+        #[cfg(windows)]
+        LogTarget::EventLog => {
+            let layer =
+                event_log::EventLogLayer::new(&config.identifier).map_err(Error::Logging)?;
+            registry.with(layer).try_init()
+        }
+        #[allow(unreachable_patterns)]
+        target => {
+            return Err(Error::Logging(format!(
+                "log target {target:?} is not supported on this platform"
+            )))
+        }
+    };
+    result.map_err(|err| Error::Logging(format!("failed to install log subscriber: {err}")))
+}
+
+#[cfg(windows)]
+mod event_log {
+    use std::fmt::Write;
+
+    use tracing::{field::Field, Event, Level, Subscriber};
+    use tracing_subscriber::{layer::Context, Layer};
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+        EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+    };
+
+    pub(super) struct EventLogLayer {
+        handle: HANDLE,
+    }
+
+    unsafe impl Send for EventLogLayer {}
+    unsafe impl Sync for EventLogLayer {}
+
+    impl EventLogLayer {
+        pub(super) fn new(source: &str) -> Result<Self, String> {
+            let source = wide(source);
+            let handle = unsafe { RegisterEventSourceW(std::ptr::null(), source.as_ptr()) };
+            if handle.is_null() {
+                return Err(format!(
+                    "failed to register event source: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+            Ok(Self { handle })
+        }
+    }
+
+    impl Drop for EventLogLayer {
+        fn drop(&mut self) {
+            unsafe {
+                DeregisterEventSource(self.handle);
+            }
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for EventLogLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let metadata = event.metadata();
+            let (kind, id) = match *metadata.level() {
+                Level::ERROR => (EVENTLOG_ERROR_TYPE, 1),
+                Level::WARN => (EVENTLOG_WARNING_TYPE, 2),
+                Level::INFO => (EVENTLOG_INFORMATION_TYPE, 3),
+                Level::DEBUG | Level::TRACE => (EVENTLOG_INFORMATION_TYPE, 4),
+            };
+
+            let mut visitor = Message::default();
+            event.record(&mut visitor);
+            let text = wide(&format!(
+                "{}: {}{}",
+                metadata.target(),
+                visitor.message,
+                visitor.fields
+            ));
+            let strings = [text.as_ptr()];
+            unsafe {
+                ReportEventW(
+                    self.handle,
+                    kind,
+                    0,
+                    id,
+                    std::ptr::null_mut(),
+                    1,
+                    0,
+                    strings.as_ptr(),
+                    std::ptr::null(),
+                );
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Message {
+        message: String,
+        fields: String,
+    }
+
+    impl tracing::field::Visit for Message {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "message" {
+                self.message.push_str(value);
+            } else {
+                let _ = write!(self.fields, " {}={}", field.name(), value);
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                let _ = write!(self.message, "{value:?}");
+            } else {
+                let _ = write!(self.fields, " {}={:?}", field.name(), value);
+            }
+        }
+    }
+
+    fn wide(value: &str) -> Vec<u16> {
+        value.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+}
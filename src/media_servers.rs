@@ -0,0 +1,197 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use reqwest::Method;
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::MediaServersConfig;
+use crate::unix_now;
+
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_RECENT: usize = 20;
+
+// Asks Jellyfin and Plex to rescan after new anime files land in the local library,
+// so a server sharing the folder doesn't need its own scan schedule.
+pub(crate) struct MediaServers {
+    config: MediaServersConfig,
+    dirty: AtomicBool,
+    wake: Notify,
+    recent: Mutex<VecDeque<Value>>,
+}
+
+impl MediaServers {
+    pub(crate) fn new(config: MediaServersConfig) -> Self {
+        Self {
+            config,
+            dirty: AtomicBool::new(false),
+            wake: Notify::new(),
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // Imports come in bursts; everything within the delay window shares one refresh.
+    pub(crate) fn notify(&self) {
+        if self.config.is_enabled() {
+            self.dirty.store(true, Ordering::Relaxed);
+            self.wake.notify_one();
+        }
+    }
+
+    fn record(&self, outcome: Value) {
+        let mut recent = self.recent.lock().unwrap_or_else(|err| err.into_inner());
+        if recent.len() == MAX_RECENT {
+            recent.pop_back();
+        }
+        recent.push_front(outcome);
+    }
+}
+
+async fn refresh_jellyfin(state: &AppState, url: &str) -> Result<(), String> {
+    let config = &state.media_servers.config;
+    let mut request = state
+        .outbound
+        .post(&format!("{}/Library/Refresh", url.trim_end_matches('/')))
+        .timeout(REFRESH_TIMEOUT);
+    if let Some(key) = config.jellyfin_api_key.as_deref() {
+        request = request.header("X-Emby-Token", key);
+    }
+    request
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+// Without configured sections Plex scans every library.
+async fn refresh_plex(state: &AppState, url: &str) -> Result<(), String> {
+    let config = &state.media_servers.config;
+    let sections = if config.plex_sections.is_empty() {
+        vec!["all".to_string()]
+    } else {
+        config.plex_sections.clone()
+    };
+    for section in sections {
+        let mut request = state
+            .outbound
+            .request(
+                Method::GET,
+                &format!(
+                    "{}/library/sections/{}/refresh",
+                    url.trim_end_matches('/'),
+                    section
+                ),
+            )
+            .timeout(REFRESH_TIMEOUT);
+        if let Some(token) = config.plex_token.as_deref() {
+            request = request.header("X-Plex-Token", token);
+        }
+        request
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|err| format!("section {section}: {err}"))?;
+    }
+    Ok(())
+}
+
+async fn refresh(state: &AppState) -> Vec<Value> {
+    let config = &state.media_servers.config;
+    let mut outcomes = Vec::new();
+    for (server, url) in [
+        ("jellyfin", config.jellyfin_url.as_deref()),
+        ("plex", config.plex_url.as_deref()),
+    ] {
+        let Some(url) = url else {
+            continue;
+        };
+        let result = match server {
+            "jellyfin" => refresh_jellyfin(state, url).await,
+            _ => refresh_plex(state, url).await,
+        };
+        let mut outcome = json!({ "server": server, "at": unix_now() });
+        match result {
+            Ok(()) => info!("asked {} to refresh its library", server),
+            Err(err) => {
+                warn!("{} library refresh failed: {}", server, err);
+                outcome["error"] = json!(err);
+            }
+        }
+        state.media_servers.record(outcome.clone());
+        outcomes.push(outcome);
+    }
+    outcomes
+}
+
+pub(crate) fn spawn(state: AppState) {
+    if !state.media_servers.config.is_enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        let servers = state.media_servers.clone();
+        let delay = Duration::from_secs(servers.config.delay_seconds);
+        loop {
+            servers.wake.notified().await;
+            tokio::time::sleep(delay).await;
+            // A wake-up left over from the last window finds nothing to do.
+            if servers.dirty.swap(false, Ordering::Relaxed) {
+                refresh(&state).await;
+            }
+        }
+    });
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/media-servers", get(status))
+        .route("/admin/media-servers/refresh", post(refresh_now))
+}
+
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    let config = &state.media_servers.config;
+    Json(json!({
+        "jellyfin": config.jellyfin_url,
+        "plex": config.plex_url,
+        "plex_sections": config.plex_sections,
+        "delay_seconds": config.delay_seconds,
+        "pending": state.media_servers.dirty.load(Ordering::Relaxed),
+        "recent": *state
+            .media_servers
+            .recent
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()),
+    }))
+}
+
+// Skips the delay, for checking the URLs and credentials.
+async fn refresh_now(State(state): State<AppState>) -> Response {
+    if !state.media_servers.config.is_enabled() {
+        return (
+            StatusCode::NOT_FOUND,
+            "no media server is configured (MANATAN_JELLYFIN_URL, MANATAN_PLEX_URL)",
+        )
+            .into_response();
+    }
+    let outcomes = refresh(&state).await;
+    let status = if outcomes
+        .iter()
+        .any(|outcome| outcome.get("error").is_some())
+    {
+        StatusCode::BAD_GATEWAY
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(json!({ "results": outcomes }))).into_response()
+}
@@ -0,0 +1,392 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "metrics")]
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+#[cfg(feature = "metrics")]
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use futures::StreamExt;
+use tracing::info;
+
+use crate::app::AppState;
+use crate::layers::is_proxied_path;
+
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    #[cfg(feature = "metrics")]
+    fn buckets(&self) -> impl Iterator<Item = (String, u64)> + '_ {
+        LATENCY_BUCKETS
+            .iter()
+            .zip(self.buckets)
+            .map(|(bound, count)| (bound.to_string(), count))
+            .chain(std::iter::once(("+Inf".to_string(), self.count)))
+    }
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+struct HistogramFamily {
+    help: &'static str,
+    series: BTreeMap<Vec<(&'static str, String)>, Histogram>,
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+struct Family {
+    help: &'static str,
+    kind: Kind,
+    series: BTreeMap<Vec<(&'static str, String)>, f64>,
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) struct Sample {
+    pub name: String,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+    families: Mutex<BTreeMap<&'static str, Family>>,
+    histograms: Mutex<BTreeMap<&'static str, HistogramFamily>>,
+}
+
+impl Metrics {
+    pub(crate) fn inc(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+    ) {
+        self.add(name, help, labels, 1.0);
+    }
+
+    pub(crate) fn add(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        value: f64,
+    ) {
+        self.update(name, help, Kind::Counter, labels, |current| {
+            *current += value
+        });
+    }
+
+    pub(crate) fn set(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        value: f64,
+    ) {
+        self.update(name, help, Kind::Gauge, labels, |current| *current = value);
+    }
+
+    pub(crate) fn add_gauge(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        delta: f64,
+    ) {
+        self.update(name, help, Kind::Gauge, labels, |current| *current += delta);
+    }
+
+    pub(crate) fn observe(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        value: f64,
+    ) {
+        let key = labels
+            .iter()
+            .map(|(label, value)| (*label, value.to_string()))
+            .collect::<Vec<_>>();
+        let mut histograms = self
+            .histograms
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let family = histograms.entry(name).or_insert_with(|| HistogramFamily {
+            help,
+            series: BTreeMap::new(),
+        });
+        let histogram = family.series.entry(key).or_default();
+        for (bucket, bound) in histogram.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    fn update(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        kind: Kind,
+        labels: &[(&'static str, &str)],
+        apply: impl FnOnce(&mut f64),
+    ) {
+        let key = labels
+            .iter()
+            .map(|(label, value)| (*label, value.to_string()))
+            .collect::<Vec<_>>();
+        let mut families = self.families.lock().unwrap_or_else(|err| err.into_inner());
+        let family = families.entry(name).or_insert_with(|| Family {
+            help,
+            kind,
+            series: BTreeMap::new(),
+        });
+        apply(family.series.entry(key).or_insert(0.0));
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn render(&self) -> String {
+        let families = self.families.lock().unwrap_or_else(|err| err.into_inner());
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let kind = match family.kind {
+                Kind::Counter => "counter",
+                Kind::Gauge => "gauge",
+            };
+            let _ = writeln!(out, "# HELP {name} {}", family.help);
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, value) in &family.series {
+                let _ = writeln!(out, "{name}{} {value}", format_labels(labels));
+            }
+        }
+        drop(families);
+
+        let histograms = self
+            .histograms
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        for (name, family) in histograms.iter() {
+            let _ = writeln!(out, "# HELP {name} {}", family.help);
+            let _ = writeln!(out, "# TYPE {name} histogram");
+            for (labels, histogram) in &family.series {
+                for (le, count) in histogram.buckets() {
+                    let mut bucket_labels = labels.clone();
+                    bucket_labels.push(("le", le));
+                    let _ = writeln!(
+                        out,
+                        "{name}_bucket{} {count}",
+                        format_labels(&bucket_labels)
+                    );
+                }
+                let labels = format_labels(labels);
+                let _ = writeln!(out, "{name}_sum{labels} {}", histogram.sum);
+                let _ = writeln!(out, "{name}_count{labels} {}", histogram.count);
+            }
+        }
+        out
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn samples(&self) -> Vec<Sample> {
+        let families = self.families.lock().unwrap_or_else(|err| err.into_inner());
+        let mut samples: Vec<Sample> = families
+            .iter()
+            .flat_map(|(name, family)| {
+                family.series.iter().map(|(labels, value)| Sample {
+                    name: name.to_string(),
+                    labels: labels.clone(),
+                    value: *value,
+                })
+            })
+            .collect();
+        drop(families);
+
+        let histograms = self
+            .histograms
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        for (name, family) in histograms.iter() {
+            for (labels, histogram) in &family.series {
+                for (le, count) in histogram.buckets() {
+                    let mut bucket_labels = labels.clone();
+                    bucket_labels.push(("le", le));
+                    samples.push(Sample {
+                        name: format!("{name}_bucket"),
+                        labels: bucket_labels,
+                        value: count as f64,
+                    });
+                }
+                samples.push(Sample {
+                    name: format!("{name}_sum"),
+                    labels: labels.clone(),
+                    value: histogram.sum,
+                });
+                samples.push(Sample {
+                    name: format!("{name}_count"),
+                    labels: labels.clone(),
+                    value: histogram.count as f64,
+                });
+            }
+        }
+        samples
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn format_labels(labels: &[(&'static str, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let inner = labels
+        .iter()
+        .map(|(label, value)| {
+            let escaped = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{label}=\"{escaped}\"")
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{{{inner}}}")
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) async fn metrics_handler(State(state): State<AppState>) -> Response {
+    if !state.config.metrics.enabled {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+        .into_response()
+}
+
+pub(crate) async fn middleware(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config.metrics;
+    if !config.enabled && !config.request_log {
+        return next.run(req).await;
+    }
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let route = route_label(&path);
+    let started = Instant::now();
+    let resp = next.run(req).await;
+    let elapsed = started.elapsed();
+    let status = resp.status();
+
+    if config.enabled {
+        let status_label = status.as_u16().to_string();
+        let labels = [
+            ("method", method.as_str()),
+            ("route", route.as_str()),
+            ("status", status_label.as_str()),
+        ];
+        state.metrics.inc(
+            "manatan_http_requests_total",
+            "HTTP requests handled by the proxy.",
+            &labels,
+        );
+        state.metrics.observe(
+            "manatan_http_request_duration_seconds",
+            "Time to response headers for HTTP requests.",
+            &[("method", method.as_str()), ("route", route.as_str())],
+            elapsed.as_secs_f64(),
+        );
+    }
+    if config.request_log {
+        info!(
+            target: "manatan::access",
+            method = %method,
+            path = %path,
+            status = status.as_u16(),
+            duration_ms = elapsed.as_millis() as u64,
+            "request"
+        );
+    }
+    if !config.enabled || !is_proxied_path(&path) {
+        return resp;
+    }
+
+    let (parts, body) = resp.into_parts();
+    let mut counter = ByteCounter {
+        metrics: state.metrics.clone(),
+        route,
+        bytes: 0,
+    };
+    let stream = body.into_data_stream().map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            counter.record(chunk.len());
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+struct ByteCounter {
+    metrics: Arc<Metrics>,
+    route: String,
+    bytes: u64,
+}
+
+impl ByteCounter {
+    fn record(&mut self, len: usize) {
+        self.bytes += len as u64;
+    }
+}
+
+impl Drop for ByteCounter {
+    fn drop(&mut self) {
+        self.metrics.add(
+            "manatan_proxied_bytes_total",
+            "Response bytes proxied from the backend.",
+            &[("route", self.route.as_str())],
+            self.bytes as f64,
+        );
+    }
+}
+
+fn route_label(path: &str) -> String {
+    let mut segments = path.trim_start_matches('/').split('/');
+    match segments.next().unwrap_or_default() {
+        "" => "/".to_string(),
+        "api" => {
+            let version = segments.next().unwrap_or_default();
+            match segments.next().filter(|resource| !resource.is_empty()) {
+                Some(resource) if !resource.chars().all(|c| c.is_ascii_digit()) => {
+                    format!("/api/{version}/{resource}")
+                }
+                _ => format!("/api/{version}"),
+            }
+        }
+        first => format!("/{first}"),
+    }
+}
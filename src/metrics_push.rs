@@ -0,0 +1,164 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::Method;
+use tracing::warn;
+
+use crate::config::MetricsPushConfig;
+use crate::metrics::{Metrics, Sample};
+use crate::outbound::Outbound;
+
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) fn spawn(
+    config: MetricsPushConfig,
+    instance: String,
+    metrics: Arc<Metrics>,
+    outbound: Arc<Outbound>,
+) {
+    if !config.is_enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(config.interval_seconds));
+        loop {
+            ticker.tick().await;
+            if let Some(url) = config.pushgateway_url.as_deref() {
+                if let Err(err) = push_gateway(&config, url, &instance, &metrics, &outbound).await {
+                    warn!("pushgateway push to {} failed: {}", url, err);
+                    metrics.inc(
+                        "manatan_metrics_push_failures_total",
+                        "Failed metric pushes by target.",
+                        &[("target", "pushgateway")],
+                    );
+                }
+            }
+            if let Some(url) = config.remote_write_url.as_deref() {
+                if let Err(err) = remote_write(&config, url, &instance, &metrics, &outbound).await {
+                    warn!("remote write to {} failed: {}", url, err);
+                    metrics.inc(
+                        "manatan_metrics_push_failures_total",
+                        "Failed metric pushes by target.",
+                        &[("target", "remote_write")],
+                    );
+                }
+            }
+        }
+    });
+}
+
+async fn push_gateway(
+    config: &MetricsPushConfig,
+    url: &str,
+    instance: &str,
+    metrics: &Metrics,
+    outbound: &Outbound,
+) -> Result<(), String> {
+    let url = format!(
+        "{}/metrics/job@base64/{}/instance@base64/{}",
+        url.trim_end_matches('/'),
+        URL_SAFE_NO_PAD.encode(&config.job),
+        URL_SAFE_NO_PAD.encode(instance),
+    );
+    let mut request = outbound
+        .request(Method::PUT, &url)
+        .timeout(PUSH_TIMEOUT)
+        .header("content-type", "text/plain; version=0.0.4")
+        .body(metrics.render());
+    if let Some(token) = config.bearer_token.as_deref() {
+        request = request.bearer_auth(token);
+    }
+    let resp = request.send().await.map_err(|err| err.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("status {}", resp.status()));
+    }
+    Ok(())
+}
+
+async fn remote_write(
+    config: &MetricsPushConfig,
+    url: &str,
+    instance: &str,
+    metrics: &Metrics,
+    outbound: &Outbound,
+) -> Result<(), String> {
+    let timestamp = chrono::Utc::now().timestamp_millis();
+    let body = encode_write_request(&metrics.samples(), &config.job, instance, timestamp);
+    let body = snap::raw::Encoder::new()
+        .compress_vec(&body)
+        .map_err(|err| err.to_string())?;
+    let mut request = outbound
+        .request(Method::POST, url)
+        .timeout(PUSH_TIMEOUT)
+        .header("content-type", "application/x-protobuf")
+        .header("content-encoding", "snappy")
+        .header("x-prometheus-remote-write-version", "0.1.0")
+        .body(body);
+    if let Some(token) = config.bearer_token.as_deref() {
+        request = request.bearer_auth(token);
+    }
+    let resp = request.send().await.map_err(|err| err.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("status {}", resp.status()));
+    }
+    Ok(())
+}
+
+// prometheus.WriteRequest { repeated TimeSeries timeseries = 1; }
+// TimeSeries { repeated Label labels = 1; repeated Sample samples = 2; }
+// Label { string name = 1; string value = 2; }
+// Sample { double value = 1; int64 timestamp = 2; }
+fn encode_write_request(samples: &[Sample], job: &str, instance: &str, timestamp: i64) -> Vec<u8> {
+    let mut out = Vec::new();
+    for sample in samples {
+        let mut labels = vec![
+            ("__name__", sample.name.as_str()),
+            ("instance", instance),
+            ("job", job),
+        ];
+        labels.extend(
+            sample
+                .labels
+                .iter()
+                .filter(|(name, _)| !matches!(*name, "__name__" | "instance" | "job"))
+                .map(|(name, value)| (*name, value.as_str())),
+        );
+        labels.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut series = Vec::new();
+        for (name, value) in labels {
+            let mut label = Vec::new();
+            put_bytes(&mut label, 1, name.as_bytes());
+            put_bytes(&mut label, 2, value.as_bytes());
+            put_bytes(&mut series, 1, &label);
+        }
+        let mut point = Vec::new();
+        put_key(&mut point, 1, 1);
+        point.extend_from_slice(&sample.value.to_le_bytes());
+        put_key(&mut point, 2, 0);
+        put_varint(&mut point, timestamp as u64);
+        put_bytes(&mut series, 2, &point);
+
+        put_bytes(&mut out, 1, &series);
+    }
+    out
+}
+
+fn put_key(out: &mut Vec<u8>, field: u64, wire_type: u64) {
+    put_varint(out, (field << 3) | wire_type);
+}
+
+fn put_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_key(out, field, 2);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
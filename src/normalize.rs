@@ -0,0 +1,94 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, uri::PathAndQuery, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::app::AppState;
+use crate::config::{PathsConfig, TrailingSlash};
+
+pub(crate) fn normalize_path(path: &str, config: &PathsConfig) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let raw = path.strip_prefix('/').unwrap_or(path).split('/');
+    let count = raw.clone().count();
+    for (index, segment) in raw.enumerate() {
+        let last = index + 1 == count;
+        if segment.is_empty() && config.collapse_slashes && !last {
+            continue;
+        }
+        if config.remove_dot_segments {
+            match decode_dots(segment).as_deref() {
+                Some(".") => {
+                    if last {
+                        segments.push("");
+                    }
+                    continue;
+                }
+                Some("..") => {
+                    segments.pop();
+                    if last {
+                        segments.push("");
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        segments.push(segment);
+    }
+
+    format!("/{}", segments.join("/"))
+}
+
+fn decode_dots(segment: &str) -> Option<String> {
+    if segment.len() > 6 {
+        return None;
+    }
+    let decoded = segment.replace("%2e", ".").replace("%2E", ".");
+    (decoded == "." || decoded == "..").then_some(decoded)
+}
+
+pub(crate) async fn middleware(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config.paths;
+    let path = req.uri().path();
+    let mut normalized = normalize_path(path, config);
+
+    let trailing = normalized.len() > 1 && normalized.ends_with('/');
+    if trailing && config.trailing_slash != TrailingSlash::Keep {
+        normalized.truncate(normalized.trim_end_matches('/').len().max(1));
+        if config.trailing_slash == TrailingSlash::Redirect {
+            let location = match req.uri().query() {
+                Some(query) => format!("{normalized}?{query}"),
+                None => normalized,
+            };
+            return (
+                StatusCode::PERMANENT_REDIRECT,
+                [(header::LOCATION, location)],
+            )
+                .into_response();
+        }
+    }
+
+    if normalized != path {
+        let path_and_query = match req.uri().query() {
+            Some(query) => format!("{normalized}?{query}"),
+            None => normalized,
+        };
+        let mut parts = req.uri().clone().into_parts();
+        match PathAndQuery::try_from(path_and_query) {
+            Ok(value) => parts.path_and_query = Some(value),
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+        }
+        match Uri::from_parts(parts) {
+            Ok(uri) => *req.uri_mut() = uri,
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+        }
+    }
+
+    next.run(req).await
+}
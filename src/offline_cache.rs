@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, request::Parts, HeaderMap, HeaderValue, Method, StatusCode},
+    response::Response,
+};
+use sha2::{Digest, Sha256};
+
+use crate::body::buffer;
+use crate::config::OfflineCacheConfig;
+use crate::image_cache;
+use crate::supervisor::BackendUnreachable;
+
+const MAX_ENTRY_BYTES: usize = 4 << 20;
+const KEYED_HEADERS: [header::HeaderName; 3] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::ACCEPT_ENCODING,
+];
+
+struct Entry {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored_at: u64,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    total: u64,
+}
+
+pub(crate) struct OfflineCache {
+    config: OfflineCacheConfig,
+    inner: Mutex<Inner>,
+}
+
+impl OfflineCache {
+    pub(crate) fn new(config: OfflineCacheConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    // Responses depend on who is asking, so the credentials are part of the key.
+    pub(crate) fn key(&self, parts: &Parts) -> Option<String> {
+        let path = parts.uri.path();
+        if !self.config.enabled
+            || parts.method != Method::GET
+            || image_cache::is_image_path(path)
+            || !self
+                .config
+                .paths
+                .iter()
+                .any(|prefix| path.starts_with(prefix))
+        {
+            return None;
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(
+            parts
+                .uri
+                .path_and_query()
+                .map(|value| value.as_str())
+                .unwrap_or(path),
+        );
+        for name in KEYED_HEADERS {
+            hasher.update(b"\n");
+            if let Some(value) = parts.headers.get(name) {
+                hasher.update(value.as_bytes());
+            }
+        }
+        Some(
+            hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn stale(&self, key: &str) -> Option<Response> {
+        let now = crate::unix_now();
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let entry = inner.entries.get_mut(key)?;
+        let age = now.saturating_sub(entry.stored_at);
+        if age > self.config.max_stale_seconds {
+            return None;
+        }
+        entry.last_used = now;
+        let mut resp = Response::new(Body::from(entry.body.clone()));
+        *resp.status_mut() = entry.status;
+        *resp.headers_mut() = entry.headers.clone();
+        let headers = resp.headers_mut();
+        headers.insert(header::AGE, HeaderValue::from(age));
+        headers.insert(
+            header::WARNING,
+            HeaderValue::from_static("110 - \"Response is Stale\""),
+        );
+        headers.insert("x-manatan-stale", HeaderValue::from_static("true"));
+        headers.insert("x-manatan-cached-at", HeaderValue::from(entry.stored_at));
+        Some(resp)
+    }
+
+    // Successful responses are remembered; a backend failure is answered from
+    // the last good copy when there is one.
+    pub(crate) async fn settle(&self, key: &str, resp: Response) -> Response {
+        let failed = resp.extensions().get::<BackendUnreachable>().is_some()
+            || matches!(
+                resp.status(),
+                StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT
+            );
+        if failed {
+            return self.stale(key).unwrap_or(resp);
+        }
+        if resp.status() != StatusCode::OK {
+            if matches!(resp.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+                self.remove(key);
+            }
+            return resp;
+        }
+
+        let (parts, body) = resp.into_parts();
+        match buffer(body, MAX_ENTRY_BYTES).await {
+            Ok(bytes) => {
+                self.insert(key, parts.status, &parts.headers, bytes.clone());
+                Response::from_parts(parts, Body::from(bytes))
+            }
+            Err(body) => Response::from_parts(parts, body),
+        }
+    }
+
+    fn insert(&self, key: &str, status: StatusCode, headers: &HeaderMap, body: Bytes) {
+        let size = body.len() as u64;
+        if size > self.config.max_bytes {
+            return;
+        }
+        let mut headers = headers.clone();
+        for name in [header::TRANSFER_ENCODING, header::CONNECTION] {
+            headers.remove(name);
+        }
+        let now = crate::unix_now();
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(previous) = inner.entries.remove(key) {
+            inner.total -= previous.body.len() as u64;
+        }
+        while inner.total + size > self.config.max_bytes {
+            let Some(oldest) = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(evicted) = inner.entries.remove(&oldest) {
+                inner.total -= evicted.body.len() as u64;
+            }
+        }
+        inner.total += size;
+        inner.entries.insert(
+            key.to_string(),
+            Entry {
+                status,
+                headers,
+                body,
+                stored_at: now,
+                last_used: now,
+            },
+        );
+    }
+
+    fn remove(&self, key: &str) {
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(previous) = inner.entries.remove(key) {
+            inner.total -= previous.body.len() as u64;
+        }
+    }
+}
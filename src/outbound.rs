@@ -0,0 +1,437 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Body, Client, Method, Request, RequestBuilder, Response, Url};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config::{IpFamily, OutboundConfig};
+use crate::unix_now;
+
+const REDACTED: &str = "[redacted]";
+// Header and query parameter names containing any of these have their values left out.
+const SECRET_NAMES: [&str; 8] = [
+    "auth", "cookie", "token", "secret", "key", "session", "sig", "password",
+];
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct HeaderProfile {
+    pub user_agent: String,
+    #[serde(default)]
+    pub accept_language: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+pub(crate) struct Outbound {
+    client: Client,
+    profiles: BTreeMap<String, HeaderProfile>,
+    default_profile: Option<String>,
+    domain_profiles: Vec<(String, String)>,
+    local_address: Option<IpAddr>,
+    ip_family: IpFamily,
+    log: Option<RequestLog>,
+}
+
+// The most recent outbound requests, kept when `MANATAN_OUTBOUND_LOG` is on so users
+// can see exactly what the server fetched and from where.
+struct RequestLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<LogEntry>>,
+}
+
+#[derive(Clone, Serialize)]
+struct LogEntry {
+    at: u64,
+    method: String,
+    host: String,
+    path: String,
+    headers: BTreeMap<String, String>,
+    status: Option<u16>,
+    duration_ms: u64,
+    error: Option<&'static str>,
+}
+
+impl LogEntry {
+    fn new(request: &Request) -> Self {
+        let url = request.url();
+        let mut path = url.path().to_string();
+        if url.query().is_some() {
+            let query = url
+                .query_pairs()
+                .map(|(name, value)| {
+                    let value = if is_secret(&name) {
+                        REDACTED.into()
+                    } else {
+                        value
+                    };
+                    format!("{name}={value}")
+                })
+                .collect::<Vec<_>>();
+            path = format!("{path}?{}", query.join("&"));
+        }
+        let headers = request
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if is_secret(name.as_str()) {
+                    REDACTED.to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        Self {
+            at: unix_now(),
+            method: request.method().to_string(),
+            host: match url.port() {
+                Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+                None => url.host_str().unwrap_or_default().to_string(),
+            },
+            path,
+            headers,
+            status: None,
+            duration_ms: 0,
+            error: None,
+        }
+    }
+}
+
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_NAMES.iter().any(|secret| name.contains(secret))
+}
+
+// reqwest errors carry the full URL, query included, so only their kind is logged.
+fn error_kind(err: &reqwest::Error) -> &'static str {
+    if err.is_timeout() {
+        "timeout"
+    } else if err.is_connect() {
+        "connect"
+    } else if err.is_redirect() {
+        "redirect"
+    } else if err.is_body() || err.is_decode() {
+        "body"
+    } else {
+        "request"
+    }
+}
+
+impl RequestLog {
+    fn push(&self, entry: LogEntry) {
+        info!(
+            "outbound {} {}{} -> {} in {}ms",
+            entry.method,
+            entry.host,
+            entry.path,
+            entry
+                .status
+                .map(|status| status.to_string())
+                .or(entry.error.map(str::to_string))
+                .unwrap_or_default(),
+            entry.duration_ms
+        );
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        entries.push_front(entry);
+        entries.truncate(self.capacity);
+    }
+}
+
+// A request on the outbound client, recorded in the request log when it is sent.
+pub(crate) struct OutboundRequest<'a> {
+    builder: RequestBuilder,
+    log: Option<&'a RequestLog>,
+}
+
+impl OutboundRequest<'_> {
+    pub(crate) fn header(mut self, name: &str, value: &str) -> Self {
+        self.builder = self.builder.header(name, value);
+        self
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn bearer_auth(mut self, token: &str) -> Self {
+        self.builder = self.builder.bearer_auth(token);
+        self
+    }
+
+    pub(crate) fn timeout(mut self, timeout: Duration) -> Self {
+        self.builder = self.builder.timeout(timeout);
+        self
+    }
+
+    pub(crate) fn body(mut self, body: impl Into<Body>) -> Self {
+        self.builder = self.builder.body(body);
+        self
+    }
+
+    pub(crate) fn json<T: Serialize + ?Sized>(mut self, json: &T) -> Self {
+        self.builder = self.builder.json(json);
+        self
+    }
+
+    pub(crate) async fn send(self) -> reqwest::Result<Response> {
+        let Some(log) = self.log else {
+            return self.builder.send().await;
+        };
+        let (client, request) = self.builder.build_split();
+        let request = request?;
+        let mut entry = LogEntry::new(&request);
+        let started = Instant::now();
+        let result = client.execute(request).await;
+        entry.duration_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(resp) => entry.status = Some(resp.status().as_u16()),
+            Err(err) => entry.error = Some(error_kind(err)),
+        }
+        log.push(entry);
+        result
+    }
+}
+
+impl Outbound {
+    pub(crate) fn new(config: &OutboundConfig) -> Self {
+        let mut profiles = builtin_profiles();
+        if let Some(path) = config.profiles_file.as_deref() {
+            match std::fs::read(path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| {
+                    serde_json::from_slice::<BTreeMap<String, HeaderProfile>>(&bytes)
+                        .map_err(|err| err.to_string())
+                }) {
+                Ok(custom) => profiles.extend(custom),
+                Err(err) => warn!("ignoring outbound profiles file {}: {}", path, err),
+            }
+        }
+
+        for name in config
+            .default_profile
+            .iter()
+            .chain(config.domain_profiles.iter().map(|(_, name)| name))
+        {
+            if !profiles.contains_key(name) {
+                warn!("unknown outbound header profile: {}", name);
+            }
+        }
+
+        let local_address = config.bind_address.or_else(|| {
+            let name = config.interface.as_deref()?;
+            let address = interface_address(name, config.ip_family);
+            if address.is_none() {
+                warn!(
+                    "outbound interface {} has no usable address; not binding",
+                    name
+                );
+            }
+            address
+        });
+        let mut builder = Client::builder().local_address(local_address);
+        if local_address.is_some() || config.ip_family != IpFamily::Any {
+            builder = builder.dns_resolver(Arc::new(FamilyResolver {
+                family: config.ip_family,
+                local_address,
+            }));
+        }
+
+        Self {
+            client: builder.build().unwrap_or_default(),
+            profiles,
+            default_profile: config.default_profile.clone(),
+            domain_profiles: config.domain_profiles.clone(),
+            local_address,
+            ip_family: config.ip_family,
+            log: config.log.then(|| RequestLog {
+                capacity: config.log_entries,
+                entries: Mutex::new(VecDeque::new()),
+            }),
+        }
+    }
+
+    pub(crate) fn request(&self, method: Method, url: &str) -> OutboundRequest<'_> {
+        let builder = self.client.request(method, url);
+        let builder = match Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+            .and_then(|host| self.profile_for(&host))
+        {
+            Some(profile) => apply(builder, profile),
+            None => builder,
+        };
+        OutboundRequest {
+            builder,
+            log: self.log.as_ref(),
+        }
+    }
+
+    pub(crate) fn post(&self, url: &str) -> OutboundRequest<'_> {
+        self.request(Method::POST, url)
+    }
+
+    pub(crate) fn log(&self) -> serde_json::Value {
+        let entries = self
+            .log
+            .as_ref()
+            .map(|log| {
+                log.entries
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        serde_json::json!({ "enabled": self.log.is_some(), "entries": entries })
+    }
+
+    pub(crate) fn describe(&self) -> serde_json::Value {
+        serde_json::json!({
+            "profiles": self.profiles,
+            "default_profile": self.default_profile,
+            "domains": self
+                .domain_profiles
+                .iter()
+                .map(|(domain, profile)| serde_json::json!({ "domain": domain, "profile": profile }))
+                .collect::<Vec<_>>(),
+            "local_address": self.local_address,
+            "ip_family": self.ip_family,
+        })
+    }
+
+    fn profile_for(&self, host: &str) -> Option<&HeaderProfile> {
+        let name = self
+            .domain_profiles
+            .iter()
+            .filter(|(domain, _)| {
+                host == domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            })
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(_, name)| name)
+            .or(self.default_profile.as_ref())?;
+        self.profiles.get(name)
+    }
+}
+
+// Interfaces are bound through one of their addresses, which keeps this working where
+// binding to a device needs extra privileges or is not supported at all.
+fn interface_address(name: &str, family: IpFamily) -> Option<IpAddr> {
+    let mut addresses = if_addrs::get_if_addrs()
+        .ok()?
+        .into_iter()
+        .filter(|interface| interface.name == name)
+        .map(|interface| interface.ip())
+        .filter(|ip| match ip {
+            IpAddr::V6(ip) => !ip.is_unicast_link_local(),
+            IpAddr::V4(ip) => !ip.is_link_local(),
+        })
+        .collect::<Vec<_>>();
+    addresses.sort_by_key(|ip| !prefers(family, ip));
+    addresses.into_iter().next()
+}
+
+fn prefers(family: IpFamily, ip: &IpAddr) -> bool {
+    match family {
+        IpFamily::Any | IpFamily::Ipv4 => ip.is_ipv4(),
+        IpFamily::Ipv6 => ip.is_ipv6(),
+    }
+}
+
+// Puts the preferred family first so the connector tries it before falling back, and
+// drops addresses a bound socket could not reach.
+struct FamilyResolver {
+    family: IpFamily,
+    local_address: Option<IpAddr>,
+}
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let (family, local_address) = (self.family, self.local_address);
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let mut addresses = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .collect::<Vec<SocketAddr>>();
+            if let Some(local) = local_address {
+                addresses.retain(|address| address.is_ipv4() == local.is_ipv4());
+            }
+            if family != IpFamily::Any {
+                addresses.sort_by_key(|address| !prefers(family, &address.ip()));
+            }
+            if addresses.is_empty() {
+                return Err(
+                    format!("{host} has no address reachable from the bound address").into(),
+                );
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn apply(mut builder: RequestBuilder, profile: &HeaderProfile) -> RequestBuilder {
+    builder = builder.header("user-agent", &profile.user_agent);
+    if let Some(language) = profile.accept_language.as_deref() {
+        builder = builder.header("accept-language", language);
+    }
+    for (name, value) in &profile.headers {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    builder
+}
+
+fn builtin_profiles() -> BTreeMap<String, HeaderProfile> {
+    let chromium_hints = |platform: &str, mobile: bool| {
+        BTreeMap::from([
+            (
+                "sec-ch-ua".to_string(),
+                "\"Chromium\";v=\"124\", \"Google Chrome\";v=\"124\", \"Not-A.Brand\";v=\"99\""
+                    .to_string(),
+            ),
+            (
+                "sec-ch-ua-mobile".to_string(),
+                if mobile { "?1" } else { "?0" }.to_string(),
+            ),
+            ("sec-ch-ua-platform".to_string(), format!("\"{platform}\"")),
+        ])
+    };
+
+    BTreeMap::from([
+        (
+            "chrome-desktop".to_string(),
+            HeaderProfile {
+                user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36".to_string(),
+                accept_language: Some("en-US,en;q=0.9".to_string()),
+                headers: chromium_hints("Windows", false),
+            },
+        ),
+        (
+            "chrome-android".to_string(),
+            HeaderProfile {
+                user_agent: "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Mobile Safari/537.36".to_string(),
+                accept_language: Some("en-US,en;q=0.9".to_string()),
+                headers: chromium_hints("Android", true),
+            },
+        ),
+        (
+            "firefox-desktop".to_string(),
+            HeaderProfile {
+                user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:125.0) Gecko/20100101 Firefox/125.0".to_string(),
+                accept_language: Some("en-US,en;q=0.5".to_string()),
+                headers: BTreeMap::new(),
+            },
+        ),
+        (
+            "safari-ios".to_string(),
+            HeaderProfile {
+                user_agent: "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Mobile/15E148 Safari/604.1".to_string(),
+                accept_language: Some("en-US,en;q=0.9".to_string()),
+                headers: BTreeMap::new(),
+            },
+        ),
+    ])
+}
@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path as UrlPath, Request, State},
+    http::{header, Method, StatusCode},
+    response::Response,
+    routing::{delete, get},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::body::buffer;
+use crate::config::PageArchiveConfig;
+use crate::image_cache::{hex_digest, is_cacheable, Meta, MAX_CACHED_BODY};
+use crate::library;
+
+// Where a chapter page lives in the archive: one directory per series, then per chapter.
+pub(crate) struct PageKey {
+    manga_id: i64,
+    chapter: String,
+    page: String,
+}
+
+// Pages of series in the library, kept until the series leaves it. Unlike the image
+// cache nothing expires or is evicted, so a re-read never goes back to the source.
+pub(crate) struct PageArchive {
+    config: PageArchiveConfig,
+    dir: PathBuf,
+    library: RwLock<HashSet<i64>>,
+    total: AtomicU64,
+    full: AtomicBool,
+}
+
+impl PageArchive {
+    pub(crate) fn new(config: PageArchiveConfig, data_path: &str) -> Self {
+        let dir = config
+            .path
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(data_path).join("page-archive"));
+        let total = if config.enabled { dir_size(&dir) } else { 0 };
+        if config.enabled && total > 0 {
+            info!("page archive at {} holds {} bytes", dir.display(), total);
+        }
+        Self {
+            config,
+            dir,
+            library: RwLock::new(HashSet::new()),
+            total: AtomicU64::new(total),
+            full: AtomicBool::new(false),
+        }
+    }
+
+    // The archive key for a GET of a chapter page, whatever its query.
+    pub(crate) fn key(&self, req: &Request) -> Option<PageKey> {
+        if !self.config.enabled || req.method() != Method::GET {
+            return None;
+        }
+        let rest = req.uri().path().strip_prefix("/api/v1/manga/")?;
+        let segments = rest.split('/').collect::<Vec<_>>();
+        let [manga_id, "chapter", chapter, "page", page] = segments.as_slice() else {
+            return None;
+        };
+        let numeric = |value: &str| !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit());
+        if !numeric(chapter) || !numeric(page) {
+            return None;
+        }
+        Some(PageKey {
+            manga_id: manga_id.parse().ok()?,
+            chapter: chapter.to_string(),
+            page: page.to_string(),
+        })
+    }
+
+    fn series_dir(&self, manga_id: i64) -> PathBuf {
+        self.dir.join(manga_id.to_string())
+    }
+
+    fn data_path(&self, key: &PageKey) -> PathBuf {
+        self.series_dir(key.manga_id)
+            .join(&key.chapter)
+            .join(format!("{}.bin", key.page))
+    }
+
+    fn meta_path(&self, key: &PageKey) -> PathBuf {
+        self.series_dir(key.manga_id)
+            .join(&key.chapter)
+            .join(format!("{}.json", key.page))
+    }
+
+    pub(crate) async fn lookup(&self, key: &PageKey) -> Option<(Meta, Bytes)> {
+        let meta = tokio::fs::read(self.meta_path(key)).await.ok()?;
+        let meta: Meta = serde_json::from_slice(&meta).ok()?;
+        let body = tokio::fs::read(self.data_path(key)).await.ok()?;
+        Some((meta, Bytes::from(body)))
+    }
+
+    fn in_library(&self, manga_id: i64) -> bool {
+        self.library
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .contains(&manga_id)
+    }
+
+    // Archives a page the backend just served when its series is in the library; the
+    // response is passed on either way.
+    pub(crate) async fn keep(&self, key: PageKey, resp: Response) -> Response {
+        if !self.in_library(key.manga_id) || !is_cacheable(&resp) {
+            return resp;
+        }
+        let (parts, body) = resp.into_parts();
+        let bytes = match buffer(body, MAX_CACHED_BODY).await {
+            Ok(bytes) => bytes,
+            Err(body) => return Response::from_parts(parts, body),
+        };
+        let meta = Meta {
+            content_type: parts
+                .headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            etag: parts
+                .headers
+                .get(header::ETAG)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("\"{}\"", &hex_digest(&bytes)[..24])),
+            stored_at: crate::unix_now(),
+        };
+        self.store(&key, &meta, &bytes).await;
+        Response::from_parts(parts, Body::from(bytes))
+    }
+
+    async fn store(&self, key: &PageKey, meta: &Meta, body: &Bytes) {
+        let size = body.len() as u64;
+        let max = self.config.max_bytes;
+        if max > 0 && self.total.load(Ordering::Relaxed) + size > max {
+            if !self.full.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "page archive at {} reached MANATAN_PAGE_ARCHIVE_SIZE; new pages are not archived",
+                    self.dir.display()
+                );
+            }
+            return;
+        }
+        let data_path = self.data_path(key);
+        let written = async {
+            if let Some(parent) = data_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&data_path, body).await?;
+            tokio::fs::write(self.meta_path(key), serde_json::to_vec(meta)?).await
+        }
+        .await;
+        match written {
+            Ok(()) => {
+                self.total.fetch_add(size, Ordering::Relaxed);
+            }
+            Err(err) => {
+                warn!(
+                    "page archive write to {} failed: {}",
+                    data_path.display(),
+                    err
+                );
+                let _ = tokio::fs::remove_file(&data_path).await;
+                let _ = tokio::fs::remove_file(self.meta_path(key)).await;
+            }
+        }
+    }
+
+    async fn remove_series(&self, manga_id: i64) -> bool {
+        let dir = self.series_dir(manga_id);
+        let size = tokio::task::spawn_blocking({
+            let dir = dir.clone();
+            move || dir_size(&dir)
+        })
+        .await
+        .unwrap_or(0);
+        if tokio::fs::remove_dir_all(&dir).await.is_err() {
+            return false;
+        }
+        let _ = self
+            .total
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                Some(total.saturating_sub(size))
+            });
+        self.full.store(false, Ordering::Relaxed);
+        true
+    }
+
+    fn archived_series(&self) -> Vec<i64> {
+        std::fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let mut total = 0u64;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                total += entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+            }
+        }
+    }
+    total
+}
+
+// Keeps the set of library series current and drops the pages of series that left it.
+pub(crate) fn spawn(state: AppState) {
+    if !state.page_archive.config.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(
+            state.page_archive.config.refresh_seconds,
+        ));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let series = match library::library(&state).await {
+                Ok(manga) => manga
+                    .into_iter()
+                    .map(|manga| manga.id)
+                    .collect::<HashSet<_>>(),
+                Err(err) => {
+                    warn!("page archive could not read the library: {}", err);
+                    continue;
+                }
+            };
+            let archive = &state.page_archive;
+            for manga_id in archive.archived_series() {
+                if !series.contains(&manga_id) && archive.remove_series(manga_id).await {
+                    info!(
+                        "page archive dropped manga {} after it left the library",
+                        manga_id
+                    );
+                }
+            }
+            *archive
+                .library
+                .write()
+                .unwrap_or_else(|err| err.into_inner()) = series;
+        }
+    });
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/page-archive", get(status))
+        .route("/admin/page-archive/{manga_id}", delete(remove))
+}
+
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    let archive = &state.page_archive;
+    Json(json!({
+        "enabled": archive.config.enabled,
+        "path": archive.dir.display().to_string(),
+        "bytes": archive.total.load(Ordering::Relaxed),
+        "max_bytes": archive.config.max_bytes,
+        "full": archive.full.load(Ordering::Relaxed),
+        "series": archive.archived_series(),
+        "library_series": archive.library.read().unwrap_or_else(|err| err.into_inner()).len(),
+    }))
+}
+
+async fn remove(State(state): State<AppState>, UrlPath(manga_id): UrlPath<i64>) -> StatusCode {
+    if state.page_archive.remove_series(manga_id).await {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
@@ -0,0 +1,25 @@
+#[cfg(feature = "auth")]
+use std::collections::BTreeSet;
+
+#[cfg(feature = "auth")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "auth")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Scope {
+    Read,
+    Write,
+    Admin,
+    Downloads,
+}
+
+// Who a request acts as, set by token or basic auth and by signed requests. Kept apart
+// from the token store so builds without the `auth` feature still know signed callers.
+#[derive(Clone, Debug)]
+pub(crate) struct Principal {
+    pub id: String,
+    #[cfg(feature = "auth")]
+    pub scopes: BTreeSet<Scope>,
+    pub image_profile: Option<String>,
+}
@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use reqwest::Method;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::{HealthProbe, ProbeTarget, ProbesConfig};
+use crate::supervisor::Lifecycle;
+use crate::unix_now;
+
+#[derive(Clone, Serialize)]
+struct Outcome {
+    target: String,
+    optional: bool,
+    healthy: bool,
+    checked_at: Option<u64>,
+    latency_ms: Option<u64>,
+    detail: Option<String>,
+}
+
+// Checks on the services the server leans on besides its backend (a NAS mount, the
+// torrent client, trackers), run on a timer so `/readyz` answers from the last round.
+pub(crate) struct Probes {
+    config: ProbesConfig,
+    outcomes: Mutex<BTreeMap<String, Outcome>>,
+}
+
+impl Probes {
+    pub(crate) fn new(config: ProbesConfig) -> Self {
+        let outcomes = config
+            .probes
+            .iter()
+            .map(|probe| {
+                let outcome = Outcome {
+                    target: describe(&probe.target),
+                    optional: probe.optional,
+                    healthy: false,
+                    checked_at: None,
+                    latency_ms: None,
+                    detail: Some("not checked yet".to_string()),
+                };
+                (probe.name.clone(), outcome)
+            })
+            .collect();
+        Self {
+            config,
+            outcomes: Mutex::new(outcomes),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Outcome>> {
+        self.outcomes.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // Optional probes never hold readiness back; the others count from their first pass.
+    pub(crate) fn ready(&self) -> bool {
+        self.lock()
+            .values()
+            .all(|outcome| outcome.optional || outcome.healthy)
+    }
+
+    pub(crate) fn describe(&self) -> Value {
+        json!({
+            "ready": self.ready(),
+            "interval_seconds": self.config.interval_seconds,
+            "checks": *self.lock(),
+        })
+    }
+}
+
+fn describe(target: &ProbeTarget) -> String {
+    match target {
+        ProbeTarget::Http(url) => url.clone(),
+        ProbeTarget::Tcp(addr) => format!("tcp:{addr}"),
+        ProbeTarget::Path(path) => format!("path:{path}"),
+        ProbeTarget::Exec(command) => format!("exec:{}", command.join(" ")),
+    }
+}
+
+async fn check(
+    state: &AppState,
+    target: &ProbeTarget,
+    timeout: Duration,
+) -> Result<String, String> {
+    match target {
+        ProbeTarget::Http(url) => {
+            let resp = state
+                .outbound
+                .request(Method::GET, url)
+                .timeout(timeout)
+                .send()
+                .await
+                .map_err(|err| err.to_string())?;
+            // Anything short of a server error means something is answering.
+            if resp.status().is_server_error() {
+                Err(format!("HTTP {}", resp.status()))
+            } else {
+                Ok(format!("HTTP {}", resp.status()))
+            }
+        }
+        ProbeTarget::Tcp(addr) => {
+            match tokio::time::timeout(timeout, tokio::net::TcpStream::connect(addr)).await {
+                Ok(Ok(_)) => Ok("connected".to_string()),
+                Ok(Err(err)) => Err(err.to_string()),
+                Err(_) => Err("timed out".to_string()),
+            }
+        }
+        // A stale network mount blocks instead of failing, hence the timeout around it.
+        ProbeTarget::Path(path) => {
+            let path = path.clone();
+            let listing = tokio::task::spawn_blocking(move || {
+                std::fs::read_dir(&path).map(|entries| entries.count())
+            });
+            match tokio::time::timeout(timeout, listing).await {
+                Ok(Ok(Ok(entries))) => Ok(format!("{entries} entries")),
+                Ok(Ok(Err(err))) => Err(err.to_string()),
+                Ok(Err(err)) => Err(err.to_string()),
+                Err(_) => Err("timed out".to_string()),
+            }
+        }
+        ProbeTarget::Exec(command) => {
+            let command = command.clone();
+            tokio::task::spawn_blocking(move || run(&command, timeout))
+                .await
+                .map_err(|err| err.to_string())?
+        }
+    }
+}
+
+// The script's last line of output becomes the probe's detail. Scripts are expected to
+// print little; one that fills the pipe before exiting runs into the timeout.
+fn run(command: &[String], timeout: Duration) -> Result<String, String> {
+    let (program, args) = command.split_first().ok_or("empty command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("{program}: {err}"))?;
+    let deadline = Instant::now() + timeout;
+    while child.try_wait().map_err(|err| err.to_string())?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err("timed out".to_string());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let output = child.wait_with_output().map_err(|err| err.to_string())?;
+    let last_line = |bytes: &[u8]| {
+        String::from_utf8_lossy(bytes)
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .map(|line| line.trim().to_string())
+    };
+    let detail = last_line(&output.stdout).or_else(|| last_line(&output.stderr));
+    if output.status.success() {
+        Ok(detail.unwrap_or_else(|| "ok".to_string()))
+    } else {
+        Err(detail.unwrap_or_else(|| output.status.to_string()))
+    }
+}
+
+async fn probe(state: &AppState, probe: &HealthProbe) {
+    let started = Instant::now();
+    let timeout = Duration::from_secs(state.probes.config.timeout_seconds);
+    let result = check(state, &probe.target, timeout).await;
+    let healthy = result.is_ok();
+    let mut outcomes = state.probes.lock();
+    let Some(outcome) = outcomes.get_mut(&probe.name) else {
+        return;
+    };
+    let first = outcome.checked_at.is_none();
+    match &result {
+        Err(err) if first || outcome.healthy => {
+            warn!("health probe {} failed: {}", probe.name, err)
+        }
+        Ok(_) if !first && !outcome.healthy => info!("health probe {} recovered", probe.name),
+        _ => {}
+    }
+    outcome.healthy = healthy;
+    outcome.checked_at = Some(unix_now());
+    outcome.latency_ms = Some(started.elapsed().as_millis() as u64);
+    outcome.detail = Some(match result {
+        Ok(detail) | Err(detail) => detail,
+    });
+    drop(outcomes);
+    if state.config.metrics.enabled {
+        state.metrics.set(
+            "manatan_health_probe_up",
+            "Whether each configured health probe passed its last check.",
+            &[("probe", probe.name.as_str())],
+            if healthy { 1.0 } else { 0.0 },
+        );
+    }
+}
+
+pub(crate) fn spawn(state: AppState) {
+    if state.probes.config.probes.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(state.probes.config.interval_seconds));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            futures::future::join_all(
+                state
+                    .probes
+                    .config
+                    .probes
+                    .iter()
+                    .map(|health_probe| probe(&state, health_probe)),
+            )
+            .await;
+        }
+    });
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/readyz", get(readyz))
+}
+
+// The backend counts as a required check of its own.
+async fn readyz(State(state): State<AppState>) -> Response {
+    let lifecycle = state.supervisor.lifecycle();
+    let backend = lifecycle == Lifecycle::Ready && state.backend.port().is_some();
+    let mut checks = json!({
+        "backend": { "healthy": backend, "detail": lifecycle },
+    });
+    for (name, outcome) in state.probes.lock().iter() {
+        checks[name] = json!(outcome);
+    }
+    let ready = backend && state.probes.ready();
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "checks": checks,
+    });
+    (status, Json(body)).into_response()
+}
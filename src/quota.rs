@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::{request::Parts, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::QuotaConfig;
+use crate::library;
+use crate::unix_now;
+
+const PRUNE_BATCH: usize = 10;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct Usage {
+    pub total_bytes: u64,
+    pub categories: BTreeMap<String, u64>,
+    pub scanned_at: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Exceeded {
+    pub scope: &'static str,
+    pub category: Option<String>,
+    pub used_bytes: u64,
+    pub limit_bytes: u64,
+}
+
+pub(crate) struct Quota {
+    config: QuotaConfig,
+    root: PathBuf,
+    usage: Mutex<Option<Usage>>,
+}
+
+impl Quota {
+    pub(crate) fn new(config: QuotaConfig, downloads_path: &str) -> Self {
+        Self {
+            config,
+            root: PathBuf::from(downloads_path),
+            usage: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn usage(&self) -> Option<Usage> {
+        self.usage
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    fn global_exceeded(&self, usage: &Usage) -> Option<Exceeded> {
+        let limit = self.config.global_bytes?;
+        (usage.total_bytes >= limit).then_some(Exceeded {
+            scope: "global",
+            category: None,
+            used_bytes: usage.total_bytes,
+            limit_bytes: limit,
+        })
+    }
+
+    fn category_exceeded(&self, usage: &Usage, category: &str) -> Option<Exceeded> {
+        let limit = self.config.category_limit(category)?;
+        let used = usage
+            .categories
+            .get(&category.to_lowercase())
+            .copied()
+            .unwrap_or(0);
+        (used >= limit).then_some(Exceeded {
+            scope: "category",
+            category: Some(category.to_string()),
+            used_bytes: used,
+            limit_bytes: limit,
+        })
+    }
+
+    fn all_exceeded(&self, usage: &Usage) -> Vec<Exceeded> {
+        self.global_exceeded(usage)
+            .into_iter()
+            .chain(
+                self.config
+                    .category_bytes
+                    .iter()
+                    .filter_map(|(category, _)| self.category_exceeded(usage, category)),
+            )
+            .collect()
+    }
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/downloads/quota", get(show))
+        .route("/admin/downloads/quota/scan", post(scan))
+}
+
+async fn show(State(state): State<AppState>) -> Json<Value> {
+    let usage = state.quota.usage();
+    let exceeded = usage
+        .as_ref()
+        .map(|usage| state.quota.all_exceeded(usage))
+        .unwrap_or_default();
+    Json(json!({
+        "enabled": state.quota.config.is_enabled(),
+        "global_bytes": state.quota.config.global_bytes,
+        "category_bytes": state.quota.config.category_bytes.iter().cloned().collect::<BTreeMap<_, _>>(),
+        "auto_prune": state.quota.config.auto_prune,
+        "usage": usage,
+        "exceeded": exceeded,
+    }))
+}
+
+async fn scan(State(state): State<AppState>) -> Response {
+    match refresh(&state).await {
+        Ok(usage) => Json(usage).into_response(),
+        Err(err) => (StatusCode::BAD_GATEWAY, err).into_response(),
+    }
+}
+
+// Download triggers: GET/POST/PUT /api/v1/download/{mangaId}/chapter/{index}
+// and POST /api/v1/download/batch.
+fn trigger_manga(method: &Method, path: &str) -> Option<Option<i64>> {
+    let rest = path.strip_prefix("/api/v1/download/")?;
+    if rest == "batch" {
+        return (method == Method::POST).then_some(None);
+    }
+    if method == Method::DELETE || method == Method::PATCH {
+        return None;
+    }
+    let mut segments = rest.split('/');
+    let manga_id = segments.next()?.parse::<i64>().ok()?;
+    (segments.next()? == "chapter" && segments.next().is_some()).then_some(Some(manga_id))
+}
+
+pub(crate) async fn check(state: &AppState, parts: &Parts) -> Option<Response> {
+    if !state.quota.config.is_enabled() {
+        return None;
+    }
+    let manga_id = trigger_manga(&parts.method, parts.uri.path())?;
+    let usage = state.quota.usage()?;
+    let categories = match manga_id {
+        Some(id) if !state.quota.config.category_bytes.is_empty() => {
+            library::manga_categories(state, id, &parts.headers)
+                .await
+                .unwrap_or_default()
+        }
+        _ => Vec::new(),
+    };
+    let exceeded = state.quota.global_exceeded(&usage).or_else(|| {
+        categories
+            .iter()
+            .find_map(|category| state.quota.category_exceeded(&usage, category))
+    })?;
+    let message = match exceeded.category.as_deref() {
+        Some(category) => format!(
+            "download quota for category {category} exceeded ({} of {} bytes used)",
+            exceeded.used_bytes, exceeded.limit_bytes
+        ),
+        None => format!(
+            "download quota exceeded ({} of {} bytes used)",
+            exceeded.used_bytes, exceeded.limit_bytes
+        ),
+    };
+    Some(
+        (
+            StatusCode::CONFLICT,
+            Json(json!({ "error": message, "quota": exceeded })),
+        )
+            .into_response(),
+    )
+}
+
+pub(crate) fn spawn(state: AppState) {
+    if !state.quota.config.is_enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(
+            state.quota.config.scan_interval_seconds,
+        ));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let usage = match refresh(&state).await {
+                Ok(usage) => usage,
+                Err(err) => {
+                    warn!("download quota scan failed: {}", err);
+                    continue;
+                }
+            };
+            if !state.quota.config.auto_prune {
+                continue;
+            }
+            for exceeded in state.quota.all_exceeded(&usage) {
+                match prune(&state, exceeded.category.as_deref()).await {
+                    Ok(0) => warn!(
+                        "download quota exceeded ({}) but no read chapters are left to prune",
+                        exceeded.category.as_deref().unwrap_or("global")
+                    ),
+                    Ok(deleted) => info!(
+                        "pruned {} read chapters for the {} download quota",
+                        deleted,
+                        exceeded.category.as_deref().unwrap_or("global")
+                    ),
+                    Err(err) => warn!("download quota prune failed: {}", err),
+                }
+            }
+        }
+    });
+}
+
+pub(crate) async fn refresh(state: &AppState) -> Result<Usage, String> {
+    let root = state.quota.root.clone();
+    let (total_bytes, by_title) = tokio::task::spawn_blocking(move || scan_sizes(&root))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut categories = BTreeMap::new();
+    if !state.quota.config.category_bytes.is_empty() {
+        for manga in library::library(state).await? {
+            let size = by_title.get(&dir_name(&manga.title)).copied().unwrap_or(0);
+            for category in manga.categories {
+                *categories.entry(category.to_lowercase()).or_insert(0) += size;
+            }
+        }
+    }
+
+    let usage = Usage {
+        total_bytes,
+        categories,
+        scanned_at: unix_now(),
+    };
+    *state
+        .quota
+        .usage
+        .lock()
+        .unwrap_or_else(|err| err.into_inner()) = Some(usage.clone());
+    Ok(usage)
+}
+
+async fn prune(state: &AppState, category: Option<&str>) -> Result<usize, String> {
+    let mut candidates = Vec::new();
+    for manga in library::library(state).await? {
+        if let Some(category) = category {
+            if !manga
+                .categories
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(category))
+            {
+                continue;
+            }
+        }
+        for chapter in library::chapters(state, manga.id).await? {
+            if chapter.downloaded && chapter.read {
+                candidates.push((chapter.last_read_at, manga.id, chapter.index));
+            }
+        }
+    }
+    candidates.sort();
+
+    let mut deleted = 0;
+    for batch in candidates.chunks(PRUNE_BATCH) {
+        for (_, manga_id, index) in batch {
+            library::delete_download(state, *manga_id, *index).await?;
+            deleted += 1;
+        }
+        let usage = refresh(state).await?;
+        let over = match category {
+            Some(category) => state.quota.category_exceeded(&usage, category),
+            None => state.quota.global_exceeded(&usage),
+        };
+        if over.is_none() {
+            break;
+        }
+    }
+    Ok(deleted)
+}
+
+// Suwayomi stores downloads as {root}/mangas/{source}/{title}/... (and
+// anime/ likewise); usage is attributed to the title directory.
+fn scan_sizes(root: &Path) -> (u64, HashMap<String, u64>) {
+    let mut total = 0u64;
+    let mut by_title: HashMap<String, u64> = HashMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                let size = entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+                total += size;
+                if let Some(title) = path
+                    .strip_prefix(root)
+                    .ok()
+                    .and_then(|relative| relative.components().nth(2))
+                {
+                    let title = title.as_os_str().to_string_lossy().to_lowercase();
+                    *by_title.entry(title).or_insert(0) += size;
+                }
+            }
+        }
+    }
+    (total, by_title)
+}
+
+pub(crate) fn dir_name(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .trim_end_matches(['.', ' '])
+        .to_lowercase()
+}
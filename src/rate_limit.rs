@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::app::AppState;
+use crate::config::RateLimitConfig;
+#[cfg(feature = "auth")]
+use crate::principal::Principal;
+use crate::ws::WsBridge;
+
+const PRUNE_AT: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+// Rides along in the request extensions so the bridge can hold it for the
+// life of the connection.
+#[derive(Clone)]
+pub(crate) struct WebSocketPermit {
+    _permit: Arc<OwnedSemaphorePermit>,
+}
+
+pub(crate) struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    in_flight: Option<Arc<Semaphore>>,
+    websockets: Option<Arc<Semaphore>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: RateLimitConfig) -> Self {
+        Self {
+            in_flight: config
+                .max_concurrent
+                .map(|limit| Arc::new(Semaphore::new(limit))),
+            websockets: config
+                .max_websockets
+                .map(|limit| Arc::new(Semaphore::new(limit))),
+            buckets: Mutex::new(HashMap::new()),
+            config,
+        }
+    }
+
+    // Token bucket per address; `Err` carries the seconds until one token is back.
+    fn take(&self, ip: IpAddr) -> Result<(), u64> {
+        let Some(rate) = self.config.requests_per_second else {
+            return Ok(());
+        };
+        let burst = f64::from(self.config.burst);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+        if buckets.len() >= PRUNE_AT {
+            // Anything idle long enough to have refilled is indistinguishable from new.
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.refilled).as_secs_f64() * rate < burst
+            });
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: burst,
+            refilled: now,
+        });
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(((1.0 - bucket.tokens) / rate).ceil().max(1.0) as u64)
+    }
+
+    fn exempt(&self, ip: IpAddr) -> bool {
+        self.config.exempt_loopback && ip.is_loopback()
+    }
+}
+
+pub(crate) async fn middleware(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let limiter = &state.rate_limit;
+    let (parts, body) = req.into_parts();
+    if let Some(ip) = client_ip(&parts, &limiter.config) {
+        if !limiter.exempt(ip) {
+            if let Err(retry_after) = limiter.take(ip) {
+                record(&state, "rate");
+                return too_many("rate limit exceeded", retry_after);
+            }
+        }
+    }
+    let mut req = Request::from_parts(parts, body);
+
+    // WebSocket connections have their own cap and outlive the request.
+    if WsBridge::is_upgrade(req.headers()) {
+        if let Some(websockets) = &limiter.websockets {
+            let Ok(permit) = websockets.clone().try_acquire_owned() else {
+                record(&state, "websockets");
+                return too_many("too many open websocket connections", 5);
+            };
+            req.extensions_mut().insert(WebSocketPermit {
+                _permit: Arc::new(permit),
+            });
+        }
+        return next.run(req).await;
+    }
+    let _permit = match &limiter.in_flight {
+        Some(in_flight) => match in_flight.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                record(&state, "concurrency");
+                return too_many("too many concurrent requests", 1);
+            }
+        },
+        None => None,
+    };
+    next.run(req).await
+}
+
+// Fair-use cap on what one client may pull within a bandwidth window.
+pub(crate) fn bandwidth_cap(state: &AppState, client: &str) -> Option<Response> {
+    let cap = state.config.rate_limit.client_bytes?;
+    let (used, reset) = state.bandwidth.client_usage(client);
+    if used < cap {
+        return None;
+    }
+    record(state, "bandwidth");
+    Some(too_many("bandwidth cap exceeded", reset))
+}
+
+// Daily and monthly quotas of the calling token, on top of the instantaneous limits.
+#[cfg(feature = "auth")]
+pub(crate) fn token_quota(state: &AppState, parts: &Parts) -> Option<Response> {
+    let principal = parts.extensions.get::<Principal>()?;
+    let (message, reset) = state.auth.charge_request(&principal.id).err()?;
+    record(state, "quota");
+    Some(too_many(message, reset))
+}
+
+// `X-Forwarded-For` counts only when the connection comes from a trusted proxy. Each
+// proxy appends the address it saw, so entries further left than the configured hops
+// were written by the client and are ignored.
+fn client_ip(parts: &Parts, config: &RateLimitConfig) -> Option<IpAddr> {
+    let peer = parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let trusted = peer.is_some_and(|peer| {
+        config
+            .trusted_proxies
+            .iter()
+            .any(|range| range.contains(peer))
+    });
+    if config.trust_forwarded && trusted {
+        if let Some(ip) = forwarded_ip(&parts.headers, config.trusted_hops) {
+            return Some(ip);
+        }
+    }
+    peer
+}
+
+fn forwarded_ip(headers: &HeaderMap, hops: usize) -> Option<IpAddr> {
+    let entries = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .collect::<Vec<_>>();
+    if !entries.is_empty() {
+        // With fewer entries than hops, the leftmost is still one a proxy wrote.
+        let index = entries.len().saturating_sub(hops);
+        return entries[index].parse().ok();
+    }
+    headers
+        .get("x-real-ip")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+fn record(state: &AppState, reason: &str) {
+    if state.config.metrics.enabled {
+        state.metrics.inc(
+            "manatan_rate_limited_total",
+            "Requests rejected by the rate limiter.",
+            &[("reason", reason)],
+        );
+    }
+}
+
+fn too_many(message: &'static str, retry_after: u64) -> Response {
+    let mut resp = (StatusCode::TOO_MANY_REQUESTS, message).into_response();
+    resp.headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(trusted_proxies: &[&str], trusted_hops: usize) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_second: None,
+            burst: 1,
+            max_concurrent: None,
+            max_websockets: None,
+            trust_forwarded: true,
+            trusted_proxies: trusted_proxies
+                .iter()
+                .map(|range| range.parse().unwrap())
+                .collect(),
+            trusted_hops,
+            exempt_loopback: true,
+            client_bytes: None,
+        }
+    }
+
+    fn request(peer: &str, forwarded: &[&str]) -> Parts {
+        let mut builder = Request::builder().uri("/api/v1/manga/1");
+        for value in forwarded {
+            builder = builder.header("x-forwarded-for", *value);
+        }
+        let mut req = builder.body(axum::body::Body::empty()).unwrap();
+        let peer: SocketAddr = peer.parse().unwrap();
+        req.extensions_mut().insert(ConnectInfo(peer));
+        req.into_parts().0
+    }
+
+    fn ip(value: &str) -> Option<IpAddr> {
+        Some(value.parse().unwrap())
+    }
+
+    #[test]
+    fn takes_the_entry_the_proxy_appended() {
+        let config = config(&["10.0.0.0/8"], 1);
+        let parts = request("10.0.0.2:4000", &["6.6.6.6, 203.0.113.7"]);
+        assert_eq!(client_ip(&parts, &config), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn counts_hops_from_the_right() {
+        let config = config(&["10.0.0.0/8"], 2);
+        let parts = request("10.0.0.2:4000", &["6.6.6.6, 203.0.113.7", "10.0.0.9"]);
+        assert_eq!(client_ip(&parts, &config), ip("203.0.113.7"));
+        let short = request("10.0.0.2:4000", &["203.0.113.7"]);
+        assert_eq!(client_ip(&short, &config), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn ignores_the_header_from_untrusted_peers() {
+        let config = config(&["10.0.0.0/8"], 1);
+        let parts = request("198.51.100.4:4000", &["203.0.113.7"]);
+        assert_eq!(client_ip(&parts, &config), ip("198.51.100.4"));
+    }
+
+    #[test]
+    fn ignores_the_header_unless_enabled() {
+        let mut config = config(&["10.0.0.0/8"], 1);
+        config.trust_forwarded = false;
+        let parts = request("10.0.0.2:4000", &["203.0.113.7"]);
+        assert_eq!(client_ip(&parts, &config), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn matches_mapped_and_v6_ranges() {
+        let config = config(&["127.0.0.1", "fd00::/8"], 1);
+        let mapped = request("[::ffff:127.0.0.1]:4000", &["203.0.113.7"]);
+        assert_eq!(client_ip(&mapped, &config), ip("203.0.113.7"));
+        let v6 = request("[fd12::1]:4000", &["2001:db8::1"]);
+        assert_eq!(client_ip(&v6, &config), ip("2001:db8::1"));
+    }
+}
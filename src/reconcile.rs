@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::jobs::{JobContext, JobResult};
+use crate::library;
+use crate::quota::dir_name;
+
+const JOB_KIND: &str = "reconcile_downloads";
+
+// What sits directly in a title directory: chapter folders or `.cbz` files.
+struct Stored {
+    path: PathBuf,
+    key: String,
+    bytes: u64,
+}
+
+// Suwayomi names chapter downloads `{scanlator}_{name}`, or just `{name}`, made
+// filename-safe; `dir_name` folds both sides the same way.
+pub(crate) fn chapter_keys(chapter: &library::Chapter) -> Vec<String> {
+    let mut keys = vec![dir_name(&chapter.name)];
+    if let Some(scanlator) = &chapter.scanlator {
+        keys.insert(0, dir_name(&format!("{scanlator}_{}", chapter.name)));
+    }
+    keys
+}
+
+// Title directories under `{root}/mangas/{source}/`, by folded title. Hidden entries
+// and `_tmp` folders of downloads in progress are skipped.
+fn scan(root: &Path) -> HashMap<String, Vec<Stored>> {
+    let mut titles: HashMap<String, Vec<Stored>> = HashMap::new();
+    let Ok(sources) = std::fs::read_dir(root.join("mangas")) else {
+        return titles;
+    };
+    for source in sources.flatten() {
+        let Ok(dirs) = std::fs::read_dir(source.path()) else {
+            continue;
+        };
+        for dir in dirs.flatten() {
+            let Ok(entries) = std::fs::read_dir(dir.path()) else {
+                continue;
+            };
+            let stored = titles
+                .entry(dir_name(&dir.file_name().to_string_lossy()))
+                .or_default();
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') || name.ends_with("_tmp") {
+                    continue;
+                }
+                let Ok(path) = entry.path().strip_prefix(root).map(Path::to_path_buf) else {
+                    continue;
+                };
+                stored.push(Stored {
+                    key: dir_name(name.strip_suffix(".cbz").unwrap_or(&name)),
+                    bytes: tree_size(&entry.path()),
+                    path,
+                });
+            }
+        }
+    }
+    titles
+}
+
+fn tree_size(path: &Path) -> u64 {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| tree_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+// Compares the chapters the backend reports as downloaded with the files under the
+// downloads root. `missing` are flagged downloaded without files, `orphans` are files
+// of library series no downloaded chapter accounts for, and `unknown_titles` are
+// title directories matching no library series.
+pub(crate) async fn run_job(state: AppState, ctx: JobContext) -> JobResult {
+    let root = PathBuf::from(state.backend.settings().downloads_path.unwrap_or_default());
+    let mut titles = tokio::task::spawn_blocking({
+        let root = root.clone();
+        move || scan(&root)
+    })
+    .await
+    .map_err(|err| err.to_string())?;
+    let library = library::library(&state).await?;
+
+    let total = library.len();
+    let mut missing = Vec::new();
+    let mut orphans = Vec::new();
+    let mut failed = Vec::new();
+    let mut downloaded = 0usize;
+    for (done, manga) in library.iter().enumerate() {
+        if ctx.is_cancelled() {
+            return Err("cancelled".to_string());
+        }
+        ctx.progress(
+            done as f64 / total.max(1) as f64,
+            &format!("checked {done} of {total} series"),
+        );
+        let chapters = match library::chapters(&state, manga.id).await {
+            Ok(chapters) => chapters,
+            Err(err) => {
+                failed.push(json!({ "manga_id": manga.id, "error": err }));
+                continue;
+            }
+        };
+        let mut stored = titles.remove(&dir_name(&manga.title)).unwrap_or_default();
+        for chapter in chapters.iter().filter(|chapter| chapter.downloaded) {
+            downloaded += 1;
+            let keys = chapter_keys(chapter);
+            let before = stored.len();
+            stored.retain(|entry| !keys.contains(&entry.key));
+            if stored.len() == before {
+                missing.push(json!({
+                    "manga_id": manga.id,
+                    "title": manga.title,
+                    "index": chapter.index,
+                    "name": chapter.name,
+                }));
+            }
+        }
+        orphans.extend(stored.into_iter().map(|entry| {
+            json!({
+                "manga_id": manga.id,
+                "title": manga.title,
+                "path": entry.path.display().to_string(),
+                "bytes": entry.bytes,
+            })
+        }));
+    }
+    let unknown_titles = titles
+        .into_values()
+        .flatten()
+        .filter_map(|entry| {
+            let dir = entry.path.parent()?.display().to_string();
+            Some((dir, entry.bytes))
+        })
+        .fold(HashMap::<String, u64>::new(), |mut dirs, (dir, bytes)| {
+            *dirs.entry(dir).or_default() += bytes;
+            dirs
+        })
+        .into_iter()
+        .map(|(path, bytes)| json!({ "path": path, "bytes": bytes }))
+        .collect::<Vec<_>>();
+
+    if !missing.is_empty() || !orphans.is_empty() {
+        info!(
+            "download check found {} missing chapters and {} orphaned files",
+            missing.len(),
+            orphans.len()
+        );
+    }
+    Ok(json!({
+        "root": root.display().to_string(),
+        "series": total,
+        "downloaded": downloaded,
+        "missing": missing,
+        "orphans": orphans,
+        "unknown_titles": unknown_titles,
+        "failed": failed,
+    }))
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/downloads/reconcile", post(start))
+        .route("/admin/downloads/reconcile/{job}/cleanup", post(cleanup))
+        .route(
+            "/admin/downloads/reconcile/{job}/redownload",
+            post(redownload),
+        )
+}
+
+async fn start(State(state): State<AppState>) -> Response {
+    match state.jobs.enqueue(JOB_KIND, json!({}), Some(1)) {
+        Ok(id) => (
+            StatusCode::ACCEPTED,
+            Json(state.jobs.get(id).unwrap_or_else(|| json!({ "id": id }))),
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    }
+}
+
+// The report of a finished check; actions work from it rather than rescanning.
+fn report(state: &AppState, job: i64) -> Result<Value, (StatusCode, &'static str)> {
+    let Some(job) = state.jobs.get(job) else {
+        return Err((StatusCode::NOT_FOUND, "no such job"));
+    };
+    if job.get("kind").and_then(Value::as_str) != Some(JOB_KIND)
+        || job.get("state").and_then(Value::as_str) != Some("succeeded")
+    {
+        return Err((StatusCode::CONFLICT, "not a finished download check"));
+    }
+    Ok(job.get("result").cloned().unwrap_or_default())
+}
+
+#[derive(Default, Deserialize)]
+struct Cleanup {
+    #[serde(default)]
+    unknown_titles: bool,
+}
+
+// Moves orphaned files, and unknown title directories when asked, to the trash.
+async fn cleanup(
+    State(state): State<AppState>,
+    UrlPath(job): UrlPath<i64>,
+    body: Option<Json<Cleanup>>,
+) -> Response {
+    let report = match report(&state, job) {
+        Ok(report) => report,
+        Err(err) => return err.into_response(),
+    };
+    let Json(body) = body.unwrap_or_default();
+    let root = PathBuf::from(report["root"].as_str().unwrap_or_default());
+    let mut paths = report["orphans"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry["path"].as_str())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if body.unknown_titles {
+        paths.extend(
+            report["unknown_titles"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry["path"].as_str())
+                .map(str::to_string),
+        );
+    }
+    let trash = state.trash.clone();
+    let outcome = tokio::task::spawn_blocking(move || {
+        let mut removed = 0usize;
+        let mut failed = Vec::new();
+        for path in paths {
+            let relative = Path::new(&path);
+            if !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                failed.push(json!({ "path": path, "error": "not under the downloads root" }));
+                continue;
+            }
+            let full = root.join(relative);
+            if !full.exists() {
+                continue;
+            }
+            match trash.discard(&full, "orphaned download") {
+                Ok(()) => removed += 1,
+                Err(err) => failed.push(json!({ "path": path, "error": err.to_string() })),
+            }
+        }
+        (removed, failed)
+    })
+    .await;
+    match outcome {
+        Ok((removed, failed)) => {
+            info!("download check cleanup removed {} paths", removed);
+            Json(json!({ "removed": removed, "failed": failed })).into_response()
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+// Clears the downloaded flag of chapters whose files are gone and queues them again.
+async fn redownload(State(state): State<AppState>, UrlPath(job): UrlPath<i64>) -> Response {
+    let report = match report(&state, job) {
+        Ok(report) => report,
+        Err(err) => return err.into_response(),
+    };
+    let mut queued = 0usize;
+    let mut failed = Vec::new();
+    for chapter in report["missing"].as_array().into_iter().flatten() {
+        let (Some(manga_id), Some(index)) =
+            (chapter["manga_id"].as_i64(), chapter["index"].as_i64())
+        else {
+            continue;
+        };
+        let requeued = async {
+            library::forget_download(&state, manga_id, index).await?;
+            library::queue_download(&state, manga_id, index).await
+        }
+        .await;
+        match requeued {
+            Ok(()) => queued += 1,
+            Err(err) => {
+                warn!(
+                    "could not requeue manga {} chapter {}: {}",
+                    manga_id, index, err
+                );
+                failed.push(json!({ "manga_id": manga_id, "index": index, "error": err }));
+            }
+        }
+    }
+    Json(json!({ "queued": queued, "failed": failed })).into_response()
+}
@@ -0,0 +1,394 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use reqwest::Method;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::RelaySyncConfig;
+use crate::devices::Position;
+use crate::keys::random_id;
+use crate::store::{load_json, save_json};
+use crate::unix_now;
+
+const SALT: &[u8] = b"manatan-relay-sync-v1";
+const PBKDF2_ROUNDS: u32 = 210_000;
+const VERSION: u8 = 1;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_BLOB: usize = 1024 * 1024;
+const MAX_SLOTS: usize = 16;
+const MAX_CHANNELS: usize = 256;
+const SLOT_TTL_SECONDS: u64 = 30 * 24 * 3600;
+
+type Series = BTreeMap<i64, BTreeMap<String, Position>>;
+
+// Everything derived from the pairing phrase. The relay only ever sees the channel id,
+// which reveals nothing about the phrase or the key.
+struct Keys {
+    channel: String,
+    cipher: LessSafeKey,
+}
+
+impl Keys {
+    fn derive(phrase: &str) -> Self {
+        // Typed on two machines, so case and spacing are not allowed to matter.
+        let phrase = phrase
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        let mut master = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(PBKDF2_ROUNDS).unwrap(),
+            SALT,
+            phrase.as_bytes(),
+            &mut master,
+        );
+        let subkey = |label: &[u8]| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(&master).expect("hmac takes any key");
+            mac.update(label);
+            mac.finalize().into_bytes()
+        };
+        let channel = subkey(b"channel")
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &subkey(b"key")).expect("32-byte key");
+        Self {
+            channel,
+            cipher: LessSafeKey::new(key),
+        }
+    }
+
+    // Ties a blob to the channel and slot it was written for, so the relay cannot pass
+    // one instance's data off as another's.
+    fn aad(&self, slot: &str) -> String {
+        format!("{}\n{}", self.channel, slot)
+    }
+
+    fn seal(&self, slot: &str, series: &Series) -> Result<Vec<u8>, String> {
+        let mut data = serde_json::to_vec(series).map_err(|err| err.to_string())?;
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+        self.cipher
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(self.aad(slot).as_bytes()),
+                &mut data,
+            )
+            .map_err(|_| "encryption failed".to_string())?;
+        let mut blob = Vec::with_capacity(1 + NONCE_LEN + data.len());
+        blob.push(VERSION);
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&data);
+        Ok(blob)
+    }
+
+    fn open(&self, slot: &str, blob: &[u8]) -> Result<Series, String> {
+        let Some((&VERSION, rest)) = blob.split_first() else {
+            return Err("unknown blob version".to_string());
+        };
+        if rest.len() < NONCE_LEN {
+            return Err("truncated blob".to_string());
+        }
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "bad nonce")?;
+        let mut sealed = sealed.to_vec();
+        let plain = self
+            .cipher
+            .open_in_place(nonce, Aad::from(self.aad(slot).as_bytes()), &mut sealed)
+            .map_err(|_| "does not decrypt with this phrase".to_string())?;
+        serde_json::from_slice(plain).map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Identity {
+    instance: String,
+}
+
+#[derive(Default, Serialize)]
+struct Status {
+    last_sync: Option<u64>,
+    last_error: Option<String>,
+    last_result: Option<Value>,
+}
+
+// Slots on a relay channel, one per instance, each holding that instance's latest
+// encrypted progress.
+#[derive(Default, Serialize, Deserialize)]
+struct Mailbox {
+    slots: BTreeMap<String, Slot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Slot {
+    blob: String,
+    updated_at: u64,
+}
+
+#[derive(Deserialize)]
+struct Listing {
+    slots: BTreeMap<String, Slot>,
+}
+
+// Device progress shared between instances that are never online together, through a
+// relay that stores only ciphertext. Any instance with `MANATAN_RELAY_SERVE` can be the
+// relay; syncing instances pair by using the same phrase.
+pub(crate) struct RelaySync {
+    config: RelaySyncConfig,
+    instance: String,
+    relay_dir: PathBuf,
+    keys: OnceLock<Keys>,
+    status: Mutex<Status>,
+    mailboxes: Mutex<()>,
+}
+
+impl RelaySync {
+    pub(crate) fn new(config: RelaySyncConfig, data_path: &str) -> Self {
+        let data_path = PathBuf::from(data_path);
+        let identity_path = data_path.join("relay-sync.json");
+        let mut identity: Identity = load_json(&identity_path);
+        if identity.instance.is_empty() && config.is_enabled() {
+            identity.instance = random_id();
+            if let Err(err) = save_json(&identity_path, &identity) {
+                warn!(
+                    "failed to persist relay identity to {}: {}",
+                    identity_path.display(),
+                    err
+                );
+            }
+        }
+        Self {
+            config,
+            instance: identity.instance,
+            relay_dir: data_path.join("relay"),
+            keys: OnceLock::new(),
+            status: Mutex::new(Status::default()),
+            mailboxes: Mutex::new(()),
+        }
+    }
+
+    fn lock_status(&self) -> std::sync::MutexGuard<'_, Status> {
+        self.status.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn mailbox_path(&self, channel: &str) -> PathBuf {
+        self.relay_dir.join(format!("{channel}.json"))
+    }
+
+    // Slots nobody has written to for a month are dropped on the next access.
+    fn load_mailbox(&self, channel: &str) -> Mailbox {
+        let mut mailbox: Mailbox = load_json(&self.mailbox_path(channel));
+        let cutoff = unix_now().saturating_sub(SLOT_TTL_SECONDS);
+        mailbox.slots.retain(|_, slot| slot.updated_at >= cutoff);
+        mailbox
+    }
+}
+
+async fn sync(state: &AppState) -> Result<Value, String> {
+    let relay = &state.relay_sync;
+    let (Some(base), Some(keys)) = (relay.config.url.as_deref(), relay.keys.get()) else {
+        return Err("relay sync is not ready".to_string());
+    };
+    let url = format!("{base}/relay/{}", keys.channel);
+    let listing = state
+        .outbound
+        .request(Method::GET, &url)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|err| err.to_string())?
+        .json::<Listing>()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let (mut peers, mut merged, mut rejected) = (0, 0, 0);
+    for (slot, entry) in listing.slots {
+        if slot == relay.instance {
+            continue;
+        }
+        let opened = STANDARD
+            .decode(&entry.blob)
+            .map_err(|err| err.to_string())
+            .and_then(|blob| keys.open(&slot, &blob));
+        match opened {
+            Ok(series) => {
+                peers += 1;
+                merged += state.devices.merge(series);
+            }
+            Err(err) => {
+                rejected += 1;
+                warn!("ignoring relay slot {}: {}", slot, err);
+            }
+        }
+    }
+
+    let blob = keys.seal(&relay.instance, &state.devices.snapshot())?;
+    state
+        .outbound
+        .request(Method::PUT, &format!("{url}/{}", relay.instance))
+        .timeout(REQUEST_TIMEOUT)
+        .header("content-type", "application/octet-stream")
+        .body(blob)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|err| err.to_string())?;
+    if merged > 0 {
+        info!("relay sync took {} positions from {} peers", merged, peers);
+    }
+    Ok(json!({ "peers": peers, "merged": merged, "rejected": rejected }))
+}
+
+async fn sync_and_record(state: &AppState) -> Result<Value, String> {
+    let result = sync(state).await;
+    let mut status = state.relay_sync.lock_status();
+    status.last_sync = Some(unix_now());
+    match &result {
+        Ok(outcome) => {
+            status.last_error = None;
+            status.last_result = Some(outcome.clone());
+        }
+        Err(err) => {
+            warn!("relay sync failed: {}", err);
+            status.last_error = Some(err.clone());
+        }
+    }
+    result
+}
+
+pub(crate) fn spawn(state: AppState) {
+    let config = &state.relay_sync.config;
+    let Some(phrase) = config.phrase.clone().filter(|_| config.is_enabled()) else {
+        return;
+    };
+    tokio::spawn(async move {
+        // Key stretching takes a noticeable moment, so it stays off the runtime threads.
+        let Ok(keys) = tokio::task::spawn_blocking(move || Keys::derive(&phrase)).await else {
+            return;
+        };
+        let _ = state.relay_sync.keys.set(keys);
+        let mut ticker = tokio::time::interval(Duration::from_secs(
+            state.relay_sync.config.interval_seconds,
+        ));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let _ = sync_and_record(&state).await;
+        }
+    });
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/relay/{channel}", get(list_slots))
+        .route("/relay/{channel}/{slot}", put(put_slot))
+        .route("/admin/relay", get(status))
+        .route("/admin/relay/sync", post(sync_now))
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+async fn list_slots(State(state): State<AppState>, Path(channel): Path<String>) -> Response {
+    let relay = &state.relay_sync;
+    if !relay.config.serve || !is_hex(&channel, 64) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let _guard = relay
+        .mailboxes
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    Json(json!({ "slots": relay.load_mailbox(&channel).slots })).into_response()
+}
+
+async fn put_slot(
+    State(state): State<AppState>,
+    Path((channel, slot)): Path<(String, String)>,
+    body: Bytes,
+) -> Response {
+    let relay = &state.relay_sync;
+    if !relay.config.serve || !is_hex(&channel, 64) || !is_hex(&slot, 32) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if body.len() > MAX_BLOB {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+    let _guard = relay
+        .mailboxes
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let path = relay.mailbox_path(&channel);
+    if !path.exists() {
+        let channels = std::fs::read_dir(&relay.relay_dir)
+            .map(|entries| entries.count())
+            .unwrap_or(0);
+        if channels >= MAX_CHANNELS {
+            return (StatusCode::INSUFFICIENT_STORAGE, "relay is full").into_response();
+        }
+    }
+    let mut mailbox = relay.load_mailbox(&channel);
+    if !mailbox.slots.contains_key(&slot) && mailbox.slots.len() >= MAX_SLOTS {
+        return (StatusCode::CONFLICT, "channel is full").into_response();
+    }
+    mailbox.slots.insert(
+        slot,
+        Slot {
+            blob: STANDARD.encode(&body),
+            updated_at: unix_now(),
+        },
+    );
+    if let Err(err) = save_json(&path, &mailbox) {
+        warn!("failed to store relay channel {}: {}", path.display(), err);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    let relay = &state.relay_sync;
+    Json(json!({
+        "enabled": relay.config.is_enabled(),
+        "serve": relay.config.serve,
+        "relay": relay.config.url,
+        "interval_seconds": relay.config.interval_seconds,
+        "instance": relay.config.is_enabled().then_some(&relay.instance),
+        "channel": relay.keys.get().map(|keys| &keys.channel[..8]),
+        "status": *relay.lock_status(),
+    }))
+}
+
+async fn sync_now(State(state): State<AppState>) -> Response {
+    if !state.relay_sync.config.is_enabled() {
+        return (
+            StatusCode::NOT_FOUND,
+            "relay sync is not configured (MANATAN_RELAY_URL, MANATAN_RELAY_PHRASE)",
+        )
+            .into_response();
+    }
+    match sync_and_record(&state).await {
+        Ok(outcome) => Json(outcome).into_response(),
+        Err(err) => (StatusCode::BAD_GATEWAY, err).into_response(),
+    }
+}
@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use axum::{extract::State, routing::get, Json, Router};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::app::AppState;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Default, Serialize)]
+struct Side {
+    memory_bytes: Option<u64>,
+    cpu_seconds: Option<f64>,
+    threads: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Usage {
+    process: Side,
+    backend: Side,
+    proxy: Side,
+    // The backend's own numbers as reported by the library, e.g. JVM heap sizes.
+    backend_detail: Option<Value>,
+    source: &'static str,
+}
+
+#[derive(Default)]
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+struct ProcStats {
+    rss_bytes: u64,
+    cpu_seconds: f64,
+    threads: u64,
+    backend_cpu_seconds: f64,
+    backend_threads: u64,
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/admin/runtime", get(runtime))
+}
+
+async fn runtime(State(state): State<AppState>) -> Json<Usage> {
+    Json(sample(&state).await)
+}
+
+pub(crate) fn spawn(state: AppState) {
+    if !state.config.metrics.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let usage = sample(&state).await;
+            for (side, usage) in [("backend", &usage.backend), ("proxy", &usage.proxy)] {
+                if let Some(bytes) = usage.memory_bytes {
+                    state.metrics.set(
+                        "manatan_memory_bytes",
+                        "Resident memory attributed to the backend or the proxy.",
+                        &[("side", side)],
+                        bytes as f64,
+                    );
+                }
+                if let Some(seconds) = usage.cpu_seconds {
+                    state.metrics.set(
+                        "manatan_cpu_seconds",
+                        "CPU time used by the backend or the proxy since start.",
+                        &[("side", side)],
+                        seconds,
+                    );
+                }
+            }
+        }
+    });
+}
+
+async fn sample(state: &AppState) -> Usage {
+    let backend = state.backend.clone();
+    let (detail, proc) =
+        tokio::task::spawn_blocking(move || (backend.resource_usage(), proc_stats()))
+            .await
+            .unwrap_or_default();
+    let number = |key: &str| {
+        detail
+            .as_ref()
+            .and_then(|detail| detail.get(key))
+            .and_then(Value::as_f64)
+    };
+
+    // The library knows its heap; without it only CPU can be split, by thread name.
+    let backend_memory = number("memory_bytes")
+        .map(|bytes| bytes as u64)
+        .or_else(|| {
+            let heap = number("heap_committed_bytes")?;
+            Some((heap + number("non_heap_bytes").unwrap_or(0.0)) as u64)
+        });
+    let backend = Side {
+        memory_bytes: backend_memory,
+        cpu_seconds: number("cpu_seconds").or(proc.as_ref().map(|proc| proc.backend_cpu_seconds)),
+        threads: number("threads")
+            .map(|threads| threads as u64)
+            .or(proc.as_ref().map(|proc| proc.backend_threads)),
+    };
+    let process = Side {
+        memory_bytes: proc.as_ref().map(|proc| proc.rss_bytes),
+        cpu_seconds: proc.as_ref().map(|proc| proc.cpu_seconds),
+        threads: proc.as_ref().map(|proc| proc.threads),
+    };
+    let proxy = Side {
+        memory_bytes: process
+            .memory_bytes
+            .zip(backend.memory_bytes)
+            .map(|(total, backend)| total.saturating_sub(backend)),
+        cpu_seconds: process
+            .cpu_seconds
+            .zip(backend.cpu_seconds)
+            .map(|(total, backend)| (total - backend).max(0.0)),
+        threads: process
+            .threads
+            .zip(backend.threads)
+            .map(|(total, backend)| total.saturating_sub(backend)),
+    };
+    Usage {
+        source: if detail.is_some() { "library" } else { "proc" },
+        process,
+        backend,
+        proxy,
+        backend_detail: detail,
+    }
+}
+
+// Threads the Rust side starts; everything else in the process belongs to the backend.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_proxy_thread(name: &str) -> bool {
+    ["tokio-", "ffi-", "mdns"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn proc_stats() -> Option<ProcStats> {
+    // USER_HZ is 100 on every Linux ABI.
+    const TICKS_PER_SECOND: f64 = 100.0;
+
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+    let mut stats = ProcStats {
+        rss_bytes: field("VmRSS:")? * 1024,
+        threads: field("Threads:").unwrap_or(0),
+        ..ProcStats::default()
+    };
+    // `comm` sits in parentheses and may hold spaces, so fields are counted after it.
+    let ticks = |stat: &str| -> Option<(String, f64)> {
+        let (name, rest) = stat.split_once(" (")?.1.rsplit_once(") ")?;
+        let fields = rest.split_whitespace().collect::<Vec<_>>();
+        let utime = fields.get(11)?.parse::<f64>().ok()?;
+        let stime = fields.get(12)?.parse::<f64>().ok()?;
+        Some((name.to_string(), utime + stime))
+    };
+    let (_, total) = ticks(&std::fs::read_to_string("/proc/self/stat").ok()?)?;
+    stats.cpu_seconds = total / TICKS_PER_SECOND;
+    let main = std::process::id().to_string();
+    for task in std::fs::read_dir("/proc/self/task").ok()?.flatten() {
+        let Ok(stat) = std::fs::read_to_string(task.path().join("stat")) else {
+            continue;
+        };
+        let Some((name, ticks)) = ticks(&stat) else {
+            continue;
+        };
+        if task.file_name().to_string_lossy() == main || is_proxy_thread(&name) {
+            continue;
+        }
+        stats.backend_cpu_seconds += ticks / TICKS_PER_SECOND;
+        stats.backend_threads += 1;
+    }
+    Some(stats)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn proc_stats() -> Option<ProcStats> {
+    None
+}
@@ -0,0 +1,512 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{head, options},
+    Router,
+};
+use base64::Engine;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::keys::random_id;
+use crate::{backup, local_manga, unix_now, uploads};
+
+// tus 1.0 with the creation, expiration and termination extensions.
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,expiration,termination";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Target {
+    Backup,
+    LocalManga,
+}
+
+impl Target {
+    fn collection(self) -> &'static str {
+        match self {
+            Self::Backup => "/api/proxy/backup/uploads",
+            Self::LocalManga => "/local-manga/uploads",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Upload {
+    target: Target,
+    length: u64,
+    expires_at: u64,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+}
+
+// Uploads with a PATCH in flight; a second writer would interleave bytes.
+#[derive(Default)]
+pub(crate) struct ResumableUploads {
+    active: Mutex<HashSet<String>>,
+}
+
+struct Writing<'a> {
+    uploads: &'a ResumableUploads,
+    id: String,
+}
+
+impl ResumableUploads {
+    fn claim(&self, id: &str) -> Option<Writing<'_>> {
+        let mut active = self.active.lock().unwrap_or_else(|err| err.into_inner());
+        active.insert(id.to_string()).then(|| Writing {
+            uploads: self,
+            id: id.to_string(),
+        })
+    }
+
+    fn is_active(&self, id: &str) -> bool {
+        self.active
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .contains(id)
+    }
+}
+
+impl Drop for Writing<'_> {
+    fn drop(&mut self) {
+        self.uploads
+            .active
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&self.id);
+    }
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    [Target::Backup, Target::LocalManga]
+        .into_iter()
+        .fold(Router::new(), |router, target| {
+            router
+                .route(
+                    target.collection(),
+                    options(protocol).post(move |state: State<AppState>, headers: HeaderMap| {
+                        create(state, headers, target)
+                    }),
+                )
+                .route(
+                    &format!("{}/{{id}}", target.collection()),
+                    head(
+                        move |state: State<AppState>, id: Path<String>, headers: HeaderMap| {
+                            status(state, id, headers, target)
+                        },
+                    )
+                    .patch(
+                        move |state: State<AppState>,
+                              id: Path<String>,
+                              headers: HeaderMap,
+                              body: Body| {
+                            append(state, id, headers, body, target)
+                        },
+                    )
+                    .delete(
+                        move |state: State<AppState>, id: Path<String>, headers: HeaderMap| {
+                            cancel(state, id, headers, target)
+                        },
+                    )
+                    .layer(DefaultBodyLimit::disable()),
+                )
+        })
+}
+
+fn dir(state: &AppState) -> PathBuf {
+    PathBuf::from(&state.config.proxy_data_path).join("uploads")
+}
+
+fn data_path(state: &AppState, id: &str) -> PathBuf {
+    dir(state).join(format!("{id}.part"))
+}
+
+fn info_path(state: &AppState, id: &str) -> PathBuf {
+    dir(state).join(format!("{id}.json"))
+}
+
+// Ids are generated here; anything else in the path must not reach the filesystem.
+fn valid_id(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+fn reply(status: StatusCode) -> Response {
+    let mut resp = status.into_response();
+    resp.headers_mut()
+        .insert("tus-resumable", HeaderValue::from_static(TUS_VERSION));
+    resp
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    let mut resp = (status, message.into()).into_response();
+    resp.headers_mut()
+        .insert("tus-resumable", HeaderValue::from_static(TUS_VERSION));
+    resp
+}
+
+fn expires_header(expires_at: u64) -> Option<HeaderValue> {
+    let when = chrono::DateTime::from_timestamp(expires_at as i64, 0)?;
+    HeaderValue::from_str(&when.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).ok()
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+// Clients that send a version must speak ours; plain HTTP clients may leave it out.
+fn unsupported_version(headers: &HeaderMap) -> Option<Response> {
+    let version = headers.get("tus-resumable")?;
+    if version.as_bytes() == TUS_VERSION.as_bytes() {
+        return None;
+    }
+    let mut resp = error(StatusCode::PRECONDITION_FAILED, "unsupported tus version");
+    resp.headers_mut()
+        .insert("tus-version", HeaderValue::from_static(TUS_VERSION));
+    Some(resp)
+}
+
+// `Upload-Metadata` is a comma separated list of `key base64(value)` pairs.
+fn metadata(headers: &HeaderMap) -> Result<HashMap<String, String>, String> {
+    let Some(raw) = headers.get("upload-metadata") else {
+        return Ok(HashMap::new());
+    };
+    let raw = raw.to_str().map_err(|err| err.to_string())?;
+    let mut metadata = HashMap::new();
+    for pair in raw
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+        let value = base64::engine::general_purpose::STANDARD
+            .decode(value.trim())
+            .ok()
+            .and_then(|value| String::from_utf8(value).ok())
+            .ok_or_else(|| format!("metadata {key} is not base64 encoded text"))?;
+        metadata.insert(key.to_string(), value);
+    }
+    Ok(metadata)
+}
+
+async fn load(state: &AppState, id: &str, target: Target) -> Option<Upload> {
+    if !valid_id(id) {
+        return None;
+    }
+    let raw = tokio::fs::read(info_path(state, id)).await.ok()?;
+    let upload = serde_json::from_slice::<Upload>(&raw).ok()?;
+    if upload.target != target {
+        return None;
+    }
+    if upload.expires_at <= unix_now() && !state.resumable.is_active(id) {
+        remove(state, id).await;
+        return None;
+    }
+    Some(upload)
+}
+
+async fn save(state: &AppState, id: &str, upload: &Upload) -> std::io::Result<()> {
+    let raw = serde_json::to_vec(upload).map_err(std::io::Error::other)?;
+    let path = info_path(state, id);
+    let partial = path.with_extension("json.tmp");
+    tokio::fs::write(&partial, raw).await?;
+    tokio::fs::rename(&partial, &path).await
+}
+
+async fn remove(state: &AppState, id: &str) {
+    let _ = tokio::fs::remove_file(info_path(state, id)).await;
+    let _ = tokio::fs::remove_file(data_path(state, id)).await;
+}
+
+async fn offset(state: &AppState, id: &str) -> u64 {
+    tokio::fs::metadata(data_path(state, id))
+        .await
+        .map(|meta| meta.len())
+        .unwrap_or(0)
+}
+
+async fn protocol(State(state): State<AppState>) -> Response {
+    let mut resp = reply(StatusCode::NO_CONTENT);
+    let headers = resp.headers_mut();
+    headers.insert("tus-version", HeaderValue::from_static(TUS_VERSION));
+    headers.insert("tus-extension", HeaderValue::from_static(TUS_EXTENSIONS));
+    headers.insert("tus-max-size", state.config.uploads.max_bytes.into());
+    resp
+}
+
+async fn create(State(state): State<AppState>, headers: HeaderMap, target: Target) -> Response {
+    if let Some(resp) = unsupported_version(&headers) {
+        return resp;
+    }
+    let Some(length) = header_u64(&headers, "upload-length") else {
+        return error(StatusCode::BAD_REQUEST, "Upload-Length is required");
+    };
+    let max_bytes = state.config.uploads.max_bytes;
+    if length > max_bytes {
+        return uploads::too_large(max_bytes);
+    }
+    let metadata = match metadata(&headers) {
+        Ok(metadata) => metadata,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    // Reject a bad file name now rather than after the whole archive has arrived.
+    if target == Target::LocalManga {
+        let name = metadata.get("filename").map(String::as_str).unwrap_or("");
+        if local_manga::archive_name(name).is_none() {
+            return error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "filename metadata must name a .cbz or .cbr archive",
+            );
+        }
+    }
+
+    let id = random_id();
+    let upload = Upload {
+        target,
+        length,
+        expires_at: unix_now() + state.config.uploads.resume_expiry_seconds,
+        metadata,
+    };
+    let created = async {
+        tokio::fs::create_dir_all(dir(&state)).await?;
+        tokio::fs::File::create(data_path(&state, &id)).await?;
+        save(&state, &id, &upload).await
+    }
+    .await;
+    if let Err(err) = created {
+        remove(&state, &id).await;
+        return error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
+    }
+    info!(
+        "resumable {:?} upload {} started ({} bytes)",
+        target, id, length
+    );
+
+    let mut resp = reply(StatusCode::CREATED);
+    let headers = resp.headers_mut();
+    if let Ok(location) = HeaderValue::from_str(&format!("{}/{id}", target.collection())) {
+        headers.insert(header::LOCATION, location);
+    }
+    headers.insert("upload-offset", HeaderValue::from(0u64));
+    if let Some(expires) = expires_header(upload.expires_at) {
+        headers.insert("upload-expires", expires);
+    }
+    resp
+}
+
+async fn status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    target: Target,
+) -> Response {
+    if let Some(resp) = unsupported_version(&headers) {
+        return resp;
+    }
+    let Some(upload) = load(&state, &id, target).await else {
+        return error(StatusCode::NOT_FOUND, "no such upload");
+    };
+    let mut resp = reply(StatusCode::OK);
+    let headers = resp.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers.insert("upload-offset", offset(&state, &id).await.into());
+    headers.insert("upload-length", upload.length.into());
+    if let Some(expires) = expires_header(upload.expires_at) {
+        headers.insert("upload-expires", expires);
+    }
+    resp
+}
+
+async fn append(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+    target: Target,
+) -> Response {
+    if let Some(resp) = unsupported_version(&headers) {
+        return resp;
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    if content_type != Some(OFFSET_CONTENT_TYPE) {
+        return error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Content-Type must be {OFFSET_CONTENT_TYPE}"),
+        );
+    }
+    let Some(mut upload) = load(&state, &id, target).await else {
+        return error(StatusCode::NOT_FOUND, "no such upload");
+    };
+    let Some(_writing) = state.resumable.claim(&id) else {
+        return error(
+            StatusCode::CONFLICT,
+            "another request is writing this upload",
+        );
+    };
+    let current = offset(&state, &id).await;
+    if header_u64(&headers, "upload-offset") != Some(current) {
+        let mut resp = error(StatusCode::CONFLICT, "Upload-Offset does not match");
+        resp.headers_mut().insert("upload-offset", current.into());
+        return resp;
+    }
+
+    // Whatever arrives before a disconnect is kept, so the client resumes from there.
+    let received = write(&state, &id, body, upload.length.saturating_sub(current)).await;
+    let current = offset(&state, &id).await;
+    if let Err(resp) = received {
+        return resp;
+    }
+    upload.expires_at = unix_now() + state.config.uploads.resume_expiry_seconds;
+    if current < upload.length {
+        if let Err(err) = save(&state, &id, &upload).await {
+            warn!("failed to update upload {}: {}", id, err);
+        }
+        let mut resp = reply(StatusCode::NO_CONTENT);
+        resp.headers_mut().insert("upload-offset", current.into());
+        if let Some(expires) = expires_header(upload.expires_at) {
+            resp.headers_mut().insert("upload-expires", expires);
+        }
+        return resp;
+    }
+
+    // The last chunk answers with the result of the restore or import.
+    info!("resumable {:?} upload {} complete", target, id);
+    let mut resp = complete(&state, &id, headers, &upload).await;
+    remove(&state, &id).await;
+    resp.headers_mut()
+        .insert("tus-resumable", HeaderValue::from_static(TUS_VERSION));
+    resp.headers_mut().insert("upload-offset", current.into());
+    resp
+}
+
+async fn write(state: &AppState, id: &str, body: Body, remaining: u64) -> Result<(), Response> {
+    let io_error = |err: std::io::Error| error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(data_path(state, id))
+        .await
+        .map_err(io_error)?;
+    let mut remaining = remaining;
+    let mut stream = body.into_data_stream();
+    let mut result = Ok(());
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                result = Err(error(StatusCode::BAD_REQUEST, err.to_string()));
+                break;
+            }
+        };
+        if chunk.len() as u64 > remaining {
+            result = Err(error(
+                StatusCode::BAD_REQUEST,
+                "body runs past Upload-Length",
+            ));
+            break;
+        }
+        if let Err(err) = file.write_all(&chunk).await {
+            result = Err(io_error(err));
+            break;
+        }
+        remaining -= chunk.len() as u64;
+    }
+    file.flush().await.map_err(io_error)?;
+    result
+}
+
+async fn complete(state: &AppState, id: &str, headers: HeaderMap, upload: &Upload) -> Response {
+    let path = data_path(state, id);
+    match upload.target {
+        Target::Backup => backup::restore_upload(state, path).await,
+        Target::LocalManga => {
+            let flag = upload.metadata.get("overwrite").map(String::as_str);
+            local_manga::import_file(
+                state,
+                headers,
+                upload.metadata.get("series").map(String::as_str),
+                upload
+                    .metadata
+                    .get("filename")
+                    .map(String::as_str)
+                    .unwrap_or(""),
+                matches!(flag, Some("1" | "true")),
+                &path,
+            )
+            .await
+        }
+    }
+}
+
+async fn cancel(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    target: Target,
+) -> Response {
+    if let Some(resp) = unsupported_version(&headers) {
+        return resp;
+    }
+    if load(&state, &id, target).await.is_none() {
+        return error(StatusCode::NOT_FOUND, "no such upload");
+    }
+    let Some(_writing) = state.resumable.claim(&id) else {
+        return error(
+            StatusCode::CONFLICT,
+            "another request is writing this upload",
+        );
+    };
+    remove(&state, &id).await;
+    info!("resumable upload {} cancelled", id);
+    reply(StatusCode::NO_CONTENT)
+}
+
+// Drops uploads nobody has touched within the expiry, and data left without its record.
+pub(crate) fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let Ok(mut entries) = tokio::fs::read_dir(dir(&state)).await else {
+                continue;
+            };
+            let now = unix_now();
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let Some(id) = name.strip_suffix(".part") else {
+                    continue;
+                };
+                if state.resumable.is_active(id) {
+                    continue;
+                }
+                let expired = match tokio::fs::read(info_path(&state, id)).await {
+                    Ok(raw) => serde_json::from_slice::<Upload>(&raw)
+                        .map(|upload| upload.expires_at <= now)
+                        .unwrap_or(true),
+                    Err(_) => true,
+                };
+                if expired {
+                    info!("removing expired upload {}", id);
+                    remove(&state, id).await;
+                }
+            }
+        }
+    });
+}
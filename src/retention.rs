@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::jobs::{JobContext, JobResult};
+use crate::library;
+use crate::unix_now;
+
+const JOB_KIND: &str = "prune_downloads";
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Planned {
+    pub manga_id: i64,
+    pub title: String,
+    pub chapter_index: i64,
+    pub reason: &'static str,
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/admin/downloads/retention", get(preview))
+}
+
+async fn preview(State(state): State<AppState>) -> Response {
+    let config = &state.config.retention;
+    match plan(&state).await {
+        Ok(planned) => Json(json!({
+            "enabled": config.enabled,
+            "read_days": config.read_days,
+            "max_unread_per_series": config.max_unread_per_series,
+            "categories": config.categories,
+            "dry_run": true,
+            "chapters": planned.len(),
+            "planned": planned,
+        }))
+        .into_response(),
+        Err(err) => (StatusCode::BAD_GATEWAY, err).into_response(),
+    }
+}
+
+pub(crate) async fn plan(state: &AppState) -> Result<Vec<Planned>, String> {
+    let config = &state.config.retention;
+    if !config.has_rules() {
+        return Ok(Vec::new());
+    }
+    let now = unix_now() as i64;
+    let read_cutoff = config
+        .read_days
+        .map(|days| now - (days as i64).saturating_mul(86_400));
+
+    let mut planned = Vec::new();
+    for manga in library::library(state).await? {
+        if !config.categories.is_empty()
+            && !manga.categories.iter().any(|name| {
+                config
+                    .categories
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(name))
+            })
+        {
+            continue;
+        }
+        let mut chapters = library::chapters(state, manga.id).await?;
+        chapters.retain(|chapter| chapter.downloaded);
+        chapters.sort_by_key(|chapter| chapter.index);
+
+        if let Some(cutoff) = read_cutoff {
+            for chapter in chapters.iter().filter(|chapter| chapter.read) {
+                let last_read = seconds(chapter.last_read_at);
+                if last_read > 0 && last_read < cutoff {
+                    planned.push(Planned {
+                        manga_id: manga.id,
+                        title: manga.title.clone(),
+                        chapter_index: chapter.index,
+                        reason: "read_expired",
+                    });
+                }
+            }
+        }
+        if let Some(keep) = config.max_unread_per_series {
+            planned.extend(
+                chapters
+                    .iter()
+                    .filter(|chapter| !chapter.read)
+                    .skip(keep)
+                    .map(|chapter| Planned {
+                        manga_id: manga.id,
+                        title: manga.title.clone(),
+                        chapter_index: chapter.index,
+                        reason: "unread_limit",
+                    }),
+            );
+        }
+    }
+    Ok(planned)
+}
+
+// lastReadAt is epoch seconds on current backends and milliseconds on older ones.
+fn seconds(value: i64) -> i64 {
+    if value > 100_000_000_000 {
+        value / 1000
+    } else {
+        value
+    }
+}
+
+pub(crate) async fn run_job(state: AppState, ctx: JobContext) -> JobResult {
+    let dry_run = ctx
+        .payload
+        .get("dry_run")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let planned = plan(&state).await?;
+    if dry_run {
+        return Ok(json!({ "dry_run": true, "planned": planned }));
+    }
+
+    let total = planned.len();
+    let mut deleted = 0usize;
+    let mut failed = Vec::new();
+    for (index, chapter) in planned.iter().enumerate() {
+        if ctx.is_cancelled() {
+            return Err("cancelled".to_string());
+        }
+        match library::delete_download(&state, chapter.manga_id, chapter.chapter_index).await {
+            Ok(()) => deleted += 1,
+            Err(err) => failed.push(json!({ "chapter": chapter, "error": err })),
+        }
+        ctx.progress(
+            (index + 1) as f64 / total as f64,
+            &format!("pruned {} of {} chapters", index + 1, total),
+        );
+    }
+    if deleted > 0 {
+        info!("retention pruned {} downloaded chapters", deleted);
+    }
+    Ok(json!({ "dry_run": false, "deleted": deleted, "failed": failed }))
+}
+
+pub(crate) fn spawn(state: AppState) {
+    let config = &state.config.retention;
+    if !config.enabled || !config.has_rules() || !state.config.jobs.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(state.config.retention.interval_seconds));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if state.jobs.has_pending(JOB_KIND) {
+                continue;
+            }
+            if let Err(err) = state.jobs.enqueue(JOB_KIND, json!({}), Some(1)) {
+                warn!("failed to schedule {}: {}", JOB_KIND, err);
+            }
+        }
+    });
+}
@@ -0,0 +1,42 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde_json::json;
+use tracing::info;
+
+use crate::app::AppState;
+use crate::config::ConfigUpdate;
+use crate::Error;
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/api/proxy/config", get(current).patch(update))
+}
+
+async fn current(State(state): State<AppState>) -> Json<ConfigUpdate> {
+    Json(state.live_config())
+}
+
+async fn update(State(state): State<AppState>, Json(body): Json<ConfigUpdate>) -> Response {
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || state.update_config(&body)
+    })
+    .await;
+    match result {
+        Ok(Ok(applied)) => {
+            if !applied.is_empty() {
+                info!("applied config changes: {}", applied.join(", "));
+            }
+            Json(json!({ "applied": applied, "config": state.live_config() })).into_response()
+        }
+        Ok(Err(err @ Error::InvalidArgument { .. })) => {
+            (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+        Ok(Err(err)) => (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
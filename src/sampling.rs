@@ -0,0 +1,103 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use tracing::{field::Empty, info, warn, Instrument, Span};
+
+use crate::app::AppState;
+use crate::config::SamplingConfig;
+
+pub(crate) struct Sampler {
+    config: SamplingConfig,
+}
+
+impl Sampler {
+    pub(crate) fn new(config: SamplingConfig) -> Self {
+        Self { config }
+    }
+
+    fn rate_for(&self, path: &str) -> f64 {
+        self.config
+            .route_rates
+            .iter()
+            .filter_map(|(route, rate)| route_specificity(route, path).map(|score| (score, *rate)))
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, rate)| rate)
+            .unwrap_or(self.config.sample_rate)
+    }
+
+    fn sample(&self, path: &str, headers: &HeaderMap) -> bool {
+        if let Some(sampled) = parent_sampled(headers) {
+            return sampled;
+        }
+        let rate = self.rate_for(path);
+        rate >= 1.0 || (rate > 0.0 && rand::random::<f64>() < rate)
+    }
+}
+
+// Patterns match leading path segments; `*` matches any single segment.
+fn route_specificity(route: &str, path: &str) -> Option<usize> {
+    let pattern = route.split('/').filter(|segment| !segment.is_empty());
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    let mut score = 0;
+    for expected in pattern {
+        let actual = segments.next()?;
+        if expected != "*" && expected != actual {
+            return None;
+        }
+        score += if expected == "*" { 1 } else { 2 };
+    }
+    Some(score)
+}
+
+fn parent_sampled(headers: &HeaderMap) -> Option<bool> {
+    let value = headers.get("traceparent")?.to_str().ok()?;
+    let flags = value.trim().rsplit('-').next()?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    Some(flags & 0x01 == 0x01)
+}
+
+pub(crate) async fn middleware(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let sampled = state.sampler.sample(&path, req.headers());
+    let started = Instant::now();
+
+    let span = if sampled {
+        tracing::info_span!(
+            "request",
+            method = %method,
+            path = %path,
+            status = Empty,
+            duration_ms = Empty
+        )
+    } else {
+        Span::none()
+    };
+    let resp = next.run(req).instrument(span.clone()).await;
+
+    let status = resp.status().as_u16();
+    let duration_ms = started.elapsed().as_millis() as u64;
+    if sampled {
+        span.record("status", status);
+        span.record("duration_ms", duration_ms);
+        info!(parent: &span, status, duration_ms, "request completed");
+    } else if state.sampler.config.always_sample_errors && resp.status().is_server_error() {
+        warn!(
+            method = %method,
+            path = %path,
+            status,
+            duration_ms,
+            "request failed"
+        );
+    }
+    resp
+}
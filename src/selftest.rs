@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::State,
+    http::{Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::app::AppState;
+use crate::ws;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const OUTBOUND_PROBE_URL: &str = "https://github.com";
+const WS_PROBE_PATH: &str = "/api/v1/downloads";
+const PAYLOAD: &[u8] = b"manatan-selftest";
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Pass,
+    Fail,
+    Skip,
+}
+
+#[derive(Serialize)]
+struct Check {
+    name: String,
+    status: Outcome,
+    duration_ms: u64,
+    detail: Option<String>,
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/admin/selftest", get(selftest))
+}
+
+async fn run<F>(name: impl Into<String>, check: F) -> Check
+where
+    F: std::future::Future<Output = Option<Result<String, String>>>,
+{
+    let started = Instant::now();
+    let timed_out = || Some(Err(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())));
+    let result = tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| timed_out());
+    let (status, detail) = match result {
+        Some(Ok(detail)) => (
+            Outcome::Pass,
+            Some(detail).filter(|detail| !detail.is_empty()),
+        ),
+        Some(Err(err)) => (Outcome::Fail, Some(err)),
+        None => (Outcome::Skip, None),
+    };
+    Check {
+        name: name.into(),
+        status,
+        duration_ms: started.elapsed().as_millis() as u64,
+        detail,
+    }
+}
+
+async fn selftest(State(state): State<AppState>) -> Response {
+    let started = Instant::now();
+    let mut disk = Vec::new();
+    for (name, path) in data_paths(&state) {
+        disk.push(run(format!("disk:{name}"), disk_check(path)));
+    }
+    let (backend, websocket, cache, outbound, disk) = futures::join!(
+        run("backend", backend_check(&state)),
+        run("websocket", websocket_check(&state)),
+        run("image_cache", async {
+            state
+                .image_cache
+                .self_test()
+                .await
+                .map(|result| result.map(|()| String::new()))
+        }),
+        run("outbound", outbound_check(&state)),
+        futures::future::join_all(disk),
+    );
+    let mut checks = vec![backend, websocket, cache, outbound];
+    checks.extend(disk);
+
+    let ok = !checks
+        .iter()
+        .any(|check| matches!(check.status, Outcome::Fail));
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(json!({
+            "ok": ok,
+            "duration_ms": started.elapsed().as_millis() as u64,
+            "checks": checks,
+        })),
+    )
+        .into_response()
+}
+
+fn data_paths(state: &AppState) -> Vec<(&'static str, PathBuf)> {
+    let config = &state.config;
+    let db_dir = Path::new(&config.db_path)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    vec![
+        ("database", db_dir),
+        ("proxy_data", PathBuf::from(&config.proxy_data_path)),
+        ("downloads", PathBuf::from(&config.downloads_path)),
+        ("local_manga", PathBuf::from(&config.local_manga_path)),
+        ("local_anime", PathBuf::from(&config.local_anime_path)),
+        ("aidoku_cache", PathBuf::from(&config.aidoku_cache_path)),
+    ]
+}
+
+async fn backend_check(state: &AppState) -> Option<Result<String, String>> {
+    let url = format!("{}/api/v1/settings/about", state.backend_url);
+    Some(match state.client.get(url).send().await {
+        Ok(resp) if resp.status().is_success() => Ok(String::new()),
+        Ok(resp) => Err(format!("backend returned {}", resp.status())),
+        Err(err) => Err(err.to_string()),
+    })
+}
+
+async fn websocket_check(state: &AppState) -> Option<Result<String, String>> {
+    let url = format!("{}{WS_PROBE_PATH}", ws::backend_ws_url(&state.backend_url));
+    let result = async {
+        let mut socket = ws::connect_backend(url.as_str(), &state.backend_transport)
+            .await
+            .map_err(|err| err.to_string())?;
+        socket
+            .send(Message::Ping(PAYLOAD.to_vec()))
+            .await
+            .map_err(|err| err.to_string())?;
+        while let Some(msg) = socket.next().await {
+            match msg.map_err(|err| err.to_string())? {
+                Message::Pong(payload) if payload == PAYLOAD => {
+                    let _ = socket.close(None).await;
+                    return Ok(String::new());
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        Err("connection closed before the pong".to_string())
+    }
+    .await;
+    Some(result)
+}
+
+async fn disk_check(dir: PathBuf) -> Option<Result<String, String>> {
+    let probe = dir.join(format!(".manatan-selftest-{}", std::process::id()));
+    let result = async {
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::write(&probe, PAYLOAD).await?;
+        let read = tokio::fs::read(&probe).await?;
+        if read != PAYLOAD {
+            return Err(std::io::Error::other("read back different bytes"));
+        }
+        Ok(())
+    }
+    .await;
+    let _ = tokio::fs::remove_file(&probe).await;
+    Some(
+        result
+            .map(|()| dir.display().to_string())
+            .map_err(|err| format!("{}: {}", dir.display(), err)),
+    )
+}
+
+async fn outbound_check(state: &AppState) -> Option<Result<String, String>> {
+    Some(
+        match state
+            .outbound
+            .request(Method::HEAD, OUTBOUND_PROBE_URL)
+            .send()
+            .await
+        {
+            Ok(resp) => Ok(format!("{} {}", OUTBOUND_PROBE_URL, resp.status())),
+            Err(err) => Err(err.to_string()),
+        },
+    )
+}
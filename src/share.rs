@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::{any, delete, get},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::warn;
+
+use crate::app::{proxy, AppState};
+use crate::config::ShareConfig;
+use crate::content_filter;
+use crate::keys::{random_id, SigningKey};
+use crate::store::{load_json, save_json};
+use crate::unix_now;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ShareKind {
+    Series,
+    Chapter,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ShareLink {
+    pub id: String,
+    pub kind: ShareKind,
+    pub manga_id: i64,
+    pub chapter_index: Option<i64>,
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
+impl ShareLink {
+    fn allows(&self, path: &str) -> bool {
+        if path
+            .split('/')
+            .any(|segment| segment == "." || segment == ".." || segment.contains('%'))
+        {
+            return false;
+        }
+        let series = format!("manga/{}", self.manga_id);
+        let Some(rest) = path.strip_prefix(&series) else {
+            return false;
+        };
+        if rest.is_empty() || rest == "/" || rest == "/thumbnail" {
+            return true;
+        }
+        match (self.kind, self.chapter_index) {
+            (ShareKind::Series, _) => rest.starts_with('/'),
+            (ShareKind::Chapter, Some(index)) => {
+                let chapter = format!("/chapter/{index}");
+                rest == chapter || rest.starts_with(&format!("{chapter}/"))
+            }
+            (ShareKind::Chapter, None) => false,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ShareData {
+    links: BTreeMap<String, ShareLink>,
+}
+
+pub(crate) struct Shares {
+    config: ShareConfig,
+    path: PathBuf,
+    key: Option<SigningKey>,
+    data: Mutex<ShareData>,
+}
+
+impl Shares {
+    pub(crate) fn new(config: ShareConfig, data_path: &str) -> Self {
+        let dir = PathBuf::from(data_path);
+        let key = if config.enabled {
+            SigningKey::load_or_create(&dir.join("share.key"))
+                .map_err(|err| warn!("share links disabled: cannot load signing key: {}", err))
+                .ok()
+        } else {
+            None
+        };
+        let path = dir.join("shares.json");
+        let data = load_json(&path);
+        Self {
+            config,
+            path,
+            key,
+            data: Mutex::new(data),
+        }
+    }
+
+    fn token(&self, link: &ShareLink) -> Option<String> {
+        let message = format!("{}.{}", link.id, link.expires_at);
+        let signature = self.key.as_ref()?.sign(message.as_bytes());
+        Some(format!("{message}.{signature}"))
+    }
+
+    pub(crate) fn resolve(&self, token: &str) -> Option<ShareLink> {
+        let key = self.key.as_ref()?;
+        let mut parts = token.splitn(3, '.');
+        let (id, expires, signature) = (parts.next()?, parts.next()?, parts.next()?);
+        if !key.verify(format!("{id}.{expires}").as_bytes(), signature) {
+            return None;
+        }
+        let expires = expires.parse::<u64>().ok()?;
+        if expires <= unix_now() {
+            return None;
+        }
+        let data = self.data.lock().unwrap_or_else(|err| err.into_inner());
+        data.links
+            .get(id)
+            .filter(|link| link.expires_at == expires)
+            .cloned()
+    }
+
+    fn persist(&self, data: &mut ShareData) {
+        let now = unix_now();
+        data.links.retain(|_, link| link.expires_at > now);
+        if let Err(err) = save_json(&self.path, data) {
+            warn!(
+                "failed to persist share links to {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/share", get(list).post(create))
+        .route("/share/{id}", delete(revoke))
+        .route("/s/{token}", get(describe))
+        .route("/s/{token}/{*path}", any(access))
+}
+
+#[derive(Deserialize)]
+struct CreateShare {
+    manga_id: i64,
+    chapter_index: Option<i64>,
+    ttl_seconds: Option<u64>,
+}
+
+async fn create(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    Json(body): Json<CreateShare>,
+) -> Response {
+    let shares = &state.shares;
+    if shares.key.is_none() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "share links are disabled").into_response();
+    }
+    let series = format!("/api/v1/manga/{}", body.manga_id);
+    if let Some(resp) = content_filter::block_link(&state, &headers, &uri, &series).await {
+        return resp;
+    }
+    let ttl = body
+        .ttl_seconds
+        .unwrap_or(shares.config.default_ttl_seconds)
+        .clamp(60, shares.config.max_ttl_seconds);
+    let now = unix_now();
+    let link = ShareLink {
+        id: random_id(),
+        kind: if body.chapter_index.is_some() {
+            ShareKind::Chapter
+        } else {
+            ShareKind::Series
+        },
+        manga_id: body.manga_id,
+        chapter_index: body.chapter_index,
+        created_at: now,
+        expires_at: now + ttl,
+    };
+    let Some(token) = shares.token(&link) else {
+        return (StatusCode::SERVICE_UNAVAILABLE, "share links are disabled").into_response();
+    };
+
+    {
+        let mut data = shares.data.lock().unwrap_or_else(|err| err.into_inner());
+        data.links.insert(link.id.clone(), link.clone());
+        shares.persist(&mut data);
+    }
+
+    let url = format!("{}/s/{}", state.external_base_url(&headers), token);
+    (
+        StatusCode::CREATED,
+        Json(json!({ "link": link, "token": token, "url": url })),
+    )
+        .into_response()
+}
+
+async fn list(State(state): State<AppState>) -> Json<Vec<ShareLink>> {
+    let now = unix_now();
+    let data = state
+        .shares
+        .data
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    Json(
+        data.links
+            .values()
+            .filter(|link| link.expires_at > now)
+            .cloned()
+            .collect(),
+    )
+}
+
+async fn revoke(State(state): State<AppState>, Path(id): Path<String>) -> StatusCode {
+    let mut data = state
+        .shares
+        .data
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    if data.links.remove(&id).is_none() {
+        return StatusCode::NOT_FOUND;
+    }
+    state.shares.persist(&mut data);
+    StatusCode::NO_CONTENT
+}
+
+async fn describe(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    match state.shares.resolve(&token) {
+        Some(link) => Json(link).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn access(
+    State(state): State<AppState>,
+    Path((token, path)): Path<(String, String)>,
+    req: Request,
+) -> Response {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+    let Some(link) = state.shares.resolve(&token) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !link.allows(&path) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let query = req
+        .uri()
+        .query()
+        .map(|query| format!("?{query}"))
+        .unwrap_or_default();
+    let Ok(uri) = format!("/api/v1/{path}{query}").parse::<Uri>() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let (mut parts, _) = req.into_parts();
+    parts.uri = uri;
+    for name in ["authorization", "cookie"] {
+        parts.headers.remove(name);
+    }
+    proxy(state, Request::from_parts(parts, Body::empty())).await
+}
@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, Request, State},
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::{any, get},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::warn;
+
+use crate::app::{proxy, AppState};
+use crate::config::SignedUrlConfig;
+use crate::content_filter;
+use crate::keys::SigningKey;
+use crate::unix_now;
+
+// Expiring links to pages, covers and episodes for players that cannot send the
+// bearer token. The signature covers the expiry and the media the link points at.
+pub(crate) struct SignedUrls {
+    config: SignedUrlConfig,
+    key: Option<SigningKey>,
+}
+
+impl SignedUrls {
+    pub(crate) fn new(config: SignedUrlConfig, data_path: &str) -> Self {
+        let key = if config.enabled {
+            SigningKey::load_or_create(&PathBuf::from(data_path).join("media.key"))
+                .map_err(|err| warn!("signed media urls disabled: cannot load key: {}", err))
+                .ok()
+        } else {
+            None
+        };
+        Self { config, key }
+    }
+
+    fn token(&self, scope: &str, expires_at: u64) -> Option<String> {
+        let signature = self
+            .key
+            .as_ref()?
+            .sign(format!("{expires_at}\n{scope}").as_bytes());
+        Some(format!("{expires_at}.{signature}"))
+    }
+
+    fn verify(&self, token: &str, scope: &str) -> bool {
+        let Some(key) = self.key.as_ref() else {
+            return false;
+        };
+        let Some((expires, signature)) = token.split_once('.') else {
+            return false;
+        };
+        expires.parse::<u64>().is_ok_and(|at| at > unix_now())
+            && key.verify(format!("{expires}\n{scope}").as_bytes(), signature)
+    }
+}
+
+// What a link grants: a single cover or page, or everything under an episode so a
+// player can follow its playlist, segments and subtitles with relative URLs.
+fn scope(path: &str) -> Option<String> {
+    let segments = path.split('/').collect::<Vec<_>>();
+    if segments.iter().any(|segment| {
+        segment.is_empty() || *segment == "." || *segment == ".." || segment.contains('%')
+    }) {
+        return None;
+    }
+    match segments.as_slice() {
+        ["manga" | "anime", _, "thumbnail"] | ["manga", _, "chapter", _, "page", _] => {
+            Some(path.to_string())
+        }
+        ["anime", id, "episode", index, ..] => Some(format!("anime/{id}/episode/{index}")),
+        _ => None,
+    }
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/proxy/media/sign", get(sign))
+        .route("/m/{token}/{*path}", any(access))
+}
+
+#[derive(Deserialize)]
+struct SignQuery {
+    path: String,
+    ttl_seconds: Option<u64>,
+}
+
+async fn sign(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    Query(query): Query<SignQuery>,
+) -> Response {
+    let signed = &state.signed_urls;
+    let (path, rest) = query
+        .path
+        .split_once('?')
+        .map_or((query.path.as_str(), None), |(path, rest)| {
+            (path, Some(rest))
+        });
+    let path = path.trim_start_matches('/');
+    let path = path.strip_prefix("api/v1/").unwrap_or(path);
+    let Some(scope) = scope(path) else {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "only cover, page and episode paths can be signed",
+        )
+            .into_response();
+    };
+    let media = format!("/api/v1/{path}");
+    if let Some(resp) = content_filter::block_link(&state, &headers, &uri, &media).await {
+        return resp;
+    }
+    let ttl = query
+        .ttl_seconds
+        .unwrap_or(signed.config.default_ttl_seconds)
+        .clamp(60, signed.config.max_ttl_seconds);
+    let expires_at = unix_now() + ttl;
+    let Some(token) = signed.token(&scope, expires_at) else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "signed media urls are disabled",
+        )
+            .into_response();
+    };
+    let query = rest.map(|rest| format!("?{rest}")).unwrap_or_default();
+    let url = format!(
+        "{}/m/{}/{}{}",
+        state.external_base_url(&headers),
+        token,
+        path,
+        query
+    );
+    Json(json!({ "url": url, "expires_at": expires_at })).into_response()
+}
+
+async fn access(
+    State(state): State<AppState>,
+    Path((token, path)): Path<(String, String)>,
+    req: Request,
+) -> Response {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+    let Some(scope) = scope(&path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !state.signed_urls.verify(&token, &scope) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let query = req
+        .uri()
+        .query()
+        .map(|query| format!("?{query}"))
+        .unwrap_or_default();
+    let Ok(uri) = format!("/api/v1/{path}{query}").parse::<Uri>() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let (mut parts, _) = req.into_parts();
+    parts.uri = uri;
+    for name in ["authorization", "cookie"] {
+        parts.headers.remove(name);
+    }
+    proxy(state, Request::from_parts(parts, Body::empty())).await
+}
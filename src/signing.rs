@@ -0,0 +1,229 @@
+#[cfg(feature = "auth")]
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Request},
+    http::{request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+use crate::app::AppState;
+use crate::config::SigningConfig;
+use crate::keys::SigningKey;
+use crate::principal::Principal;
+#[cfg(feature = "auth")]
+use crate::principal::Scope;
+use crate::unix_now;
+
+const MAX_SIGNED_BODY: usize = 16 * 1024 * 1024;
+const TIMESTAMP_HEADER: &str = "x-manatan-timestamp";
+const SIGNATURE_HEADER: &str = "x-manatan-signature";
+
+pub(crate) struct RequestSigning {
+    config: SigningConfig,
+    key: Option<SigningKey>,
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl RequestSigning {
+    pub(crate) fn new(config: SigningConfig) -> Self {
+        let key = config
+            .secret
+            .as_deref()
+            .map(|secret| SigningKey::from_secret(secret.as_bytes()));
+        Self {
+            config,
+            key,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn covers(&self, path: &str) -> bool {
+        self.config.is_active()
+            && self.config.routes.iter().any(|route| {
+                path == route || path.starts_with(&format!("{}/", route.trim_end_matches('/')))
+            })
+    }
+
+    fn verify(
+        &self,
+        headers: &HeaderMap,
+        method: &str,
+        uri: &str,
+        body: &[u8],
+    ) -> Result<(), &'static str> {
+        let key = self
+            .key
+            .as_ref()
+            .ok_or("request signing is not configured")?;
+        let timestamp = header(headers, TIMESTAMP_HEADER).ok_or("missing signature timestamp")?;
+        let signature = header(headers, SIGNATURE_HEADER).ok_or("missing request signature")?;
+        let signed_at = timestamp
+            .parse::<u64>()
+            .map_err(|_| "invalid signature timestamp")?;
+        let now = unix_now();
+        if now.abs_diff(signed_at) > self.config.max_skew_seconds {
+            return Err("signature timestamp outside the allowed window");
+        }
+
+        let message = format!("{timestamp}\n{method}\n{uri}\n{}", hex_digest(body));
+        let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+        if !key.verify_hex(message.as_bytes(), signature) {
+            return Err("invalid request signature");
+        }
+
+        let mut seen = self.seen.lock().unwrap_or_else(|err| err.into_inner());
+        let window = self.config.max_skew_seconds;
+        seen.retain(|_, at| now.saturating_sub(*at) <= window * 2);
+        if seen.insert(signature.to_ascii_lowercase(), now).is_some() {
+            return Err("request signature already used");
+        }
+        Ok(())
+    }
+}
+
+pub(crate) async fn check(state: &AppState, req: Request) -> Result<Request, Response> {
+    if !state.signing.covers(req.uri().path()) {
+        return Ok(req);
+    }
+
+    let (mut parts, body) = req.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_SIGNED_BODY).await else {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "signed request body too large",
+        )
+            .into_response());
+    };
+    let uri = signed_uri(&parts);
+    if let Err(reason) = state
+        .signing
+        .verify(&parts.headers, parts.method.as_str(), &uri, &bytes)
+    {
+        return Err((StatusCode::UNAUTHORIZED, reason).into_response());
+    }
+
+    parts.extensions.insert(Principal {
+        id: "signed-request".to_string(),
+        #[cfg(feature = "auth")]
+        scopes: BTreeSet::from([Scope::Read, Scope::Write]),
+        image_profile: None,
+    });
+    Ok(Request::from_parts(parts, Body::from(bytes)))
+}
+
+// Clients sign the path and query they sent. The base path and normalize middleware
+// rewrite the request URI before it gets here, so their original is used when present.
+fn signed_uri(parts: &Parts) -> String {
+    let uri = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or(&parts.uri, |original| &original.0);
+    uri.path_and_query()
+        .map(|value| value.as_str().to_string())
+        .unwrap_or_else(|| uri.path().to_string())
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::{Hmac, Mac};
+
+    use super::*;
+
+    const SECRET: &str = "signing-secret";
+
+    fn signing() -> RequestSigning {
+        RequestSigning::new(SigningConfig {
+            secret: Some(SECRET.to_string()),
+            routes: vec!["/api/v1/backup".to_string()],
+            max_skew_seconds: 300,
+        })
+    }
+
+    fn signed(timestamp: u64, method: &str, uri: &str, body: &[u8]) -> HeaderMap {
+        let message = format!("{timestamp}\n{method}\n{uri}\n{}", hex_digest(body));
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(message.as_bytes());
+        let signature = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMESTAMP_HEADER, timestamp.to_string().parse().unwrap());
+        headers.insert(
+            SIGNATURE_HEADER,
+            format!("sha256={signature}").parse().unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn valid_signature_is_accepted_once() {
+        let signing = signing();
+        let headers = signed(unix_now(), "POST", "/api/v1/backup?x=1", b"body");
+        assert_eq!(
+            signing.verify(&headers, "POST", "/api/v1/backup?x=1", b"body"),
+            Ok(())
+        );
+        assert_eq!(
+            signing.verify(&headers, "POST", "/api/v1/backup?x=1", b"body"),
+            Err("request signature already used")
+        );
+    }
+
+    #[test]
+    fn tampered_or_stale_requests_are_rejected() {
+        let signing = signing();
+        let now = unix_now();
+        let headers = signed(now, "POST", "/api/v1/backup", b"body");
+        for (method, uri, body) in [
+            ("PUT", "/api/v1/backup", &b"body"[..]),
+            ("POST", "/api/v1/backup/other", &b"body"[..]),
+            ("POST", "/api/v1/backup", &b"other"[..]),
+        ] {
+            assert_eq!(
+                signing.verify(&headers, method, uri, body),
+                Err("invalid request signature")
+            );
+        }
+        let stale = signed(now - 301, "POST", "/api/v1/backup", b"body");
+        assert_eq!(
+            signing.verify(&stale, "POST", "/api/v1/backup", b"body"),
+            Err("signature timestamp outside the allowed window")
+        );
+    }
+
+    #[test]
+    fn the_uri_the_client_sent_is_the_one_verified() {
+        let (mut parts, ()) = Request::builder()
+            .uri("/api/v1/backup?x=1")
+            .body(())
+            .unwrap()
+            .into_parts();
+        assert_eq!(signed_uri(&parts), "/api/v1/backup?x=1");
+        parts
+            .extensions
+            .insert(OriginalUri("/manatan//api/v1/backup/?x=1".parse().unwrap()));
+        assert_eq!(signed_uri(&parts), "/manatan//api/v1/backup/?x=1");
+    }
+}
@@ -0,0 +1,523 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::SkipMarkersConfig;
+use crate::jobs::{JobContext, JobResult};
+use crate::unix_now;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS markers (
+    episode TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    start_seconds REAL NOT NULL,
+    end_seconds REAL NOT NULL,
+    matched_with TEXT NOT NULL,
+    analyzed_at INTEGER NOT NULL,
+    PRIMARY KEY (episode, kind)
+);
+";
+
+const VIDEO_EXTENSIONS: &[&str] = &["mkv", "mp4", "m4v", "webm", "avi", "mov", "ts"];
+
+// Audio is decoded to 8 kHz mono and cut into overlapping frames of 128ms every 64ms.
+const SAMPLE_RATE: usize = 8000;
+const FRAME: usize = 1024;
+const HOP: usize = 512;
+// 33 bands between 250 Hz and 2 kHz give one 32-bit sub-fingerprint per frame.
+const BANDS: usize = 33;
+const LOW_HZ: f32 = 250.0;
+const HIGH_HZ: f32 = 2000.0;
+// Frames match when at most this many of their 32 bits differ; a run survives a few
+// mismatched frames in a row so a dropped frame or a sound effect does not split it.
+const MAX_BIT_ERRORS: u32 = 10;
+const MAX_GAP: usize = 4;
+
+pub(crate) struct SkipMarkers {
+    config: SkipMarkersConfig,
+    conn: Mutex<Connection>,
+}
+
+#[derive(Clone, Copy)]
+struct Marker {
+    start: f64,
+    end: f64,
+}
+
+// Sub-fingerprints of the two stretches where intros and outros live.
+struct Clip {
+    intro: Vec<u32>,
+    outro: Vec<u32>,
+    outro_start: f64,
+}
+
+impl SkipMarkers {
+    pub(crate) fn new(config: SkipMarkersConfig, data_path: &str) -> Self {
+        let path = PathBuf::from(data_path).join("skip_markers.sqlite3");
+        let conn = match open(&path) {
+            Ok(conn) => conn,
+            Err(err) => {
+                warn!(
+                    "skip markers falling back to memory: cannot open {}: {}",
+                    path.display(),
+                    err
+                );
+                let conn = Connection::open_in_memory().expect("in-memory sqlite");
+                let _ = conn.execute_batch(SCHEMA);
+                conn
+            }
+        };
+        Self {
+            config,
+            conn: Mutex::new(conn),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn store(&self, episode: &str, kind: &str, marker: Marker, matched_with: &str) {
+        let conn = self.lock();
+        if let Err(err) = conn.execute(
+            "INSERT OR REPLACE INTO markers \
+             (episode, kind, start_seconds, end_seconds, matched_with, analyzed_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                episode,
+                kind,
+                marker.start,
+                marker.end,
+                matched_with,
+                unix_now() as i64
+            ],
+        ) {
+            warn!("failed to store {} marker for {}: {}", kind, episode, err);
+        }
+    }
+
+    fn get(&self, episode: &str) -> rusqlite::Result<Option<Value>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT kind, start_seconds, end_seconds, matched_with, analyzed_at \
+             FROM markers WHERE episode = ?1",
+        )?;
+        let rows = stmt.query_map(params![episode], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                json!({
+                    "start": row.get::<_, f64>(1)?,
+                    "end": row.get::<_, f64>(2)?,
+                    "matched_with": row.get::<_, String>(3)?,
+                    "analyzed_at": row.get::<_, i64>(4)?,
+                }),
+            ))
+        })?;
+        let mut markers = serde_json::Map::new();
+        for row in rows {
+            let (kind, marker) = row?;
+            markers.insert(kind, marker);
+        }
+        if markers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(json!({
+            "episode": episode,
+            "intro": markers.remove("intro"),
+            "outro": markers.remove("outro"),
+        })))
+    }
+}
+
+fn open(path: &Path) -> rusqlite::Result<Connection> {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let conn = Connection::open(path)?;
+    conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+    conn.execute_batch(SCHEMA)?;
+    Ok(conn)
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/skip-markers/{*episode}", get(markers))
+}
+
+async fn markers(State(state): State<AppState>, UrlPath(episode): UrlPath<String>) -> Response {
+    let episode = episode.trim_matches('/').to_string();
+    let skip_markers = state.skip_markers.clone();
+    let found = tokio::task::spawn_blocking(move || skip_markers.get(&episode)).await;
+    match found {
+        Ok(Ok(Some(markers))) => Json(markers).into_response(),
+        Ok(Ok(None)) => StatusCode::NOT_FOUND.into_response(),
+        Ok(Err(err)) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+// Episodes are compared with their neighbours in the same folder; the longest stretch
+// of audio two episodes share near the start is the intro, near the end the outro.
+pub(crate) async fn run_job(state: AppState, ctx: JobContext) -> JobResult {
+    let roots = match ctx.payload.get("paths").and_then(Value::as_array) {
+        Some(paths) => paths
+            .iter()
+            .filter_map(Value::as_str)
+            .map(PathBuf::from)
+            .collect::<Vec<_>>(),
+        None => vec![
+            PathBuf::from(&state.config.local_anime_path),
+            PathBuf::from(&state.config.downloads_path).join("anime"),
+        ],
+    };
+    let skip_markers = state.skip_markers.clone();
+    tokio::task::spawn_blocking(move || analyze_all(&skip_markers, &roots, &ctx))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+fn analyze_all(skip_markers: &SkipMarkers, roots: &[PathBuf], ctx: &JobContext) -> JobResult {
+    let seasons = find_seasons(roots);
+    let total = seasons.values().map(Vec::len).sum::<usize>();
+    let mut analyzed = 0usize;
+    let mut failed = Vec::new();
+    let mut intros = 0usize;
+    let mut outros = 0usize;
+    for episodes in seasons.values() {
+        let mut clips = Vec::with_capacity(episodes.len());
+        for (path, _) in episodes {
+            if ctx.is_cancelled() {
+                return Err("cancelled".to_string());
+            }
+            match decode(&skip_markers.config, path) {
+                Ok(clip) => clips.push(Some(clip)),
+                Err(err) => {
+                    failed.push(json!({ "path": path.display().to_string(), "error": err }));
+                    clips.push(None);
+                }
+            }
+            analyzed += 1;
+            ctx.progress(
+                analyzed as f64 / total as f64,
+                &format!("analyzed {analyzed} of {total} episodes"),
+            );
+        }
+
+        let min_frames = skip_markers.config.min_seconds as usize * SAMPLE_RATE / HOP;
+        let mut best: HashMap<(usize, &str), (Marker, usize)> = HashMap::new();
+        let mut keep = |index: usize, kind: &'static str, marker: Marker, other: usize| {
+            let longer = best
+                .get(&(index, kind))
+                .is_none_or(|(kept, _)| marker.end - marker.start > kept.end - kept.start);
+            if longer {
+                best.insert((index, kind), (marker, other));
+            }
+        };
+        for index in 0..clips.len().saturating_sub(1) {
+            let (Some(left), Some(right)) = (&clips[index], &clips[index + 1]) else {
+                continue;
+            };
+            if let Some((a, b)) = longest_match(&left.intro, &right.intro, min_frames) {
+                keep(index, "intro", marker(&a, 0.0), index + 1);
+                keep(index + 1, "intro", marker(&b, 0.0), index);
+            }
+            if let Some((a, b)) = longest_match(&left.outro, &right.outro, min_frames) {
+                keep(index, "outro", marker(&a, left.outro_start), index + 1);
+                keep(index + 1, "outro", marker(&b, right.outro_start), index);
+            }
+        }
+        for ((index, kind), (marker, other)) in best {
+            match kind {
+                "intro" => intros += 1,
+                _ => outros += 1,
+            }
+            skip_markers.store(&episodes[index].1, kind, marker, &episodes[other].1);
+        }
+    }
+
+    info!(
+        "skip markers: {} intros and {} outros across {} episodes",
+        intros, outros, analyzed
+    );
+    Ok(json!({
+        "episodes": analyzed,
+        "intros": intros,
+        "outros": outros,
+        "failed": failed,
+    }))
+}
+
+// Video files grouped by folder and sorted by name, each with its key: the path below
+// the root it was found in.
+fn find_seasons(roots: &[PathBuf]) -> BTreeMap<PathBuf, Vec<(PathBuf, String)>> {
+    let mut seasons: BTreeMap<PathBuf, Vec<(PathBuf, String)>> = BTreeMap::new();
+    for root in roots {
+        let mut pending = vec![root.clone()];
+        while let Some(dir) = pending.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                let path = entry.path();
+                if file_type.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+                let video = path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+                if !file_type.is_file() || !video {
+                    continue;
+                }
+                let Ok(relative) = path.strip_prefix(root) else {
+                    continue;
+                };
+                let key = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                seasons.entry(dir.clone()).or_default().push((path, key));
+            }
+        }
+    }
+    seasons.retain(|_, episodes| episodes.len() > 1);
+    for episodes in seasons.values_mut() {
+        episodes.sort_by(|a, b| a.1.cmp(&b.1));
+    }
+    seasons
+}
+
+fn marker(frames: &Range<usize>, offset: f64) -> Marker {
+    let seconds = |frame: usize| (frame * HOP) as f64 / SAMPLE_RATE as f64;
+    Marker {
+        start: offset + seconds(frames.start),
+        end: offset + seconds(frames.end) + FRAME as f64 / SAMPLE_RATE as f64,
+    }
+}
+
+// Runs ffmpeg over the whole file, keeping only the first and last windows of audio.
+fn decode(config: &SkipMarkersConfig, path: &Path) -> Result<Clip, String> {
+    let mut child = Command::new(&config.ffmpeg_path)
+        .args(["-nostdin", "-v", "error", "-i"])
+        .arg(path)
+        .args(["-vn", "-ac", "1", "-ar", "8000", "-f", "s16le", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("cannot run {}: {err}", config.ffmpeg_path))?;
+    let mut stdout = child.stdout.take().ok_or("ffmpeg has no stdout")?;
+
+    let intro_len = config.intro_window_seconds as usize * SAMPLE_RATE;
+    let outro_len = config.outro_window_seconds as usize * SAMPLE_RATE;
+    let mut intro = Vec::with_capacity(intro_len);
+    let mut outro = VecDeque::with_capacity(outro_len);
+    let mut total = 0usize;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut carry = None;
+    loop {
+        let read = stdout.read(&mut buf).map_err(|err| err.to_string())?;
+        if read == 0 {
+            break;
+        }
+        let mut bytes = &buf[..read];
+        let mut next = |sample: i16| {
+            let sample = f32::from(sample) / 32768.0;
+            if intro.len() < intro_len {
+                intro.push(sample);
+            }
+            if outro.len() == outro_len {
+                outro.pop_front();
+            }
+            outro.push_back(sample);
+            total += 1;
+        };
+        if let Some(low) = carry.take() {
+            next(i16::from_le_bytes([low, bytes[0]]));
+            bytes = &bytes[1..];
+        }
+        let mut pairs = bytes.chunks_exact(2);
+        for pair in &mut pairs {
+            next(i16::from_le_bytes([pair[0], pair[1]]));
+        }
+        carry = pairs.remainder().first().copied();
+    }
+    let status = child.wait().map_err(|err| err.to_string())?;
+    if !status.success() {
+        return Err(format!("ffmpeg exited with {status}"));
+    }
+    if total < FRAME * 2 {
+        return Err("no audio".to_string());
+    }
+
+    let outro = outro.into_iter().collect::<Vec<_>>();
+    Ok(Clip {
+        intro: fingerprint(&intro),
+        outro_start: (total - outro.len()) as f64 / SAMPLE_RATE as f64,
+        outro: fingerprint(&outro),
+    })
+}
+
+// One sub-fingerprint per frame after the first: each bit says whether the energy
+// difference between two neighbouring bands grew or shrank since the previous frame.
+fn fingerprint(samples: &[f32]) -> Vec<u32> {
+    if samples.len() < FRAME {
+        return Vec::new();
+    }
+    let fft = Fft::new(FRAME);
+    let window = (0..FRAME)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FRAME as f32).cos())
+        .collect::<Vec<_>>();
+    let edges = (0..=BANDS)
+        .map(|band| {
+            let hz = LOW_HZ * (HIGH_HZ / LOW_HZ).powf(band as f32 / BANDS as f32);
+            (hz * FRAME as f32 / SAMPLE_RATE as f32).round() as usize
+        })
+        .collect::<Vec<_>>();
+
+    let mut re = vec![0.0; FRAME];
+    let mut im = vec![0.0; FRAME];
+    let mut previous: Option<[f32; BANDS]> = None;
+    let mut prints = Vec::with_capacity((samples.len() - FRAME) / HOP + 1);
+    for start in (0..=samples.len() - FRAME).step_by(HOP) {
+        for (i, value) in re.iter_mut().enumerate() {
+            *value = samples[start + i] * window[i];
+        }
+        im.fill(0.0);
+        fft.run(&mut re, &mut im);
+        let mut energy = [0.0f32; BANDS];
+        for (band, value) in energy.iter_mut().enumerate() {
+            *value = (edges[band]..edges[band + 1].max(edges[band] + 1))
+                .map(|bin| re[bin] * re[bin] + im[bin] * im[bin])
+                .sum();
+        }
+        if let Some(previous) = previous {
+            let mut bits = 0u32;
+            for band in 0..BANDS - 1 {
+                let now = energy[band] - energy[band + 1];
+                let before = previous[band] - previous[band + 1];
+                if now - before > 0.0 {
+                    bits |= 1 << band;
+                }
+            }
+            prints.push(bits);
+        }
+        previous = Some(energy);
+    }
+    prints
+}
+
+// The longest stretch where the two clips line up, as frame ranges in each.
+fn longest_match(a: &[u32], b: &[u32], min_frames: usize) -> Option<(Range<usize>, Range<usize>)> {
+    let mut best: Option<(Range<usize>, isize)> = None;
+    let mut consider = |run: Range<usize>, shift: isize| {
+        if run.len() >= min_frames && best.as_ref().is_none_or(|(kept, _)| run.len() > kept.len()) {
+            best = Some((run, shift));
+        }
+    };
+    // `shift` is how far into `a` the start of `b` falls.
+    for shift in -(b.len() as isize)..a.len() as isize {
+        let from = shift.max(0) as usize;
+        let to = a.len().min((b.len() as isize + shift) as usize);
+        if to.saturating_sub(from) < min_frames {
+            continue;
+        }
+        let mut run: Option<usize> = None;
+        let mut last_hit = 0;
+        let mut misses = 0;
+        let pairs = a[from..to]
+            .iter()
+            .zip(&b[(from as isize - shift) as usize..]);
+        for (i, (left, right)) in (from..).zip(pairs) {
+            if (left ^ right).count_ones() <= MAX_BIT_ERRORS {
+                run.get_or_insert(i);
+                last_hit = i;
+                misses = 0;
+            } else if let Some(start) = run {
+                misses += 1;
+                if misses > MAX_GAP {
+                    consider(start..last_hit + 1, shift);
+                    run = None;
+                }
+            }
+        }
+        if let Some(start) = run {
+            consider(start..last_hit + 1, shift);
+        }
+    }
+    best.map(|(run, shift)| {
+        let start = (run.start as isize - shift) as usize;
+        let end = (run.end as isize - shift) as usize;
+        (run, start..end)
+    })
+}
+
+struct Fft {
+    cos: Vec<f32>,
+    sin: Vec<f32>,
+}
+
+impl Fft {
+    fn new(size: usize) -> Self {
+        let angle = |k: usize| -2.0 * std::f32::consts::PI * k as f32 / size as f32;
+        Self {
+            cos: (0..size / 2).map(|k| angle(k).cos()).collect(),
+            sin: (0..size / 2).map(|k| angle(k).sin()).collect(),
+        }
+    }
+
+    // In-place iterative radix-2; `re` and `im` must be the size the table was built for.
+    fn run(&self, re: &mut [f32], im: &mut [f32]) {
+        let size = re.len();
+        let mut j = 0;
+        for i in 1..size {
+            let mut bit = size >> 1;
+            while j & bit != 0 {
+                j ^= bit;
+                bit >>= 1;
+            }
+            j |= bit;
+            if i < j {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+        let mut len = 2;
+        while len <= size {
+            let step = size / len;
+            for start in (0..size).step_by(len) {
+                for k in 0..len / 2 {
+                    let (cos, sin) = (self.cos[k * step], self.sin[k * step]);
+                    let a = start + k;
+                    let b = a + len / 2;
+                    let tr = re[b] * cos - im[b] * sin;
+                    let ti = re[b] * sin + im[b] * cos;
+                    re[b] = re[a] - tr;
+                    im[b] = im[a] - ti;
+                    re[a] += tr;
+                    im[a] += ti;
+                }
+            }
+            len <<= 1;
+        }
+    }
+}
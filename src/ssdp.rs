@@ -0,0 +1,259 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::Config;
+
+const MULTICAST: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const PORT: u16 = 1900;
+const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:Basic:1";
+const DESCRIPTION_PATH: &str = "/ssdp/device.xml";
+const MAX_MX_SECONDS: u64 = 5;
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route(DESCRIPTION_PATH, get(description))
+}
+
+pub(crate) fn spawn(state: AppState) {
+    if !state.config.ssdp.enabled {
+        return;
+    }
+    let socket = match bind(state.config.ssdp.ttl) {
+        Ok(socket) => Arc::new(socket),
+        Err(err) => {
+            warn!("ssdp disabled: cannot join {}:{}: {}", MULTICAST, PORT, err);
+            return;
+        }
+    };
+    let uuid = device_uuid(&state.config);
+    info!("announcing uuid:{} over ssdp", uuid);
+    tokio::spawn(async move {
+        let interval = state.config.ssdp.max_age_seconds / 2;
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval.max(30)));
+        let mut buf = [0u8; 2048];
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => announce(&socket, &state, &uuid).await,
+                received = socket.recv_from(&mut buf) => match received {
+                    Ok((len, peer)) => {
+                        let Some((st, mx)) = parse_search(&buf[..len]) else {
+                            continue;
+                        };
+                        let socket = socket.clone();
+                        let state = state.clone();
+                        let uuid = uuid.clone();
+                        tokio::spawn(async move {
+                            let window = mx.min(MAX_MX_SECONDS) * 1000;
+                            let delay = rand::thread_rng().gen_range(0..=window);
+                            tokio::time::sleep(Duration::from_millis(delay)).await;
+                            respond(&socket, &state, &uuid, &st, peer).await;
+                        });
+                    }
+                    Err(err) => warn!("ssdp receive failed: {}", err),
+                },
+            }
+        }
+    });
+}
+
+fn bind(ttl: u32) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT).into())?;
+    socket.join_multicast_v4(&MULTICAST, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_ttl_v4(ttl)?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn device_uuid(config: &Config) -> String {
+    let digest = Sha256::digest(format!(
+        "manatan-ssdp:{}:{}",
+        config.instance_name, config.port
+    ));
+    let hex = digest
+        .iter()
+        .take(16)
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn targets(uuid: &str) -> [(String, String); 3] {
+    [
+        (
+            "upnp:rootdevice".to_string(),
+            format!("uuid:{uuid}::upnp:rootdevice"),
+        ),
+        (format!("uuid:{uuid}"), format!("uuid:{uuid}")),
+        (
+            DEVICE_TYPE.to_string(),
+            format!("uuid:{uuid}::{DEVICE_TYPE}"),
+        ),
+    ]
+}
+
+fn server_header() -> String {
+    format!(
+        "{}/1.0 UPnP/1.1 Manatan/{}",
+        std::env::consts::OS,
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+fn location(state: &AppState, peer: SocketAddr) -> Option<String> {
+    let host = match state.config.host.parse::<IpAddr>() {
+        Ok(ip) if !ip.is_unspecified() => ip,
+        _ => local_ip(peer)?,
+    };
+    let scheme = if state.config.tls_enabled() {
+        "https"
+    } else {
+        "http"
+    };
+    Some(format!(
+        "{scheme}://{host}:{}{}{DESCRIPTION_PATH}",
+        state.config.port, state.config.base_path
+    ))
+}
+
+// Connecting a UDP socket picks the interface the OS would route `peer` through.
+fn local_ip(peer: SocketAddr) -> Option<IpAddr> {
+    let probe = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    probe.connect(peer).ok()?;
+    probe.local_addr().ok().map(|addr| addr.ip())
+}
+
+async fn announce(socket: &UdpSocket, state: &AppState, uuid: &str) {
+    let group = SocketAddr::V4(SocketAddrV4::new(MULTICAST, PORT));
+    let Some(location) = location(state, group) else {
+        return;
+    };
+    for (nt, usn) in targets(uuid) {
+        let message = format!(
+            "NOTIFY * HTTP/1.1\r\n\
+             HOST: {MULTICAST}:{PORT}\r\n\
+             CACHE-CONTROL: max-age={}\r\n\
+             LOCATION: {location}\r\n\
+             NT: {nt}\r\n\
+             NTS: ssdp:alive\r\n\
+             SERVER: {}\r\n\
+             USN: {usn}\r\n\r\n",
+            state.config.ssdp.max_age_seconds,
+            server_header(),
+        );
+        if let Err(err) = socket.send_to(message.as_bytes(), group).await {
+            warn!("ssdp announce failed: {}", err);
+            return;
+        }
+    }
+}
+
+fn parse_search(packet: &[u8]) -> Option<(String, u64)> {
+    let text = std::str::from_utf8(packet).ok()?;
+    let mut lines = text.split("\r\n");
+    if !lines.next()?.starts_with("M-SEARCH * HTTP/1.1") {
+        return None;
+    }
+    let mut st = None;
+    let mut mx = 1;
+    let mut discover = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_uppercase().as_str() {
+            "ST" => st = Some(value.to_string()),
+            "MX" => mx = value.parse().unwrap_or(1),
+            "MAN" => discover = value.trim_matches('"') == "ssdp:discover",
+            _ => {}
+        }
+    }
+    if !discover {
+        return None;
+    }
+    Some((st?, mx))
+}
+
+async fn respond(socket: &UdpSocket, state: &AppState, uuid: &str, st: &str, peer: SocketAddr) {
+    let Some(location) = location(state, peer) else {
+        return;
+    };
+    let date = chrono::Utc::now()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    for (target, usn) in targets(uuid) {
+        if st != "ssdp:all" && st != target {
+            continue;
+        }
+        let message = format!(
+            "HTTP/1.1 200 OK\r\n\
+             CACHE-CONTROL: max-age={}\r\n\
+             DATE: {date}\r\n\
+             EXT:\r\n\
+             LOCATION: {location}\r\n\
+             SERVER: {}\r\n\
+             ST: {target}\r\n\
+             USN: {usn}\r\n\r\n",
+            state.config.ssdp.max_age_seconds,
+            server_header(),
+        );
+        if let Err(err) = socket.send_to(message.as_bytes(), peer).await {
+            warn!("ssdp reply to {} failed: {}", peer, err);
+            return;
+        }
+    }
+}
+
+async fn description(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let xml = format!(
+        "<?xml version=\"1.0\"?>\n\
+         <root xmlns=\"urn:schemas-upnp-org:device-1-0\">\n\
+         <specVersion><major>1</major><minor>0</minor></specVersion>\n\
+         <device>\n\
+         <deviceType>{DEVICE_TYPE}</deviceType>\n\
+         <friendlyName>{}</friendlyName>\n\
+         <manufacturer>Manatan</manufacturer>\n\
+         <modelName>Manatan Server</modelName>\n\
+         <modelNumber>{}</modelNumber>\n\
+         <UDN>uuid:{}</UDN>\n\
+         <presentationURL>{}/</presentationURL>\n\
+         </device>\n\
+         </root>\n",
+        escape(&state.config.instance_name),
+        env!("CARGO_PKG_VERSION"),
+        device_uuid(&state.config),
+        escape(&state.external_base_url(&headers)),
+    );
+    ([(header::CONTENT_TYPE, "text/xml; charset=\"utf-8\"")], xml).into_response()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::app::AppState;
+use crate::config::StatsConfig;
+use crate::store::{load_json, save_json};
+use crate::unix_now;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+struct Aggregate {
+    seconds: u64,
+    sessions: u32,
+    updates: u32,
+}
+
+impl Aggregate {
+    fn merge(&mut self, other: &Aggregate) {
+        self.seconds += other.seconds;
+        self.sessions += other.sessions;
+        self.updates += other.updates;
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct StatsData {
+    days: BTreeMap<String, BTreeMap<String, Aggregate>>,
+}
+
+struct Session {
+    series: String,
+    last: u64,
+}
+
+struct Inner {
+    data: StatsData,
+    sessions: HashMap<String, Session>,
+    dirty: bool,
+}
+
+pub(crate) struct ReadingStats {
+    config: StatsConfig,
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+impl ReadingStats {
+    pub(crate) fn new(config: StatsConfig, data_path: &str) -> Self {
+        let path = PathBuf::from(data_path).join("reading-stats.json");
+        let data = if config.enabled {
+            load_json(&path)
+        } else {
+            StatsData::default()
+        };
+        Self {
+            config,
+            path,
+            inner: Mutex::new(Inner {
+                data,
+                sessions: HashMap::new(),
+                dirty: false,
+            }),
+        }
+    }
+
+    pub(crate) fn record(&self, client: &str, series: String, now: u64) {
+        if !self.config.enabled {
+            return;
+        }
+        let day = day_of(now);
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let continued = inner.sessions.get(client).and_then(|session| {
+            let gap = now.saturating_sub(session.last);
+            (session.series == series && gap <= self.config.session_gap_seconds).then_some(gap)
+        });
+
+        let aggregate = inner
+            .data
+            .days
+            .entry(day)
+            .or_default()
+            .entry(series.clone())
+            .or_default();
+        aggregate.updates += 1;
+        match continued {
+            Some(gap) => aggregate.seconds += gap,
+            None => aggregate.sessions += 1,
+        }
+        inner
+            .sessions
+            .insert(client.to_string(), Session { series, last: now });
+        inner.dirty = true;
+    }
+
+    fn flush(&self) {
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        if !inner.dirty {
+            return;
+        }
+        let cutoff = unix_now().saturating_sub(self.config.session_gap_seconds);
+        inner.sessions.retain(|_, session| session.last >= cutoff);
+        if let Err(err) = save_json(&self.path, &inner.data) {
+            warn!(
+                "failed to persist reading stats to {}: {}",
+                self.path.display(),
+                err
+            );
+            return;
+        }
+        inner.dirty = false;
+    }
+
+    fn report(&self, days: u32, series: Option<&str>) -> Value {
+        let inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let today = Local::now().date_naive();
+        let since = today - chrono::Days::new(u64::from(days.saturating_sub(1)));
+
+        let mut per_day = Vec::new();
+        let mut per_series: BTreeMap<&str, (Aggregate, &str)> = BTreeMap::new();
+        let mut totals = Aggregate::default();
+        for (day, entries) in &inner.data.days {
+            let Ok(date) = NaiveDate::parse_from_str(day, "%Y-%m-%d") else {
+                continue;
+            };
+            if date < since {
+                continue;
+            }
+            let mut day_total = Aggregate::default();
+            for (key, aggregate) in entries {
+                if series.is_some_and(|wanted| wanted != key) {
+                    continue;
+                }
+                day_total.merge(aggregate);
+                let entry = per_series
+                    .entry(key.as_str())
+                    .or_insert((Aggregate::default(), day.as_str()));
+                entry.0.merge(aggregate);
+                entry.1 = day.as_str();
+            }
+            if day_total.updates > 0 {
+                totals.merge(&day_total);
+                per_day.push(json!({
+                    "date": day,
+                    "seconds": day_total.seconds,
+                    "sessions": day_total.sessions,
+                    "updates": day_total.updates,
+                }));
+            }
+        }
+
+        let series_list = per_series
+            .into_iter()
+            .map(|(key, (aggregate, last_read))| {
+                json!({
+                    "series": key,
+                    "seconds": aggregate.seconds,
+                    "sessions": aggregate.sessions,
+                    "updates": aggregate.updates,
+                    "last_read": last_read,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let (current, longest) = streaks(&inner.data, today, series);
+        json!({
+            "since": since.format("%Y-%m-%d").to_string(),
+            "totals": {
+                "seconds": totals.seconds,
+                "sessions": totals.sessions,
+                "updates": totals.updates,
+            },
+            "days": per_day,
+            "series": series_list,
+            "streak": { "current": current, "longest": longest },
+        })
+    }
+}
+
+fn streaks(data: &StatsData, today: NaiveDate, series: Option<&str>) -> (u32, u32) {
+    let active = data
+        .days
+        .iter()
+        .filter(|(_, entries)| match series {
+            Some(wanted) => entries.contains_key(wanted),
+            None => !entries.is_empty(),
+        })
+        .filter_map(|(day, _)| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok())
+        .collect::<Vec<_>>();
+
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for date in &active {
+        run = match previous {
+            Some(prev) if prev.succ_opt() == Some(*date) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(*date);
+    }
+
+    let current = match active.last() {
+        Some(last) if *last == today || last.succ_opt() == Some(today) => run,
+        _ => 0,
+    };
+    (current, longest)
+}
+
+fn day_of(unix: u64) -> String {
+    Local
+        .timestamp_opt(unix as i64, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+pub(crate) fn progress_series(method: &axum::http::Method, path: &str) -> Option<String> {
+    if !matches!(method.as_str(), "PATCH" | "PUT" | "POST") {
+        return None;
+    }
+    let segments = path
+        .strip_prefix("/api/v1/")?
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    match segments.as_slice() {
+        ["manga", id, "chapter", _index, ..] | ["anime", id, "episode", _index, ..]
+            if id.bytes().all(|byte| byte.is_ascii_digit()) =>
+        {
+            Some(format!("{}:{}", segments[0], id))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn spawn_flusher(stats: &Arc<ReadingStats>) {
+    if !stats.config.enabled {
+        return;
+    }
+    let stats = Arc::downgrade(stats);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let Some(stats) = stats.upgrade() else {
+                break;
+            };
+            let _ = tokio::task::spawn_blocking(move || stats.flush()).await;
+        }
+    });
+}
+
+#[derive(Deserialize)]
+struct ReportQuery {
+    days: Option<u32>,
+    series: Option<String>,
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/stats/reading", get(reading))
+}
+
+async fn reading(State(state): State<AppState>, Query(query): Query<ReportQuery>) -> Json<Value> {
+    let days = query.days.unwrap_or(30).clamp(1, 3650);
+    Json(state.stats.report(days, query.series.as_deref()))
+}
@@ -0,0 +1,391 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::{self, ConfigUpdate};
+use crate::jobs::{JobContext, JobResult};
+use crate::Error;
+
+const JOB_KIND: &str = "migrate_data";
+const CHUNK_BYTES: usize = 256 * 1024;
+
+// A data root the backend reads by absolute path, and where it goes under the target.
+struct Root {
+    name: &'static str,
+    key: &'static str,
+    from: PathBuf,
+    to: PathBuf,
+    files: Vec<(PathBuf, u64)>,
+}
+
+fn migration_error(context: &str, err: impl std::fmt::Display) -> Error {
+    Error::Migration(format!("{context}: {err}"))
+}
+
+fn invalid(name: &str, reason: String) -> Error {
+    Error::InvalidArgument {
+        name: name.to_string(),
+        reason,
+    }
+}
+
+// Copies the downloads and local library roots under `to` (as `downloads`,
+// `local-manga` and `local-anime`), verifies every file by size and SHA-256, points
+// the backend at the copies and writes the new paths to the config file. The
+// originals stay in place unless `remove_source` is set.
+pub fn migrate_data(state: &AppState, to: &Path, remove_source: bool) -> Result<Value, Error> {
+    migrate(state, to, remove_source, &|_, _| {}, &|| false)
+}
+
+fn migrate(
+    state: &AppState,
+    to: &Path,
+    remove_source: bool,
+    progress: &dyn Fn(f64, &str),
+    cancelled: &dyn Fn() -> bool,
+) -> Result<Value, Error> {
+    let roots = plan(state, to)?;
+    let total = roots
+        .iter()
+        .flat_map(|root| root.files.iter().map(|(_, size)| size))
+        .sum::<u64>();
+
+    let pause = state.backend.pause_writes()?;
+    let copied = copy_roots(&roots, total, progress, cancelled)
+        .and_then(|()| verify_roots(&roots, total, progress, cancelled))
+        .and_then(|()| {
+            let path = |name: &str| {
+                roots
+                    .iter()
+                    .find(|root| root.name == name)
+                    .map(|root| root.to.to_string_lossy().into_owned())
+            };
+            state
+                .backend
+                .apply(&ConfigUpdate {
+                    downloads_path: path("downloads"),
+                    local_manga_path: path("local-manga"),
+                    local_anime_path: path("local-anime"),
+                    ..ConfigUpdate::default()
+                })
+                .map(|_| ())
+        });
+    drop(pause);
+    if let Err(err) = copied {
+        for root in &roots {
+            let _ = std::fs::remove_dir_all(&root.to);
+        }
+        return Err(err);
+    }
+    info!("moved data roots to {}", to.display());
+
+    let keys = roots
+        .iter()
+        .map(|root| (root.key, root.to.to_string_lossy().into_owned()))
+        .collect::<Vec<_>>();
+    let config_file = state.config.config_file.as_deref().map(|path| {
+        let values = keys
+            .iter()
+            .map(|(key, value)| (*key, value.as_str()))
+            .collect::<Vec<_>>();
+        match config::set_file_keys(path, &values) {
+            Ok(()) => json!({ "path": path.display().to_string(), "updated": true }),
+            Err(err) => {
+                warn!("could not update {}: {}", path.display(), err);
+                json!({
+                    "path": path.display().to_string(),
+                    "updated": false,
+                    "error": err.to_string(),
+                })
+            }
+        }
+    });
+    // Set variables win over the file, so these have to be changed by hand.
+    let env = keys
+        .iter()
+        .map(|(key, value)| (format!("MANATAN_{}", key.to_uppercase()), value))
+        .filter(|(name, _)| std::env::var_os(name).is_some())
+        .map(|(name, value)| (name, Value::from(value.as_str())))
+        .collect::<serde_json::Map<_, _>>();
+
+    if remove_source {
+        for root in &roots {
+            if let Err(err) = remove_dir(&root.from) {
+                warn!("could not remove {}: {}", root.from.display(), err);
+            }
+        }
+    }
+    Ok(json!({
+        "to": to.display().to_string(),
+        "roots": roots.iter().map(|root| json!({
+            "name": root.name,
+            "from": root.from.display().to_string(),
+            "to": root.to.display().to_string(),
+            "files": root.files.len(),
+            "bytes": root.files.iter().map(|(_, size)| size).sum::<u64>(),
+        })).collect::<Vec<_>>(),
+        "config_file": config_file,
+        "env": env,
+        "removed_source": remove_source,
+    }))
+}
+
+// Checks every root before anything is copied: targets must be new or empty and may
+// not overlap their source.
+fn plan(state: &AppState, to: &Path) -> Result<Vec<Root>, Error> {
+    if !to.is_absolute() {
+        return Err(invalid("to", "must be an absolute path".to_string()));
+    }
+    let settings = state.backend.settings();
+    let mut roots = Vec::new();
+    for (name, key, current) in [
+        ("downloads", "downloads_path", settings.downloads_path),
+        ("local-manga", "local_manga_path", settings.local_manga_path),
+        ("local-anime", "local_anime_path", settings.local_anime_path),
+    ] {
+        let from = PathBuf::from(current.unwrap_or_default());
+        let from = from.canonicalize().unwrap_or(from);
+        let target = to.join(name);
+        let resolved = target.canonicalize().unwrap_or_else(|_| target.clone());
+        if resolved.starts_with(&from) || from.starts_with(&resolved) {
+            return Err(invalid(
+                "to",
+                format!("overlaps {} at {}", key, from.display()),
+            ));
+        }
+        let occupied = std::fs::read_dir(&target)
+            .map(|mut entries| entries.next().is_some())
+            .unwrap_or(false);
+        if occupied || target.is_file() {
+            return Err(invalid(
+                "to",
+                format!("{} already exists and is not empty", target.display()),
+            ));
+        }
+        let mut files = Vec::new();
+        if from.is_dir() {
+            list_files(&from, Path::new(""), &mut files)
+                .map_err(|err| Error::io(format!("read {}", from.display()), err))?;
+        }
+        roots.push(Root {
+            name,
+            key,
+            from,
+            to: target,
+            files,
+        });
+    }
+    Ok(roots)
+}
+
+// Paths relative to the root, following symlinks the way the backend does.
+fn list_files(
+    root: &Path,
+    relative: &Path,
+    files: &mut Vec<(PathBuf, u64)>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        let meta = std::fs::metadata(entry.path())?;
+        if meta.is_dir() {
+            list_files(root, &path, files)?;
+        } else {
+            files.push((path, meta.len()));
+        }
+    }
+    Ok(())
+}
+
+fn copy_roots(
+    roots: &[Root],
+    total: u64,
+    progress: &dyn Fn(f64, &str),
+    cancelled: &dyn Fn() -> bool,
+) -> Result<(), Error> {
+    let mut done = 0u64;
+    for root in roots {
+        std::fs::create_dir_all(&root.to)
+            .map_err(|err| Error::io(format!("create {}", root.to.display()), err))?;
+        for (relative, size) in &root.files {
+            if cancelled() {
+                return Err(Error::Migration("cancelled".to_string()));
+            }
+            let from = root.from.join(relative);
+            let to = root.to.join(relative);
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|err| Error::io(format!("create {}", parent.display()), err))?;
+            }
+            copy_file(&from, &to)
+                .map_err(|err| Error::io(format!("copy {}", from.display()), err))?;
+            done += size;
+            progress(
+                fraction(done, total) * 0.5,
+                &format!("copied {} of {} bytes", done, total),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn copy_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut source = File::open(from)?;
+    let mut target = File::create(to)?;
+    let mut buf = vec![0u8; CHUNK_BYTES];
+    loop {
+        let read = source.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        target.write_all(&buf[..read])?;
+    }
+    target.sync_all()
+}
+
+// Reads both sides back rather than trusting the copy, so a failing disk shows up
+// before the backend is pointed at it.
+fn verify_roots(
+    roots: &[Root],
+    total: u64,
+    progress: &dyn Fn(f64, &str),
+    cancelled: &dyn Fn() -> bool,
+) -> Result<(), Error> {
+    let mut done = 0u64;
+    for root in roots {
+        for (relative, size) in &root.files {
+            if cancelled() {
+                return Err(Error::Migration("cancelled".to_string()));
+            }
+            let from = root.from.join(relative);
+            let to = root.to.join(relative);
+            let (source_size, source_hash) =
+                digest(&from).map_err(|err| Error::io(format!("read {}", from.display()), err))?;
+            let (copy_size, copy_hash) =
+                digest(&to).map_err(|err| Error::io(format!("read {}", to.display()), err))?;
+            if source_size != copy_size || source_hash != copy_hash {
+                return Err(migration_error(
+                    "verification failed",
+                    format!("{} does not match {}", to.display(), from.display()),
+                ));
+            }
+            done += size;
+            progress(
+                0.5 + fraction(done, total) * 0.5,
+                &format!("verified {} of {} bytes", done, total),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn digest(path: &Path) -> std::io::Result<(u64, [u8; 32])> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; CHUNK_BYTES];
+    let mut size = 0u64;
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+        size += read as u64;
+    }
+    Ok((size, hasher.finalize().into()))
+}
+
+fn fraction(done: u64, total: u64) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        done as f64 / total as f64
+    }
+}
+
+fn remove_dir(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_dir_all(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) async fn run_job(state: AppState, ctx: JobContext) -> JobResult {
+    let Some(to) = ctx.payload.get("to").and_then(Value::as_str) else {
+        return Err("payload needs a `to` path".to_string());
+    };
+    let to = PathBuf::from(to);
+    let remove_source = ctx
+        .payload
+        .get("remove_source")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    tokio::task::spawn_blocking(move || {
+        // One progress row per percent; a library has far more files than that.
+        let reported = std::sync::atomic::AtomicU64::new(0);
+        let progress = |value: f64, message: &str| {
+            let percent = (value * 100.0) as u64;
+            if reported.fetch_max(percent, std::sync::atomic::Ordering::Relaxed) < percent {
+                ctx.progress(value, message);
+            }
+        };
+        migrate(&state, &to, remove_source, &progress, &|| {
+            ctx.is_cancelled()
+        })
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/admin/storage/migrate", post(start))
+}
+
+#[derive(Deserialize)]
+struct Migrate {
+    to: String,
+    #[serde(default)]
+    remove_source: bool,
+}
+
+async fn start(State(state): State<AppState>, Json(body): Json<Migrate>) -> Response {
+    if !state.config.jobs.enabled {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "the job queue is disabled (MANATAN_JOBS_ENABLED)",
+        )
+            .into_response();
+    }
+    let checked = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let to = PathBuf::from(&body.to);
+        move || plan(&state, &to).map(|_| ())
+    })
+    .await;
+    if let Ok(Err(err)) = checked {
+        return (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response();
+    }
+    let payload = json!({ "to": body.to, "remove_source": body.remove_source });
+    match state.jobs.enqueue(JOB_KIND, payload, Some(1)) {
+        Ok(id) => (
+            StatusCode::ACCEPTED,
+            Json(state.jobs.get(id).unwrap_or_else(|| json!({ "id": id }))),
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    }
+}
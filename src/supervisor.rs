@@ -0,0 +1,206 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, routing::get, Json, Router};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+use crate::app::AppState;
+use crate::config::SupervisorConfig;
+use crate::embedded::{EmbeddedServer, RestartOutcome};
+use crate::metrics::Metrics;
+use crate::{unix_now, Error};
+
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Lifecycle {
+    Ready,
+    Restarting,
+    Failed,
+}
+
+#[derive(Clone, Serialize)]
+struct Status {
+    lifecycle: Lifecycle,
+    since: u64,
+    consecutive_refusals: u32,
+    restarts: u32,
+    last_error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BackendUnreachable;
+
+pub(crate) struct Supervisor {
+    config: SupervisorConfig,
+    status: Mutex<Status>,
+    wake: Notify,
+}
+
+impl Supervisor {
+    pub(crate) fn new(config: SupervisorConfig) -> Self {
+        Self {
+            config,
+            status: Mutex::new(Status {
+                lifecycle: Lifecycle::Ready,
+                since: unix_now(),
+                consecutive_refusals: 0,
+                restarts: 0,
+                last_error: None,
+            }),
+            wake: Notify::new(),
+        }
+    }
+
+    pub(crate) fn lifecycle(&self) -> Lifecycle {
+        self.lock().lifecycle
+    }
+
+    pub(crate) fn record_ok(&self) {
+        self.lock().consecutive_refusals = 0;
+    }
+
+    pub(crate) fn record_refusal(&self) {
+        let mut status = self.lock();
+        status.consecutive_refusals += 1;
+        if self.config.enabled
+            && status.lifecycle != Lifecycle::Restarting
+            && status.consecutive_refusals >= self.config.refusal_threshold
+        {
+            drop(status);
+            self.wake.notify_one();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Status> {
+        self.status.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn set(&self, lifecycle: Lifecycle, error: Option<String>) {
+        let mut status = self.lock();
+        if status.lifecycle != lifecycle {
+            info!(
+                "supervisor: backend {:?} -> {:?}",
+                status.lifecycle, lifecycle
+            );
+            status.lifecycle = lifecycle;
+            status.since = unix_now();
+        }
+        if lifecycle == Lifecycle::Ready {
+            status.consecutive_refusals = 0;
+        }
+        if error.is_some() {
+            status.last_error = error;
+        }
+    }
+}
+
+pub(crate) async fn wait_ready(
+    client: &Client,
+    backend_url: &str,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let deadline = Instant::now() + timeout;
+    let url = format!("{backend_url}/health");
+    loop {
+        let last_error = match client.get(&url).timeout(PROBE_TIMEOUT).send().await {
+            Ok(resp) if resp.status().is_success() => return Ok(()),
+            Ok(resp) => format!("status {}", resp.status()),
+            Err(err) => err.to_string(),
+        };
+        if Instant::now() >= deadline {
+            return Err(Error::BackendUnavailable(format!(
+                "backend not ready after {}s: {last_error}",
+                timeout.as_secs()
+            )));
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+}
+
+pub(crate) fn spawn(
+    supervisor: Arc<Supervisor>,
+    server: Weak<EmbeddedServer>,
+    client: Client,
+    backend_url: String,
+    metrics: Arc<Metrics>,
+) {
+    if !supervisor.config.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            supervisor.wake.notified().await;
+            let Some(backend) = server.upgrade() else {
+                break;
+            };
+            warn!(
+                "supervisor: backend refused {} consecutive connections, restarting",
+                supervisor.lock().consecutive_refusals
+            );
+            supervisor.set(Lifecycle::Restarting, None);
+            let restarted = tokio::task::spawn_blocking(move || backend.recover())
+                .await
+                .map_err(|err| err.to_string())
+                .and_then(|result| result.map_err(|err| err.to_string()));
+            match restarted {
+                Err(err) => {
+                    error!("supervisor: restart failed: {}", err);
+                    supervisor.set(Lifecycle::Failed, Some(err));
+                    continue;
+                }
+                Ok(RestartOutcome::Restarted) => {
+                    metrics.inc(
+                        "manatan_supervisor_restarts_total",
+                        "Backend restarts triggered by the supervisor.",
+                        &[],
+                    );
+                    supervisor.lock().restarts += 1;
+                }
+                // Someone else's restart is waited on like our own.
+                Ok(RestartOutcome::InProgress) => {
+                    info!("supervisor: not restarting, {}", RestartOutcome::InProgress)
+                }
+                Ok(outcome @ RestartOutcome::BackingOff(_)) => {
+                    warn!("supervisor: not restarting, {}", outcome);
+                    supervisor.set(Lifecycle::Failed, Some(format!("restart {outcome}")));
+                    continue;
+                }
+            }
+            let timeout = Duration::from_secs(supervisor.config.ready_timeout_seconds);
+            match wait_ready(&client, &backend_url, timeout).await {
+                Ok(()) => supervisor.set(Lifecycle::Ready, None),
+                Err(err) => {
+                    error!("supervisor: {}", err);
+                    supervisor.set(Lifecycle::Failed, Some(err.to_string()));
+                }
+            }
+        }
+    });
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/proxy/status", get(status))
+}
+
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    let status = state.supervisor.lock().clone();
+    Json(json!({
+        "lifecycle": status.lifecycle,
+        "since": status.since,
+        "consecutive_refusals": status.consecutive_refusals,
+        "supervisor_restarts": status.restarts,
+        "last_error": status.last_error,
+        "backend": {
+            "url": state.backend_url,
+            "port": state.backend.port(),
+            "restarts": state.backend.restarts(),
+        },
+    }))
+}
@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, ConfigError};
+
+const CONF_FILE: &str = "server.conf";
+
+// What an existing Suwayomi (formerly Tachidesk) install translates to. `values` are
+// config file keys; `skipped` lists the `server.conf` settings with no Manatan
+// equivalent, for the user to look over.
+#[derive(Clone, Debug)]
+pub struct Migration {
+    pub data_dir: PathBuf,
+    pub values: BTreeMap<String, toml::Value>,
+    pub skipped: Vec<String>,
+}
+
+// Where Suwayomi keeps its data when `rootDir` was never changed.
+pub fn default_data_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        PathBuf::from(std::env::var_os("LOCALAPPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support")
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".local/share")))?
+    };
+    Some(base.join("Tachidesk"))
+}
+
+// Reads `{data_dir}/server.conf` and maps it onto Manatan settings. The data directory
+// itself becomes `migrate_path`, so the backend imports the library database, and the
+// downloads and local source folders are used where they are rather than copied.
+pub fn import(data_dir: &Path) -> Result<Migration, ConfigError> {
+    let path = data_dir.join(CONF_FILE);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        // A server that never had its settings changed may not have written the file.
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && data_dir.is_dir() => {
+            String::new()
+        }
+        Err(source) => return Err(ConfigError::Io { path, source }),
+    };
+    let settings = parse_conf(&text).map_err(|message| ConfigError::Parse {
+        path: path.clone(),
+        message,
+    })?;
+
+    let mut migration = Migration {
+        data_dir: data_dir.to_path_buf(),
+        values: BTreeMap::new(),
+        skipped: Vec::new(),
+    };
+    let folder = |key: &str, default: &str| {
+        settings
+            .get(key)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| data_dir.join(default))
+            .display()
+            .to_string()
+    };
+    migration.set("migrate_path", data_dir.display().to_string());
+    migration.set(
+        "downloads_path",
+        folder("server.downloadsPath", "downloads"),
+    );
+    migration.set(
+        "local_manga_path",
+        folder("server.localSourcePath", "local"),
+    );
+
+    let auth = settings
+        .get("server.authMode")
+        .map(|mode| mode != "none")
+        .or_else(|| {
+            settings
+                .get("server.basicAuthEnabled")
+                .map(|on| on == "true")
+        })
+        .unwrap_or(false);
+    for (key, value) in &settings {
+        let name = key.strip_prefix("server.").unwrap_or(key);
+        match name {
+            "ip" => migration.set("host", value.clone()),
+            "port" => match value.parse::<i64>() {
+                Ok(port) => {
+                    migration
+                        .values
+                        .insert("port".to_string(), toml::Value::Integer(port));
+                }
+                Err(_) => migration.skipped.push(key.clone()),
+            },
+            "debugLogsEnabled" if value == "true" => migration.set("log", "debug".to_string()),
+            "basicAuthUsername" | "authUsername" if auth && !value.is_empty() => {
+                migration.set("auth_user", value.clone())
+            }
+            "basicAuthPassword" | "authPassword" if auth && !value.is_empty() => {
+                migration.set("auth_password", value.clone())
+            }
+            "downloadsPath" | "localSourcePath" | "authMode" | "basicAuthEnabled"
+            | "debugLogsEnabled" | "basicAuthUsername" | "authUsername" | "basicAuthPassword"
+            | "authPassword" => {}
+            _ => migration.skipped.push(key.clone()),
+        }
+    }
+    Ok(migration)
+}
+
+impl Migration {
+    fn set(&mut self, key: &str, value: String) {
+        self.values
+            .insert(key.to_string(), toml::Value::String(value));
+    }
+
+    // The settings as a Manatan config file, with the skipped ones noted at the end.
+    pub fn to_toml(&self) -> String {
+        let mut text = format!(
+            "# Imported from the Suwayomi install at {}\n",
+            self.data_dir.display()
+        );
+        for (key, value) in &self.values {
+            text.push_str(&format!("{key} = {value}\n"));
+        }
+        if !self.skipped.is_empty() {
+            text.push_str("\n# Not carried over from server.conf:\n");
+            for key in &self.skipped {
+                text.push_str(&format!("#   {key}\n"));
+            }
+        }
+        text
+    }
+
+    // Writes `to_toml` to `path`, refusing to replace a config that is already there.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        file.write_all(self.to_toml().as_bytes())
+    }
+
+    // The imported settings with environment variables and defaults layered over them.
+    pub fn config(&self) -> Result<Config, ConfigError> {
+        Config::builder().suwayomi(&self.data_dir).build()
+    }
+
+    pub(crate) fn vars(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        self.values.iter().map(|(key, value)| {
+            let value = match value {
+                toml::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            (format!("MANATAN_{}", key.to_uppercase()), value)
+        })
+    }
+}
+
+// The subset of HOCON that Suwayomi writes: `key = value` lines with dotted keys,
+// `server { .. }` blocks, quoted strings, `#` and `//` comments, and arrays that may
+// span lines. Arrays come back comma-joined.
+fn parse_conf(text: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut values = BTreeMap::new();
+    let mut prefix: Vec<String> = Vec::new();
+    let mut pending: Option<(String, String)> = None;
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim().to_string();
+        if let Some((key, mut value)) = pending.take() {
+            value.push(' ');
+            value.push_str(&line);
+            if value.contains(']') {
+                values.insert(key, array(&value));
+            } else {
+                pending = Some((key, value));
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        if line == "}" {
+            prefix
+                .pop()
+                .ok_or_else(|| format!("line {}: unmatched }}", number + 1))?;
+            continue;
+        }
+        if let Some(block) = line.strip_suffix('{') {
+            let block = block.trim().trim_end_matches(['=', ':']).trim();
+            prefix.push(unquote(block));
+            continue;
+        }
+        let Some(split) = line.find(['=', ':']) else {
+            return Err(format!("line {}: expected key = value", number + 1));
+        };
+        let name = unquote(line[..split].trim());
+        let key = prefix
+            .iter()
+            .cloned()
+            .chain(std::iter::once(name))
+            .collect::<Vec<_>>()
+            .join(".");
+        let value = line[split + 1..].trim().to_string();
+        if value.starts_with('[') && !value.contains(']') {
+            pending = Some((key, value));
+        } else if value.starts_with('[') {
+            values.insert(key, array(&value));
+        } else {
+            values.insert(key, unquote(&value));
+        }
+    }
+    if pending.is_some() {
+        return Err("unterminated array".to_string());
+    }
+    Ok(values)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            '/' if !quoted && line[index..].starts_with("//") => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim().trim_end_matches(',').trim();
+    match value
+        .strip_prefix('"')
+        .and_then(|inner| inner.strip_suffix('"'))
+    {
+        Some(inner) => inner
+            .replace("\\\"", "\"")
+            .replace("\\n", "\n")
+            .replace("\\\\", "\\"),
+        None => value.to_string(),
+    }
+}
+
+fn array(value: &str) -> String {
+    value
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(unquote)
+        .filter(|item| !item.is_empty())
+        .collect::<Vec<_>>()
+        .join(",")
+}
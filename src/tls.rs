@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{extract::ConnectInfo, Extension, Router};
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{self, pki_types::CertificateDer};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, warn};
+
+use crate::config::Config;
+use crate::Error;
+
+pub(crate) fn acceptor(config: &Config) -> Result<Option<TlsAcceptor>, Error> {
+    let (cert_path, key_path) = match (
+        config.tls_cert_path.as_deref(),
+        config.tls_key_path.as_deref(),
+    ) {
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        (None, None) => return Ok(None),
+        _ => {
+            return Err(Error::Tls(
+                "tls_cert_path and tls_key_path must be set together".to_string(),
+            ))
+        }
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+        .map_err(|err| Error::io(format!("failed to read {cert_path}"), err))?;
+    if certs.is_empty() {
+        return Err(Error::Tls(format!("no certificates found in {cert_path}")));
+    }
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)
+        .map_err(|err| Error::io(format!("failed to read {key_path}"), err))?
+        .ok_or_else(|| Error::Tls(format!("no private key found in {key_path}")))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut server_config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| Error::Tls(err.to_string()))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| Error::Tls(err.to_string()))?;
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
+}
+
+fn open(path: &str) -> Result<BufReader<File>, Error> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| Error::io(format!("failed to open {path}"), err))
+}
+
+pub(crate) async fn serve(listener: TcpListener, acceptor: TlsAcceptor, router: Router) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                warn!("accept failed: {}", err);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(router.clone().layer(Extension(ConnectInfo(peer))));
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("tls handshake with {} failed: {}", peer, err);
+                    return;
+                }
+            };
+            if let Err(err) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades()
+                .await
+            {
+                debug!("connection from {} closed: {}", peer, err);
+            }
+        });
+    }
+}
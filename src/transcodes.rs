@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::app::AppState;
+use crate::config::TranscodeConfig;
+use crate::keys::random_id;
+use crate::unix_now;
+
+// The backend runs ffmpeg for episode streams and HLS playlists; each client watching
+// an episode is one session, however many segment requests it takes.
+#[derive(Clone, Serialize)]
+struct Session {
+    id: String,
+    client: String,
+    anime_id: String,
+    episode: String,
+    path: String,
+    codec: Option<String>,
+    started_at: u64,
+    last_seen_at: u64,
+    requests: u64,
+    bytes: u64,
+    // Where the current stream is in the file, when the backend reports a length.
+    position_bytes: Option<u64>,
+    total_bytes: Option<u64>,
+    progress: Option<f64>,
+    streams: usize,
+    #[serde(skip)]
+    kill: Arc<watch::Sender<bool>>,
+}
+
+pub(crate) struct Transcodes {
+    config: TranscodeConfig,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+pub(crate) struct Admitted {
+    key: String,
+    kill: watch::Receiver<bool>,
+}
+
+// Keeps the session's stream count and progress current; dropped with the body.
+struct Streaming {
+    transcodes: Arc<Transcodes>,
+    key: String,
+    start: u64,
+}
+
+impl Drop for Streaming {
+    fn drop(&mut self) {
+        let mut sessions = self.transcodes.lock();
+        if let Some(session) = sessions.get_mut(&self.key) {
+            session.streams = session.streams.saturating_sub(1);
+            session.last_seen_at = unix_now();
+        }
+    }
+}
+
+fn episode(path: &str) -> Option<(&str, &str)> {
+    let segments = path
+        .strip_prefix("/api/v1/")?
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    match segments.as_slice() {
+        ["anime", id, "episode", index, _, ..] => Some((id, index)),
+        _ => None,
+    }
+}
+
+fn codec(parts: &Parts) -> Option<String> {
+    parts.uri.query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        matches!(key, "codec" | "videoCodec" | "video_codec")
+            .then(|| value.to_string())
+            .filter(|value| !value.is_empty())
+    })
+}
+
+// `bytes start-end/total`, or the whole body from the start.
+fn range(headers: &HeaderMap) -> (u64, Option<u64>) {
+    let value = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    if let Some((start, total)) = value(header::CONTENT_RANGE)
+        .and_then(|range| range.strip_prefix("bytes "))
+        .and_then(|range| range.split_once('/'))
+    {
+        let start = start
+            .split_once('-')
+            .and_then(|(start, _)| start.parse().ok())
+            .unwrap_or(0);
+        return (start, total.parse().ok());
+    }
+    (
+        0,
+        value(header::CONTENT_LENGTH).and_then(|length| length.parse().ok()),
+    )
+}
+
+impl Transcodes {
+    pub(crate) fn new(config: TranscodeConfig) -> Self {
+        Self {
+            config,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn prune(&self, sessions: &mut HashMap<String, Session>, now: u64) {
+        let idle = self.config.idle_seconds;
+        sessions.retain(|_, session| {
+            session.streams > 0 || now.saturating_sub(session.last_seen_at) < idle
+        });
+    }
+
+    // Joins the client's session for the episode or opens one within the limit. Killed
+    // sessions keep refusing until they go idle, so the player does not just reconnect.
+    pub(crate) fn admit(&self, parts: &Parts, client: &str) -> Option<Result<Admitted, Response>> {
+        let (anime_id, episode) = episode(parts.uri.path())?;
+        let key = format!("{client}\n{anime_id}/{episode}");
+        let now = unix_now();
+        let mut sessions = self.lock();
+        self.prune(&mut sessions, now);
+        if let Some(session) = sessions.get_mut(&key) {
+            if *session.kill.borrow() {
+                return Some(Err(
+                    (StatusCode::GONE, "transcode was stopped by an admin").into_response()
+                ));
+            }
+            session.requests += 1;
+            session.streams += 1;
+            session.last_seen_at = now;
+            session.path = parts.uri.path().to_string();
+            if let Some(codec) = codec(parts) {
+                session.codec = Some(codec);
+            }
+            return Some(Ok(Admitted {
+                key,
+                kill: session.kill.subscribe(),
+            }));
+        }
+
+        let max = self.config.max_concurrent;
+        if max > 0 && sessions.len() >= max {
+            return Some(Err(Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(header::RETRY_AFTER, self.config.idle_seconds.to_string())
+                .body(Body::from(format!("{max} transcodes are already running")))
+                .unwrap()));
+        }
+        let (kill, receiver) = watch::channel(false);
+        let session = Session {
+            id: random_id(),
+            client: client.to_string(),
+            anime_id: anime_id.to_string(),
+            episode: episode.to_string(),
+            path: parts.uri.path().to_string(),
+            codec: codec(parts),
+            started_at: now,
+            last_seen_at: now,
+            requests: 1,
+            bytes: 0,
+            position_bytes: None,
+            total_bytes: None,
+            progress: None,
+            streams: 1,
+            kill: Arc::new(kill),
+        };
+        info!(
+            "transcode {} started: anime {} episode {} for {}",
+            session.id, anime_id, episode, client
+        );
+        sessions.insert(key.clone(), session);
+        Some(Ok(Admitted {
+            key,
+            kill: receiver,
+        }))
+    }
+
+    // Counts the body as it goes out and cuts it off when the session is killed, which
+    // drops the backend connection and with it the ffmpeg process behind it.
+    pub(crate) fn track(self: &Arc<Self>, admitted: Admitted, resp: Response) -> Response {
+        let (start, total) = range(resp.headers());
+        {
+            let mut sessions = self.lock();
+            if let Some(session) = sessions.get_mut(&admitted.key) {
+                let content_type = resp
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok());
+                if session.codec.is_none() {
+                    session.codec = content_type
+                        .filter(|value| value.starts_with("video/"))
+                        .map(str::to_string);
+                }
+                session.position_bytes = Some(start);
+                session.total_bytes = total;
+            }
+        }
+        let mut streaming = Streaming {
+            transcodes: self.clone(),
+            key: admitted.key,
+            start,
+        };
+        let mut kill = admitted.kill;
+        let killed = async move {
+            let _ = kill.wait_for(|killed| *killed).await;
+        };
+        let (parts, body) = resp.into_parts();
+        let stream = body
+            .into_data_stream()
+            .map(move |chunk| {
+                // Borrow the whole guard so the closure owns it, not just its fields.
+                let streaming = &mut streaming;
+                if let Ok(chunk) = &chunk {
+                    streaming.record(chunk.len() as u64);
+                }
+                chunk
+            })
+            .take_until(Box::pin(killed));
+        Response::from_parts(parts, Body::from_stream(stream))
+    }
+
+    fn list(&self) -> Vec<Session> {
+        let mut sessions = self.lock();
+        self.prune(&mut sessions, unix_now());
+        let mut list = sessions.values().cloned().collect::<Vec<_>>();
+        list.sort_by_key(|session| session.started_at);
+        list
+    }
+
+    fn kill(&self, id: &str) -> bool {
+        let mut sessions = self.lock();
+        let Some(session) = sessions.values_mut().find(|session| session.id == id) else {
+            return false;
+        };
+        session.kill.send_replace(true);
+        session.last_seen_at = unix_now();
+        info!("transcode {} stopped by an admin", id);
+        true
+    }
+}
+
+impl Streaming {
+    fn record(&mut self, bytes: u64) {
+        let mut sessions = self.transcodes.lock();
+        let Some(session) = sessions.get_mut(&self.key) else {
+            return;
+        };
+        session.bytes += bytes;
+        let position = session.position_bytes.unwrap_or(self.start) + bytes;
+        session.position_bytes = Some(position);
+        session.progress = session
+            .total_bytes
+            .filter(|total| *total > 0)
+            .map(|total| (position as f64 / total as f64).min(1.0));
+    }
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/transcodes", get(list))
+        .route("/admin/transcodes/{id}", delete(kill))
+}
+
+async fn list(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let sessions = state.transcodes.list();
+    Json(serde_json::json!({
+        "max_concurrent": state.transcodes.config.max_concurrent,
+        "sessions": sessions,
+    }))
+}
+
+async fn kill(State(state): State<AppState>, Path(id): Path<String>) -> StatusCode {
+    if state.transcodes.kill(&id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
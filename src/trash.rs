@@ -0,0 +1,442 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::{request::Parts, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::backup::{move_path, remove_path};
+use crate::config::TrashConfig;
+use crate::library;
+use crate::quota::dir_name;
+use crate::reconcile::chapter_keys;
+use crate::unix_now;
+
+const ENTRY: &str = "entry.json";
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Entry {
+    id: String,
+    kind: String,
+    label: String,
+    // Where `paths` were relative to when they were deleted.
+    root: String,
+    paths: Vec<String>,
+    bytes: u64,
+    deleted_at: u64,
+}
+
+// A chapter's download directory, linked into the trash before the backend deletes
+// the chapter so whatever it removes can be kept.
+pub(crate) struct Held {
+    id: String,
+    root: PathBuf,
+    label: String,
+}
+
+// Files deleted through the proxy wait here for `retention_days` before they are gone
+// for good. Each entry is `{dir}/{id}/files/..` laid out as under its root, plus
+// `entry.json` describing it.
+pub(crate) struct Trash {
+    config: TrashConfig,
+    dir: PathBuf,
+    seq: AtomicU64,
+}
+
+impl Trash {
+    pub(crate) fn new(config: TrashConfig, data_path: &str) -> Self {
+        let dir = config
+            .path
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(data_path).join("trash"));
+        let trash = Self {
+            config,
+            dir,
+            seq: AtomicU64::new(0),
+        };
+        // Entries without a description were still being staged when the process died.
+        for id in trash.ids() {
+            if !trash.entry_dir(&id).join(ENTRY).is_file() {
+                let _ = std::fs::remove_dir_all(trash.entry_dir(&id));
+            }
+        }
+        trash
+    }
+
+    fn next_id(&self) -> String {
+        format!(
+            "{}-{}",
+            unix_now(),
+            self.seq.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+
+    fn entry_dir(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    fn files_dir(&self, id: &str) -> PathBuf {
+        self.entry_dir(id).join("files")
+    }
+
+    fn ids(&self) -> Vec<String> {
+        std::fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn read_entry(&self, id: &str) -> Option<Entry> {
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit() || b == b'-') {
+            return None;
+        }
+        let bytes = std::fs::read(self.entry_dir(id).join(ENTRY)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn write_entry(&self, entry: &Entry) -> std::io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(entry)?;
+        std::fs::write(self.entry_dir(&entry.id).join(ENTRY), bytes)
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let mut entries = self
+            .ids()
+            .iter()
+            .filter_map(|id| self.read_entry(id))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+        entries
+    }
+
+    // Moves a file or directory the proxy itself is about to delete into the trash,
+    // or deletes it outright when the trash is off.
+    pub(crate) fn discard(&self, path: &Path, label: &str) -> std::io::Result<()> {
+        if !self.config.enabled {
+            return remove_path(path);
+        }
+        let (Some(root), Some(name)) = (path.parent(), path.file_name()) else {
+            return remove_path(path);
+        };
+        let id = self.next_id();
+        let files = self.files_dir(&id);
+        std::fs::create_dir_all(&files)?;
+        let bytes = tree_size(path);
+        if let Err(err) = move_path(path, &files.join(name)) {
+            let _ = std::fs::remove_dir_all(self.entry_dir(&id));
+            return Err(err);
+        }
+        self.write_entry(&Entry {
+            id,
+            kind: "file".to_string(),
+            label: label.to_string(),
+            root: root.display().to_string(),
+            paths: vec![name.to_string_lossy().into_owned()],
+            bytes,
+            deleted_at: unix_now(),
+        })?;
+        info!("moved {} to the trash", path.display());
+        Ok(())
+    }
+
+    // Links the chapter's download (its folder or `.cbz`) into a staging entry. Hard
+    // links cost nothing on the same disk; elsewhere the files are copied.
+    pub(crate) async fn hold_chapter(
+        &self,
+        state: &AppState,
+        manga_id: i64,
+        index: i64,
+    ) -> Option<Held> {
+        if !self.config.enabled {
+            return None;
+        }
+        let manga = state
+            .backend_json(&format!("/api/v1/manga/{manga_id}"), &Default::default())
+            .await
+            .ok()?;
+        let title = manga.get("title").and_then(Value::as_str)?.to_string();
+        let chapter = library::chapters(state, manga_id)
+            .await
+            .ok()?
+            .into_iter()
+            .find(|chapter| chapter.index == index)?;
+        let keys = chapter_keys(&chapter);
+        let root = PathBuf::from(state.backend.settings().downloads_path.unwrap_or_default());
+        let id = self.next_id();
+        let files = self.files_dir(&id);
+        let held = Held {
+            id,
+            root: root.clone(),
+            label: format!("{title} chapter {index}"),
+        };
+        let linked = tokio::task::spawn_blocking(move || {
+            let wanted = dir_name(&title);
+            let mut linked = false;
+            let Ok(sources) = std::fs::read_dir(root.join("mangas")) else {
+                return false;
+            };
+            for source in sources.flatten() {
+                let Ok(titles) = std::fs::read_dir(source.path()) else {
+                    continue;
+                };
+                for dir in titles.flatten() {
+                    if dir_name(&dir.file_name().to_string_lossy()) != wanted {
+                        continue;
+                    }
+                    let Ok(entries) = std::fs::read_dir(dir.path()) else {
+                        continue;
+                    };
+                    for entry in entries.flatten() {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        if !keys.contains(&dir_name(name.strip_suffix(".cbz").unwrap_or(&name))) {
+                            continue;
+                        }
+                        let Ok(relative) = entry.path().strip_prefix(&root).map(Path::to_path_buf)
+                        else {
+                            continue;
+                        };
+                        match link_tree(&entry.path(), &files.join(relative)) {
+                            Ok(()) => linked = true,
+                            Err(err) => {
+                                warn!("trash could not hold {}: {}", entry.path().display(), err)
+                            }
+                        }
+                    }
+                }
+            }
+            linked
+        })
+        .await
+        .unwrap_or(false);
+        if linked {
+            Some(held)
+        } else {
+            let _ = tokio::fs::remove_dir_all(self.entry_dir(&held.id)).await;
+            None
+        }
+    }
+
+    // Keeps what the delete removed and drops the links to everything still in place.
+    pub(crate) async fn settle(&self, held: Held, deleted: bool) {
+        let entry_dir = self.entry_dir(&held.id);
+        if !deleted {
+            let _ = tokio::fs::remove_dir_all(&entry_dir).await;
+            return;
+        }
+        let files = self.files_dir(&held.id);
+        let root = held.root.clone();
+        let kept = tokio::task::spawn_blocking(move || {
+            let mut kept = Vec::new();
+            let mut bytes = 0u64;
+            let mut staged = Vec::new();
+            list_files(&files, Path::new(""), &mut staged);
+            for (relative, size) in staged {
+                if root.join(&relative).exists() {
+                    let _ = std::fs::remove_file(files.join(&relative));
+                } else {
+                    kept.push(relative.to_string_lossy().into_owned());
+                    bytes += size;
+                }
+            }
+            (kept, bytes)
+        })
+        .await;
+        let Ok((paths, bytes)) = kept else {
+            let _ = tokio::fs::remove_dir_all(&entry_dir).await;
+            return;
+        };
+        if paths.is_empty() {
+            let _ = tokio::fs::remove_dir_all(&entry_dir).await;
+            return;
+        }
+        let entry = Entry {
+            id: held.id,
+            kind: "chapter".to_string(),
+            label: held.label,
+            root: held.root.display().to_string(),
+            paths,
+            bytes,
+            deleted_at: unix_now(),
+        };
+        match self.write_entry(&entry) {
+            Ok(()) => info!("moved {} to the trash", entry.label),
+            Err(err) => {
+                warn!("trash could not keep {}: {}", entry.label, err);
+                let _ = tokio::fs::remove_dir_all(&entry_dir).await;
+            }
+        }
+    }
+
+    // Puts every file back where it was; nothing moves if one of them has been replaced.
+    fn restore(&self, id: &str) -> Result<Entry, (StatusCode, String)> {
+        let entry = self
+            .read_entry(id)
+            .ok_or((StatusCode::NOT_FOUND, format!("no trash entry {id}")))?;
+        let root = PathBuf::from(&entry.root);
+        let taken = entry
+            .paths
+            .iter()
+            .filter(|path| root.join(path).exists())
+            .cloned()
+            .collect::<Vec<_>>();
+        if !taken.is_empty() {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("already exist again: {}", taken.join(", ")),
+            ));
+        }
+        let files = self.files_dir(id);
+        for path in &entry.paths {
+            let target = root.join(path);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+            }
+            move_path(&files.join(path), &target)
+                .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+        }
+        let _ = std::fs::remove_dir_all(self.entry_dir(id));
+        info!("restored {} from the trash", entry.label);
+        Ok(entry)
+    }
+
+    fn purge(&self, id: &str) -> bool {
+        self.read_entry(id).is_some() && std::fs::remove_dir_all(self.entry_dir(id)).is_ok()
+    }
+
+    fn purge_expired(&self) -> usize {
+        if self.config.retention_days == 0 {
+            return 0;
+        }
+        let cutoff = unix_now().saturating_sub(self.config.retention_days * 86_400);
+        self.entries()
+            .iter()
+            .filter(|entry| entry.deleted_at < cutoff && self.purge(&entry.id))
+            .count()
+    }
+}
+
+fn link_tree(from: &Path, to: &Path) -> std::io::Result<()> {
+    let meta = std::fs::metadata(from)?;
+    if !meta.is_dir() {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        return std::fs::hard_link(from, to).or_else(|_| std::fs::copy(from, to).map(|_| ()));
+    }
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        link_tree(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+fn list_files(root: &Path, relative: &Path, files: &mut Vec<(PathBuf, u64)>) {
+    let Ok(entries) = std::fs::read_dir(root.join(relative)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = relative.join(entry.file_name());
+        match entry.metadata() {
+            Ok(meta) if meta.is_dir() => list_files(root, &path, files),
+            Ok(meta) => files.push((path, meta.len())),
+            Err(_) => {}
+        }
+    }
+}
+
+fn tree_size(path: &Path) -> u64 {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => {
+            let mut files = Vec::new();
+            list_files(path, Path::new(""), &mut files);
+            files.iter().map(|(_, size)| size).sum()
+        }
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
+
+// `DELETE /api/v1/manga/{id}/chapter/{index}` removes a downloaded chapter.
+pub(crate) async fn hold(state: &AppState, parts: &Parts) -> Option<Held> {
+    if parts.method != Method::DELETE {
+        return None;
+    }
+    let rest = parts.uri.path().strip_prefix("/api/v1/manga/")?;
+    let segments = rest.split('/').collect::<Vec<_>>();
+    let [manga_id, "chapter", index] = segments.as_slice() else {
+        return None;
+    };
+    let (manga_id, index) = (manga_id.parse().ok()?, index.parse().ok()?);
+    state.trash.hold_chapter(state, manga_id, index).await
+}
+
+pub(crate) fn spawn(state: AppState) {
+    if !state.trash.config.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PURGE_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let trash = state.trash.clone();
+            if let Ok(purged @ 1..) =
+                tokio::task::spawn_blocking(move || trash.purge_expired()).await
+            {
+                info!("trash purged {} expired entries", purged);
+            }
+        }
+    });
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/trash", get(list))
+        .route("/admin/trash/{id}", delete(remove))
+        .route("/admin/trash/{id}/restore", post(restore))
+}
+
+async fn list(State(state): State<AppState>) -> Json<Value> {
+    let trash = &state.trash;
+    let entries = trash.entries();
+    Json(json!({
+        "enabled": trash.config.enabled,
+        "path": trash.dir.display().to_string(),
+        "retention_days": trash.config.retention_days,
+        "bytes": entries.iter().map(|entry| entry.bytes).sum::<u64>(),
+        "entries": entries,
+    }))
+}
+
+async fn restore(State(state): State<AppState>, UrlPath(id): UrlPath<String>) -> Response {
+    let trash = state.trash.clone();
+    match tokio::task::spawn_blocking(move || trash.restore(&id)).await {
+        Ok(Ok(entry)) => Json(entry).into_response(),
+        Ok(Err((status, message))) => (status, message).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+async fn remove(State(state): State<AppState>, UrlPath(id): UrlPath<String>) -> StatusCode {
+    if state.trash.purge(&id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
@@ -0,0 +1,90 @@
+use axum::http::HeaderMap;
+use serde_json::Value;
+
+use crate::app::AppState;
+
+const MAX_PAGES: u32 = 10;
+
+pub(crate) struct RecentChapter {
+    pub manga_id: i64,
+    pub manga_title: String,
+    pub manga_status: String,
+    #[cfg(feature = "opds")]
+    pub chapter_id: i64,
+    #[cfg(feature = "opds")]
+    pub chapter_index: i64,
+    pub chapter_name: String,
+    pub upload_date: i64,
+    pub fetched_at: i64,
+}
+
+impl RecentChapter {
+    fn from_value(item: &Value) -> Option<Self> {
+        let manga = item.get("manga")?;
+        let chapter = item.get("chapter")?;
+        Some(Self {
+            manga_id: manga.get("id")?.as_i64()?,
+            manga_title: manga
+                .get("title")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            manga_status: manga
+                .get("status")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            #[cfg(feature = "opds")]
+            chapter_id: chapter
+                .get("id")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+            #[cfg(feature = "opds")]
+            chapter_index: chapter
+                .get("index")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+            chapter_name: chapter
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            upload_date: chapter
+                .get("uploadDate")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+            fetched_at: chapter
+                .get("fetchedAt")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+        })
+    }
+
+    pub(crate) fn released_at(&self) -> i64 {
+        if self.upload_date > 0 {
+            self.upload_date / 1000
+        } else {
+            self.fetched_at
+        }
+    }
+}
+
+pub(crate) async fn recent_chapters(
+    state: &AppState,
+    headers: &HeaderMap,
+    pages: u32,
+) -> Result<Vec<RecentChapter>, String> {
+    let mut chapters = Vec::new();
+    for page in 0..pages.clamp(1, MAX_PAGES) {
+        let value = state
+            .backend_json(&format!("/api/v1/update/recentChapters/{page}"), headers)
+            .await?;
+        if let Some(items) = value.get("page").and_then(Value::as_array) {
+            chapters.extend(items.iter().filter_map(RecentChapter::from_value));
+        }
+        if value.get("hasNextPage").and_then(Value::as_bool) != Some(true) {
+            break;
+        }
+    }
+    Ok(chapters)
+}
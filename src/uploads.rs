@@ -0,0 +1,77 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use tracing::warn;
+
+use crate::app::{proxy_request, AppState};
+
+pub(crate) fn is_multipart(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .trim_start()
+                .to_ascii_lowercase()
+                .starts_with("multipart/")
+        })
+}
+
+pub(crate) async fn forward(state: &AppState, req: Request) -> Response {
+    let max_bytes = state.config.uploads.max_bytes;
+    let declared = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    if declared.is_some_and(|length| length > max_bytes) {
+        return too_large(max_bytes);
+    }
+
+    let (mut parts, body) = req.into_parts();
+    parts.headers.remove(header::EXPECT);
+
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let flag = exceeded.clone();
+    let mut received = 0u64;
+    let stream = body.into_data_stream().map(move |chunk| {
+        let chunk = chunk?;
+        received += chunk.len() as u64;
+        if received > max_bytes {
+            flag.store(true, Ordering::Relaxed);
+            return Err(axum::Error::new("upload exceeds the configured size limit"));
+        }
+        Ok(chunk)
+    });
+
+    let path = parts.uri.path().to_string();
+    let req = Request::from_parts(parts, Body::from_stream(stream));
+    let resp = proxy_request(
+        state.client.clone(),
+        req,
+        &state.backend_url,
+        "",
+        &state.config.routes,
+    )
+    .await;
+    if exceeded.load(Ordering::Relaxed) {
+        warn!("rejected upload to {} over {} bytes", path, max_bytes);
+        return too_large(max_bytes);
+    }
+    resp
+}
+
+pub(crate) fn too_large(max_bytes: u64) -> Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("upload exceeds the {max_bytes} byte limit"),
+    )
+        .into_response()
+}
@@ -0,0 +1,272 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::config::{RecoveryAction, WatchdogConfig};
+use crate::embedded::{EmbeddedServer, RestartOutcome};
+use crate::metrics::Metrics;
+use crate::outbound::Outbound;
+use crate::unix_now;
+
+const MAX_TRANSITIONS: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Health {
+    Unknown,
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+impl Health {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::Healthy => "healthy",
+            Self::Degraded => "degraded",
+            Self::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct Transition {
+    pub at: u64,
+    pub from: Health,
+    pub to: Health,
+    pub detail: String,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct WatchdogStatus {
+    pub enabled: bool,
+    pub health: Health,
+    pub consecutive_failures: u32,
+    pub last_check: Option<u64>,
+    pub last_error: Option<String>,
+    pub last_action: Option<&'static str>,
+    pub transitions: VecDeque<Transition>,
+}
+
+pub(crate) struct Watchdog {
+    config: WatchdogConfig,
+    status: Mutex<WatchdogStatus>,
+}
+
+impl Watchdog {
+    pub(crate) fn new(config: WatchdogConfig) -> Self {
+        let status = WatchdogStatus {
+            enabled: config.enabled,
+            health: Health::Unknown,
+            consecutive_failures: 0,
+            last_check: None,
+            last_error: None,
+            last_action: None,
+            transitions: VecDeque::new(),
+        };
+        Self {
+            config,
+            status: Mutex::new(status),
+        }
+    }
+
+    pub(crate) fn status(&self) -> WatchdogStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    fn record_success(&self, metrics: &Metrics) {
+        let mut status = self.status.lock().unwrap_or_else(|err| err.into_inner());
+        status.last_check = Some(unix_now());
+        status.consecutive_failures = 0;
+        status.last_error = None;
+        transition(&mut status, Health::Healthy, "probe succeeded".to_string());
+        metrics.set(
+            "manatan_watchdog_healthy",
+            "Whether the last watchdog probe succeeded.",
+            &[],
+            1.0,
+        );
+    }
+
+    fn record_failure(&self, err: String, metrics: &Metrics) -> Option<RecoveryAction> {
+        let mut status = self.status.lock().unwrap_or_else(|err| err.into_inner());
+        status.last_check = Some(unix_now());
+        status.consecutive_failures += 1;
+        status.last_error = Some(err.clone());
+        metrics.inc(
+            "manatan_watchdog_probe_failures_total",
+            "Watchdog probes that failed.",
+            &[],
+        );
+        metrics.set(
+            "manatan_watchdog_healthy",
+            "Whether the last watchdog probe succeeded.",
+            &[],
+            0.0,
+        );
+
+        let threshold = self.config.failure_threshold;
+        if status.consecutive_failures < threshold {
+            transition(&mut status, Health::Degraded, err);
+            return None;
+        }
+        transition(&mut status, Health::Unhealthy, err);
+
+        if !status.consecutive_failures.is_multiple_of(threshold) {
+            return None;
+        }
+        let step = (status.consecutive_failures / threshold - 1) as usize;
+        let action = self
+            .config
+            .actions
+            .get(step)
+            .or_else(|| self.config.actions.last())
+            .copied()?;
+        status.last_action = Some(action.as_str());
+        Some(action)
+    }
+}
+
+fn transition(status: &mut WatchdogStatus, to: Health, detail: String) {
+    if status.health == to {
+        return;
+    }
+    info!(
+        "watchdog: backend {} -> {} ({})",
+        status.health.as_str(),
+        to.as_str(),
+        detail
+    );
+    status.transitions.push_back(Transition {
+        at: unix_now(),
+        from: status.health,
+        to,
+        detail,
+    });
+    while status.transitions.len() > MAX_TRANSITIONS {
+        status.transitions.pop_front();
+    }
+    status.health = to;
+}
+
+pub(crate) fn spawn(
+    watchdog: Arc<Watchdog>,
+    server: Weak<EmbeddedServer>,
+    client: Client,
+    outbound: Arc<Outbound>,
+    backend_url: String,
+    metrics: Arc<Metrics>,
+) {
+    if !watchdog.config.enabled {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(watchdog.config.interval_seconds));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if server.strong_count() == 0 {
+                break;
+            }
+
+            match probe(&client, &backend_url, &watchdog.config).await {
+                Ok(()) => watchdog.record_success(&metrics),
+                Err(err) => {
+                    if let Some(action) = watchdog.record_failure(err, &metrics) {
+                        metrics.inc(
+                            "manatan_watchdog_actions_total",
+                            "Recovery actions executed by the watchdog.",
+                            &[("action", action.as_str())],
+                        );
+                        run_action(action, &watchdog, &server, &outbound, &metrics).await;
+                    }
+                }
+            }
+        }
+    });
+}
+
+async fn probe(client: &Client, backend_url: &str, config: &WatchdogConfig) -> Result<(), String> {
+    for endpoint in &config.endpoints {
+        let url = format!("{backend_url}{endpoint}");
+        let resp = client
+            .get(&url)
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .send()
+            .await
+            .map_err(|err| format!("{endpoint}: {err}"))?;
+        if resp.status().is_server_error() {
+            return Err(format!("{endpoint}: status {}", resp.status()));
+        }
+    }
+    Ok(())
+}
+
+async fn run_action(
+    action: RecoveryAction,
+    watchdog: &Watchdog,
+    server: &Weak<EmbeddedServer>,
+    outbound: &Outbound,
+    metrics: &Metrics,
+) {
+    let status = watchdog.status();
+    match action {
+        RecoveryAction::Log => {
+            warn!(
+                "watchdog: backend unhealthy after {} failures: {}",
+                status.consecutive_failures,
+                status.last_error.as_deref().unwrap_or("unknown error")
+            );
+        }
+        RecoveryAction::Notify => {
+            let Some(url) = watchdog.config.notify_url.as_deref() else {
+                warn!("watchdog: notify action configured without MANATAN_WATCHDOG_NOTIFY_URL");
+                return;
+            };
+            let payload = serde_json::json!({
+                "event": "watchdog",
+                "health": status.health,
+                "consecutive_failures": status.consecutive_failures,
+                "error": status.last_error,
+            });
+            if let Err(err) = outbound.post(url).json(&payload).send().await {
+                warn!("watchdog: notify to {} failed: {}", url, err);
+            }
+        }
+        RecoveryAction::RestartBackend => {
+            let Some(server) = server.upgrade() else {
+                return;
+            };
+            warn!("watchdog: restarting embedded backend");
+            let result = tokio::task::spawn_blocking(move || server.recover()).await;
+            match result {
+                Ok(Ok(RestartOutcome::Restarted)) => metrics.inc(
+                    "manatan_backend_restarts_total",
+                    "Embedded backend restarts.",
+                    &[],
+                ),
+                Ok(Ok(outcome)) => info!("watchdog: not restarting, {}", outcome),
+                Ok(Err(err)) => error!("watchdog: backend restart failed: {}", err),
+                Err(err) => error!("watchdog: backend restart task failed: {}", err),
+            }
+        }
+        RecoveryAction::RestartProcess => {
+            error!("watchdog: backend unrecoverable, exiting so the service manager restarts us");
+            if let Some(server) = server.upgrade() {
+                let _ = tokio::task::spawn_blocking(move || server.stop()).await;
+            }
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use tower::ServiceExt;
+use tower_http::services::{ServeDir, ServeFile};
+
+use crate::app::AppState;
+
+#[cfg(feature = "webui-embed")]
+static EMBEDDED: include_dir::Dir<'static> = include_dir::include_dir!("$MANATAN_WEBUI_DIST");
+
+// Unmatched GETs land here: real files are served as-is, and extensionless paths
+// fall back to index.html so the SPA's client-side router can take over.
+pub(crate) async fn fallback(State(state): State<AppState>, req: Request) -> Response {
+    if !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let spa_route = !req
+        .uri()
+        .path()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .contains('.');
+
+    let mut resp = if let Some(root) = state.config.webui_path.as_deref() {
+        from_disk(root, spa_route, req).await
+    } else {
+        from_embedded(req.uri().path(), spa_route)
+    };
+    let html = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    if html {
+        resp.headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
+    resp
+}
+
+async fn from_disk(root: &str, spa_route: bool, req: Request) -> Response {
+    let result = if spa_route {
+        ServeDir::new(root)
+            .fallback(ServeFile::new(Path::new(root).join("index.html")))
+            .oneshot(req)
+            .await
+    } else {
+        ServeDir::new(root).oneshot(req).await
+    };
+    match result {
+        Ok(resp) => resp.into_response(),
+        Err(err) => match err {},
+    }
+}
+
+#[cfg(feature = "webui-embed")]
+fn from_embedded(path: &str, spa_route: bool) -> Response {
+    let path = path.trim_start_matches('/');
+    let path = if path.is_empty() { "index.html" } else { path };
+    let file = EMBEDDED
+        .get_file(path)
+        .or_else(|| spa_route.then(|| EMBEDDED.get_file("index.html")).flatten());
+    match file {
+        Some(file) => {
+            let name = file.path().to_string_lossy();
+            (
+                [(header::CONTENT_TYPE, content_type(&name))],
+                file.contents(),
+            )
+                .into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(not(feature = "webui-embed"))]
+fn from_embedded(_path: &str, _spa_route: bool) -> Response {
+    StatusCode::NOT_FOUND.into_response()
+}
+
+#[cfg(feature = "webui-embed")]
+fn content_type(name: &str) -> &'static str {
+    let ext = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "html" => "text/html; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff2" => "font/woff2",
+        "woff" => "font/woff",
+        "wasm" => "application/wasm",
+        "txt" => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
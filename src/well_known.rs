@@ -0,0 +1,112 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, StatusCode},
+    response::Response,
+    routing::get,
+    Router,
+};
+use chrono::{Duration, Utc};
+use tracing::warn;
+
+use crate::app::AppState;
+use crate::config::WellKnownConfig;
+
+const DEFAULT_ROBOTS: &str = "User-agent: *\nDisallow: /\n";
+
+pub(crate) struct WellKnown {
+    favicon: Option<(Bytes, &'static str)>,
+    robots: Bytes,
+    security: Option<Bytes>,
+}
+
+impl WellKnown {
+    pub(crate) fn new(config: &WellKnownConfig) -> Self {
+        let favicon = config.favicon_path.as_deref().and_then(|path| {
+            read(path).map(|bytes| {
+                let content_type = match path.rsplit('.').next().map(str::to_lowercase).as_deref() {
+                    Some("png") => "image/png",
+                    Some("svg") => "image/svg+xml",
+                    Some("gif") => "image/gif",
+                    _ => "image/x-icon",
+                };
+                (bytes, content_type)
+            })
+        });
+        let robots = config
+            .robots_txt_path
+            .as_deref()
+            .and_then(read)
+            .unwrap_or_else(|| Bytes::from_static(DEFAULT_ROBOTS.as_bytes()));
+        let security = config
+            .security_txt_path
+            .as_deref()
+            .and_then(read)
+            .or_else(|| {
+                config.security_contact.as_deref().map(|contact| {
+                    let expires = Utc::now() + Duration::days(365);
+                    Bytes::from(format!(
+                        "Contact: {}\nExpires: {}\n",
+                        contact,
+                        expires.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+                    ))
+                })
+            });
+
+        Self {
+            favicon,
+            robots,
+            security,
+        }
+    }
+}
+
+fn read(path: &str) -> Option<Bytes> {
+    match std::fs::read(path) {
+        Ok(bytes) => Some(Bytes::from(bytes)),
+        Err(err) => {
+            warn!("failed to read {}: {}", path, err);
+            None
+        }
+    }
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/favicon.ico", get(favicon))
+        .route("/robots.txt", get(robots))
+        .route("/.well-known/security.txt", get(security))
+}
+
+async fn favicon(State(state): State<AppState>) -> Response {
+    let builder = Response::builder().header(header::CACHE_CONTROL, "public, max-age=86400");
+    match &state.well_known.favicon {
+        Some((bytes, content_type)) => builder
+            .header(header::CONTENT_TYPE, *content_type)
+            .body(Body::from(bytes.clone())),
+        None => builder.status(StatusCode::NO_CONTENT).body(Body::empty()),
+    }
+    .unwrap()
+}
+
+async fn robots(State(state): State<AppState>) -> Response {
+    text(state.well_known.robots.clone())
+}
+
+async fn security(State(state): State<AppState>) -> Response {
+    match &state.well_known.security {
+        Some(bytes) => text(bytes.clone()),
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+fn text(bytes: Bytes) -> Response {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(header::CACHE_CONTROL, "public, max-age=3600")
+        .body(Body::from(bytes))
+        .unwrap()
+}
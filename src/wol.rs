@@ -0,0 +1,71 @@
+use std::net::Ipv4Addr;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+use crate::app::AppState;
+use crate::config::MacAddress;
+
+const REPEAT: usize = 3;
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/admin/wol", get(targets).post(wake))
+}
+
+#[derive(Deserialize)]
+struct WakeRequest {
+    target: String,
+}
+
+async fn targets(State(state): State<AppState>) -> Json<Value> {
+    let targets = state
+        .config
+        .wol
+        .targets
+        .iter()
+        .map(|(name, mac)| json!({ "name": name, "mac": mac.to_string() }))
+        .collect::<Vec<_>>();
+    Json(json!({ "targets": targets }))
+}
+
+async fn wake(State(state): State<AppState>, Json(body): Json<WakeRequest>) -> Response {
+    let config = &state.config.wol;
+    // Only configured machines can be woken, by name or by their address.
+    let requested = body.target.parse::<MacAddress>().ok();
+    let Some((name, mac)) = config
+        .targets
+        .iter()
+        .find(|(name, mac)| *name == body.target || Some(*mac) == requested)
+    else {
+        return (StatusCode::NOT_FOUND, "unknown wake-on-lan target").into_response();
+    };
+
+    if let Err(err) = send(*mac, &config.broadcast, config.port).await {
+        warn!("wake-on-lan for {} ({}) failed: {}", name, mac, err);
+        return (StatusCode::BAD_GATEWAY, err.to_string()).into_response();
+    }
+    info!("sent wake-on-lan packet to {} ({})", name, mac);
+    Json(json!({ "name": name, "mac": mac.to_string(), "sent": true })).into_response()
+}
+
+async fn send(mac: MacAddress, broadcast: &str, port: u16) -> std::io::Result<()> {
+    let mut packet = vec![0xffu8; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac.0);
+    }
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+    for _ in 0..REPEAT {
+        socket.send_to(&packet, (broadcast, port)).await?;
+    }
+    Ok(())
+}
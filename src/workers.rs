@@ -0,0 +1,191 @@
+use std::sync::{Arc, Mutex};
+
+use axum::http::{HeaderMap, Method, Uri};
+use serde_json::json;
+use tokio::sync::Notify;
+
+use crate::config::{JobClass, WorkersConfig};
+
+#[derive(Default)]
+struct PoolState {
+    running: [usize; 4],
+    waiting: [usize; 4],
+}
+
+pub(crate) struct WorkerPool {
+    config: WorkersConfig,
+    state: Mutex<PoolState>,
+    notify: Notify,
+}
+
+pub(crate) struct WorkerPermit {
+    pool: Arc<WorkerPool>,
+    class: JobClass,
+}
+
+struct Waiting<'a> {
+    pool: &'a WorkerPool,
+    class: JobClass,
+}
+
+impl WorkerPool {
+    pub(crate) fn new(config: WorkersConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(PoolState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) async fn acquire(self: &Arc<Self>, class: JobClass) -> WorkerPermit {
+        let mut waiting = None;
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut state = self.lock();
+                if self.can_start(&state, class) {
+                    if let Some(registration) = waiting.take() {
+                        state.waiting[slot(class)] -= 1;
+                        std::mem::forget(registration);
+                    }
+                    state.running[slot(class)] += 1;
+                    return WorkerPermit {
+                        pool: self.clone(),
+                        class,
+                    };
+                }
+                if waiting.is_none() {
+                    state.waiting[slot(class)] += 1;
+                    waiting = Some(Waiting { pool: self, class });
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    fn can_start(&self, state: &PoolState, class: JobClass) -> bool {
+        if state.running[slot(class)] >= self.config.limit(class) {
+            return false;
+        }
+        self.config
+            .priority
+            .iter()
+            .take_while(|higher| **higher != class)
+            .all(|higher| state.waiting[slot(*higher)] == 0)
+    }
+
+    pub(crate) fn describe(&self) -> serde_json::Value {
+        let state = self.lock();
+        json!({
+            "enabled": self.config.enabled,
+            "priority": self.config.priority.iter().map(JobClass::as_str).collect::<Vec<_>>(),
+            "classes": JobClass::ALL
+                .iter()
+                .map(|class| json!({
+                    "class": class.as_str(),
+                    "limit": self.config.limit(*class),
+                    "running": state.running[slot(*class)],
+                    "waiting": state.waiting[slot(*class)],
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+}
+
+impl Drop for WorkerPermit {
+    fn drop(&mut self) {
+        self.pool.lock().running[slot(self.class)] -= 1;
+        self.pool.notify.notify_waiters();
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let mut state = self.pool.lock();
+        state.waiting[slot(self.class)] -= 1;
+        drop(state);
+        self.pool.notify.notify_waiters();
+    }
+}
+
+fn slot(class: JobClass) -> usize {
+    match class {
+        JobClass::Interactive => 0,
+        JobClass::Prefetch => 1,
+        JobClass::Thumbnail => 2,
+        JobClass::Background => 3,
+    }
+}
+
+const PRIORITY_HEADER: &str = "x-manatan-priority";
+
+pub(crate) fn classify(method: &Method, uri: &Uri, headers: &HeaderMap) -> Option<JobClass> {
+    let segments = uri
+        .path()
+        .strip_prefix("/api/v1/")?
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    match priority_hint(headers) {
+        Some(class) => return Some(class),
+        None if is_bulk(method, uri, &segments) => return Some(JobClass::Background),
+        None => {}
+    }
+    if method != Method::GET {
+        return None;
+    }
+    match segments.as_slice() {
+        ["manga" | "anime", _, "thumbnail"] => Some(JobClass::Thumbnail),
+        ["manga", _, "chapter", _, "page", _] if is_prefetch(headers) => Some(JobClass::Prefetch),
+        ["manga", _, "chapter", _, "page", _] => Some(JobClass::Interactive),
+        _ => None,
+    }
+}
+
+// Clients doing a library-wide refresh or queueing downloads can say so, and the
+// endpoints that only exist for that are recognised without the hint.
+fn priority_hint(headers: &HeaderMap) -> Option<JobClass> {
+    let value = headers.get(PRIORITY_HEADER)?.to_str().ok()?;
+    match value.trim().to_ascii_lowercase().as_str() {
+        "background" | "bulk" | "low" => Some(JobClass::Background),
+        "interactive" | "high" => Some(JobClass::Interactive),
+        _ => None,
+    }
+}
+
+fn is_bulk(method: &Method, uri: &Uri, segments: &[&str]) -> bool {
+    let online_fetch = uri.query().is_some_and(|query| {
+        query
+            .split('&')
+            .any(|pair| pair.eq_ignore_ascii_case("onlineFetch=true"))
+    });
+    match segments {
+        ["update", "fetch"] | ["download", "batch"] | ["chapter", "batch"] => true,
+        ["manga", _, "chapter", "batch"] | ["backup", ..] => true,
+        ["manga", _] | ["manga", _, "full" | "chapters"] => method == Method::GET && online_fetch,
+        _ => false,
+    }
+}
+
+fn is_prefetch(headers: &HeaderMap) -> bool {
+    ["sec-purpose", "purpose", "x-manatan-prefetch"]
+        .iter()
+        .filter_map(|name| headers.get(*name))
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| {
+            let value = value.to_ascii_lowercase();
+            value.contains("prefetch") || value == "1" || value == "true"
+        })
+}
@@ -0,0 +1,631 @@
+use std::collections::BTreeMap;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{close_code, Message, WebSocket, WebSocketUpgrade},
+        FromRequestParts, State,
+    },
+    http::{request::Parts, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio_tungstenite::{
+    client_async_tls,
+    tungstenite::{
+        client::IntoClientRequest,
+        error::UrlError,
+        protocol::{frame::coding::CloseCode, CloseFrame, Message as TungsteniteMessage},
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+use tracing::warn;
+
+use crate::app::AppState;
+use crate::backend_addr::Transport;
+use crate::metrics::Metrics;
+use crate::rate_limit::WebSocketPermit;
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(75);
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// TCP, scoped IPv6 and Unix socket connections all end up behind one type.
+pub(crate) trait BackendIo: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> BackendIo for T {}
+
+type BackendSocket = WebSocketStream<MaybeTlsStream<Box<dyn BackendIo>>>;
+
+const FORWARDED_HEADERS: [&str; 5] = [
+    "cookie",
+    "authorization",
+    "user-agent",
+    "sec-websocket-protocol",
+    "origin",
+];
+
+#[derive(Clone)]
+pub struct WsBridge {
+    backend_ws: String,
+    metrics: Option<Arc<Metrics>>,
+    bridges: Option<Arc<Bridges>>,
+    permit: Option<WebSocketPermit>,
+    transport: Transport,
+}
+
+impl std::fmt::Debug for WsBridge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WsBridge")
+            .field("backend_ws", &self.backend_ws)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WsBridge {
+    pub fn new(backend_url: &str) -> Self {
+        Self {
+            backend_ws: backend_ws_url(backend_url),
+            metrics: None,
+            bridges: None,
+            permit: None,
+            transport: Transport::Url,
+        }
+    }
+
+    // Bridges to the backend `state` runs, over whatever transport it is reached by
+    // (a Unix socket or scoped IPv6 address included), counted in its metrics and listing.
+    pub fn from_state(state: &AppState) -> Self {
+        Self::new(&state.backend_url)
+            .with_metrics(state.metrics.clone())
+            .with_bridges(state.ws_bridges.clone())
+            .with_transport(state.backend_transport.clone())
+    }
+
+    pub(crate) fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // Lists the connection in `/admin/websockets` while it is open.
+    pub(crate) fn with_bridges(mut self, bridges: Arc<Bridges>) -> Self {
+        self.bridges = Some(bridges);
+        self
+    }
+
+    // Held for the life of the bridged connection so connection caps count it.
+    pub(crate) fn with_permit(mut self, permit: Option<WebSocketPermit>) -> Self {
+        self.permit = permit;
+        self
+    }
+
+    // How to reach the backend when the URL host is only a placeholder.
+    pub(crate) fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn is_upgrade(headers: &HeaderMap) -> bool {
+        headers
+            .get("upgrade")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.eq_ignore_ascii_case("websocket"))
+            .unwrap_or(false)
+    }
+
+    pub async fn upgrade(&self, parts: &mut Parts) -> Response {
+        let path_query = parts
+            .uri
+            .path_and_query()
+            .map(|v| v.as_str())
+            .unwrap_or(parts.uri.path());
+        let backend_url = format!("{}{path_query}", self.backend_ws);
+        let headers = parts.headers.clone();
+        let protocols: Vec<String> = parts
+            .headers
+            .get("sec-websocket-protocol")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(',').map(|s| s.trim().to_string()).collect())
+            .unwrap_or_default();
+
+        // The query is left out of the listing; it can carry tokens.
+        let stats = Arc::new(BridgeStats::new(parts.uri.path()));
+        let metrics = self.metrics.clone();
+        let bridges = self.bridges.clone();
+        let permit = self.permit.clone();
+        let transport = self.transport.clone();
+        match WebSocketUpgrade::from_request_parts(parts, &()).await {
+            Ok(ws) => ws
+                .protocols(protocols)
+                .on_upgrade(move |socket| async move {
+                    let _guard = metrics.clone().map(ConnectionGuard::new);
+                    let _listing = bridges.map(|bridges| Listing::new(bridges, stats.clone()));
+                    let _permit = permit;
+                    bridge(socket, headers, backend_url, &transport, metrics, &stats).await
+                })
+                .into_response(),
+            Err(err) => err.into_response(),
+        }
+    }
+}
+
+struct ConnectionGuard(Arc<Metrics>);
+
+impl ConnectionGuard {
+    fn new(metrics: Arc<Metrics>) -> Self {
+        metrics.add_gauge(
+            "manatan_websocket_connections",
+            "Active proxied WebSocket connections.",
+            &[],
+            1.0,
+        );
+        Self(metrics)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.add_gauge(
+            "manatan_websocket_connections",
+            "Active proxied WebSocket connections.",
+            &[],
+            -1.0,
+        );
+    }
+}
+
+// Delivery figures for one bridge in the backend-to-client direction, which carries the
+// download and update progress events. Lag runs from a frame arriving from the backend to
+// the client socket accepting it, so a slow client or a congested link shows up here.
+pub(crate) struct BridgeStats {
+    path: String,
+    started: Instant,
+    frames: AtomicU64,
+    dropped: AtomicU64,
+    in_flight: AtomicBool,
+    last_lag_us: AtomicU64,
+    max_lag_us: AtomicU64,
+    total_lag_us: AtomicU64,
+}
+
+impl BridgeStats {
+    fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            started: Instant::now(),
+            frames: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            in_flight: AtomicBool::new(false),
+            last_lag_us: AtomicU64::new(0),
+            max_lag_us: AtomicU64::new(0),
+            total_lag_us: AtomicU64::new(0),
+        }
+    }
+
+    fn delivered(&self, lag: Duration) {
+        let lag_us = lag.as_micros() as u64;
+        self.in_flight.store(false, Ordering::Relaxed);
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.last_lag_us.store(lag_us, Ordering::Relaxed);
+        self.max_lag_us.fetch_max(lag_us, Ordering::Relaxed);
+        self.total_lag_us.fetch_add(lag_us, Ordering::Relaxed);
+    }
+
+    fn describe(&self, id: u64) -> Value {
+        let ms = |us: u64| us as f64 / 1000.0;
+        let frames = self.frames.load(Ordering::Relaxed);
+        let total = self.total_lag_us.load(Ordering::Relaxed);
+        json!({
+            "id": id,
+            "path": self.path,
+            "age_seconds": self.started.elapsed().as_secs(),
+            "frames": frames,
+            "dropped": self.dropped.load(Ordering::Relaxed),
+            "lag_ms": {
+                "last": ms(self.last_lag_us.load(Ordering::Relaxed)),
+                "max": ms(self.max_lag_us.load(Ordering::Relaxed)),
+                "mean": ms(total.checked_div(frames).unwrap_or(0)),
+            },
+        })
+    }
+}
+
+// The bridges open right now, for `/admin/websockets`.
+#[derive(Default)]
+pub(crate) struct Bridges {
+    next: AtomicU64,
+    open: std::sync::Mutex<BTreeMap<u64, Arc<BridgeStats>>>,
+}
+
+impl Bridges {
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, Arc<BridgeStats>>> {
+        self.open.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+struct Listing(Arc<Bridges>, u64);
+
+impl Listing {
+    fn new(bridges: Arc<Bridges>, stats: Arc<BridgeStats>) -> Self {
+        let id = bridges.next.fetch_add(1, Ordering::Relaxed) + 1;
+        bridges.lock().insert(id, stats);
+        Self(bridges, id)
+    }
+}
+
+impl Drop for Listing {
+    fn drop(&mut self) {
+        self.0.lock().remove(&self.1);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum End {
+    ClientClosed,
+    BackendClosed,
+    ClientLost,
+    BackendLost,
+    ClientTimeout,
+    BackendTimeout,
+}
+
+impl End {
+    fn reason(self) -> &'static str {
+        match self {
+            Self::ClientClosed => "client_closed",
+            Self::BackendClosed => "backend_closed",
+            Self::ClientLost => "client_lost",
+            Self::BackendLost => "backend_lost",
+            Self::ClientTimeout => "client_timeout",
+            Self::BackendTimeout => "backend_timeout",
+        }
+    }
+}
+
+// Milliseconds since the bridge started at which each side last sent anything.
+struct Activity {
+    started: Instant,
+    client: AtomicU64,
+    backend: AtomicU64,
+}
+
+impl Activity {
+    fn touch(&self, side: &AtomicU64) {
+        side.store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn idle(&self, side: &AtomicU64) -> Duration {
+        let last = Duration::from_millis(side.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+}
+
+type ClientSink = Arc<Mutex<SplitSink<WebSocket, Message>>>;
+type BackendSink = Arc<Mutex<SplitSink<BackendSocket, TungsteniteMessage>>>;
+
+async fn bridge(
+    client_socket: WebSocket,
+    headers: HeaderMap,
+    backend_url: String,
+    transport: &Transport,
+    metrics: Option<Arc<Metrics>>,
+    stats: &BridgeStats,
+) {
+    let (client_sender, mut client_receiver) = client_socket.split();
+    let client_sender: ClientSink = Arc::new(Mutex::new(client_sender));
+    let backend_socket = match connect(&headers, &backend_url, transport).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            warn!("backend ws connect to {} failed: {}", backend_url, err);
+            close_client(&client_sender, close_code::ERROR, "backend unavailable").await;
+            record_abnormal(metrics.as_deref(), "connect_failed");
+            return;
+        }
+    };
+    let (backend_sender, mut backend_receiver) = backend_socket.split();
+    let backend_sender: BackendSink = Arc::new(Mutex::new(backend_sender));
+    let activity = Activity {
+        started: Instant::now(),
+        client: AtomicU64::new(0),
+        backend: AtomicU64::new(0),
+    };
+
+    // Each direction pumps on its own, so a slow reader only stalls the
+    // frames headed its way.
+    let mut to_backend = pin!(client_to_backend(
+        &mut client_receiver,
+        &backend_sender,
+        &activity
+    ));
+    let mut to_client = pin!(backend_to_client(
+        &mut backend_receiver,
+        &client_sender,
+        &activity,
+        stats,
+        metrics.as_deref()
+    ));
+    let mut keepalive = pin!(keepalive(&client_sender, &backend_sender, &activity));
+    let end = tokio::select! {
+        end = &mut to_backend => end,
+        end = &mut to_client => end,
+        end = &mut keepalive => end,
+    };
+
+    match end {
+        // Give the other side a moment to answer the close handshake.
+        End::ClientClosed => {
+            let _ = tokio::time::timeout(CLOSE_TIMEOUT, to_client).await;
+        }
+        End::BackendClosed => {
+            let _ = tokio::time::timeout(CLOSE_TIMEOUT, to_backend).await;
+        }
+        End::ClientLost | End::ClientTimeout => {
+            close_backend(&backend_sender, CloseCode::Away, "client went away").await;
+        }
+        End::BackendLost | End::BackendTimeout => {
+            close_client(&client_sender, close_code::ERROR, "backend connection lost").await;
+        }
+    }
+    // A frame still waiting on the client when the bridge ends never reaches it.
+    if stats.in_flight.swap(false, Ordering::Relaxed) {
+        stats.dropped.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = metrics.as_deref() {
+            metrics.inc(
+                "manatan_websocket_dropped_frames_total",
+                "Backend frames a proxied WebSocket client never received.",
+                &[("reason", end.reason())],
+            );
+        }
+    }
+    if !matches!(end, End::ClientClosed | End::BackendClosed) {
+        warn!(
+            "websocket bridge to {} ended: {} ({} frames delivered, {} dropped)",
+            backend_url,
+            end.reason(),
+            stats.frames.load(Ordering::Relaxed),
+            stats.dropped.load(Ordering::Relaxed)
+        );
+        record_abnormal(metrics.as_deref(), end.reason());
+    }
+}
+
+async fn connect(
+    headers: &HeaderMap,
+    backend_url: &str,
+    transport: &Transport,
+) -> Result<BackendSocket, tokio_tungstenite::tungstenite::Error> {
+    let mut request = backend_url.into_client_request()?;
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = headers.get(name) {
+            request.headers_mut().insert(name, value.clone());
+        }
+    }
+    connect_backend(request, transport).await
+}
+
+pub(crate) async fn connect_backend(
+    request: impl IntoClientRequest + Unpin,
+    transport: &Transport,
+) -> Result<BackendSocket, tokio_tungstenite::tungstenite::Error> {
+    let request = request.into_client_request()?;
+    let stream: Box<dyn BackendIo> = match transport {
+        Transport::Url => {
+            let uri = request.uri();
+            let host = uri
+                .host()
+                .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+                .ok_or(tokio_tungstenite::tungstenite::Error::Url(
+                    UrlError::NoHostName,
+                ))?;
+            let port = uri
+                .port_u16()
+                .unwrap_or(if uri.scheme_str() == Some("wss") {
+                    443
+                } else {
+                    80
+                });
+            Box::new(TcpStream::connect((host, port)).await?)
+        }
+        Transport::Scoped(addr) => Box::new(TcpStream::connect(addr).await?),
+        #[cfg(unix)]
+        Transport::Unix(path) => Box::new(tokio::net::UnixStream::connect(path).await?),
+        #[cfg(not(unix))]
+        Transport::Unix(_) => {
+            return Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into())
+        }
+    };
+    let (socket, _) = client_async_tls(request, stream).await?;
+    Ok(socket)
+}
+
+async fn client_to_backend(
+    receiver: &mut SplitStream<WebSocket>,
+    backend: &BackendSink,
+    activity: &Activity,
+) -> End {
+    while let Some(msg) = receiver.next().await {
+        let Ok(msg) = msg else {
+            return End::ClientLost;
+        };
+        activity.touch(&activity.client);
+        let close = matches!(msg, Message::Close(_));
+        let Some(msg) = axum_to_tungstenite(msg) else {
+            continue;
+        };
+        if backend.lock().await.send(msg).await.is_err() {
+            return End::BackendLost;
+        }
+        if close {
+            return End::ClientClosed;
+        }
+    }
+    End::ClientLost
+}
+
+async fn backend_to_client(
+    receiver: &mut SplitStream<BackendSocket>,
+    client: &ClientSink,
+    activity: &Activity,
+    stats: &BridgeStats,
+    metrics: Option<&Metrics>,
+) -> End {
+    while let Some(msg) = receiver.next().await {
+        let Ok(msg) = msg else {
+            return End::BackendLost;
+        };
+        let received = Instant::now();
+        activity.touch(&activity.backend);
+        let close = matches!(msg, TungsteniteMessage::Close(_));
+        let Some(msg) = tungstenite_to_axum(msg) else {
+            continue;
+        };
+        stats.in_flight.store(true, Ordering::Relaxed);
+        if client.lock().await.send(msg).await.is_err() {
+            return End::ClientLost;
+        }
+        let lag = received.elapsed();
+        stats.delivered(lag);
+        if let Some(metrics) = metrics {
+            metrics.observe(
+                "manatan_websocket_delivery_seconds",
+                "Time from a backend frame arriving to the proxied client accepting it.",
+                &[],
+                lag.as_secs_f64(),
+            );
+        }
+        if close {
+            return End::BackendClosed;
+        }
+    }
+    End::BackendLost
+}
+
+// Pings are hop-by-hop: each side gets its own, and both answer automatically.
+async fn keepalive(client: &ClientSink, backend: &BackendSink, activity: &Activity) -> End {
+    let mut ticker = tokio::time::interval(PING_INTERVAL);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if activity.idle(&activity.client) > IDLE_TIMEOUT {
+            return End::ClientTimeout;
+        }
+        if activity.idle(&activity.backend) > IDLE_TIMEOUT {
+            return End::BackendTimeout;
+        }
+        if client
+            .lock()
+            .await
+            .send(Message::Ping(Bytes::new()))
+            .await
+            .is_err()
+        {
+            return End::ClientLost;
+        }
+        if backend
+            .lock()
+            .await
+            .send(TungsteniteMessage::Ping(Vec::new()))
+            .await
+            .is_err()
+        {
+            return End::BackendLost;
+        }
+    }
+}
+
+async fn close_client(client: &ClientSink, code: u16, reason: &'static str) {
+    let frame = axum::extract::ws::CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    let mut client = client.lock().await;
+    let _ = client.send(Message::Close(Some(frame))).await;
+    let _ = client.close().await;
+}
+
+async fn close_backend(backend: &BackendSink, code: CloseCode, reason: &'static str) {
+    let frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    let mut backend = backend.lock().await;
+    let _ = backend.send(TungsteniteMessage::Close(Some(frame))).await;
+    let _ = backend.close().await;
+}
+
+fn record_abnormal(metrics: Option<&Metrics>, reason: &str) {
+    if let Some(metrics) = metrics {
+        metrics.inc(
+            "manatan_websocket_abnormal_closures_total",
+            "Proxied WebSocket bridges that ended without a close handshake.",
+            &[("reason", reason)],
+        );
+    }
+}
+
+fn axum_to_tungstenite(msg: Message) -> Option<TungsteniteMessage> {
+    match msg {
+        Message::Text(t) => Some(TungsteniteMessage::Text(t.as_str().into())),
+        Message::Binary(b) => Some(TungsteniteMessage::Binary(b.to_vec())),
+        Message::Ping(_) | Message::Pong(_) => None,
+        Message::Close(c) => {
+            let frame = c.map(|cf| CloseFrame {
+                code: CloseCode::from(cf.code),
+                reason: cf.reason.to_string().into(),
+            });
+            Some(TungsteniteMessage::Close(frame))
+        }
+    }
+}
+
+fn tungstenite_to_axum(msg: TungsteniteMessage) -> Option<Message> {
+    match msg {
+        TungsteniteMessage::Text(t) => Some(Message::Text(t.as_str().into())),
+        TungsteniteMessage::Binary(b) => Some(Message::Binary(b.into())),
+        TungsteniteMessage::Ping(_) | TungsteniteMessage::Pong(_) => None,
+        TungsteniteMessage::Close(c) => {
+            let frame = c.map(|cf| axum::extract::ws::CloseFrame {
+                code: u16::from(cf.code),
+                reason: cf.reason.to_string().into(),
+            });
+            Some(Message::Close(frame))
+        }
+        // Only produced when writing raw frames; reads always yield whole messages.
+        TungsteniteMessage::Frame(_) => None,
+    }
+}
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/admin/websockets", get(status))
+}
+
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    let bridges = state
+        .ws_bridges
+        .lock()
+        .iter()
+        .map(|(id, stats)| stats.describe(*id))
+        .collect::<Vec<_>>();
+    Json(json!({ "bridges": bridges }))
+}
+
+pub(crate) fn backend_ws_url(base: &str) -> String {
+    if let Some(stripped) = base.strip_prefix("https://") {
+        format!("wss://{}", stripped)
+    } else if let Some(stripped) = base.strip_prefix("http://") {
+        format!("ws://{}", stripped)
+    } else {
+        format!("ws://{}", base)
+    }
+}